@@ -371,6 +371,8 @@ impl FromRequest for AuthData {
         let headers = request.headers();
         if let Some(jwt) = headers.get("authorization") {
             if let Ok(k) = jwt.to_str() {
+                // LndHub clients send the access token as a bearer token.
+                let k = k.strip_prefix("Bearer ").unwrap_or(k);
                 match jwt_check(k) {
                     Ok(x) => ok(Self {
                         uid: x.claims.get_user(),
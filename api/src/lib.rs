@@ -15,11 +15,15 @@ use tokio::sync::{mpsc, Mutex};
 
 use actix_ratelimit::{MemoryStore, MemoryStoreActor, RateLimiter};
 use core_types::{DbPool, UserId};
+use utils::mailer::{Mailer, MailerSettings};
 use utils::xzmq::SocketContext;
 
 pub mod comms;
 pub mod jwt;
+pub mod lnurl_auth;
+pub mod lnurl_pay;
 pub mod routes;
+pub mod totp;
 
 use comms::*;
 use utils::xlogging::slog::Logger;
@@ -40,6 +44,27 @@ pub struct ApiSettings {
     reserved_usernames: Vec<String>,
     domain: String,
     deezy_api_token: String,
+    /// Allows resolving lightning addresses and lnurls over plain http, only meant for local testing.
+    #[serde(default)]
+    lnurl_allow_insecure: bool,
+    /// Public key of the nostr engine, handed out in Nostr Wallet Connect connection strings.
+    #[serde(default)]
+    nwc_wallet_pubkey: Option<String>,
+    /// Relay the nostr engine listens on for Nostr Wallet Connect requests.
+    #[serde(default)]
+    nwc_relay: Option<String>,
+    /// Key signing zap requests of users who don't sign their own.
+    #[serde(default)]
+    nostr_zap_private_key: Option<String>,
+    /// Relays zap receipts are requested on, shared with the nostr engine.
+    #[serde(default)]
+    nostr_relays_urls: Vec<String>,
+    /// SMTP server verification emails are sent through.
+    #[serde(default)]
+    mailer_settings: Option<MailerSettings>,
+    /// Only accounts created with a valid invite code are accepted.
+    #[serde(default)]
+    require_invite_code: bool,
 }
 
 impl ApiSettings {
@@ -158,6 +183,8 @@ pub async fn start(settings: ApiSettings, logger: Logger) -> std::io::Result<()>
         settings.creation_quota_interval_seconds,
     )));
 
+    let lnurl_auth_challenges = Arc::new(Mutex::new(lnurl_auth::LnurlAuthChallenges::default()));
+
     let admin_uids = settings
         .admin_uids
         .clone()
@@ -171,6 +198,11 @@ pub async fn start(settings: ApiSettings, logger: Logger) -> std::io::Result<()>
         .map(|username| username.to_lowercase())
         .collect::<HashSet<String>>();
 
+    let mailer = settings
+        .mailer_settings
+        .as_ref()
+        .map(|mailer_settings| Mailer::new(mailer_settings).expect("Failed to configure the mailer"));
+
     HttpServer::new(move || {
         App::new()
             .wrap(Cors::permissive())
@@ -189,12 +221,18 @@ pub async fn start(settings: ApiSettings, logger: Logger) -> std::io::Result<()>
             .app_data(Data::new(price_cache.clone()))
             .app_data(Data::new(logger.clone()))
             .app_data(Data::new(creation_limiter.clone()))
+            .app_data(Data::new(lnurl_auth_challenges.clone()))
             .app_data(Data::new(admin_uids.clone()))
             .app_data(Data::new(reserved_usernames.clone()))
             .app_data(Data::new(settings.clone()))
+            .app_data(Data::new(mailer.clone()))
             .service(routes::auth::create)
             .service(routes::auth::auth)
             .service(routes::auth::whoami)
+            .service(routes::auth::lnurl_auth)
+            .service(routes::auth::lnurl_auth_link)
+            .service(routes::auth::lnurl_auth_callback)
+            .service(routes::auth::lnurl_auth_status)
             .service(routes::user::balance)
             .service(routes::user::add_invoice)
             .service(routes::user::pay_invoice)
@@ -222,13 +260,52 @@ pub async fn start(settings: ApiSettings, logger: Logger) -> std::io::Result<()>
             .service(routes::nostr::nostr_nip05)
             .service(routes::nostr::get_nostr_profile)
             .service(routes::nostr::search_nostr_profile)
+            .service(routes::nostr::create_nip05_name)
+            .service(routes::nostr::get_nip05_names)
+            .service(routes::nostr::delete_nip05_name)
+            .service(routes::nostr::get_nostr_contacts)
+            .service(routes::nwc::create_nwc_connection)
+            .service(routes::nwc::get_nwc_connections)
+            .service(routes::nwc::revoke_nwc_connection)
+            .service(routes::zaps::zap)
             .service(routes::user_profile::get_user_profile)
             .service(routes::user_profile::user_profile)
+            .service(routes::user_profile::get_notification_preferences)
+            .service(routes::user_profile::update_notification_preferences)
+            .service(routes::email::request_email_verification)
+            .service(routes::email::verify_email)
             .service(routes::admin::disable_create)
             .service(routes::admin::enable_create)
+            .service(routes::admin::disable_nip05_name)
+            .service(routes::admin::enable_nip05_name)
+            .service(routes::invites::join_waitlist)
+            .service(routes::invites::create_invite_codes)
+            .service(routes::invites::get_invite_codes)
+            .service(routes::invites::invite_from_waitlist)
             .service(routes::user::get_dca_settings)
             .service(routes::user::delete_dca_settings)
             .service(routes::user::set_dca_settings)
+            .service(routes::totp::totp_status)
+            .service(routes::totp::enrol_totp)
+            .service(routes::totp::confirm_totp)
+            .service(routes::totp::disable_totp)
+            .service(routes::totp::set_totp_threshold)
+            .service(
+                web::scope("/lndhub")
+                    .service(routes::lndhub::create)
+                    .service(routes::lndhub::auth)
+                    .service(routes::lndhub::balance)
+                    .service(routes::lndhub::add_invoice)
+                    .service(routes::lndhub::pay_invoice)
+                    .service(routes::lndhub::get_user_invoices)
+                    .service(routes::lndhub::get_txs)
+                    .service(routes::lndhub::get_pending)
+                    .service(routes::lndhub::get_btc)
+                    .service(routes::lndhub::get_info)
+                    .service(routes::lndhub::decode_invoice)
+                    .service(routes::lndhub::check_route_invoice)
+                    .service(routes::lndhub::check_payment),
+            )
     })
     .bind(endpoint)?
     .run()
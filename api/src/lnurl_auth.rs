@@ -0,0 +1,104 @@
+use ring::constant_time::verify_slices_are_equal;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long a wallet has to sign a challenge and the client to pick up the issued tokens.
+pub const CHALLENGE_TTL: Duration = Duration::from_secs(5 * 60);
+
+struct LnurlAuthChallenge {
+    created_at: Instant,
+    /// Handed out only to the client that requested the challenge, required to collect the tokens.
+    poll_secret: String,
+    /// Set when the challenge links a new wallet to an already logged in user.
+    link_uid: Option<i32>,
+    signed: bool,
+    /// Access and refresh token issued after a successful login, waiting to be collected.
+    tokens: Option<(String, String)>,
+}
+
+/// In memory store of outstanding LUD-04 challenges. Each k1 can be signed exactly once.
+#[derive(Default)]
+pub struct LnurlAuthChallenges {
+    challenges: HashMap<String, LnurlAuthChallenge>,
+}
+
+impl LnurlAuthChallenges {
+    pub fn insert(&mut self, k1: String, poll_secret: String, link_uid: Option<i32>) {
+        self.prune();
+        self.challenges.insert(
+            k1,
+            LnurlAuthChallenge {
+                created_at: Instant::now(),
+                poll_secret,
+                link_uid,
+                signed: false,
+                tokens: None,
+            },
+        );
+    }
+
+    /// Marks the challenge as used. Returns the uid to link to, if any, on Ok and
+    /// Err if the challenge is unknown, expired or was already signed.
+    pub fn sign(&mut self, k1: &str) -> Result<Option<i32>, ()> {
+        self.prune();
+        match self.challenges.get_mut(k1) {
+            Some(challenge) if !challenge.signed => {
+                challenge.signed = true;
+                Ok(challenge.link_uid)
+            }
+            _ => Err(()),
+        }
+    }
+
+    pub fn complete(&mut self, k1: &str, token: String, refresh: String) {
+        if let Some(challenge) = self.challenges.get_mut(k1) {
+            challenge.tokens = Some((token, refresh));
+        }
+    }
+
+    /// Returns whether the challenge is still known to the holder of `poll_secret` and hands out the
+    /// tokens once available.
+    pub fn take_tokens(&mut self, k1: &str, poll_secret: &str) -> (bool, Option<(String, String)>) {
+        self.prune();
+        match self.challenges.get_mut(k1) {
+            Some(challenge)
+                if verify_slices_are_equal(challenge.poll_secret.as_bytes(), poll_secret.as_bytes()).is_ok() =>
+            {
+                let tokens = challenge.tokens.take();
+                if tokens.is_some() {
+                    self.challenges.remove(k1);
+                }
+                (true, tokens)
+            }
+            _ => (false, None),
+        }
+    }
+
+    fn prune(&mut self) {
+        self.challenges
+            .retain(|_, challenge| challenge.created_at.elapsed() < CHALLENGE_TTL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_challenge_single_use() {
+        let mut challenges = LnurlAuthChallenges::default();
+        challenges.insert(String::from("k1"), String::from("secret"), None);
+        assert_eq!(challenges.sign("k1"), Ok(None));
+        assert_eq!(challenges.sign("k1"), Err(()));
+        assert_eq!(challenges.sign("unknown"), Err(()));
+
+        assert_eq!(challenges.take_tokens("k1", "secret"), (true, None));
+        challenges.complete("k1", String::from("token"), String::from("refresh"));
+        assert_eq!(challenges.take_tokens("k1", "wrong secret"), (false, None));
+        assert_eq!(
+            challenges.take_tokens("k1", "secret"),
+            (true, Some((String::from("token"), String::from("refresh"))))
+        );
+        assert_eq!(challenges.take_tokens("k1", "secret"), (false, None));
+    }
+}
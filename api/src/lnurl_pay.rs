@@ -0,0 +1,678 @@
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+use xerror::api::LnurlPayError;
+
+type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REDIRECTS: usize = 5;
+/// LUD-09 limits on the success action fields.
+const MAX_SUCCESS_MESSAGE_LEN: usize = 144;
+const MAX_SUCCESS_DESCRIPTION_LEN: usize = 144;
+/// LUD-18 payer identifiers a receiver can ask for. `auth` isn't offered as it needs its own challenge.
+pub const PAYER_DATA_FIELDS: [&str; 4] = ["name", "pubkey", "identifier", "email"];
+const MAX_PAYER_DATA_LEN: usize = 1024;
+
+/// LUD-06 `payRequest` parameters of a recipient.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PayParams {
+    pub callback: String,
+    pub min_sendable: u64,
+    pub max_sendable: u64,
+    pub metadata: String,
+    #[serde(default)]
+    pub comment_allowed: u64,
+    pub tag: String,
+    /// NIP-57 support, zap receipts are then published by `nostr_pubkey`.
+    #[serde(default)]
+    pub allows_nostr: bool,
+    #[serde(default)]
+    pub nostr_pubkey: Option<String>,
+}
+
+/// LUD-09 success actions, `aes` ones are encrypted with the payment preimage as described in LUD-10.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "tag", rename_all = "lowercase")]
+pub enum SuccessAction {
+    Message {
+        message: String,
+    },
+    Url {
+        description: String,
+        url: String,
+    },
+    Aes {
+        description: String,
+        ciphertext: String,
+        iv: String,
+    },
+}
+
+#[derive(Deserialize, Debug)]
+struct CallbackResponse {
+    pr: String,
+    #[serde(rename = "successAction")]
+    success_action: Option<SuccessAction>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedPayment {
+    pub payment_request: String,
+    pub success_action: Option<SuccessAction>,
+}
+
+/// Whether the recipient has to be resolved by us rather than paid internally by the bank.
+pub fn is_external_recipient(recipient: &str, domain: &str) -> bool {
+    match recipient.split_once('@') {
+        Some((_, recipient_domain)) => !recipient_domain.eq_ignore_ascii_case(domain),
+        None => strip_lightning_scheme(recipient).to_lowercase().starts_with("lnurl1"),
+    }
+}
+
+fn strip_lightning_scheme(recipient: &str) -> &str {
+    recipient
+        .strip_prefix("lightning:")
+        .or_else(|| recipient.strip_prefix("LIGHTNING:"))
+        .unwrap_or(recipient)
+}
+
+/// Turns a LUD-16 lightning address or a bech32 encoded lnurl into the url of its pay parameters.
+pub fn pay_params_url(recipient: &str, allow_insecure: bool) -> Result<String, LnurlPayError> {
+    let recipient = strip_lightning_scheme(recipient.trim());
+
+    let url = match recipient.split_once('@') {
+        Some((username, domain)) => {
+            if username.is_empty() || domain.is_empty() || domain.contains('/') {
+                return Err(LnurlPayError::InvalidRecipient);
+            }
+            let scheme = if domain.ends_with(".onion") || allow_insecure {
+                "http"
+            } else {
+                "https"
+            };
+            format!("{scheme}://{domain}/.well-known/lnurlp/{}", username.to_lowercase())
+        }
+        None => utils::lnurl::decode(&recipient.to_lowercase()).map_err(|_| LnurlPayError::InvalidRecipient)?,
+    };
+
+    check_url(&url, allow_insecure)?;
+    Ok(url)
+}
+
+fn check_url(url: &str, allow_insecure: bool) -> Result<(), LnurlPayError> {
+    let parsed = reqwest::Url::parse(url).map_err(|_| LnurlPayError::InvalidRecipient)?;
+    let is_onion = parsed.host_str().map(|h| h.ends_with(".onion")).unwrap_or(false);
+    match parsed.scheme() {
+        "https" => Ok(()),
+        "http" if is_onion || allow_insecure => Ok(()),
+        _ => Err(LnurlPayError::InvalidRecipient),
+    }
+}
+
+/// Whether the address can be reached over the internet, so that recipients can't make us query internal services.
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // shared address space of carrier grade NATs, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b))
+                || a == 0)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(ip));
+            }
+            let first_segment = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // unique local fc00::/7 and link local fe80::/10 addresses
+                || (first_segment & 0xfe00) == 0xfc00
+                || (first_segment & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Resolves the host of the url and rejects it unless all its addresses are public. Returns the address
+/// to connect to, `None` for onion services which are reached through the proxy and can't be resolved.
+fn public_address(url: &reqwest_blocking::Url) -> Result<Option<SocketAddr>, LnurlPayError> {
+    let host = url.host_str().ok_or(LnurlPayError::InvalidRecipient)?;
+    if host.ends_with(".onion") {
+        return Ok(None);
+    }
+    let port = url.port_or_known_default().ok_or(LnurlPayError::InvalidRecipient)?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addresses = (host, port)
+        .to_socket_addrs()
+        .map_err(|_| LnurlPayError::InvalidRecipient)?
+        .collect::<Vec<_>>();
+    if addresses.is_empty() || !addresses.iter().all(|address| is_public_ip(address.ip())) {
+        return Err(LnurlPayError::InvalidRecipient);
+    }
+    Ok(addresses.first().copied())
+}
+
+/// Connects to the address `public_address` checked rather than resolving the host again, which could
+/// then point somewhere else. Redirects are followed one by one to check each of them the same way.
+fn get_json(url: &str, allow_insecure: bool) -> Result<Value, LnurlPayError> {
+    let mut url = reqwest_blocking::Url::parse(url).map_err(|_| LnurlPayError::InvalidRecipient)?;
+    let mut redirects = 0;
+    let response = loop {
+        let mut builder = reqwest_blocking::blocking::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(reqwest_blocking::redirect::Policy::none());
+        if !allow_insecure {
+            if let Some(address) = public_address(&url)? {
+                let host = url.host_str().ok_or(LnurlPayError::InvalidRecipient)?;
+                builder = builder.resolve(host, address).no_proxy();
+            }
+        }
+        let client = builder.build().map_err(|_| LnurlPayError::ServiceUnavailable)?;
+        let response = client
+            .get(url.clone())
+            .send()
+            .map_err(|_| LnurlPayError::ServiceUnavailable)?;
+        if !response.status().is_redirection() {
+            break response;
+        }
+        redirects += 1;
+        if redirects > MAX_REDIRECTS {
+            return Err(LnurlPayError::ServiceUnavailable);
+        }
+        let location = response
+            .headers()
+            .get(reqwest_blocking::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or(LnurlPayError::ServiceUnavailable)?;
+        url = url.join(location).map_err(|_| LnurlPayError::InvalidRecipient)?;
+        check_url(url.as_str(), allow_insecure)?;
+    };
+
+    let value: Value = response.json().map_err(|_| LnurlPayError::ServiceUnavailable)?;
+
+    // LUD-06 errors are returned as `{"status": "ERROR", "reason": ..}`, often with a 200 status.
+    if value.get("status").and_then(|s| s.as_str()) == Some("ERROR") {
+        let reason = value
+            .get("reason")
+            .and_then(|r| r.as_str())
+            .unwrap_or("Unknown error")
+            .to_string();
+        return Err(LnurlPayError::ServiceError(reason));
+    }
+
+    Ok(value)
+}
+
+pub fn parse_pay_params(value: Value) -> Result<PayParams, LnurlPayError> {
+    let params: PayParams = serde_json::from_value(value).map_err(|_| LnurlPayError::InvalidRecipient)?;
+    if params.tag != "payRequest" || params.min_sendable > params.max_sendable {
+        return Err(LnurlPayError::InvalidRecipient);
+    }
+    Ok(params)
+}
+
+pub fn check_amount(params: &PayParams, amount_msat: u64) -> Result<(), LnurlPayError> {
+    if amount_msat < params.min_sendable || amount_msat > params.max_sendable {
+        return Err(LnurlPayError::AmountOutOfRange);
+    }
+    Ok(())
+}
+
+/// LUD-12 comments may only be sent if the recipient advertises `commentAllowed`.
+pub fn check_comment(params: &PayParams, comment: Option<&str>) -> Result<(), LnurlPayError> {
+    match comment {
+        Some(c) if c.chars().count() as u64 > params.comment_allowed => Err(LnurlPayError::CommentNotAllowed),
+        _ => Ok(()),
+    }
+}
+
+pub fn callback_url(
+    params: &PayParams,
+    amount_msat: u64,
+    comment: Option<&str>,
+    allow_insecure: bool,
+) -> Result<String, LnurlPayError> {
+    check_url(&params.callback, allow_insecure)?;
+    let mut url = reqwest::Url::parse(&params.callback).map_err(|_| LnurlPayError::InvalidRecipient)?;
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("amount", &amount_msat.to_string());
+        if let Some(comment) = comment.filter(|c| !c.is_empty()) {
+            query.append_pair("comment", comment);
+        }
+    }
+    Ok(url.to_string())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len() / 2)
+        .map(|i| u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok())
+        .collect()
+}
+
+/// Checks that the invoice is for the requested amount and commits to the recipient's metadata.
+pub fn verify_invoice(payment_request: &str, amount_msat: u64, metadata: &str) -> Result<(), LnurlPayError> {
+    let invoice = payment_request
+        .parse::<lightning_invoice::Invoice>()
+        .map_err(|_| LnurlPayError::InvalidInvoice)?;
+
+    if invoice.amount_milli_satoshis() != Some(amount_msat) {
+        return Err(LnurlPayError::InvalidInvoice);
+    }
+
+    let metadata_hash = to_hex(digest::digest(&digest::SHA256, metadata.as_bytes()).as_ref());
+    match invoice.description() {
+        lightning_invoice::InvoiceDescription::Hash(hash) if hash.0.to_string() == metadata_hash => Ok(()),
+        _ => Err(LnurlPayError::InvalidInvoice),
+    }
+}
+
+pub fn check_success_action(success_action: &SuccessAction, callback: &str) -> Result<(), LnurlPayError> {
+    let valid = match success_action {
+        SuccessAction::Message { message } => message.chars().count() <= MAX_SUCCESS_MESSAGE_LEN,
+        // The url has to be on the same domain as the callback.
+        SuccessAction::Url { description, url } => {
+            let domain = |u: &str| reqwest::Url::parse(u).ok().and_then(|u| u.host_str().map(String::from));
+            description.chars().count() <= MAX_SUCCESS_DESCRIPTION_LEN
+                && domain(url).is_some()
+                && domain(url) == domain(callback)
+        }
+        SuccessAction::Aes { description, .. } => description.chars().count() <= MAX_SUCCESS_DESCRIPTION_LEN,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(LnurlPayError::InvalidInvoice)
+    }
+}
+
+/// Decrypts a LUD-10 `aes` success action with the payment preimage.
+pub fn decrypt_success_action(preimage: &str, ciphertext: &str, iv: &str) -> Option<String> {
+    let key = from_hex(preimage)?;
+    let iv = base64::decode(iv).ok()?;
+    let mut buffer = base64::decode(ciphertext).ok()?;
+    let decryptor = Aes256CbcDec::new_from_slices(&key, &iv).ok()?;
+    let plaintext = decryptor.decrypt_padded_mut::<Pkcs7>(&mut buffer).ok()?;
+    String::from_utf8(plaintext.to_vec()).ok()
+}
+
+/// Success action as returned to our clients, `aes` actions are returned decrypted.
+pub fn success_action_json(success_action: &SuccessAction, preimage: Option<&str>) -> Value {
+    match success_action {
+        SuccessAction::Aes {
+            description,
+            ciphertext,
+            iv,
+        } => {
+            let plaintext = preimage.and_then(|p| decrypt_success_action(p, ciphertext, iv));
+            json!({"tag": "aes", "description": description, "plaintext": plaintext})
+        }
+        _ => json!(success_action),
+    }
+}
+
+/// LUD-18 `payerData` record of a receiver, all requested fields are optional for the payer.
+pub fn payer_data_request(fields: &[String]) -> Value {
+    let mut request = serde_json::Map::new();
+    for field in fields.iter().filter(|f| PAYER_DATA_FIELDS.contains(&f.as_str())) {
+        request.insert(field.clone(), json!({"mandatory": false}));
+    }
+    Value::Object(request)
+}
+
+/// Payer data has to be a json object of string values limited to the requested fields.
+pub fn validate_payer_data(payer_data: &str, requested: &[String]) -> bool {
+    if payer_data.len() > MAX_PAYER_DATA_LEN {
+        return false;
+    }
+    match serde_json::from_str::<Value>(payer_data) {
+        Ok(Value::Object(fields)) => fields
+            .iter()
+            .all(|(key, value)| requested.contains(key) && value.is_string()),
+        _ => false,
+    }
+}
+
+/// Resolves the recipient and fetches an invoice for `amount_msat`. Blocks on the http requests.
+pub fn resolve(
+    recipient: &str,
+    amount_msat: u64,
+    comment: Option<&str>,
+    allow_insecure: bool,
+) -> Result<ResolvedPayment, LnurlPayError> {
+    let url = pay_params_url(recipient, allow_insecure)?;
+    let params = parse_pay_params(get_json(&url, allow_insecure)?)?;
+
+    check_amount(&params, amount_msat)?;
+    check_comment(&params, comment)?;
+
+    let callback = callback_url(&params, amount_msat, comment, allow_insecure)?;
+    let response: CallbackResponse =
+        serde_json::from_value(get_json(&callback, allow_insecure)?).map_err(|_| LnurlPayError::InvalidInvoice)?;
+
+    verify_invoice(&response.pr, amount_msat, &params.metadata)?;
+
+    if let Some(success_action) = &response.success_action {
+        check_success_action(success_action, &params.callback)?;
+    }
+
+    Ok(ResolvedPayment {
+        payment_request: response.pr,
+        success_action: response.success_action,
+    })
+}
+
+/// Fetches an invoice for a NIP-57 zap request, which replaces the recipient's metadata in the description hash.
+/// Blocks on the http requests.
+pub fn resolve_zap(
+    lnurl: &str,
+    amount_msat: u64,
+    zap_request: &str,
+    allow_insecure: bool,
+) -> Result<String, LnurlPayError> {
+    let url = pay_params_url(lnurl, allow_insecure)?;
+    let params = parse_pay_params(get_json(&url, allow_insecure)?)?;
+
+    if !params.allows_nostr || params.nostr_pubkey.is_none() {
+        return Err(LnurlPayError::ZapsNotSupported);
+    }
+    check_amount(&params, amount_msat)?;
+
+    let mut callback = reqwest::Url::parse(&callback_url(&params, amount_msat, None, allow_insecure)?)
+        .map_err(|_| LnurlPayError::InvalidRecipient)?;
+    callback
+        .query_pairs_mut()
+        .append_pair("nostr", zap_request)
+        .append_pair("lnurl", lnurl);
+    let response: CallbackResponse = serde_json::from_value(get_json(callback.as_str(), allow_insecure)?)
+        .map_err(|_| LnurlPayError::InvalidInvoice)?;
+
+    verify_invoice(&response.pr, amount_msat, zap_request)?;
+
+    Ok(response.pr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::{BlockEncryptMut, KeyIvInit};
+    use bitcoin_hashes::{sha256, Hash};
+    use lightning::ln::PaymentSecret;
+    use secp256k1::{Secp256k1, SecretKey};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    const METADATA: &str = "[[\"text/plain\",\"Paid to alice\"]]";
+    const PREIMAGE: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+
+    fn test_invoice(amount_msat: u64, metadata: &str) -> String {
+        let private_key = SecretKey::from_slice(&[42; 32]).unwrap();
+        lightning_invoice::InvoiceBuilder::new(lightning_invoice::Currency::Regtest)
+            .description_hash(sha256::Hash::hash(metadata.as_bytes()))
+            .payment_hash(sha256::Hash::from_slice(&[1; 32]).unwrap())
+            .payment_secret(PaymentSecret([2; 32]))
+            .amount_milli_satoshis(amount_msat)
+            .current_timestamp()
+            .min_final_cltv_expiry(144)
+            .build_signed(|hash| Secp256k1::new().sign_recoverable(hash, &private_key))
+            .unwrap()
+            .to_string()
+    }
+
+    fn encrypt(plaintext: &str, iv: &[u8; 16]) -> String {
+        let key = from_hex(PREIMAGE).unwrap();
+        let mut buffer = vec![0u8; plaintext.len() + 16];
+        buffer[..plaintext.len()].copy_from_slice(plaintext.as_bytes());
+        let ciphertext = cbc::Encryptor::<aes::Aes256>::new_from_slices(&key, iv)
+            .unwrap()
+            .encrypt_padded_mut::<Pkcs7>(&mut buffer, plaintext.len())
+            .unwrap();
+        base64::encode(ciphertext)
+    }
+
+    /// Minimal LNURL-pay server, `alice` accepts 1 to 1000 sats and comments of up to 10 characters.
+    fn spawn_mock_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        let base = format!("http://{host}");
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => continue,
+                };
+                let mut request_line = String::new();
+                if BufReader::new(&stream).read_line(&mut request_line).is_err() {
+                    continue;
+                }
+                let target = request_line.split_whitespace().nth(1).unwrap_or("/").to_string();
+                let url = reqwest::Url::parse(&format!("http://{host}{target}")).unwrap();
+                if url.path() == "/lnurlp/alice" {
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 302 Found\r\nLocation: /.well-known/lnurlp/alice\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    );
+                    continue;
+                }
+                let body = match url.path() {
+                    "/.well-known/lnurlp/alice" => json!({
+                        "callback": format!("http://{host}/callback/alice"),
+                        "minSendable": 1000,
+                        "maxSendable": 1000000,
+                        "metadata": METADATA,
+                        "commentAllowed": 10,
+                        "tag": "payRequest",
+                        "allowsNostr": true,
+                        "nostrPubkey": "b889ff5b1513b641e2a139f661a661364979c5beee91842f8f0ef42ab558e9d4",
+                    }),
+                    "/callback/alice" => {
+                        let amount = url
+                            .query_pairs()
+                            .find(|(k, _)| k == "amount")
+                            .and_then(|(_, v)| v.parse::<u64>().ok())
+                            .unwrap_or_default();
+                        // Zap invoices commit to the zap request instead of the metadata.
+                        let description = url
+                            .query_pairs()
+                            .find(|(k, _)| k == "nostr")
+                            .map(|(_, v)| v.to_string())
+                            .unwrap_or_else(|| METADATA.to_string());
+                        json!({
+                            "pr": test_invoice(amount, &description),
+                            "successAction": {"tag": "message", "message": "Thanks"},
+                            "routes": [],
+                        })
+                    }
+                    _ => json!({"status": "ERROR", "reason": "Unknown user"}),
+                };
+                let body = body.to_string();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+        base
+    }
+
+    #[test]
+    fn test_pay_params_url() {
+        assert_eq!(
+            pay_params_url("Alice@example.com", false).unwrap(),
+            "https://example.com/.well-known/lnurlp/alice"
+        );
+        assert!(pay_params_url("alice@127.0.0.1:8080", false).is_err());
+        assert_eq!(
+            pay_params_url("alice@127.0.0.1:8080", true).unwrap(),
+            "http://127.0.0.1:8080/.well-known/lnurlp/alice"
+        );
+        let lnurl = utils::lnurl::encode("https://example.com/lnurlp/alice", None).unwrap();
+        assert_eq!(
+            pay_params_url(&format!("lightning:{}", lnurl.to_uppercase()), false).unwrap(),
+            "https://example.com/lnurlp/alice"
+        );
+        assert!(pay_params_url("alice", false).is_err());
+        assert!(is_external_recipient("alice@example.com", "local.domain"));
+        assert!(!is_external_recipient("alice@local.domain", "local.domain"));
+        assert!(!is_external_recipient("alice", "local.domain"));
+    }
+
+    #[test]
+    fn test_resolve_against_mock_server() {
+        let base = spawn_mock_server();
+        let address = format!("alice@{}", base.trim_start_matches("http://"));
+
+        let resolved = resolve(&address, 21000, Some("gm"), true).unwrap();
+        assert!(verify_invoice(&resolved.payment_request, 21000, METADATA).is_ok());
+        assert_eq!(
+            resolved.success_action,
+            Some(SuccessAction::Message {
+                message: String::from("Thanks")
+            })
+        );
+
+        let lnurl = utils::lnurl::encode(&format!("{base}/.well-known/lnurlp/alice"), None).unwrap();
+        assert!(resolve(&lnurl, 1000, None, true).is_ok());
+        // redirects are followed
+        let lnurl = utils::lnurl::encode(&format!("{base}/lnurlp/alice"), None).unwrap();
+        assert!(resolve(&lnurl, 1000, None, true).is_ok());
+
+        assert!(matches!(
+            resolve(&address, 999, None, true),
+            Err(LnurlPayError::AmountOutOfRange)
+        ));
+        assert!(matches!(
+            resolve(&address, 1000, Some("far too long comment"), true),
+            Err(LnurlPayError::CommentNotAllowed)
+        ));
+        assert!(matches!(
+            resolve(&format!("bob@{}", base.trim_start_matches("http://")), 1000, None, true),
+            Err(LnurlPayError::ServiceError(reason)) if reason == "Unknown user"
+        ));
+    }
+
+    #[test]
+    fn test_resolve_zap_against_mock_server() {
+        let base = spawn_mock_server();
+        let lnurl = utils::lnurl::encode(&format!("{base}/.well-known/lnurlp/alice"), None).unwrap();
+        let zap_request = "{\"kind\":9734,\"content\":\"\"}";
+
+        let payment_request = resolve_zap(&lnurl, 21000, zap_request, true).unwrap();
+        assert!(verify_invoice(&payment_request, 21000, zap_request).is_ok());
+        assert!(verify_invoice(&payment_request, 21000, METADATA).is_err());
+        assert!(matches!(
+            resolve_zap(&lnurl, 999, zap_request, true),
+            Err(LnurlPayError::AmountOutOfRange)
+        ));
+    }
+
+    #[test]
+    fn test_private_hosts_rejected() {
+        assert!(is_public_ip("1.1.1.1".parse().unwrap()));
+        assert!(is_public_ip("2606:4700:4700::1111".parse().unwrap()));
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{ip}");
+        }
+        let url = reqwest_blocking::Url::parse("https://1.1.1.1/.well-known/lnurlp/alice").unwrap();
+        assert_eq!(public_address(&url).unwrap(), Some("1.1.1.1:443".parse().unwrap()));
+        let url = reqwest_blocking::Url::parse("http://example.onion/.well-known/lnurlp/alice").unwrap();
+        assert_eq!(public_address(&url).unwrap(), None);
+
+        let base = spawn_mock_server();
+        let lnurl = utils::lnurl::encode(
+            &format!("{}/.well-known/lnurlp/alice", base.replace("http://", "https://")),
+            None,
+        )
+        .unwrap();
+        assert!(matches!(
+            resolve(&lnurl, 1000, None, false),
+            Err(LnurlPayError::InvalidRecipient)
+        ));
+        let lnurl = utils::lnurl::encode("https://localhost/.well-known/lnurlp/alice", None).unwrap();
+        assert!(matches!(
+            resolve(&lnurl, 1000, None, false),
+            Err(LnurlPayError::InvalidRecipient)
+        ));
+    }
+
+    #[test]
+    fn test_verify_invoice() {
+        let invoice = test_invoice(5000, METADATA);
+        assert!(verify_invoice(&invoice, 5000, METADATA).is_ok());
+        assert!(verify_invoice(&invoice, 6000, METADATA).is_err());
+        assert!(verify_invoice(&invoice, 5000, "[[\"text/plain\",\"Paid to mallory\"]]").is_err());
+    }
+
+    #[test]
+    fn test_payer_data() {
+        let requested = vec![String::from("name"), String::from("auth")];
+        assert_eq!(payer_data_request(&requested), json!({"name": {"mandatory": false}}));
+        assert!(validate_payer_data("{\"name\":\"Satoshi\"}", &requested));
+        assert!(validate_payer_data("{}", &requested));
+        assert!(!validate_payer_data("{\"email\":\"satoshi@example.com\"}", &requested));
+        assert!(!validate_payer_data("{\"name\":{\"first\":\"Satoshi\"}}", &requested));
+        assert!(!validate_payer_data("Satoshi", &requested));
+    }
+
+    #[test]
+    fn test_success_actions() {
+        let iv = [3u8; 16];
+        let ciphertext = encrypt("voucher 1234", &iv);
+        assert_eq!(
+            decrypt_success_action(PREIMAGE, &ciphertext, &base64::encode(iv)),
+            Some(String::from("voucher 1234"))
+        );
+
+        let action = SuccessAction::Aes {
+            description: String::from("Your voucher"),
+            ciphertext,
+            iv: base64::encode(iv),
+        };
+        assert_eq!(
+            success_action_json(&action, Some(PREIMAGE)),
+            json!({"tag": "aes", "description": "Your voucher", "plaintext": "voucher 1234"})
+        );
+
+        let url = SuccessAction::Url {
+            description: String::from("Receipt"),
+            url: String::from("https://example.com/receipt"),
+        };
+        assert!(check_success_action(&url, "https://example.com/callback").is_ok());
+        assert!(check_success_action(&url, "https://other.com/callback").is_err());
+    }
+}
@@ -1,8 +1,9 @@
 use crate::jwt::*;
-use crate::CreationLimiter;
-use actix_web::web::Data;
-use actix_web::{get, HttpResponse};
+use crate::{CreationLimiter, WebDbPool};
+use actix_web::web::{Data, Path};
+use actix_web::{get, post, HttpResponse};
 use core_types::UserId;
+use models::nostr_nip05_names::Nip05Name;
 use serde_json::json;
 use std::collections::HashSet;
 use std::sync::Arc;
@@ -36,3 +37,42 @@ pub async fn enable_create(
     creation_limiter.into_inner().lock().await.enable_creation();
     Ok(HttpResponse::Ok().json(json!({"success": true})))
 }
+
+async fn set_nip05_name_disabled(
+    pool: WebDbPool,
+    admin_uids: Data<HashSet<UserId>>,
+    auth_data: AuthData,
+    name: String,
+    disabled: bool,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid as u64;
+    if !admin_uids.contains(&uid) {
+        return Err(ApiError::Admin(AdminError::NoPermission));
+    }
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+    match Nip05Name::set_disabled(&conn, &name.to_lowercase(), disabled) {
+        Ok(0) => Err(ApiError::Nostr(NostrEngineError::UnknownNip05Name)),
+        Ok(_) => Ok(HttpResponse::Ok().json(json!({"success": true}))),
+        Err(_) => Err(ApiError::Db(DbError::UpdateFailed)),
+    }
+}
+
+#[post("/admin/nip05_names/{name}/disable")]
+pub async fn disable_nip05_name(
+    pool: WebDbPool,
+    admin_uids: Data<HashSet<UserId>>,
+    auth_data: AuthData,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    set_nip05_name_disabled(pool, admin_uids, auth_data, path.into_inner(), true).await
+}
+
+#[post("/admin/nip05_names/{name}/enable")]
+pub async fn enable_nip05_name(
+    pool: WebDbPool,
+    admin_uids: Data<HashSet<UserId>>,
+    auth_data: AuthData,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    set_nip05_name_disabled(pool, admin_uids, auth_data, path.into_inner(), false).await
+}
@@ -1,22 +1,30 @@
 use actix_web::web::Data;
-use actix_web::{get, post, web::Json, HttpResponse};
+use actix_web::{get, post, web::Json, web::Query, HttpResponse};
 use diesel::result::DatabaseErrorKind;
 use diesel::result::Error as DieselError;
+use diesel::Connection;
+use ring::rand::{SecureRandom, SystemRandom};
 use serde::Deserialize;
 use serde_json::json;
 use std::collections::HashSet;
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use utils::time::time_now;
 use utils::xlogging::slog as log;
 use uuid::Uuid;
 use xerror::api::*;
 
+use models::invite_codes::InviteCode;
 use models::ln_addresses::*;
+use models::nostr_nip05_names::Nip05Name;
 use models::user_profiles::*;
 use models::users::*;
 use utils::xlogging::slog::Logger;
 
+use models::lnurl_auth_keys::*;
+
 use crate::jwt::*;
+use crate::lnurl_auth::LnurlAuthChallenges;
 use crate::{ApiSettings, CreationLimiter, WebDbPool};
 
 #[derive(Deserialize)]
@@ -26,6 +34,12 @@ pub struct RegisterData {
     /// Password field on supplied json.
     pub password: String,
     pub origin: Option<String>,
+    pub invite_code: Option<String>,
+}
+
+/// Origin of accounts created with an invite code, attributing them to the code.
+fn invite_origin(code: &str) -> String {
+    format!("invite:{code}")
 }
 
 #[post("/create")]
@@ -80,9 +94,48 @@ pub async fn create(
         }
     };
 
+    // The username becomes the user's NIP-05 name, so it can't be one somebody else already holds.
+    if Nip05Name::get_by_name(&conn, &username).is_ok() {
+        {
+            let limiter = creation_limiter.clone().into_inner();
+            let mut creation_limiter_guard = limiter.lock().await;
+            creation_limiter_guard.decrease();
+        }
+        return Err(ApiError::Auth(AuthError::UserExists));
+    }
+
+    let invite_code = register_data
+        .invite_code
+        .as_deref()
+        .map(str::trim)
+        .filter(|c| !c.is_empty());
+    let invite_result = match invite_code {
+        Some(code) => InviteCode::redeem(&conn, code, time_now() as i64)
+            .map_err(|_| ApiError::Db(DbError::Unknown))
+            .and_then(|redeemed| {
+                if redeemed {
+                    Ok(())
+                } else {
+                    Err(ApiError::Auth(AuthError::InvalidInviteCode))
+                }
+            }),
+        None if settings.require_invite_code => Err(ApiError::Auth(AuthError::InviteCodeRequired)),
+        None => Ok(()),
+    };
+    if let Err(err) = invite_result {
+        {
+            let limiter = creation_limiter.clone().into_inner();
+            let mut creation_limiter_guard = limiter.lock().await;
+            creation_limiter_guard.decrease();
+        }
+        return Err(err);
+    }
+
     let hashed_password = hash(&username, &register_data.password);
 
-    let origin = if let Some(o) = register_data.origin.clone() {
+    let origin = if let Some(code) = invite_code {
+        Some(invite_origin(code))
+    } else if let Some(o) = register_data.origin.clone() {
         if o.len() > 32 {
             Some(o[..32].to_string())
         } else {
@@ -107,6 +160,9 @@ pub async fn create(
                 let mut creation_limiter_guard = limiter.lock().await;
                 creation_limiter_guard.decrease();
             }
+            if let Some(code) = invite_code {
+                let _ = InviteCode::release(&conn, code);
+            }
             return match err {
                 DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
                     Err(ApiError::Db(DbError::UserAlreadyExists))
@@ -135,6 +191,11 @@ pub async fn create(
         twitter_handle: None,
         nostr_notifications: None,
         email_notifications: None,
+        lnurl_comment_allowed: None,
+        lnurl_payer_data: None,
+        lnurl_success_message: None,
+        lnurl_success_url: None,
+        notification_locale: None,
     };
 
     if insertable_user_profile.insert(&conn).is_err() {
@@ -145,6 +206,16 @@ pub async fn create(
     Ok(HttpResponse::Ok().json(json!({"username": user.username})))
 }
 
+const ACCESS_TOKEN_EXPIRY: i64 = 10000000;
+const REFRESH_TOKEN_EXPIRY: i64 = 1000000;
+
+pub(crate) fn generate_login_tokens(uid: i32) -> Result<(String, String), ApiError> {
+    let token = jwt_generate(uid, None, UserRoles::MasterToken, ACCESS_TOKEN_EXPIRY).map_err(ApiError::JWT)?;
+    let refresh =
+        jwt_generate_refresh_token(uid, UserRoles::MasterToken, REFRESH_TOKEN_EXPIRY).map_err(ApiError::JWT)?;
+    Ok((token, refresh))
+}
+
 #[derive(Deserialize)]
 pub struct LoginData {
     /// Username field on supplied json.
@@ -166,13 +237,7 @@ pub async fn auth(pool: WebDbPool, login_data: Json<LoginData>) -> Result<HttpRe
         return Err(ApiError::Auth(AuthError::IncorrectPassword));
     }
 
-    let refresh_expiry = 1000000;
-
-    let access_expiry = 10000000;
-
-    let token = jwt_generate(user.uid, None, UserRoles::MasterToken, access_expiry).map_err(ApiError::JWT)?;
-    let refresh =
-        jwt_generate_refresh_token(user.uid, UserRoles::MasterToken, refresh_expiry).map_err(ApiError::JWT)?;
+    let (token, refresh) = generate_login_tokens(user.uid)?;
 
     // InsertableApiTokenFull::new(Uuid::new_v4().to_string(), Some(refresh.clone()), user.uid as i32).insert(&conn)?;
 
@@ -192,3 +257,203 @@ pub async fn whoami(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse
 
     Ok(HttpResponse::Ok().json(json!({"username": user.username, "uid": user.uid})))
 }
+
+fn new_lnurl_auth_challenge(
+    challenges: &mut LnurlAuthChallenges,
+    settings: &ApiSettings,
+    link_uid: Option<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let k1 = random_hex(32).ok_or(ApiError::Request(RequestError::InvalidDataSupplied))?;
+    let secret = random_hex(32).ok_or(ApiError::Request(RequestError::InvalidDataSupplied))?;
+
+    let action = if link_uid.is_some() { "link" } else { "login" };
+    let callback = format!(
+        "https://{}/api/lnurl_auth/callback?tag=login&k1={k1}&action={action}",
+        settings.domain
+    );
+    let lnurl =
+        utils::lnurl::encode(&callback, None).map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied))?;
+
+    challenges.insert(k1.clone(), secret.clone(), link_uid);
+
+    Ok(HttpResponse::Ok().json(json!({"k1": k1, "lnurl": lnurl, "secret": secret})))
+}
+
+#[get("/lnurl_auth")]
+pub async fn lnurl_auth(
+    challenges: Data<Arc<Mutex<LnurlAuthChallenges>>>,
+    settings: Data<ApiSettings>,
+) -> Result<HttpResponse, ApiError> {
+    let mut challenges = challenges.lock().await;
+    new_lnurl_auth_challenge(&mut challenges, &settings, None)
+}
+
+#[get("/lnurl_auth/link")]
+pub async fn lnurl_auth_link(
+    auth_data: AuthData,
+    challenges: Data<Arc<Mutex<LnurlAuthChallenges>>>,
+    settings: Data<ApiSettings>,
+) -> Result<HttpResponse, ApiError> {
+    let mut challenges = challenges.lock().await;
+    new_lnurl_auth_challenge(&mut challenges, &settings, Some(auth_data.uid))
+}
+
+#[derive(Deserialize)]
+pub struct LnurlAuthCallbackParams {
+    pub k1: String,
+    pub sig: String,
+    pub key: String,
+}
+
+fn lnurl_auth_error(reason: &str) -> HttpResponse {
+    HttpResponse::Ok().json(json!({"status": "ERROR", "reason": reason}))
+}
+
+/// Called by the wallet with the signed challenge. Responses follow LUD-04 rather than `ApiError`.
+#[get("/lnurl_auth/callback")]
+pub async fn lnurl_auth_callback(
+    pool: WebDbPool,
+    logger: Data<Logger>,
+    query: Query<LnurlAuthCallbackParams>,
+    challenges: Data<Arc<Mutex<LnurlAuthChallenges>>>,
+    creation_limiter: Data<Arc<Mutex<CreationLimiter>>>,
+    settings: Data<ApiSettings>,
+) -> Result<HttpResponse, ApiError> {
+    // The challenge is only used up by a valid signature, so it can't be burnt with a bogus one.
+    if !utils::lnurl::verify_auth_signature(&query.k1, &query.sig, &query.key) {
+        return Ok(lnurl_auth_error("Invalid signature."));
+    }
+
+    let link_uid = match challenges.lock().await.sign(&query.k1) {
+        Ok(link_uid) => link_uid,
+        Err(_) => return Ok(lnurl_auth_error("Unknown or expired challenge.")),
+    };
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let linking_key = query.key.to_lowercase();
+
+    let uid = match (LnurlAuthKey::get_by_linking_key(&conn, &linking_key), link_uid) {
+        (Ok(existing), Some(uid)) if existing.uid != uid => {
+            return Ok(lnurl_auth_error("Key is already linked to another account."));
+        }
+        (Ok(existing), _) => existing.uid,
+        (Err(_), Some(uid)) => {
+            let auth_key = InsertableLnurlAuthKey { linking_key, uid };
+            if auth_key.insert(&conn).is_err() {
+                return Ok(lnurl_auth_error("Failed to link key."));
+            }
+            uid
+        }
+        (Err(_), None) => {
+            if settings.require_invite_code {
+                return Ok(lnurl_auth_error("New accounts require an invite code."));
+            }
+            {
+                let mut creation_limiter_guard = creation_limiter.lock().await;
+                if !creation_limiter_guard.is_creation_enabled() || creation_limiter_guard.increase().is_err() {
+                    return Ok(lnurl_auth_error("New registrations are currently not possible."));
+                }
+            }
+            match create_lnurl_auth_user(&conn, &settings, linking_key) {
+                Ok(uid) => {
+                    log::warn!(logger, "Successful wallet creation via lnurl auth for uid: {}", uid);
+                    uid
+                }
+                Err(_) => {
+                    creation_limiter.lock().await.decrease();
+                    return Ok(lnurl_auth_error("Failed to create account."));
+                }
+            }
+        }
+    };
+
+    let (token, refresh) = generate_login_tokens(uid)?;
+    challenges.lock().await.complete(&query.k1, token, refresh);
+
+    Ok(HttpResponse::Ok().json(json!({"status": "OK"})))
+}
+
+/// Returns `len` random bytes hex encoded.
+pub(crate) fn random_hex(len: usize) -> Option<String> {
+    let mut bytes = vec![0u8; len];
+    SystemRandom::new().fill(&mut bytes).ok()?;
+    Some(bytes.iter().map(|b| format!("{b:02x}")).collect::<String>())
+}
+
+/// Inserts a user together with its lightning address and an empty profile.
+pub(crate) fn insert_user_with_profile(
+    conn: &diesel::PgConnection,
+    settings: &ApiSettings,
+    username: &str,
+    password: &str,
+    origin: Option<String>,
+) -> Result<i32, DieselError> {
+    let user = InsertableUser {
+        username: username.to_string(),
+        password: hash(username, password),
+        is_internal: false,
+        origin,
+    };
+    let uid = user.insert(conn)?;
+
+    let ln_address = InsertableLnAddress {
+        username: format!("{username}@{}", settings.domain),
+        domain: settings.domain.clone(),
+    };
+    ln_address.insert(conn)?;
+
+    let insertable_user_profile = InsertableUserProfile {
+        uid,
+        email: None,
+        img_url: None,
+        is_email_verified: None,
+        is_twitter_verified: None,
+        twitter_handle: None,
+        nostr_notifications: None,
+        email_notifications: None,
+        lnurl_comment_allowed: None,
+        lnurl_payer_data: None,
+        lnurl_success_message: None,
+        lnurl_success_url: None,
+        notification_locale: None,
+    };
+    insertable_user_profile.insert(conn)?;
+
+    Ok(uid)
+}
+
+fn create_lnurl_auth_user(
+    conn: &diesel::PgConnection,
+    settings: &ApiSettings,
+    linking_key: String,
+) -> Result<i32, DieselError> {
+    conn.transaction(|| {
+        let username = Uuid::new_v4().to_string().to_lowercase();
+        // Nobody knows this password, the account can only be accessed with the linking key.
+        let password = random_hex(32).ok_or(DieselError::RollbackTransaction)?;
+        let uid = insert_user_with_profile(conn, settings, &username, &password, Some(String::from("lnurl_auth")))?;
+        InsertableLnurlAuthKey { linking_key, uid }.insert(conn)?;
+        Ok(uid)
+    })
+}
+
+#[derive(Deserialize)]
+pub struct LnurlAuthStatusParams {
+    pub k1: String,
+    /// Secret returned together with the challenge, the k1 alone is public as part of the lnurl.
+    pub secret: String,
+}
+
+/// Polled by the client that requested the challenge until the wallet has signed it.
+#[get("/lnurl_auth/status")]
+pub async fn lnurl_auth_status(
+    query: Query<LnurlAuthStatusParams>,
+    challenges: Data<Arc<Mutex<LnurlAuthChallenges>>>,
+) -> Result<HttpResponse, ApiError> {
+    match challenges.lock().await.take_tokens(&query.k1, &query.secret) {
+        (_, Some((token, refresh))) => Ok(HttpResponse::Ok().json(json!({"token": token, "refresh": refresh}))),
+        (true, None) => Ok(HttpResponse::Ok().json(json!({"status": "pending"}))),
+        (false, None) => Err(ApiError::Auth(AuthError::InvalidLnurlAuthChallenge)),
+    }
+}
@@ -0,0 +1,119 @@
+use actix_web::web::{Data, Query};
+use actix_web::{get, post, HttpResponse};
+use ring::digest;
+use serde::Deserialize;
+use serde_json::json;
+use utils::mailer::Mailer;
+use utils::time::{time_now, MILLISECONDS_IN_DAY, MILLISECONDS_IN_SECOND};
+use xerror::api::*;
+
+use models::email_verification_tokens::EmailVerificationToken;
+use models::user_profiles::{UpdateUserProfile, UserProfile};
+
+use crate::jwt::*;
+use crate::routes::auth::random_hex;
+use crate::{ApiSettings, WebDbPool};
+
+const VERIFICATION_TOKEN_LEN: usize = 32;
+const VERIFICATION_TOKEN_TTL_MS: u64 = MILLISECONDS_IN_DAY;
+const VERIFICATION_RESEND_INTERVAL_MS: u64 = 60 * MILLISECONDS_IN_SECOND;
+
+fn hash_token(token: &str) -> String {
+    digest::digest(&digest::SHA256, token.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[post("/request_email_verification")]
+pub async fn request_email_verification(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    settings: Data<ApiSettings>,
+    mailer: Data<Option<Mailer>>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid;
+
+    let mailer = mailer
+        .get_ref()
+        .clone()
+        .ok_or(ApiError::Email(EmailError::EmailNotConfigured))?;
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let profile = UserProfile::get_by_uid(&conn, uid).map_err(|_| ApiError::Db(DbError::UserDoesNotExist))?;
+    let email = profile
+        .email
+        .filter(|email| !email.is_empty())
+        .ok_or(ApiError::Email(EmailError::NoEmailAddress))?;
+    if profile.is_email_verified == Some(true) {
+        return Err(ApiError::Email(EmailError::EmailAlreadyVerified));
+    }
+
+    let now = time_now();
+    let latest =
+        EmailVerificationToken::get_latest_by_uid(&conn, uid).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+    if let Some(latest) = latest {
+        if latest.email == email && now < latest.created_at as u64 + VERIFICATION_RESEND_INTERVAL_MS {
+            return Err(ApiError::Email(EmailError::VerificationRequestedTooOften));
+        }
+    }
+
+    let token = random_hex(VERIFICATION_TOKEN_LEN).ok_or(ApiError::Db(DbError::Unknown))?;
+    EmailVerificationToken {
+        token_hash: hash_token(&token),
+        uid,
+        email: email.clone(),
+        created_at: now as i64,
+        expires_at: (now + VERIFICATION_TOKEN_TTL_MS) as i64,
+    }
+    .replace(&conn)
+    .map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    let link = format!("https://{}/api/verify_email?token={token}", settings.domain);
+    let body = format!(
+        "Please confirm this is your email address by opening the link below within 24 hours.\n\n{link}\n\n\
+        If you didn't add this address to a Kollider Wallet you can ignore this email."
+    );
+    actix_web::web::block(move || mailer.send(&email, "Verify your email address", &body))
+        .await
+        .map_err(|_| ApiError::Email(EmailError::FailedToSendEmail))?
+        .map_err(|_| ApiError::Email(EmailError::FailedToSendEmail))?;
+
+    Ok(HttpResponse::Ok().json(json!({"status": "ok"})))
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailParams {
+    pub token: String,
+}
+
+#[get("/verify_email")]
+pub async fn verify_email(pool: WebDbPool, params: Query<VerifyEmailParams>) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let token = EmailVerificationToken::get_by_token_hash(&conn, &hash_token(&params.token))
+        .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?
+        .ok_or(ApiError::Email(EmailError::InvalidVerificationToken))?;
+    if (token.expires_at as u64) < time_now() {
+        return Err(ApiError::Email(EmailError::InvalidVerificationToken));
+    }
+
+    // The address may have been changed after the token was sent.
+    let profile = UserProfile::get_by_uid(&conn, token.uid).map_err(|_| ApiError::Db(DbError::UserDoesNotExist))?;
+    if profile.email.as_deref() != Some(token.email.as_str()) {
+        return Err(ApiError::Email(EmailError::InvalidVerificationToken));
+    }
+
+    let update_user_profile = UpdateUserProfile {
+        is_email_verified: Some(true),
+        ..Default::default()
+    };
+    update_user_profile
+        .update(&conn, token.uid)
+        .map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+    EmailVerificationToken::delete_by_uid(&conn, token.uid).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+
+    Ok(HttpResponse::Ok().json(json!({"status": "ok"})))
+}
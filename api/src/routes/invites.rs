@@ -0,0 +1,183 @@
+use actix_web::web::{Data, Json};
+use actix_web::{get, post, HttpResponse};
+use core_types::UserId;
+use diesel::result::DatabaseErrorKind;
+use diesel::result::Error as DieselError;
+use diesel::Connection;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+use utils::mailer::{is_valid_email, Mailer};
+use utils::time::{time_now, MILLISECONDS_IN_SECOND};
+use xerror::api::*;
+
+use models::invite_codes::{InsertableInviteCode, InviteCode};
+use models::pre_signups::{InsertablePreSignup, PreSignup};
+
+use crate::jwt::*;
+use crate::routes::auth::random_hex;
+use crate::{ApiSettings, WebDbPool};
+
+const INVITE_CODE_LEN: usize = 8;
+const MAX_INVITE_CODES_PER_REQUEST: u32 = 100;
+
+#[derive(Deserialize)]
+pub struct WaitlistData {
+    pub email: String,
+}
+
+#[post("/waitlist")]
+pub async fn join_waitlist(pool: WebDbPool, data: Json<WaitlistData>) -> Result<HttpResponse, ApiError> {
+    let email = data.email.trim().to_lowercase();
+    if !is_valid_email(&email) {
+        return Err(ApiError::Email(EmailError::InvalidEmailAddress));
+    }
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    // Signing up twice is not an error, it also doesn't tell who is already on the waitlist.
+    match (InsertablePreSignup { email }).insert(&conn) {
+        Ok(_) | Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Ok(HttpResponse::Ok().json(json!({"status": "ok"})))
+        }
+        Err(_) => Err(ApiError::Db(DbError::Unknown)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateInviteCodesData {
+    /// Accounts each code can create, defaults to one.
+    pub max_uses: Option<i32>,
+    pub expires_in_seconds: Option<u64>,
+    /// Number of codes to generate, defaults to one.
+    pub count: Option<u32>,
+}
+
+impl CreateInviteCodesData {
+    fn validate(&self) -> Result<(i32, Option<i64>, u32), ApiError> {
+        let max_uses = self.max_uses.unwrap_or(1);
+        let count = self.count.unwrap_or(1);
+        if max_uses < 1 || count < 1 || count > MAX_INVITE_CODES_PER_REQUEST {
+            return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+        }
+        let expires_at = self
+            .expires_in_seconds
+            .map(|seconds| (time_now() + seconds * MILLISECONDS_IN_SECOND) as i64);
+        Ok((max_uses, expires_at, count))
+    }
+}
+
+fn generate_invite_code(
+    conn: &diesel::PgConnection,
+    created_by: i32,
+    max_uses: i32,
+    expires_at: Option<i64>,
+) -> Result<String, DieselError> {
+    let code = random_hex(INVITE_CODE_LEN).ok_or(DieselError::RollbackTransaction)?;
+    InsertableInviteCode {
+        code: code.clone(),
+        created_by: Some(created_by),
+        max_uses,
+        expires_at,
+    }
+    .insert(conn)?;
+    Ok(code)
+}
+
+#[post("/admin/invite_codes")]
+pub async fn create_invite_codes(
+    pool: WebDbPool,
+    admin_uids: Data<HashSet<UserId>>,
+    auth_data: AuthData,
+    data: Json<CreateInviteCodesData>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid as u64;
+    if !admin_uids.contains(&uid) {
+        return Err(ApiError::Admin(AdminError::NoPermission));
+    }
+    let (max_uses, expires_at, count) = data.validate()?;
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let codes = (0..count)
+        .map(|_| generate_invite_code(&conn, auth_data.uid, max_uses, expires_at))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| ApiError::Db(DbError::Unknown))?;
+
+    Ok(HttpResponse::Ok().json(json!({ "codes": codes })))
+}
+
+#[get("/admin/invite_codes")]
+pub async fn get_invite_codes(
+    pool: WebDbPool,
+    admin_uids: Data<HashSet<UserId>>,
+    auth_data: AuthData,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid as u64;
+    if !admin_uids.contains(&uid) {
+        return Err(ApiError::Admin(AdminError::NoPermission));
+    }
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let codes = InviteCode::get_all(&conn).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+    Ok(HttpResponse::Ok().json(codes))
+}
+
+/// Sends a code to each of the oldest waitlist entries that haven't been invited yet.
+#[post("/admin/waitlist/invite")]
+pub async fn invite_from_waitlist(
+    pool: WebDbPool,
+    admin_uids: Data<HashSet<UserId>>,
+    auth_data: AuthData,
+    settings: Data<ApiSettings>,
+    mailer: Data<Option<Mailer>>,
+    data: Json<CreateInviteCodesData>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid as u64;
+    if !admin_uids.contains(&uid) {
+        return Err(ApiError::Admin(AdminError::NoPermission));
+    }
+    let (max_uses, expires_at, count) = data.validate()?;
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let pre_signups =
+        PreSignup::get_uninvited(&conn, count as i64).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    let mut invited = Vec::with_capacity(pre_signups.len());
+    for pre_signup in pre_signups {
+        // Entries invited by a concurrent request in the meantime are skipped, along with their code.
+        let code = match conn.transaction(|| {
+            let code = generate_invite_code(&conn, auth_data.uid, max_uses, expires_at)?;
+            if !PreSignup::mark_invited(&conn, pre_signup.uid, &code)? {
+                return Err(DieselError::RollbackTransaction);
+            }
+            Ok(code)
+        }) {
+            Ok(code) => code,
+            Err(DieselError::RollbackTransaction) => continue,
+            Err(_) => return Err(ApiError::Db(DbError::UpdateFailed)),
+        };
+
+        let emailed = match mailer.get_ref().clone() {
+            Some(mailer) => {
+                let email = pre_signup.email.clone();
+                let body = format!(
+                    "You're off the waitlist! Create your Kollider Wallet at https://{} with the invite code below.\n\n\
+                    {code}",
+                    settings.domain
+                );
+                matches!(
+                    actix_web::web::block(move || mailer.send(&email, "Your Kollider Wallet invite", &body)).await,
+                    Ok(Ok(()))
+                )
+            }
+            None => false,
+        };
+
+        invited.push(json!({"email": pre_signup.email, "code": code, "emailed": emailed}));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({ "invited": invited })))
+}
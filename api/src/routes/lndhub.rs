@@ -0,0 +1,716 @@
+//! BlueWallet LndHub compatible api, served under `/lndhub`.
+//!
+//! Requests and responses mirror the original LndHub formats so wallets can connect unmodified,
+//! errors are returned as `{"error": true, "code": .., "message": ..}` with a 200 status.
+use actix_web::web::Data;
+use actix_web::{
+    get, post,
+    web::{Json, Path, Query},
+    HttpResponse,
+};
+use core_types::{Currency, LndNodeInfo, Money};
+use diesel::Connection;
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::timeout;
+use uuid::Uuid;
+use xerror::api::*;
+
+use models::invoices::Invoice;
+use models::users::*;
+use msgs::api::*;
+use msgs::*;
+
+use crate::comms::*;
+use crate::jwt::*;
+use crate::routes::auth::{generate_login_tokens, insert_user_with_profile, random_hex};
+use crate::{ApiSettings, CreationLimiter, WebDbPool, WebSender};
+
+pub const BAD_AUTH: (u8, &str) = (1, "bad auth");
+pub const NOT_ENOUGH_BALANCE: (u8, &str) = (
+    2,
+    "not enough balance. Make sure you have at least 1% reserved for potential fees",
+);
+pub const NOT_A_VALID_INVOICE: (u8, &str) = (4, "not a valid invoice");
+pub const GENERAL_SERVER_ERROR: (u8, &str) = (6, "Something went wrong. Please try again later");
+pub const BAD_ARGUMENTS: (u8, &str) = (8, "Bad arguments");
+pub const TRY_AGAIN_LATER: (u8, &str) = (9, "Your previous payment is in transit. Try again in 5 minutes");
+pub const PAYMENT_FAILED: (u8, &str) = (10, "Payment failed. Does the receiver have enough inbound capacity?");
+pub const TWO_FACTOR_REQUIRED: (u8, &str) = (11, "Two factor authentication is required for this payment");
+pub const INVITE_CODE_REQUIRED: (u8, &str) = (12, "New accounts require an invite code");
+
+pub fn error_json((code, message): (u8, &str)) -> Value {
+    json!({"error": true, "code": code, "message": message})
+}
+
+fn error_response(error: (u8, &str)) -> HttpResponse {
+    HttpResponse::Ok().json(error_json(error))
+}
+
+fn sats(money: &Money) -> i64 {
+    money
+        .try_sats()
+        .ok()
+        .and_then(|s| s.floor().to_i64())
+        .unwrap_or_default()
+}
+
+/// LndHub encodes `r_hash` the way node serializes a `Buffer`.
+fn r_hash_buffer(payment_hash: &str) -> Value {
+    let data = (0..payment_hash.len() / 2)
+        .filter_map(|i| u8::from_str_radix(&payment_hash[i * 2..i * 2 + 2], 16).ok())
+        .collect::<Vec<u8>>();
+    json!({"type": "Buffer", "data": data})
+}
+
+pub fn balance_json(balances: &Balances) -> Value {
+    let available = balances
+        .accounts
+        .values()
+        .filter(|account| account.currency == Currency::BTC)
+        .map(|account| sats(&Money::from_btc(account.balance)))
+        .sum::<i64>();
+    json!({"BTC": {"AvailableBalance": available}})
+}
+
+pub fn add_invoice_json(invoice: &InvoiceResponse) -> Value {
+    let payment_hash = invoice.payment_hash.clone().unwrap_or_default();
+    let payment_request = invoice.payment_request.clone().unwrap_or_default();
+    json!({
+        "r_hash": r_hash_buffer(&payment_hash),
+        "payment_request": payment_request,
+        "add_index": "0",
+        "pay_req": payment_request,
+    })
+}
+
+pub fn user_invoice_json(invoice: &Invoice) -> Value {
+    json!({
+        "r_hash": r_hash_buffer(&invoice.payment_hash),
+        "payment_request": invoice.payment_request,
+        "add_index": invoice.add_index.to_string(),
+        "description": invoice.reference.clone().unwrap_or_default(),
+        "payment_hash": invoice.payment_hash,
+        "ispaid": invoice.settled,
+        "amt": invoice.value,
+        "expire_time": invoice.expiry,
+        "timestamp": invoice.created_at / 1000,
+        "type": "user_invoice",
+        "keysend": false,
+    })
+}
+
+pub fn paid_invoice_json(invoice: &Invoice) -> Value {
+    let fee = invoice.fees.unwrap_or_default();
+    json!({
+        "type": "paid_invoice",
+        "fee": fee,
+        "value": invoice.value + fee,
+        "timestamp": invoice.settled_date / 1000,
+        "memo": invoice.description.clone().or_else(|| invoice.reference.clone()).unwrap_or_default(),
+        "payment_hash": invoice.payment_hash,
+        "pay_req": invoice.payment_request,
+    })
+}
+
+pub fn payment_json(payment: &PaymentResponse) -> Value {
+    let amount = payment.amount.as_ref().map(sats).unwrap_or_default();
+    let fees = payment.fees.as_ref().map(sats).unwrap_or_default();
+    json!({
+        "payment_error": "",
+        "payment_preimage": payment.payment_preimage.clone().unwrap_or_default(),
+        "payment_hash": payment.payment_hash,
+        "payment_route": {
+            "total_amt": amount,
+            "total_fees": fees,
+            "total_amt_msat": amount * 1000,
+            "total_fees_msat": fees * 1000,
+        },
+        "pay_req": payment.payment_request.clone().unwrap_or_default(),
+    })
+}
+
+pub fn payment_error(error: &PaymentResponseError) -> (u8, &'static str) {
+    match error {
+        PaymentResponseError::InsufficientFunds | PaymentResponseError::InsufficientFundsForFees => NOT_ENOUGH_BALANCE,
+        PaymentResponseError::InvalidInvoice
+        | PaymentResponseError::ZeroAmountInvoice
+        | PaymentResponseError::InvoiceAlreadyPaid
+        | PaymentResponseError::SelfPayment => NOT_A_VALID_INVOICE,
+        PaymentResponseError::InvalidAmount => BAD_ARGUMENTS,
+        PaymentResponseError::RequestLimitExceeded => TRY_AGAIN_LATER,
+        _ => PAYMENT_FAILED,
+    }
+}
+
+pub fn getinfo_json(node_info: &LndNodeInfo) -> Value {
+    let network = if node_info.testnet { "testnet" } else { "mainnet" };
+    json!({
+        "identity_pubkey": node_info.identity_pubkey,
+        "alias": "",
+        "num_pending_channels": node_info.num_pending_channels,
+        "num_active_channels": node_info.num_active_channels,
+        "num_peers": node_info.num_peers,
+        "uris": node_info.uris,
+        "synced_to_chain": true,
+        "testnet": node_info.testnet,
+        "chains": [{"chain": "bitcoin", "network": network}],
+    })
+}
+
+pub fn decoded_invoice_json(invoice: &lightning_invoice::Invoice) -> Value {
+    let num_msat = invoice.amount_milli_satoshis().unwrap_or_default();
+    let timestamp = invoice
+        .timestamp()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (description, description_hash) = match invoice.description() {
+        lightning_invoice::InvoiceDescription::Direct(d) => (d.to_string(), String::new()),
+        lightning_invoice::InvoiceDescription::Hash(h) => (String::new(), h.0.to_string()),
+    };
+    json!({
+        "destination": invoice.recover_payee_pub_key().to_string(),
+        "payment_hash": invoice.payment_hash().to_string(),
+        "num_satoshis": (num_msat / 1000).to_string(),
+        "timestamp": timestamp.to_string(),
+        "expiry": invoice.expiry_time().as_secs().to_string(),
+        "description": description,
+        "description_hash": description_hash,
+        "fallback_addr": "",
+        "route_hints": [],
+        "num_msat": num_msat.to_string(),
+    })
+}
+
+/// Sends a message to the bank and waits for the first response accepted by `response_filter`.
+async fn request(
+    web_sender: WebSender,
+    message: Message,
+    response_filter: Box<dyn Send + Fn(&Message) -> bool>,
+    wait: Duration,
+) -> Result<Message, ApiError> {
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    Arc::make_mut(&mut web_sender.into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    match timeout(wait, response_rx.recv()).await {
+        Ok(Some(Ok(message))) => Ok(message),
+        _ => Err(ApiError::Comms(CommsError::ServerResponseTimeout)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LndhubCreateData {
+    pub partnerid: Option<String>,
+    pub accounttype: Option<String>,
+}
+
+#[post("/create")]
+pub async fn create(
+    pool: WebDbPool,
+    creation_limiter: Data<Arc<Mutex<CreationLimiter>>>,
+    settings: Data<ApiSettings>,
+    _data: Option<Json<LndhubCreateData>>,
+) -> Result<HttpResponse, ApiError> {
+    if settings.require_invite_code {
+        return Ok(error_response(INVITE_CODE_REQUIRED));
+    }
+
+    {
+        let mut creation_limiter_guard = creation_limiter.lock().await;
+        if !creation_limiter_guard.is_creation_enabled() || creation_limiter_guard.increase().is_err() {
+            return Ok(error_response(TRY_AGAIN_LATER));
+        }
+    }
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let login = Uuid::new_v4().to_string().to_lowercase();
+    let password = match random_hex(10) {
+        Some(p) => p,
+        None => return Ok(error_response(GENERAL_SERVER_ERROR)),
+    };
+
+    let created = conn
+        .transaction(|| insert_user_with_profile(&conn, &settings, &login, &password, Some(String::from("lndhub"))));
+    if created.is_err() {
+        creation_limiter.lock().await.decrease();
+        return Ok(error_response(GENERAL_SERVER_ERROR));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({"login": login, "password": password})))
+}
+
+#[derive(Deserialize)]
+pub struct LndhubAuthParams {
+    #[serde(rename = "type")]
+    pub auth_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LndhubAuthData {
+    pub login: Option<String>,
+    pub password: Option<String>,
+    pub refresh_token: Option<String>,
+}
+
+#[post("/auth")]
+pub async fn auth(
+    pool: WebDbPool,
+    _query: Query<LndhubAuthParams>,
+    data: Json<LndhubAuthData>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = match (&data.login, &data.password, &data.refresh_token) {
+        (Some(login), Some(password), _) => {
+            let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+            match User::get_by_username(&conn, login.to_lowercase()) {
+                Ok(user) if verify(&user.username.to_lowercase(), &user.password, password) => user.uid,
+                _ => return Ok(error_response(BAD_AUTH)),
+            }
+        }
+        (_, _, Some(refresh_token)) => match jwt_check_refresh_token(refresh_token) {
+            Ok(token) => token.claims.get_user(),
+            Err(_) => return Ok(error_response(BAD_AUTH)),
+        },
+        _ => return Ok(error_response(BAD_ARGUMENTS)),
+    };
+
+    let (access_token, refresh_token) = generate_login_tokens(uid)?;
+
+    Ok(HttpResponse::Ok().json(json!({"refresh_token": refresh_token, "access_token": access_token})))
+}
+
+#[get("/balance")]
+pub async fn balance(auth_data: Option<AuthData>, web_sender: WebSender) -> Result<HttpResponse, ApiError> {
+    let auth_data = match auth_data {
+        Some(a) => a,
+        None => return Ok(error_response(BAD_AUTH)),
+    };
+
+    let req_id = Uuid::new_v4();
+    let message = Message::Api(Api::GetBalances(GetBalances {
+        req_id,
+        uid: auth_data.uid as u64,
+    }));
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> =
+        Box::new(move |message| matches!(message, Message::Api(Api::Balances(b)) if b.req_id == req_id));
+
+    match request(web_sender, message, response_filter, Duration::from_secs(5)).await? {
+        Message::Api(Api::Balances(balances)) => Ok(HttpResponse::Ok().json(balance_json(&balances))),
+        _ => Ok(error_response(GENERAL_SERVER_ERROR)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LndhubAddInvoiceData {
+    pub amt: Value,
+    pub memo: Option<String>,
+    /// Only accepted for compatibility, we can't commit to a hash without its preimage.
+    pub description_hash: Option<String>,
+}
+
+#[post("/addinvoice")]
+pub async fn add_invoice(
+    auth_data: Option<AuthData>,
+    web_sender: WebSender,
+    data: Json<LndhubAddInvoiceData>,
+) -> Result<HttpResponse, ApiError> {
+    let auth_data = match auth_data {
+        Some(a) => a,
+        None => return Ok(error_response(BAD_AUTH)),
+    };
+
+    // Wallets send the amount both as a number and as a string.
+    let amt = match &data.amt {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.parse::<u64>().ok(),
+        _ => None,
+    };
+    let amt = match amt {
+        Some(a) if a > 0 => a,
+        _ => return Ok(error_response(BAD_ARGUMENTS)),
+    };
+
+    let meta = data.memo.clone().unwrap_or_else(|| String::from("Lndhubx Invoice"));
+    if meta.len() > 128 {
+        return Ok(error_response(BAD_ARGUMENTS));
+    }
+
+    let req_id = Uuid::new_v4();
+    let message = Message::Api(Api::InvoiceRequest(InvoiceRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        amount: Money::from_sats(Decimal::from(amt)),
+        meta,
+        metadata: None,
+        currency: Currency::BTC,
+        account_id: None,
+        target_account_currency: None,
+    }));
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> =
+        Box::new(move |message| matches!(message, Message::Api(Api::InvoiceResponse(i)) if i.req_id == req_id));
+
+    match request(web_sender, message, response_filter, Duration::from_secs(5)).await? {
+        Message::Api(Api::InvoiceResponse(invoice)) if invoice.error.is_none() => {
+            Ok(HttpResponse::Ok().json(add_invoice_json(&invoice)))
+        }
+        _ => Ok(error_response(GENERAL_SERVER_ERROR)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LndhubPayInvoiceData {
+    pub invoice: String,
+    /// Amount in sats for zero amount invoices.
+    pub amount: Option<u64>,
+}
+
+#[post("/payinvoice")]
+pub async fn pay_invoice(
+    auth_data: Option<AuthData>,
+    web_sender: WebSender,
+    pool: WebDbPool,
+    data: Json<LndhubPayInvoiceData>,
+) -> Result<HttpResponse, ApiError> {
+    let auth_data = match auth_data {
+        Some(a) => a,
+        None => return Ok(error_response(BAD_AUTH)),
+    };
+
+    if data.invoice.len() > 1024 {
+        return Ok(error_response(NOT_A_VALID_INVOICE));
+    }
+
+    let decoded = match data.invoice.parse::<lightning_invoice::Invoice>() {
+        Ok(d) => d,
+        Err(_) => return Ok(error_response(NOT_A_VALID_INVOICE)),
+    };
+
+    let amount = data
+        .amount
+        .filter(|a| *a > 0)
+        .map(|a| Money::from_sats(Decimal::from(a)));
+    let invoice_amount = decoded
+        .amount_milli_satoshis()
+        .map(|msat| Money::from_sats(Decimal::new(msat as i64, 3)));
+
+    {
+        // LndHub clients have no way to pass a code, so transfers above the threshold are refused.
+        let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+        match crate::totp::ensure_transfer_authorized(&conn, auth_data.uid, amount.or(invoice_amount), None) {
+            Ok(_) => {}
+            Err(ApiError::Totp(_)) => return Ok(error_response(TWO_FACTOR_REQUIRED)),
+            Err(err) => return Err(err),
+        }
+    }
+
+    let req_id = Uuid::new_v4();
+    let message = Message::Api(Api::PaymentRequest(PaymentRequest {
+        req_id,
+        uid: auth_data.uid as u64,
+        payment_request: Some(data.invoice.clone()),
+        currency: Currency::BTC,
+        recipient: None,
+        destination: None,
+        amount,
+        invoice_amount: None,
+        rate: None,
+        fees: None,
+    }));
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> =
+        Box::new(move |message| matches!(message, Message::Api(Api::PaymentResponse(p)) if p.req_id == req_id));
+
+    match request(web_sender, message, response_filter, Duration::from_secs(10)).await? {
+        Message::Api(Api::PaymentResponse(payment)) => match &payment.error {
+            Some(error) => Ok(error_response(payment_error(error))),
+            None if payment.success => Ok(HttpResponse::Ok().json(payment_json(&payment))),
+            None => Ok(error_response(PAYMENT_FAILED)),
+        },
+        _ => Ok(error_response(GENERAL_SERVER_ERROR)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LndhubListParams {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[get("/getuserinvoices")]
+pub async fn get_user_invoices(
+    auth_data: Option<AuthData>,
+    pool: WebDbPool,
+    query: Query<LndhubListParams>,
+) -> Result<HttpResponse, ApiError> {
+    let auth_data = match auth_data {
+        Some(a) => a,
+        None => return Ok(error_response(BAD_AUTH)),
+    };
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+    let mut invoices = Invoice::get_invoices_by_uid(&conn, auth_data.uid)
+        .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?
+        .into_iter()
+        .filter(|invoice| invoice.incoming)
+        .collect::<Vec<Invoice>>();
+    invoices.sort_by_key(|invoice| invoice.created_at);
+
+    // LndHub returns the most recent `limit` invoices, oldest first.
+    let skip = query.limit.map(|l| invoices.len().saturating_sub(l)).unwrap_or(0);
+    let response = invoices
+        .iter()
+        .skip(skip)
+        .map(user_invoice_json)
+        .collect::<Vec<Value>>();
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[get("/gettxs")]
+pub async fn get_txs(
+    auth_data: Option<AuthData>,
+    pool: WebDbPool,
+    query: Query<LndhubListParams>,
+) -> Result<HttpResponse, ApiError> {
+    let auth_data = match auth_data {
+        Some(a) => a,
+        None => return Ok(error_response(BAD_AUTH)),
+    };
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+    let mut payments = Invoice::get_invoices_by_uid(&conn, auth_data.uid)
+        .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?
+        .into_iter()
+        .filter(|invoice| !invoice.incoming && invoice.settled)
+        .collect::<Vec<Invoice>>();
+    payments.sort_by_key(|invoice| invoice.settled_date);
+
+    let response = payments
+        .iter()
+        .skip(query.offset.unwrap_or(0))
+        .take(query.limit.unwrap_or(usize::MAX))
+        .map(paid_invoice_json)
+        .collect::<Vec<Value>>();
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Pending on-chain deposits, we don't offer on-chain wallets through LndHub.
+#[get("/getpending")]
+pub async fn get_pending(auth_data: Option<AuthData>) -> Result<HttpResponse, ApiError> {
+    if auth_data.is_none() {
+        return Ok(error_response(BAD_AUTH));
+    }
+    Ok(HttpResponse::Ok().json(json!([])))
+}
+
+/// On-chain deposit addresses, we don't offer on-chain wallets through LndHub.
+#[get("/getbtc")]
+pub async fn get_btc(auth_data: Option<AuthData>) -> Result<HttpResponse, ApiError> {
+    if auth_data.is_none() {
+        return Ok(error_response(BAD_AUTH));
+    }
+    Ok(HttpResponse::Ok().json(json!([])))
+}
+
+#[get("/getinfo")]
+pub async fn get_info(auth_data: Option<AuthData>, web_sender: WebSender) -> Result<HttpResponse, ApiError> {
+    if auth_data.is_none() {
+        return Ok(error_response(BAD_AUTH));
+    }
+
+    let req_id = Uuid::new_v4();
+    let message = Message::Api(Api::GetNodeInfoRequest(GetNodeInfoRequest { req_id }));
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> =
+        Box::new(move |message| matches!(message, Message::Api(Api::GetNodeInfoResponse(r)) if r.req_id == req_id));
+
+    match request(web_sender, message, response_filter, Duration::from_secs(5)).await? {
+        Message::Api(Api::GetNodeInfoResponse(response)) => {
+            Ok(HttpResponse::Ok().json(getinfo_json(&response.lnd_node_info)))
+        }
+        _ => Ok(error_response(GENERAL_SERVER_ERROR)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LndhubInvoiceParams {
+    pub invoice: String,
+}
+
+#[get("/decodeinvoice")]
+pub async fn decode_invoice(
+    auth_data: Option<AuthData>,
+    query: Query<LndhubInvoiceParams>,
+) -> Result<HttpResponse, ApiError> {
+    if auth_data.is_none() {
+        return Ok(error_response(BAD_AUTH));
+    }
+
+    match query.invoice.parse::<lightning_invoice::Invoice>() {
+        Ok(decoded) => Ok(HttpResponse::Ok().json(decoded_invoice_json(&decoded))),
+        Err(_) => Ok(error_response(NOT_A_VALID_INVOICE)),
+    }
+}
+
+#[get("/checkrouteinvoice")]
+pub async fn check_route_invoice(
+    auth_data: Option<AuthData>,
+    web_sender: WebSender,
+    query: Query<LndhubInvoiceParams>,
+) -> Result<HttpResponse, ApiError> {
+    if auth_data.is_none() {
+        return Ok(error_response(BAD_AUTH));
+    }
+
+    let req_id = Uuid::new_v4();
+    let message = Message::Api(Api::QueryRouteRequest(QueryRouteRequest {
+        req_id,
+        payment_request: query.invoice.clone(),
+        max_fee: None,
+    }));
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> =
+        Box::new(move |message| matches!(message, Message::Api(Api::QueryRouteResponse(r)) if r.req_id == req_id));
+
+    match request(web_sender, message, response_filter, Duration::from_secs(5)).await? {
+        Message::Api(Api::QueryRouteResponse(response)) if response.error.is_none() => {
+            let total_fees = response.total_fee.to_i64().unwrap_or_default();
+            Ok(HttpResponse::Ok().json(json!({"routes": [{"total_fees": total_fees}]})))
+        }
+        _ => Ok(error_response(NOT_A_VALID_INVOICE)),
+    }
+}
+
+#[get("/checkpayment/{payment_hash}")]
+pub async fn check_payment(
+    auth_data: Option<AuthData>,
+    pool: WebDbPool,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let auth_data = match auth_data {
+        Some(a) => a,
+        None => return Ok(error_response(BAD_AUTH)),
+    };
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+    match Invoice::get_by_payment_hash(&conn, path.into_inner()) {
+        Ok(invoice) if invoice.uid == auth_data.uid => Ok(HttpResponse::Ok().json(json!({"paid": invoice.settled}))),
+        _ => Ok(error_response(BAD_ARGUMENTS)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_types::{Account, AccountClass, AccountType};
+    use rust_decimal_macros::dec;
+    use std::collections::HashMap;
+
+    fn golden(name: &str) -> Value {
+        let path = format!("{}/testdata/lndhub/{name}.json", env!("CARGO_MANIFEST_DIR"));
+        let content = std::fs::read_to_string(&path).unwrap_or_else(|_| panic!("missing golden file {path}"));
+        serde_json::from_str(&content).expect("golden file should be valid json")
+    }
+
+    fn invoice(incoming: bool) -> Invoice {
+        Invoice {
+            payment_hash: String::from("0aff"),
+            payment_request: String::from("lnbc10u1test"),
+            created_at: 1684749600000,
+            value: 1000,
+            value_msat: 1000000,
+            expiry: 86400,
+            settled: true,
+            add_index: 7,
+            settled_date: 1684749660000,
+            account_id: Uuid::nil().to_string(),
+            uid: 1,
+            incoming,
+            owner: Some(1),
+            fees: Some(3),
+            currency: Some(String::from("BTC")),
+            target_account_currency: None,
+            reference: Some(String::from("coffee")),
+            description: None,
+            payer_comment: None,
+            payer_data: None,
+        }
+    }
+
+    #[test]
+    fn test_balance_shape() {
+        let mut btc = Account::new(Currency::BTC, AccountType::Internal, AccountClass::Cash);
+        btc.balance = dec!(0.00012345);
+        let mut usd = Account::new(Currency::USD, AccountType::Internal, AccountClass::Cash);
+        usd.balance = dec!(10);
+        let mut accounts = HashMap::new();
+        accounts.insert(btc.account_id, btc);
+        accounts.insert(usd.account_id, usd);
+        let balances = Balances {
+            req_id: Uuid::nil(),
+            uid: 1,
+            accounts,
+            error: None,
+        };
+        assert_eq!(balance_json(&balances), golden("balance"));
+    }
+
+    #[test]
+    fn test_user_invoice_shape() {
+        assert_eq!(user_invoice_json(&invoice(true)), golden("user_invoice"));
+    }
+
+    #[test]
+    fn test_paid_invoice_shape() {
+        assert_eq!(paid_invoice_json(&invoice(false)), golden("paid_invoice"));
+    }
+
+    #[test]
+    fn test_payment_shape() {
+        let payment = PaymentResponse {
+            req_id: Uuid::nil(),
+            payment_hash: String::from("0aff"),
+            uid: 1,
+            success: true,
+            currency: Currency::BTC,
+            payment_request: Some(String::from("lnbc10u1test")),
+            amount: Some(Money::from_sats(dec!(1000))),
+            fees: Some(Money::from_sats(dec!(3))),
+            rate: None,
+            error: None,
+            payment_preimage: Some(String::from("beef")),
+            destination: None,
+            description: None,
+        };
+        assert_eq!(payment_json(&payment), golden("payment"));
+    }
+
+    #[test]
+    fn test_getinfo_shape() {
+        let node_info = LndNodeInfo {
+            identity_pubkey: String::from("02abc"),
+            uris: vec![String::from("02abc@127.0.0.1:9735")],
+            num_active_channels: 2,
+            num_pending_channels: 0,
+            num_peers: 3,
+            testnet: false,
+        };
+        assert_eq!(getinfo_json(&node_info), golden("getinfo"));
+    }
+
+    #[test]
+    fn test_error_shape() {
+        assert_eq!(error_json(BAD_AUTH), golden("error_bad_auth"));
+    }
+}
@@ -1,4 +1,4 @@
-use actix_web::{get, web::Path, web::Query, HttpResponse};
+use actix_web::{get, web::Path, web::Query, HttpRequest, HttpResponse};
 
 use core_types::{Currency, Money};
 use tokio::sync::mpsc;
@@ -15,7 +15,10 @@ use utils::xlogging::slog as log;
 use uuid::Uuid;
 use xerror::api::*;
 
+use models::invoices::Invoice;
 use models::nostr_public_keys::*;
+use models::nostr_zap_keys::NostrZapKey;
+use models::user_profiles::UserProfile;
 use models::users::*;
 
 use msgs::api::*;
@@ -24,6 +27,7 @@ use utils::xlogging::slog::Logger;
 
 use crate::comms::*;
 use crate::jwt::*;
+use crate::lnurl_pay::{payer_data_request, validate_payer_data, SuccessAction};
 use crate::WebSender;
 use crate::{ApiSettings, WebDbPool};
 
@@ -35,9 +39,11 @@ pub struct CreateLnurlWithdrawalParams {
 
 #[get("/lnurl_withdrawal/create")]
 pub async fn create_lnurl_withdrawal(
+    req: HttpRequest,
     auth_data: AuthData,
     query: Query<CreateLnurlWithdrawalParams>,
     web_sender: WebSender,
+    pool: WebDbPool,
 ) -> Result<HttpResponse, ApiError> {
     let req_id = Uuid::new_v4();
 
@@ -49,6 +55,12 @@ pub async fn create_lnurl_withdrawal(
 
     let money = Money::new(query.currency, query.amount);
 
+    {
+        let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+        let totp_code = crate::totp::code_from_header(&req);
+        crate::totp::ensure_transfer_authorized(&conn, auth_data.uid, Some(money), totp_code)?;
+    }
+
     let request = CreateLnurlWithdrawalRequest {
         uid,
         req_id,
@@ -188,14 +200,19 @@ pub async fn lnurl_pay_address(
     let username = path.into_inner();
     let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
 
-    let _user = match User::get_by_username(&conn, username.clone()) {
+    let user = match User::get_by_username(&conn, username.clone()) {
         Ok(u) => u,
         Err(_) => return Err(ApiError::Db(DbError::UserDoesNotExist)),
     };
 
-    let nostr_pubkey = match NostrPublicKey::get_by_username(&conn, String::from("kollider")) {
-        Ok(pubk) => Some(pubk.pubkey),
-        Err(_) => None,
+    let profile = UserProfile::get_by_uid(&conn, user.uid).ok();
+
+    // Users get their own zap key once they link a pubkey, receipts are signed by us until then.
+    let nostr_pubkey = match NostrZapKey::get_by_uid(&conn, user.uid) {
+        Ok(zap_key) => Some(zap_key.pubkey),
+        Err(_) => NostrPublicKey::get_by_username(&conn, String::from("kollider"))
+            .ok()
+            .map(|pubk| pubk.pubkey),
     };
 
     let callback = format!("https://{}/api/pay/{username:}", settings.domain);
@@ -204,7 +221,7 @@ pub async fn lnurl_pay_address(
     let desc = format!("Paid to {username:}@{}", settings.domain);
     let metadata = json!([["text/plain", desc]]);
 
-    let resp = json!({
+    let mut resp = json!({
         "callback": callback,
         "maxSendable": max_sendable,
         "minSendable": min_sendable,
@@ -214,6 +231,22 @@ pub async fn lnurl_pay_address(
         "tag": "payRequest",
     });
 
+    if let Some(comment_allowed) = profile
+        .as_ref()
+        .and_then(|p| p.lnurl_comment_allowed)
+        .filter(|c| *c > 0)
+    {
+        resp["commentAllowed"] = json!(comment_allowed);
+    }
+
+    if let Some(payer_data) = profile
+        .as_ref()
+        .and_then(|p| p.lnurl_payer_data.as_ref())
+        .filter(|p| !p.is_empty())
+    {
+        resp["payerData"] = payer_data_request(payer_data);
+    }
+
     Ok(HttpResponse::Ok().json(resp))
 }
 
@@ -222,6 +255,28 @@ pub struct PayAddressParams {
     amount: u64,
     #[serde(default)]
     nostr: Option<String>,
+    #[serde(default)]
+    comment: Option<String>,
+    #[serde(default)]
+    payerdata: Option<String>,
+}
+
+fn lnurl_error(reason: &str) -> HttpResponse {
+    HttpResponse::Ok().json(json!({"status": "ERROR", "reason": reason}))
+}
+
+/// Success action configured by the receiver, a url takes precedence over a plain message.
+fn success_action(profile: &UserProfile) -> Option<SuccessAction> {
+    match (&profile.lnurl_success_url, &profile.lnurl_success_message) {
+        (Some(url), message) => Some(SuccessAction::Url {
+            description: message.clone().unwrap_or_else(|| String::from("Open link")),
+            url: url.clone(),
+        }),
+        (None, Some(message)) => Some(SuccessAction::Message {
+            message: message.clone(),
+        }),
+        (None, None) => None,
+    }
 }
 
 #[get("/pay/{username}")]
@@ -253,6 +308,31 @@ pub async fn pay_address(
         return Err(ApiError::Request(RequestError::InvalidDataSupplied));
     }
 
+    let profile = UserProfile::get_by_uid(&conn, user.uid).ok();
+
+    let comment = query.comment.clone().filter(|c| !c.is_empty());
+    if let Some(ref comment) = comment {
+        let comment_allowed = profile.as_ref().and_then(|p| p.lnurl_comment_allowed).unwrap_or(0);
+        if comment.chars().count() > comment_allowed.max(0) as usize {
+            return Ok(lnurl_error("Comment is too long"));
+        }
+    }
+
+    // Zap requests commit to their own description, payer data is only supported for plain payments.
+    let payer_data = match (&query.payerdata, &query.nostr) {
+        (Some(payer_data), None) => {
+            let requested = profile
+                .as_ref()
+                .and_then(|p| p.lnurl_payer_data.clone())
+                .unwrap_or_default();
+            if !validate_payer_data(payer_data, &requested) {
+                return Ok(lnurl_error("Invalid payer data"));
+            }
+            Some(payer_data.clone())
+        }
+        _ => None,
+    };
+
     let (memo, metadata) = match query.nostr {
         Some(ref zap_request_json) => {
             if let Err(err) = utils::nostr::validate_zap_request(zap_request_json, query.amount) {
@@ -266,7 +346,9 @@ pub async fn pay_address(
         None => {
             let memo = "Lnurl Pay".to_string();
             let desc = format!("Paid to {username:}@{}", settings.domain);
-            (memo, Some(format!("[[\"text/plain\",\"{desc}\"]]")))
+            // LUD-18 invoices commit to the metadata followed by the payer data.
+            let payer_data = payer_data.clone().unwrap_or_default();
+            (memo, Some(format!("[[\"text/plain\",\"{desc}\"]]{payer_data}")))
         }
     };
 
@@ -305,11 +387,22 @@ pub async fn pay_address(
     if let Ok(Some(Ok(Message::Api(Api::InvoiceResponse(invoice))))) =
         timeout(Duration::from_secs(5), response_rx.recv()).await
     {
-        let resp = json!({
+        if comment.is_some() || payer_data.is_some() {
+            if let Some(ref payment_hash) = invoice.payment_hash {
+                if Invoice::set_payer_info(&conn, payment_hash.clone(), comment, payer_data).is_err() {
+                    log::error!(logger, "Unable to store payer info for invoice {}", payment_hash);
+                }
+            }
+        }
+
+        let mut resp = json!({
             "pr": invoice.payment_request,
             "payment_hash": invoice.payment_hash,
             "routes": [],
         });
+        if let Some(success_action) = profile.as_ref().and_then(success_action) {
+            resp["successAction"] = json!(success_action);
+        }
         return Ok(HttpResponse::Ok().json(resp));
     }
     Err(ApiError::Comms(CommsError::ServerResponseTimeout))
@@ -1,7 +1,13 @@
 pub mod admin;
 pub mod auth;
+pub mod email;
 pub mod external;
+pub mod invites;
+pub mod lndhub;
 pub mod lnurl;
 pub mod nostr;
+pub mod nwc;
+pub mod totp;
 pub mod user;
 pub mod user_profile;
+pub mod zaps;
@@ -1,6 +1,6 @@
 use actix_web::{
-    get, post,
-    web::{Json, Query},
+    delete, get, post,
+    web::{Data, Json, Path, Query},
     HttpResponse,
 };
 
@@ -14,20 +14,46 @@ use xerror::api::*;
 
 use crate::comms::*;
 use crate::jwt::*;
-use crate::WebDbPool;
 use crate::WebSender;
+use crate::{ApiSettings, WebDbPool};
 use msgs::{api::*, *};
+use std::collections::HashSet;
 use std::{sync::Arc, time::Duration};
 use tokio::sync::mpsc;
 use tokio::time::timeout;
 use uuid::Uuid;
 
+use core_types::UserId;
+use models::nostr_follows::SuggestedContact;
+use models::nostr_nip05_names::*;
 use models::nostr_public_keys::*;
+use models::nostr_zap_keys::*;
 use models::users::User;
 
+const MAX_NIP05_NAMES: usize = 5;
+const MAX_NIP05_RELAYS: usize = 5;
+const MAX_RELAY_URL_LEN: usize = 256;
+
 #[derive(Deserialize)]
 pub struct NostrPubkeyData {
     pub pubkey: String,
+    /// Receive notifications as NIP-04 direct messages, for clients without NIP-17 support
+    #[serde(default)]
+    pub legacy_dms: Option<bool>,
+}
+
+/// Gives the user a key of their own to sign zap receipts with, unless zap keys can't be stored encrypted.
+fn ensure_zap_key(conn: &diesel::PgConnection, uid: i32) -> Result<(), Error> {
+    if NostrZapKey::get_by_uid(conn, uid).is_ok() {
+        return Ok(());
+    }
+    let zap_key = utils::nostr::generate_keypair_hex()
+        .ok()
+        .and_then(|(secret_key, pubkey)| InsertableNostrZapKey::new(uid, pubkey, &secret_key));
+    match zap_key {
+        Some(zap_key) => zap_key.insert_or_get(conn).map(|_| ()),
+        None => Ok(()),
+    }
 }
 
 #[post("/nostr_pubkey")]
@@ -40,13 +66,12 @@ pub async fn set_nostr_pubkey(
 
     let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
 
-    if User::get_by_id(&conn, uid as i32).is_err() {
-        return Err(ApiError::Db(DbError::UserDoesNotExist));
-    }
+    let user = User::get_by_id(&conn, uid as i32).map_err(|_| ApiError::Db(DbError::UserDoesNotExist))?;
 
     let nostr_pubkey = InsertableNostrPublicKey {
         pubkey: data.pubkey.clone(),
         uid: uid as i32,
+        legacy_dms: data.legacy_dms.unwrap_or_default(),
     };
 
     if let Err(err) = nostr_pubkey.insert(&conn) {
@@ -64,6 +89,35 @@ pub async fn set_nostr_pubkey(
         }
     }
 
+    // The username is the default NIP-05 name, registration keeps it from being held by anybody else.
+    let nip05_name = InsertableNip05Name {
+        name: user.username.to_lowercase(),
+        uid: uid as i32,
+        pubkey: data.pubkey.clone(),
+        relays: Vec::new(),
+    };
+    match nip05_name.insert(&conn) {
+        Ok(_) => {}
+        Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _))
+            if Nip05Name::get_by_name(&conn, &nip05_name.name).map(|n| n.uid).ok() == Some(uid as i32) => {}
+        Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            return Ok(HttpResponse::Ok()
+                .insert_header((header::CONTENT_TYPE, "application/json"))
+                .json(json!({"status": "Username is held as NIP-05 name by another user.".to_string()})))
+        }
+        Err(_) => {
+            return Ok(HttpResponse::Ok()
+                .insert_header((header::CONTENT_TYPE, "application/json"))
+                .json(json!({"status": "error inserting NIP-05 name.".to_string()})))
+        }
+    }
+
+    if ensure_zap_key(&conn, uid as i32).is_err() {
+        return Ok(HttpResponse::Ok()
+            .insert_header((header::CONTENT_TYPE, "application/json"))
+            .json(json!({"status": "error creating zap key.".to_string()})));
+    }
+
     Ok(HttpResponse::Ok()
         .insert_header((header::CONTENT_TYPE, "application/json"))
         .json(json!({"status": "ok".to_string()})))
@@ -79,15 +133,26 @@ pub async fn update_nostr_pubkey(
 
     let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
 
-    if NostrPublicKey::get_by_uid(&conn, uid as i32).is_ok() {
+    if let Ok(nostr_pubkey) = NostrPublicKey::get_by_uid(&conn, uid as i32) {
         let update_pubkey = UpdateNostrPublicKey {
             pubkey: data.pubkey.clone(),
+            legacy_dms: data.legacy_dms,
         };
         if update_pubkey.update(&conn, uid as i32).is_err() {
             return Ok(HttpResponse::Ok()
                 .insert_header((header::CONTENT_TYPE, "application/json"))
                 .json(json!({"status": "error updating pubkey.".to_string()})));
         }
+        if Nip05Name::update_pubkey(&conn, uid as i32, &nostr_pubkey.pubkey, &data.pubkey).is_err() {
+            return Ok(HttpResponse::Ok()
+                .insert_header((header::CONTENT_TYPE, "application/json"))
+                .json(json!({"status": "error updating NIP-05 names.".to_string()})));
+        }
+        if ensure_zap_key(&conn, uid as i32).is_err() {
+            return Ok(HttpResponse::Ok()
+                .insert_header((header::CONTENT_TYPE, "application/json"))
+                .json(json!({"status": "error creating zap key.".to_string()})));
+        }
     } else {
         return Ok(HttpResponse::Ok()
             .insert_header((header::CONTENT_TYPE, "application/json"))
@@ -106,23 +171,155 @@ pub struct Nip05Params {
 
 #[get("/.well-known/nostr.json")]
 pub async fn nostr_nip05(pool: WebDbPool, params: Query<Nip05Params>) -> Result<HttpResponse, ApiError> {
-    let username = params.name.clone();
+    let name = params.name.to_lowercase();
     let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
 
-    let nostr_pubkey = match NostrPublicKey::get_by_username(&conn, username.clone()) {
-        Ok(u) => u,
-        Err(_) => return Err(ApiError::Db(DbError::UserDoesNotExist)),
+    let nip05_name = match Nip05Name::get_by_name(&conn, &name) {
+        Ok(n) if !n.disabled => n,
+        _ => return Err(ApiError::Nostr(NostrEngineError::UnknownNip05Name)),
     };
 
-    let resp = json!({
+    let mut resp = json!({
         "names": json!({
-          format!("{username}"): nostr_pubkey.pubkey
+          format!("{name}"): nip05_name.pubkey
         })
     });
 
+    if !nip05_name.relays.is_empty() {
+        resp["relays"] = json!({ nip05_name.pubkey.clone(): nip05_name.relays });
+    }
+
     Ok(HttpResponse::Ok().json(resp))
 }
 
+#[derive(Deserialize)]
+pub struct Nip05NameData {
+    pub name: String,
+    /// Defaults to the pubkey linked to the account
+    #[serde(default)]
+    pub pubkey: Option<String>,
+    #[serde(default)]
+    pub relays: Option<Vec<String>>,
+}
+
+fn nip05_name_json(nip05_name: &Nip05Name, domain: &str) -> serde_json::Value {
+    json!({
+        "name": nip05_name.name,
+        "nip05": format!("{}@{domain}", nip05_name.name),
+        "pubkey": nip05_name.pubkey,
+        "relays": nip05_name.relays,
+        "disabled": nip05_name.disabled,
+    })
+}
+
+fn valid_relay_url(url: &str) -> bool {
+    url.len() <= MAX_RELAY_URL_LEN && (url.starts_with("wss://") || url.starts_with("ws://"))
+}
+
+#[post("/nip05_names")]
+pub async fn create_nip05_name(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    settings: Data<ApiSettings>,
+    admin_uids: Data<HashSet<UserId>>,
+    reserved_usernames: Data<HashSet<String>>,
+    data: Json<Nip05NameData>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid;
+
+    let name = data.name.to_lowercase();
+    if !utils::user::check_username_valid(&name) {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    // Reserved names can only be handed out by admins.
+    if reserved_usernames.contains(&name) && !admin_uids.contains(&(uid as u64)) {
+        return Err(ApiError::Nostr(NostrEngineError::Nip05NameReserved));
+    }
+
+    let relays = data.relays.clone().unwrap_or_default();
+    if relays.len() > MAX_NIP05_RELAYS || !relays.iter().all(|url| valid_relay_url(url)) {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let pubkey = match &data.pubkey {
+        Some(pubkey) => {
+            utils::nostr::get_pubkey_hex(pubkey).map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied))?
+        }
+        None => {
+            NostrPublicKey::get_by_uid(&conn, uid)
+                .map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied))?
+                .pubkey
+        }
+    };
+
+    // Usernames are kept for their owners so a name can't be used to impersonate another user.
+    if let Ok(user) = User::get_by_username(&conn, name.clone()) {
+        if user.uid != uid {
+            return Err(ApiError::Nostr(NostrEngineError::Nip05NameTaken));
+        }
+    }
+
+    let names = Nip05Name::get_by_uid(&conn, uid).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+    if names.len() >= MAX_NIP05_NAMES {
+        return Err(ApiError::Nostr(NostrEngineError::Nip05NameLimitReached));
+    }
+
+    let insertable_name = InsertableNip05Name {
+        name,
+        uid,
+        pubkey,
+        relays,
+    };
+
+    match insertable_name.insert(&conn) {
+        Ok(name) => {
+            let nip05_name =
+                Nip05Name::get_by_name(&conn, &name).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+            Ok(HttpResponse::Ok().json(nip05_name_json(&nip05_name, &settings.domain)))
+        }
+        Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            Err(ApiError::Nostr(NostrEngineError::Nip05NameTaken))
+        }
+        Err(_) => Err(ApiError::Db(DbError::UpdateFailed)),
+    }
+}
+
+#[get("/nip05_names")]
+pub async fn get_nip05_names(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    settings: Data<ApiSettings>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let names = Nip05Name::get_by_uid(&conn, auth_data.uid).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    Ok(HttpResponse::Ok().json(
+        names
+            .iter()
+            .map(|nip05_name| nip05_name_json(nip05_name, &settings.domain))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+#[delete("/nip05_names/{name}")]
+pub async fn delete_nip05_name(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    match Nip05Name::delete(&conn, auth_data.uid, &path.into_inner().to_lowercase()) {
+        Ok(0) => Err(ApiError::Nostr(NostrEngineError::UnknownNip05Name)),
+        Ok(_) => Ok(HttpResponse::Ok().json(json!({"success": true}))),
+        Err(_) => Err(ApiError::Db(DbError::UpdateFailed)),
+    }
+}
+
 #[derive(Deserialize)]
 pub struct GetNostrProfileParams {
     lightning_address: Option<String>,
@@ -190,9 +387,14 @@ pub async fn get_nostr_profile(
     Err(ApiError::Comms(CommsError::ServerResponseTimeout))
 }
 
+const SEARCH_NOSTR_PROFILE_DEFAULT_LIMIT: u64 = 25;
+const SEARCH_NOSTR_PROFILE_MAX_LIMIT: u64 = 100;
+
 #[derive(Deserialize)]
 pub struct SearchNostrProfileParams {
     text: String,
+    limit: Option<u64>,
+    offset: Option<u64>,
 }
 
 #[get("/search_nostr_profile")]
@@ -216,7 +418,13 @@ pub async fn search_nostr_profile(
                 req_id,
                 text: Some(params.text.clone()),
                 pubkey: None,
-                limit: Some(25),
+                limit: Some(
+                    params
+                        .limit
+                        .unwrap_or(SEARCH_NOSTR_PROFILE_DEFAULT_LIMIT)
+                        .min(SEARCH_NOSTR_PROFILE_MAX_LIMIT),
+                ),
+                offset: params.offset,
             };
             Message::Api(Api::NostrProfileSearchRequest(request))
         }
@@ -244,3 +452,60 @@ pub async fn search_nostr_profile(
     }
     Err(ApiError::Comms(CommsError::ServerResponseTimeout))
 }
+
+const NOSTR_CONTACTS_DEFAULT_LIMIT: u64 = 25;
+const NOSTR_CONTACTS_MAX_LIMIT: u64 = 100;
+
+#[derive(Deserialize)]
+pub struct NostrContactsParams {
+    limit: Option<u64>,
+    offset: Option<u64>,
+}
+
+/// Follows of the user's linked pubkey that can be paid, the ones paid most often first.
+#[get("/nostr_contacts")]
+pub async fn get_nostr_contacts(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    params: Query<NostrContactsParams>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid;
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let nostr_pubkey = match NostrPublicKey::get_by_uid(&conn, uid) {
+        Ok(nostr_pubkey) => nostr_pubkey,
+        Err(_) => return Ok(HttpResponse::Ok().json(json!({ "contacts": [] }))),
+    };
+
+    let limit = params
+        .limit
+        .unwrap_or(NOSTR_CONTACTS_DEFAULT_LIMIT)
+        .min(NOSTR_CONTACTS_MAX_LIMIT);
+    let contacts = SuggestedContact::fetch(
+        &conn,
+        &nostr_pubkey.pubkey,
+        uid,
+        limit,
+        params.offset.unwrap_or_default(),
+    )
+    .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    let contacts = contacts
+        .into_iter()
+        .map(|contact| {
+            json!({
+                "pubkey": contact.pubkey,
+                "petname": contact.petname,
+                "name": contact.name,
+                "display_name": contact.display_name,
+                "nip05": contact.nip05,
+                "lud16": contact.lud16,
+                "lnurl_pay_req": contact.lnurl_pay_req,
+                "interactions": contact.interactions,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(HttpResponse::Ok().json(json!({ "contacts": contacts })))
+}
@@ -0,0 +1,178 @@
+use actix_web::web::{Data, Path};
+use actix_web::{delete, get, post, web::Json, HttpResponse};
+use core_types::nostr::NWC_METHODS;
+use core_types::Money;
+use rust_decimal::prelude::Decimal;
+use serde::Deserialize;
+use serde_json::json;
+use xerror::api::*;
+
+use models::nwc_connections::*;
+
+use crate::jwt::*;
+use crate::totp::ensure_transfer_authorized;
+use crate::{ApiSettings, WebDbPool};
+
+#[derive(Deserialize)]
+pub struct NwcConnectionData {
+    pub name: String,
+    #[serde(default)]
+    pub allowed_methods: Option<Vec<String>>,
+    #[serde(default)]
+    pub budget_sats: Option<u64>,
+    #[serde(default)]
+    pub budget_renewal_secs: Option<u64>,
+    #[serde(default)]
+    pub totp_code: Option<String>,
+}
+
+/// Builds the connection string a NIP-47 client app is set up with.
+pub fn connection_uri(wallet_pubkey: &str, relay: &str, secret: &str) -> String {
+    match utils::Url::parse(&format!("nostr+walletconnect://{wallet_pubkey}")) {
+        Ok(mut uri) => {
+            uri.query_pairs_mut()
+                .append_pair("relay", relay)
+                .append_pair("secret", secret);
+            uri.to_string()
+        }
+        Err(_) => format!("nostr+walletconnect://{wallet_pubkey}?relay={relay}&secret={secret}"),
+    }
+}
+
+fn connection_json(connection: &NwcConnection) -> serde_json::Value {
+    json!({
+        "pubkey": connection.pubkey,
+        "name": connection.name,
+        "allowed_methods": connection.allowed_methods,
+        "budget_sats": connection.budget_msat.map(|msat| msat / 1000),
+        "budget_renewal_secs": connection.budget_renewal_secs,
+        "budget_spent_sats": connection.budget_spent_msat / 1000,
+        "budget_period_start": connection.budget_period_start,
+    })
+}
+
+#[post("/nwc/connections")]
+pub async fn create_nwc_connection(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    settings: Data<ApiSettings>,
+    data: Json<NwcConnectionData>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid;
+
+    let (wallet_pubkey, relay) = match (&settings.nwc_wallet_pubkey, &settings.nwc_relay) {
+        (Some(wallet_pubkey), Some(relay)) => (wallet_pubkey, relay),
+        _ => return Err(ApiError::Nostr(NostrEngineError::WalletConnectNotConfigured)),
+    };
+
+    let name = data.name.trim();
+    if name.is_empty() || name.len() > 64 {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let allowed_methods = match &data.allowed_methods {
+        Some(methods) => {
+            if methods.is_empty() || methods.iter().any(|method| !NWC_METHODS.contains(&method.as_str())) {
+                return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+            }
+            methods.clone()
+        }
+        None => NWC_METHODS.iter().map(|method| method.to_string()).collect(),
+    };
+
+    let budget_msat = match data.budget_sats {
+        Some(sats) => Some(
+            i64::try_from(sats)
+                .ok()
+                .and_then(|sats| sats.checked_mul(1000))
+                .ok_or(ApiError::Request(RequestError::InvalidDataSupplied))?,
+        ),
+        None => None,
+    };
+    let budget_renewal_secs = match data.budget_renewal_secs {
+        Some(0) => return Err(ApiError::Request(RequestError::InvalidDataSupplied)),
+        Some(secs) => Some(i64::try_from(secs).map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied))?),
+        None => None,
+    };
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    // A connection that can pay hands out spending rights up to its budget.
+    if allowed_methods.iter().any(|method| method == "pay_invoice") {
+        let budget = data.budget_sats.map(|sats| Money::from_sats(Decimal::from(sats)));
+        ensure_transfer_authorized(&conn, uid, budget, data.totp_code.as_deref())?;
+    }
+
+    let (secret, pubkey) = utils::nostr::generate_keypair_hex()
+        .map_err(|_| ApiError::Nostr(NostrEngineError::WalletConnectNotConfigured))?;
+
+    let insertable_connection = InsertableNwcConnection {
+        pubkey: pubkey.clone(),
+        uid,
+        name: name.to_string(),
+        allowed_methods: allowed_methods.clone(),
+        budget_msat,
+        budget_renewal_secs,
+        budget_period_start: (utils::time::time_now() / 1000) as i64,
+    };
+
+    if insertable_connection.insert(&conn).is_err() {
+        return Err(ApiError::Db(DbError::UpdateFailed));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "pubkey": pubkey,
+        "name": name,
+        "allowed_methods": allowed_methods,
+        "budget_sats": data.budget_sats,
+        "budget_renewal_secs": data.budget_renewal_secs,
+        "connection_uri": connection_uri(wallet_pubkey, relay, &secret),
+    })))
+}
+
+#[get("/nwc/connections")]
+pub async fn get_nwc_connections(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid;
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let connections = NwcConnection::get_by_uid(&conn, uid).map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+
+    Ok(HttpResponse::Ok().json(connections.iter().map(connection_json).collect::<Vec<_>>()))
+}
+
+#[delete("/nwc/connections/{pubkey}")]
+pub async fn revoke_nwc_connection(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid;
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let update = UpdateNwcConnection {
+        revoked: Some(true),
+        ..Default::default()
+    };
+
+    match update.update(&conn, uid, &path.into_inner()) {
+        Ok(0) => Err(ApiError::Nostr(NostrEngineError::UnknownWalletConnection)),
+        Ok(_) => Ok(HttpResponse::Ok().json(json!({"success": true}))),
+        Err(_) => Err(ApiError::Db(DbError::UpdateFailed)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_uri() {
+        let uri = connection_uri("b889ff5b", "wss://relay.damus.io", "71a8c14c");
+        assert_eq!(
+            uri,
+            "nostr+walletconnect://b889ff5b?relay=wss%3A%2F%2Frelay.damus.io&secret=71a8c14c"
+        );
+    }
+}
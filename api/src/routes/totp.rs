@@ -0,0 +1,188 @@
+use actix_web::web::Data;
+use actix_web::{get, post, web::Json, HttpResponse};
+use bigdecimal::BigDecimal;
+use core_types::Currency;
+use rust_decimal::prelude::Decimal;
+use rust_decimal_macros::*;
+use serde::Deserialize;
+use serde_json::json;
+use std::str::FromStr;
+use xerror::api::*;
+
+use models::user_totps::*;
+use models::users::User;
+
+use crate::jwt::*;
+use crate::totp::*;
+use crate::{ApiSettings, WebDbPool};
+
+#[get("/totp/status")]
+pub async fn totp_status(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid;
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    match UserTotp::get_by_uid(&conn, uid) {
+        Ok(totp) => Ok(HttpResponse::Ok().json(json!({
+            "enabled": totp.enabled,
+            "threshold": totp.threshold,
+            "threshold_currency": totp.threshold_currency,
+            "remaining_backup_codes": totp.backup_codes.len(),
+        }))),
+        Err(_) => Ok(HttpResponse::Ok().json(json!({ "enabled": false }))),
+    }
+}
+
+#[post("/totp/enrol")]
+pub async fn enrol_totp(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    settings: Data<ApiSettings>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid;
+
+    if !is_configured() {
+        return Err(ApiError::Totp(TotpError::TotpNotConfigured));
+    }
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let user = User::get_by_id(&conn, uid).map_err(|_| ApiError::Db(DbError::UserDoesNotExist))?;
+
+    // A pending enrolment can be restarted, an active one has to be disabled first.
+    if let Ok(existing) = UserTotp::get_by_uid(&conn, uid) {
+        if existing.enabled {
+            return Err(ApiError::Totp(TotpError::TotpAlreadyEnabled));
+        }
+        UserTotp::delete(&conn, uid).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+    }
+
+    let secret = generate_secret().map_err(ApiError::Totp)?;
+    let backup_codes = generate_backup_codes().map_err(ApiError::Totp)?;
+
+    let insertable_totp = InsertableUserTotp {
+        uid,
+        encrypted_secret: encrypt_secret(&secret).map_err(ApiError::Totp)?,
+        enabled: false,
+        threshold: BigDecimal::from(0),
+        threshold_currency: Currency::BTC.to_string(),
+        backup_codes: backup_codes.iter().map(|code| hash_backup_code(uid, code)).collect(),
+    };
+
+    if insertable_totp.insert(&conn).is_err() {
+        return Err(ApiError::Db(DbError::UpdateFailed));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "provisioning_uri": provisioning_uri(&secret, &user.username, &settings.domain),
+        "backup_codes": backup_codes,
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct TotpCodeData {
+    pub code: String,
+}
+
+#[post("/totp/confirm")]
+pub async fn confirm_totp(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    data: Json<TotpCodeData>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid;
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let totp = UserTotp::get_by_uid(&conn, uid).map_err(|_| ApiError::Totp(TotpError::TotpNotEnrolled))?;
+
+    if totp.enabled {
+        return Err(ApiError::Totp(TotpError::TotpAlreadyEnabled));
+    }
+
+    // Only a code from the authenticator app proves the secret was imported correctly.
+    let secret = decrypt_secret(&totp.encrypted_secret).map_err(ApiError::Totp)?;
+    let step = match_code(&secret, data.code.trim(), current_step(), totp.last_used_step as u64)
+        .ok_or(ApiError::Totp(TotpError::InvalidTotpCode))?;
+
+    let update = UpdateUserTotp {
+        enabled: Some(true),
+        last_used_step: Some(step as i64),
+        ..Default::default()
+    };
+
+    if update.update(&conn, uid).is_err() {
+        return Err(ApiError::Db(DbError::UpdateFailed));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({"success": true})))
+}
+
+#[post("/totp/disable")]
+pub async fn disable_totp(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    data: Json<TotpCodeData>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid;
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let totp = UserTotp::get_by_uid(&conn, uid).map_err(|_| ApiError::Totp(TotpError::TotpNotEnrolled))?;
+
+    if totp.enabled {
+        verify_code(&conn, &totp, &data.code)?;
+    }
+
+    if UserTotp::delete(&conn, uid).is_err() {
+        return Err(ApiError::Db(DbError::UpdateFailed));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({"success": true})))
+}
+
+#[derive(Deserialize)]
+pub struct TotpThresholdData {
+    pub code: String,
+    pub threshold: Decimal,
+    pub currency: Currency,
+}
+
+#[post("/totp/threshold")]
+pub async fn set_totp_threshold(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    data: Json<TotpThresholdData>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid;
+
+    if data.threshold < dec!(0) {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let totp = UserTotp::get_by_uid(&conn, uid).map_err(|_| ApiError::Totp(TotpError::TotpNotEnrolled))?;
+
+    if !totp.enabled {
+        return Err(ApiError::Totp(TotpError::TotpNotEnrolled));
+    }
+
+    // Otherwise a stolen token could simply raise the threshold.
+    verify_code(&conn, &totp, &data.code)?;
+
+    let threshold = BigDecimal::from_str(&data.threshold.to_string())
+        .map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied))?;
+
+    let update = UpdateUserTotp {
+        threshold: Some(threshold),
+        threshold_currency: Some(data.currency.to_string()),
+        ..Default::default()
+    };
+
+    if update.update(&conn, uid).is_err() {
+        return Err(ApiError::Db(DbError::UpdateFailed));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({"success": true})))
+}
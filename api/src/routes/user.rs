@@ -14,7 +14,7 @@ use actix_web::http::header;
 use std::{sync::Arc, time::Duration};
 
 use actix_web::web::Data;
-use rust_decimal::prelude::Decimal;
+use rust_decimal::prelude::{Decimal, ToPrimitive};
 use rust_decimal_macros::*;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -78,12 +78,17 @@ pub struct PayInvoiceData {
     pub currency: Option<Currency>,
     pub recipient: Option<String>,
     pub amount: Option<Decimal>,
+    pub totp_code: Option<String>,
+    /// LUD-12 comment sent along when paying an external lightning address.
+    pub comment: Option<String>,
 }
 
 #[post("/payinvoice")]
 pub async fn pay_invoice(
     auth_data: AuthData,
     web_sender: WebSender,
+    pool: WebDbPool,
+    settings: Data<ApiSettings>,
     pay_invoice_data: Json<PayInvoiceData>,
 ) -> Result<HttpResponse, ApiError> {
     let req_id = Uuid::new_v4();
@@ -116,20 +121,90 @@ pub async fn pay_invoice(
 
     let money = pay_invoice_data.amount.map(|a| Money::new(currency, a));
 
+    let mut payment_request = pay_invoice_data.payment_request.clone();
+    let mut recipient = pay_invoice_data.recipient.clone();
+    let mut success_action = None;
+
+    let external_recipient = match (&payment_request, &recipient) {
+        (None, Some(r)) if crate::lnurl_pay::is_external_recipient(r, &settings.domain) => Some(r.clone()),
+        _ => None,
+    };
+
+    // External lightning addresses and lnurls are paid in sats, the recipient sets the invoice amount.
+    let external_amount_msat = match &external_recipient {
+        Some(_) => {
+            if currency != Currency::BTC {
+                return Err(ApiError::LnurlPay(LnurlPayError::BtcAmountRequired));
+            }
+            let amount_msat = money
+                .and_then(|m| m.try_sats().ok())
+                .map(|sats| sats * dec!(1000))
+                .filter(|msat| msat.fract().is_zero())
+                .and_then(|msat| msat.to_u64())
+                .ok_or(ApiError::LnurlPay(LnurlPayError::AmountRequired))?;
+
+            if let Some(comment) = &pay_invoice_data.comment {
+                if comment.len() > 1024 {
+                    return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+                }
+            }
+            Some(amount_msat)
+        }
+        None => None,
+    };
+
+    // Checked before any request to the recipient's service, which is a url the user chose.
+    {
+        // Amountless payments fall back to the invoice amount for the two factor threshold check.
+        let transfer_amount = money.or_else(|| {
+            payment_request
+                .as_ref()
+                .and_then(|pr| pr.parse::<lightning_invoice::Invoice>().ok())
+                .and_then(|invoice| invoice.amount_milli_satoshis())
+                .map(|msat| Money::from_sats(Decimal::new(msat as i64, 3)))
+        });
+        let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+        crate::totp::ensure_transfer_authorized(
+            &conn,
+            auth_data.uid,
+            transfer_amount,
+            pay_invoice_data.totp_code.as_deref(),
+        )?;
+    }
+
+    // External lightning addresses and lnurls are resolved to an invoice here, internal ones are paid by the bank.
+    if let (Some(address), Some(amount_msat)) = (external_recipient, external_amount_msat) {
+        // Only lightning addresses are worth recording as recipient.
+        if !address.contains('@') {
+            recipient = None;
+        }
+        let comment = pay_invoice_data.comment.clone();
+        let allow_insecure = settings.lnurl_allow_insecure;
+        let resolved = actix_web::web::block(move || {
+            crate::lnurl_pay::resolve(&address, amount_msat, comment.as_deref(), allow_insecure)
+        })
+        .await
+        .map_err(|_| ApiError::External(ExternalError::FailedToFetchExternalData))?
+        .map_err(ApiError::LnurlPay)?;
+
+        payment_request = Some(resolved.payment_request);
+        success_action = resolved.success_action;
+    }
+
     let payment_request = PaymentRequest {
         currency,
         req_id,
         uid,
-        payment_request: pay_invoice_data.payment_request.clone(),
+        payment_request: payment_request.clone(),
         rate: None,
         amount: money,
         invoice_amount: None,
-        recipient: pay_invoice_data.recipient.clone(),
+        recipient,
         destination: None,
         fees: None,
     };
 
-    if pay_invoice_data.payment_request.is_none() && pay_invoice_data.recipient.is_none() {
+    if payment_request.payment_request.is_none() && payment_request.recipient.is_none() {
         return Ok(HttpResponse::Ok().json(json!({"error": "You have to specify either an invoice or a receipient"})));
     }
 
@@ -153,6 +228,12 @@ pub async fn pay_invoice(
     if let Ok(Some(Ok(Message::Api(Api::PaymentResponse(response))))) =
         timeout(Duration::from_secs(10), response_rx.recv()).await
     {
+        if let (true, Some(success_action)) = (response.success, &success_action) {
+            let mut body = serde_json::to_value(&response).map_err(|_| ApiError::Db(DbError::Unknown))?;
+            body["success_action"] =
+                crate::lnurl_pay::success_action_json(success_action, response.payment_preimage.as_deref());
+            return Ok(HttpResponse::Ok().json(body));
+        }
         return Ok(HttpResponse::Ok().json(&response));
     }
     Err(ApiError::Comms(CommsError::ServerResponseTimeout))
@@ -239,7 +320,7 @@ pub struct SwapData {
     pub from_currency: Currency,
     pub to_currency: Currency,
     pub amount: Decimal,
-    pub quote_id: Option<u128>,
+    pub quote_id: Option<String>,
 }
 
 #[post("/swap")]
@@ -260,7 +341,7 @@ pub async fn swap(auth_data: AuthData, web_sender: WebSender, data: Json<SwapDat
         from: data.from_currency,
         to: data.to_currency,
         amount: money,
-        quote_id: data.quote_id,
+        quote_id: data.quote_id.clone(),
     };
 
     let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
@@ -1,11 +1,19 @@
 use crate::jwt::*;
-use crate::WebDbPool;
-use actix_web::{get, post, web::Json, HttpResponse};
+use crate::lnurl_pay::PAYER_DATA_FIELDS;
+use crate::{ApiSettings, WebDbPool};
+use actix_web::{get, post, web::Data, web::Json, HttpResponse};
+use models::notification_preferences::*;
 use models::user_profiles::*;
 use serde::Deserialize;
 use serde_json::json;
+use std::str::FromStr;
 use xerror::api::*;
 
+const MAX_COMMENT_ALLOWED: i32 = 1000;
+const MAX_SUCCESS_MESSAGE_LEN: usize = 144;
+const MAX_SUCCESS_URL_LEN: usize = 2048;
+const MAX_LOCALE_LEN: usize = 10;
+
 #[get("/get_user_profile")]
 pub async fn get_user_profile(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
     let uid = auth_data.uid as u64;
@@ -24,6 +32,11 @@ pub async fn get_user_profile(pool: WebDbPool, auth_data: AuthData) -> Result<Ht
             twitter_handle: None,
             nostr_notifications: None,
             email_notifications: None,
+            lnurl_comment_allowed: None,
+            lnurl_payer_data: None,
+            lnurl_success_message: None,
+            lnurl_success_url: None,
+            notification_locale: None,
         };
 
         if insertable_user_profile.insert(&conn).is_err() {
@@ -40,6 +53,53 @@ pub struct UpdateProfileData {
     pub email_notifications: Option<bool>,
     pub img_url: Option<String>,
     pub twitter_handle: Option<String>,
+    /// Maximum length of LUD-12 comments payers may attach, 0 disables comments.
+    pub lnurl_comment_allowed: Option<i32>,
+    /// LUD-18 identifiers to ask payers for.
+    pub lnurl_payer_data: Option<Vec<String>>,
+    pub lnurl_success_message: Option<String>,
+    pub lnurl_success_url: Option<String>,
+    /// Language tag notifications are sent in, e.g. "en" or "de-AT".
+    pub notification_locale: Option<String>,
+}
+
+fn is_valid_locale(locale: &str) -> bool {
+    !locale.is_empty()
+        && locale.len() <= MAX_LOCALE_LEN
+        && locale
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// LUD-09 requires the success url to be on the domain of the callback, which is ours.
+fn is_valid_success_url(url: &str, domain: &str) -> bool {
+    url.len() <= MAX_SUCCESS_URL_LEN
+        && reqwest::Url::parse(url)
+            .map(|url| url.scheme() == "https" && url.host_str() == Some(domain))
+            .unwrap_or(false)
+}
+
+fn is_valid_lnurl_settings(data: &UpdateProfileData, domain: &str) -> bool {
+    let comment_allowed_valid = data
+        .lnurl_comment_allowed
+        .map(|c| (0..=MAX_COMMENT_ALLOWED).contains(&c))
+        .unwrap_or(true);
+    let payer_data_valid = data
+        .lnurl_payer_data
+        .as_ref()
+        .map(|fields| fields.iter().all(|f| PAYER_DATA_FIELDS.contains(&f.as_str())))
+        .unwrap_or(true);
+    let message_valid = data
+        .lnurl_success_message
+        .as_ref()
+        .map(|m| m.chars().count() <= MAX_SUCCESS_MESSAGE_LEN)
+        .unwrap_or(true);
+    let url_valid = data
+        .lnurl_success_url
+        .as_ref()
+        .map(|u| is_valid_success_url(u, domain))
+        .unwrap_or(true);
+    comment_allowed_valid && payer_data_valid && message_valid && url_valid
 }
 
 #[post("/user_profile")]
@@ -47,9 +107,35 @@ pub async fn user_profile(
     pool: WebDbPool,
     auth_data: AuthData,
     data: Json<UpdateProfileData>,
+    settings: Data<ApiSettings>,
 ) -> Result<HttpResponse, ApiError> {
     let uid = auth_data.uid as u64;
 
+    let email_valid = data
+        .email
+        .as_deref()
+        .map(|email| email.is_empty() || utils::mailer::is_valid_email(email))
+        .unwrap_or(true);
+    if !email_valid {
+        return Err(ApiError::Email(EmailError::InvalidEmailAddress));
+    }
+
+    if !is_valid_lnurl_settings(&data, &settings.domain)
+        || !data.notification_locale.as_deref().map(is_valid_locale).unwrap_or(true)
+    {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    // A changed address has to be verified again before emails are sent to it.
+    let email_changed = match &data.email {
+        Some(email) => UserProfile::get_by_uid(&conn, uid as i32)
+            .map(|profile| profile.email.as_ref() != Some(email))
+            .unwrap_or(true),
+        None => false,
+    };
+
     let update_user_profile = UpdateUserProfile {
         email: data.email.clone(),
         nostr_notifications: data.nostr_notifications,
@@ -57,14 +143,102 @@ pub async fn user_profile(
         img_url: data.img_url.clone(),
         twitter_handle: data.twitter_handle.clone(),
         is_twitter_verified: None,
-        is_email_verified: None,
+        is_email_verified: email_changed.then_some(false),
+        lnurl_comment_allowed: data.lnurl_comment_allowed,
+        lnurl_payer_data: data.lnurl_payer_data.clone(),
+        lnurl_success_message: data.lnurl_success_message.clone(),
+        lnurl_success_url: data.lnurl_success_url.clone(),
+        notification_locale: data.notification_locale.clone(),
     };
 
-    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
-
     if update_user_profile.update(&conn, uid as i32).is_ok() {
         Ok(HttpResponse::Ok().json(json!({"status": "ok"})))
     } else {
         Err(ApiError::Db(DbError::UserDoesNotExist))
     }
 }
+
+#[get("/notification_preferences")]
+pub async fn get_notification_preferences(pool: WebDbPool, auth_data: AuthData) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    let profile = UserProfile::get_by_uid(&conn, auth_data.uid).ok();
+    let mut preferences = Vec::new();
+    for event in NotificationEvent::ALL {
+        for channel in NotificationChannel::ALL {
+            let enabled = NotificationPreference::is_enabled(&conn, profile.as_ref(), auth_data.uid, event, channel)
+                .map_err(|_| ApiError::Db(DbError::CouldNotFetchData))?;
+            preferences.push(json!({
+                "event_type": event.as_str(),
+                "channel": channel.as_str(),
+                "enabled": enabled,
+            }));
+        }
+    }
+    let locale = profile
+        .and_then(|profile| profile.notification_locale)
+        .unwrap_or_else(|| DEFAULT_NOTIFICATION_LOCALE.to_string());
+
+    Ok(HttpResponse::Ok().json(json!({ "locale": locale, "preferences": preferences })))
+}
+
+#[derive(Deserialize)]
+pub struct NotificationPreferenceData {
+    pub event_type: String,
+    pub channel: String,
+    pub enabled: bool,
+}
+
+#[post("/notification_preferences")]
+pub async fn update_notification_preferences(
+    pool: WebDbPool,
+    auth_data: AuthData,
+    data: Json<Vec<NotificationPreferenceData>>,
+) -> Result<HttpResponse, ApiError> {
+    let preferences = data
+        .iter()
+        .map(|preference| {
+            let event = NotificationEvent::from_str(&preference.event_type)
+                .map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied))?;
+            let channel = NotificationChannel::from_str(&preference.channel)
+                .map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied))?;
+            Ok(NotificationPreference {
+                uid: auth_data.uid,
+                event_type: event.as_str().to_string(),
+                channel: channel.as_str().to_string(),
+                enabled: preference.enabled,
+            })
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?;
+
+    let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+
+    for preference in preferences {
+        preference
+            .upsert(&conn)
+            .map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+    }
+
+    Ok(HttpResponse::Ok().json(json!({"status": "ok"})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_success_url_on_own_domain() {
+        assert!(is_valid_success_url("https://lndhubx.com/thanks", "lndhubx.com"));
+        assert!(!is_valid_success_url("http://lndhubx.com/thanks", "lndhubx.com"));
+        assert!(!is_valid_success_url("https://evil.com/thanks", "lndhubx.com"));
+        assert!(!is_valid_success_url(
+            "https://lndhubx.com.evil.com/thanks",
+            "lndhubx.com"
+        ));
+        assert!(!is_valid_success_url(
+            "https://lndhubx.com@evil.com/thanks",
+            "lndhubx.com"
+        ));
+        assert!(!is_valid_success_url("https://", "lndhubx.com"));
+    }
+}
@@ -0,0 +1,384 @@
+use actix_web::web::Data;
+use actix_web::{post, web::Json, HttpResponse};
+use core_types::{Currency, Money};
+use rust_decimal::prelude::Decimal;
+use serde::Deserialize;
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use uuid::Uuid;
+use xerror::api::*;
+
+use msgs::api::*;
+use msgs::*;
+use utils::nostr::{ZapSplit, ZapTarget};
+
+use crate::comms::*;
+use crate::jwt::*;
+use crate::totp::ensure_transfer_authorized;
+use crate::{ApiSettings, WebDbPool, WebSender};
+
+const MAX_ZAP_RECIPIENTS: usize = 10;
+const MAX_ZAP_COMMENT_LEN: usize = 1024;
+
+#[derive(Deserialize)]
+pub struct ZapData {
+    /// Total amount in sats, split between the recipients of the zapped event.
+    pub amount: u64,
+    /// Recipient of a profile zap.
+    #[serde(default)]
+    pub pubkey: Option<String>,
+    /// Signed event being zapped, its `zap` tags decide who gets paid.
+    #[serde(default)]
+    pub event: Option<String>,
+    #[serde(default)]
+    pub comment: Option<String>,
+    /// Zap requests signed by the user, one per recipient. Signed with the server key when left out.
+    #[serde(default)]
+    pub zap_requests: Option<Vec<String>>,
+    #[serde(default)]
+    pub totp_code: Option<String>,
+}
+
+struct ZapPayment {
+    pubkey: String,
+    relay: Option<String>,
+    amount_msat: u64,
+    zap_request: Option<String>,
+    /// Lnurl named by the user signed zap request.
+    lnurl: Option<String>,
+}
+
+fn invalid_zap() -> ApiError {
+    ApiError::Nostr(NostrEngineError::InvalidZapRequest)
+}
+
+/// Matches user signed zap requests against the recipients of the target.
+fn signed_zap_payments(
+    zap_requests: &[String],
+    target: &ZapTarget,
+    event_id: Option<&str>,
+    amount_msat: u64,
+) -> Result<Vec<ZapPayment>, ApiError> {
+    let mut payments = Vec::with_capacity(zap_requests.len());
+    for zap_request in zap_requests {
+        let request = utils::nostr::parse_zap_request(zap_request).map_err(|_| invalid_zap())?;
+        let recipient = target.splits.iter().find(|split| split.pubkey == request.pubkey);
+        if recipient.is_none() || request.event_id.as_deref() != event_id {
+            return Err(invalid_zap());
+        }
+        payments.push(ZapPayment {
+            pubkey: request.pubkey,
+            relay: None,
+            amount_msat: request.amount_msat,
+            zap_request: Some(zap_request.clone()),
+            lnurl: request.lnurl,
+        });
+    }
+    if payments.iter().map(|payment| payment.amount_msat).sum::<u64>() != amount_msat {
+        return Err(invalid_zap());
+    }
+    Ok(payments)
+}
+
+/// Whether both lnurls, or lightning addresses, point to the same pay endpoint.
+fn is_same_lnurl(lnurl: &str, other: &str, allow_insecure: bool) -> bool {
+    match (
+        crate::lnurl_pay::pay_params_url(lnurl, allow_insecure),
+        crate::lnurl_pay::pay_params_url(other, allow_insecure),
+    ) {
+        (Ok(url), Ok(other_url)) => url == other_url,
+        _ => false,
+    }
+}
+
+async fn fetch_lnurl_pay_req(web_sender: &WebSender, pubkey: &str) -> Result<Option<String>, ApiError> {
+    let req_id = Uuid::new_v4();
+
+    let request = NostrProfileRequest {
+        req_id,
+        pubkey: Some(pubkey.to_string()),
+        lightning_address: None,
+    };
+
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+        move |message| matches!(message, Message::Api(Api::NostrProfileSearchResponse(response)) if response.req_id == req_id),
+    );
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    let message = Message::Api(Api::NostrProfileRequest(request));
+
+    Arc::make_mut(&mut web_sender.clone().into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    if let Ok(Some(Ok(Message::Api(Api::NostrProfileSearchResponse(response))))) =
+        timeout(Duration::from_secs(5), response_rx.recv()).await
+    {
+        return Ok(response
+            .data
+            .into_iter()
+            .next()
+            .and_then(|profile| profile.validated_lnurl_pay_req));
+    }
+    Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
+async fn pay_zap_invoice(
+    web_sender: &WebSender,
+    uid: u64,
+    payment_request: String,
+) -> Result<PaymentResponse, ApiError> {
+    let req_id = Uuid::new_v4();
+
+    let payment_request = PaymentRequest {
+        currency: Currency::BTC,
+        req_id,
+        uid,
+        payment_request: Some(payment_request),
+        rate: None,
+        amount: None,
+        invoice_amount: None,
+        recipient: None,
+        destination: None,
+        fees: None,
+    };
+
+    let response_filter: Box<dyn Send + Fn(&Message) -> bool> = Box::new(
+        move |message| matches!(message, Message::Api(Api::PaymentResponse(response)) if response.req_id == req_id),
+    );
+
+    let (response_tx, mut response_rx) = mpsc::channel(1);
+
+    let message = Message::Api(Api::PaymentRequest(payment_request));
+
+    Arc::make_mut(&mut web_sender.clone().into_inner())
+        .send(Envelope {
+            message,
+            response_tx: Some(response_tx),
+            response_filter: Some(response_filter),
+        })
+        .await
+        .map_err(|_| ApiError::Comms(CommsError::FailedToSendMessage))?;
+
+    if let Ok(Some(Ok(Message::Api(Api::PaymentResponse(response))))) =
+        timeout(Duration::from_secs(10), response_rx.recv()).await
+    {
+        return Ok(response);
+    }
+    Err(ApiError::Comms(CommsError::ServerResponseTimeout))
+}
+
+#[post("/zap")]
+pub async fn zap(
+    auth_data: AuthData,
+    web_sender: WebSender,
+    pool: WebDbPool,
+    settings: Data<ApiSettings>,
+    data: Json<ZapData>,
+) -> Result<HttpResponse, ApiError> {
+    let uid = auth_data.uid as u64;
+
+    let amount_msat = data
+        .amount
+        .checked_mul(1000)
+        .filter(|msat| *msat > 0)
+        .ok_or(ApiError::Request(RequestError::InvalidDataSupplied))?;
+
+    let comment = data.comment.clone().unwrap_or_default();
+    if comment.len() > MAX_ZAP_COMMENT_LEN {
+        return Err(ApiError::Request(RequestError::InvalidDataSupplied));
+    }
+
+    // Profile zaps pay the pubkey alone, event zaps pay the author unless the event names zap splits.
+    let (target, event_id) = match (&data.event, &data.pubkey) {
+        (Some(event), None) => {
+            let mut target = utils::nostr::zap_target_from_event(event).map_err(|_| invalid_zap())?;
+            if target.splits.is_empty() {
+                target.splits.push(ZapSplit {
+                    pubkey: target.pubkey.clone(),
+                    relay: None,
+                    weight: 1.0,
+                });
+            }
+            let event_id = target.event_id.clone();
+            (target, Some(event_id))
+        }
+        (None, Some(pubkey)) => {
+            let pubkey = utils::nostr::get_pubkey_hex(pubkey)
+                .map_err(|_| ApiError::Request(RequestError::InvalidDataSupplied))?;
+            let target = ZapTarget {
+                pubkey: pubkey.clone(),
+                event_id: String::new(),
+                splits: vec![ZapSplit {
+                    pubkey,
+                    relay: None,
+                    weight: 1.0,
+                }],
+            };
+            (target, None)
+        }
+        _ => return Err(ApiError::Request(RequestError::InvalidDataSupplied)),
+    };
+
+    let payments = match &data.zap_requests {
+        Some(zap_requests) => signed_zap_payments(zap_requests, &target, event_id.as_deref(), amount_msat)?,
+        None => utils::nostr::split_zap_amount(amount_msat, &target.splits)
+            .into_iter()
+            .map(|(split, amount_msat)| ZapPayment {
+                pubkey: split.pubkey,
+                relay: split.relay,
+                amount_msat,
+                zap_request: None,
+                lnurl: None,
+            })
+            .collect(),
+    };
+
+    if payments.is_empty() || payments.len() > MAX_ZAP_RECIPIENTS {
+        return Err(invalid_zap());
+    }
+
+    {
+        let conn = pool.get().map_err(|_| ApiError::Db(DbError::DbConnectionError))?;
+        let money = Money::from_sats(Decimal::from(data.amount));
+        ensure_transfer_authorized(&conn, auth_data.uid, Some(money), data.totp_code.as_deref())?;
+    }
+
+    // Every recipient is resolved to an invoice before paying any of them.
+    let mut invoices = Vec::with_capacity(payments.len());
+    for payment in payments {
+        let lnurl = fetch_lnurl_pay_req(&web_sender, &payment.pubkey)
+            .await?
+            .ok_or(ApiError::Nostr(NostrEngineError::RecipientNotZappable))?;
+
+        // The recipient checks the invoice was requested for its own lnurl.
+        if let Some(requested_lnurl) = &payment.lnurl {
+            if !is_same_lnurl(requested_lnurl, &lnurl, settings.lnurl_allow_insecure) {
+                return Err(invalid_zap());
+            }
+        }
+
+        let zap_request = match payment.zap_request {
+            Some(zap_request) => zap_request,
+            None => {
+                let secret_key = settings
+                    .nostr_zap_private_key
+                    .as_deref()
+                    .ok_or(ApiError::Nostr(NostrEngineError::ZapsNotConfigured))?;
+                let mut relays = settings.nostr_relays_urls.clone();
+                relays.extend(payment.relay.clone());
+                if relays.is_empty() {
+                    return Err(ApiError::Nostr(NostrEngineError::ZapsNotConfigured));
+                }
+                utils::nostr::create_zap_request(
+                    secret_key,
+                    &payment.pubkey,
+                    event_id.as_deref(),
+                    payment.amount_msat,
+                    &lnurl,
+                    &relays,
+                    &comment,
+                )
+                .map_err(|_| ApiError::Nostr(NostrEngineError::ZapsNotConfigured))?
+            }
+        };
+
+        let amount_msat = payment.amount_msat;
+        let allow_insecure = settings.lnurl_allow_insecure;
+        let payment_request = actix_web::web::block(move || {
+            crate::lnurl_pay::resolve_zap(&lnurl, amount_msat, &zap_request, allow_insecure)
+        })
+        .await
+        .map_err(|_| ApiError::External(ExternalError::FailedToFetchExternalData))?
+        .map_err(ApiError::LnurlPay)?;
+
+        invoices.push((payment.pubkey, amount_msat, payment_request));
+    }
+
+    let mut results = Vec::with_capacity(invoices.len());
+    for (pubkey, amount_msat, payment_request) in invoices {
+        let payment_hash = payment_request
+            .parse::<lightning_invoice::Invoice>()
+            .ok()
+            .map(|invoice| invoice.payment_hash().to_string());
+        let result = match pay_zap_invoice(&web_sender, uid, payment_request).await {
+            Ok(response) => json!({
+                "pubkey": pubkey,
+                "amount": amount_msat / 1000,
+                "success": response.success,
+                "pending": false,
+                "payment_hash": response.payment_hash,
+                "error": response.error,
+            }),
+            // The payment may still go through after we stopped waiting for it.
+            Err(ApiError::Comms(CommsError::ServerResponseTimeout)) => json!({
+                "pubkey": pubkey,
+                "amount": amount_msat / 1000,
+                "success": false,
+                "pending": true,
+                "payment_hash": payment_hash,
+                "error": null,
+            }),
+            Err(err) => json!({
+                "pubkey": pubkey,
+                "amount": amount_msat / 1000,
+                "success": false,
+                "pending": false,
+                "payment_hash": null,
+                "error": err.to_string(),
+            }),
+        };
+        results.push(result);
+    }
+
+    Ok(HttpResponse::Ok().json(json!({ "zaps": results })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_zap_payments() {
+        let (sender, _) = utils::nostr::generate_keypair_hex().unwrap();
+        let (_, recipient) = utils::nostr::generate_keypair_hex().unwrap();
+        let relays = vec![String::from("wss://relay.damus.io")];
+        let zap_request =
+            utils::nostr::create_zap_request(&sender, &recipient, None, 21_000, "lnurl1dp68gurn8ghj7", &relays, "")
+                .unwrap();
+        let target = ZapTarget {
+            pubkey: recipient.clone(),
+            event_id: String::new(),
+            splits: vec![ZapSplit {
+                pubkey: recipient.clone(),
+                relay: None,
+                weight: 1.0,
+            }],
+        };
+
+        let payments = signed_zap_payments(&[zap_request.clone()], &target, None, 21_000).unwrap();
+        assert_eq!(payments.len(), 1);
+        assert_eq!(payments[0].pubkey, recipient);
+        assert_eq!(payments[0].amount_msat, 21_000);
+
+        assert!(signed_zap_payments(&[zap_request.clone()], &target, None, 42_000).is_err());
+        assert!(signed_zap_payments(&[zap_request], &target, Some("00"), 21_000).is_err());
+    }
+
+    #[test]
+    fn test_is_same_lnurl() {
+        let lnurl = utils::lnurl::encode("https://example.com/.well-known/lnurlp/alice", None).unwrap();
+        assert!(is_same_lnurl(&lnurl, "alice@example.com", false));
+        assert!(is_same_lnurl(&lnurl.to_uppercase(), &lnurl, false));
+        assert!(!is_same_lnurl(&lnurl, "bob@example.com", false));
+        assert!(!is_same_lnurl("lnurl1dp68gurn8ghj7", "alice@example.com", false));
+    }
+}
@@ -0,0 +1,219 @@
+use actix_web::HttpRequest;
+use core_types::{Currency, Money};
+use diesel::PgConnection;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, hmac};
+use rust_decimal::prelude::Decimal;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use xerror::api::*;
+
+use models::encryption;
+use models::user_totps::UserTotp;
+
+pub const TOTP_DIGITS: u32 = 6;
+pub const TOTP_CODE_HEADER: &str = "X-Totp-Code";
+pub const TOTP_PERIOD_SECONDS: u64 = 30;
+/// Number of time steps accepted before and after the current one to allow for clock drift.
+const TOTP_ALLOWED_DRIFT_STEPS: u64 = 1;
+const TOTP_SECRET_LEN: usize = 20;
+const BACKUP_CODES_COUNT: usize = 10;
+const BACKUP_CODE_LEN: usize = 5;
+
+lazy_static::lazy_static! {
+    /// Key used to encrypt TOTP secrets at rest, derived from `TOTP_ENCRYPTION_KEY`.
+    static ref ENCRYPTION_KEY: Option<[u8; 32]> = match encryption::key_from_env("TOTP_ENCRYPTION_KEY") {
+        Some(key) => Some(key),
+        None => {
+            eprintln!("The env `TOTP_ENCRYPTION_KEY` is not set, two factor authentication is disabled");
+            None
+        }
+    };
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>, TotpError> {
+    let mut bytes = vec![0u8; len];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| TotpError::TotpNotConfigured)?;
+    Ok(bytes)
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    base32::encode(base32::Alphabet::RFC4648 { padding: false }, bytes)
+}
+
+pub fn generate_secret() -> Result<Vec<u8>, TotpError> {
+    random_bytes(TOTP_SECRET_LEN)
+}
+
+/// Returns the uri wallets and authenticator apps use to import the secret, usually rendered as a QR code.
+pub fn provisioning_uri(secret: &[u8], username: &str, issuer: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{username}?secret={}&issuer={issuer}&algorithm=SHA1&digits={TOTP_DIGITS}&period={TOTP_PERIOD_SECONDS}",
+        base32_encode(secret)
+    )
+}
+
+/// Generates the RFC 6238 code for the given time step.
+pub fn generate_code(secret: &[u8], step: u64) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let tag = hmac::sign(&key, &step.to_be_bytes());
+    let hash = tag.as_ref();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = ((hash[offset] as u32 & 0x7f) << 24)
+        | ((hash[offset + 1] as u32) << 16)
+        | ((hash[offset + 2] as u32) << 8)
+        | (hash[offset + 3] as u32);
+    let code = binary % 10u32.pow(TOTP_DIGITS);
+    format!("{:0width$}", code, width = TOTP_DIGITS as usize)
+}
+
+pub fn current_step() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time is before unix epoch")
+        .as_secs();
+    now / TOTP_PERIOD_SECONDS
+}
+
+/// Returns the time step the code belongs to if it is valid and has not been used yet.
+pub fn match_code(secret: &[u8], code: &str, step: u64, last_used_step: u64) -> Option<u64> {
+    let first = step.saturating_sub(TOTP_ALLOWED_DRIFT_STEPS);
+    (first..=step + TOTP_ALLOWED_DRIFT_STEPS)
+        .filter(|s| *s > last_used_step)
+        .find(|s| constant_time_eq(generate_code(secret, *s).as_bytes(), code.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    ring::constant_time::verify_slices_are_equal(a, b).is_ok()
+}
+
+pub fn encrypt_secret(secret: &[u8]) -> Result<String, TotpError> {
+    let key = ENCRYPTION_KEY.as_ref().ok_or(TotpError::TotpNotConfigured)?;
+    encryption::seal(key, secret).ok_or(TotpError::TotpNotConfigured)
+}
+
+pub fn decrypt_secret(encrypted_secret: &str) -> Result<Vec<u8>, TotpError> {
+    let key = ENCRYPTION_KEY.as_ref().ok_or(TotpError::TotpNotConfigured)?;
+    encryption::open(key, encrypted_secret).ok_or(TotpError::TotpNotConfigured)
+}
+
+pub fn is_configured() -> bool {
+    ENCRYPTION_KEY.is_some()
+}
+
+pub fn generate_backup_codes() -> Result<Vec<String>, TotpError> {
+    (0..BACKUP_CODES_COUNT)
+        .map(|_| random_bytes(BACKUP_CODE_LEN).map(|bytes| base32_encode(&bytes).to_lowercase()))
+        .collect()
+}
+
+pub fn hash_backup_code(uid: i32, code: &str) -> String {
+    let salted = format!("{uid}:{}", code.trim().to_lowercase());
+    base64::encode(digest::digest(&digest::SHA256, salted.as_bytes()))
+}
+
+/// Checks a TOTP or backup code and marks it as used so it can't be replayed. Codes are only accepted if
+/// marking them succeeds, so concurrent requests can't both redeem the same code.
+pub fn verify_code(conn: &PgConnection, totp: &UserTotp, code: &str) -> Result<(), ApiError> {
+    let code = code.trim();
+    let secret = decrypt_secret(&totp.encrypted_secret).map_err(ApiError::Totp)?;
+
+    if let Some(step) = match_code(&secret, code, current_step(), totp.last_used_step as u64) {
+        let updated =
+            UserTotp::use_step(conn, totp.uid, step as i64).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+        if updated == 0 {
+            return Err(ApiError::Totp(TotpError::InvalidTotpCode));
+        }
+        return Ok(());
+    }
+
+    let hashed = hash_backup_code(totp.uid, code);
+    if totp.backup_codes.contains(&hashed) {
+        let updated =
+            UserTotp::use_backup_code(conn, totp.uid, &hashed).map_err(|_| ApiError::Db(DbError::UpdateFailed))?;
+        if updated == 0 {
+            return Err(ApiError::Totp(TotpError::InvalidTotpCode));
+        }
+        return Ok(());
+    }
+
+    Err(ApiError::Totp(TotpError::InvalidTotpCode))
+}
+
+/// Code of a transfer request sent as a header, for requests that have no body.
+pub fn code_from_header(request: &HttpRequest) -> Option<&str> {
+    request
+        .headers()
+        .get(TOTP_CODE_HEADER)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Whether a transfer of `amount` needs a code. Transfers of unknown amount or in a currency
+/// other than the threshold currency always need one.
+pub fn requires_code(totp: &UserTotp, amount: Option<Money>) -> bool {
+    if !totp.enabled {
+        return false;
+    }
+    let threshold = match Decimal::from_str(&totp.threshold.to_string()) {
+        Ok(t) => t,
+        Err(_) => return true,
+    };
+    let threshold_currency = match Currency::from_str(&totp.threshold_currency) {
+        Ok(c) => c,
+        Err(_) => return true,
+    };
+    match amount {
+        Some(amount) => amount.currency() != threshold_currency || amount.value() > threshold,
+        None => true,
+    }
+}
+
+/// Enforces two factor authentication for transfer class requests of users who enrolled.
+pub fn ensure_transfer_authorized(
+    conn: &PgConnection,
+    uid: i32,
+    amount: Option<Money>,
+    code: Option<&str>,
+) -> Result<(), ApiError> {
+    let totp = match UserTotp::get_by_uid(conn, uid) {
+        Ok(totp) => totp,
+        Err(diesel::result::Error::NotFound) => return Ok(()),
+        Err(_) => return Err(ApiError::Db(DbError::CouldNotFetchData)),
+    };
+
+    if !requires_code(&totp, amount) {
+        return Ok(());
+    }
+
+    match code {
+        Some(code) => verify_code(conn, &totp, code),
+        None => Err(ApiError::Totp(TotpError::TotpCodeRequired)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_code_rfc6238() {
+        // Test vectors from RFC 6238 appendix B, truncated to 6 digits.
+        let secret = b"12345678901234567890";
+        assert_eq!(generate_code(secret, 59 / TOTP_PERIOD_SECONDS), "287082");
+        assert_eq!(generate_code(secret, 1111111109 / TOTP_PERIOD_SECONDS), "081804");
+        assert_eq!(generate_code(secret, 1234567890 / TOTP_PERIOD_SECONDS), "005924");
+    }
+
+    #[test]
+    fn test_match_code_rejects_replay() {
+        let secret = b"12345678901234567890";
+        let step = 1000;
+        let code = generate_code(secret, step);
+        assert_eq!(match_code(secret, &code, step, 0), Some(step));
+        assert_eq!(match_code(secret, &code, step + 1, 0), Some(step));
+        assert_eq!(match_code(secret, &code, step, step), None);
+        assert_eq!(match_code(secret, &code, step + 2, 0), None);
+    }
+}
@@ -45,6 +45,12 @@ pub fn reconcile_ledger(ledger: &Ledger) -> Result<(), ReconcilationError> {
         tally += acc.balance
     });
 
+    let insurance_fund = &ledger.insurance_fund_account;
+    let mut tally = user_accounts_by_currency
+        .entry(insurance_fund.currency)
+        .or_insert(dec!(0));
+    tally += insurance_fund.balance;
+
     user_accounts_by_currency.iter().for_each(|(curr, balance)| {
         if *balance != dec!(0) {
             error.net_zero.push((*curr, *balance));
@@ -4,11 +4,16 @@ use rust_decimal_macros::*;
 
 use bigdecimal::BigDecimal;
 use std::collections::HashMap;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
 use core_types::*;
 use diesel::result::Error as DieselError;
+use models::dealer_pnl_entries::{self, InsertableDealerPnlEntry};
+use models::insurance_fund_movements::{self, InsertableInsuranceFundMovement};
+use models::notification_preferences::{
+    NotificationChannel, NotificationEvent, NotificationPreference, DEFAULT_NOTIFICATION_LOCALE,
+};
 use models::{
     accounts, dca::DcaSetting, invoices::Invoice, nostr_public_keys::NostrPublicKey,
     summary_transactions::SummaryTransaction, user_profiles::UserProfile, users::User,
@@ -21,6 +26,7 @@ use msgs::journal::*;
 use msgs::*;
 use std::iter::Iterator;
 use utils::currencies::{SATS_DECIMALS, SATS_IN_BITCOIN};
+use utils::mailer::{Mailer, MailerSettings};
 use utils::xlogging::*;
 use xerror::bank_engine::*;
 
@@ -29,14 +35,24 @@ use lnd_connector::connector::{LndConnector, LndConnectorSettings};
 use rand_core::{OsRng, RngCore};
 
 use influxdb2::Client;
-use msgs::cli::{Cli, MakeTx, MakeTxResult};
+use msgs::cli::{
+    Cli, DailyDealerPnl, DealerPnlReport, DealerPnlRequest, InsuranceFundMovement, InsuranceFundReport,
+    InsuranceFundRequest, MakeTx, MakeTxResult,
+};
 use msgs::nostr::Nostr;
 use serde::{Deserialize, Serialize};
 
+use crate::insurance::InsuranceFundSettings;
 use crate::ledger::*;
+use crate::notifications::{format_money, TemplateStore};
 
 const BANK_UID: u64 = 23193913;
 const DEALER_UID: u64 = 52172712;
+/// How long a swap or payment is remembered as a DCA run or LNURL claim while waiting for its result.
+const PENDING_NOTIFICATION_TTL: Duration = Duration::from_secs(3600);
+const DAY_MS: u64 = 86_400_000;
+/// Rates the dealer reported longer ago are not used to value balances.
+const MAX_RATE_AGE_MS: u64 = 30_000;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RateLimiterSettings {
@@ -72,6 +88,15 @@ pub struct BankEngineSettings {
     #[serde(default)]
     pub normalize_account_balances: bool,
     pub domain: String,
+    /// JSON file with notification templates by locale, overriding the built-in ones.
+    #[serde(default)]
+    pub notification_templates_path: Option<String>,
+    /// SMTP server email notifications are sent through.
+    #[serde(default)]
+    pub mailer_settings: Option<MailerSettings>,
+    /// Without it margin calls and losses are left to the dealer's own funds.
+    #[serde(default)]
+    pub insurance_fund_settings: Option<InsuranceFundSettings>,
 }
 
 impl Default for Ledger {
@@ -130,7 +155,15 @@ pub struct BankEngine {
     pub withdrawal_request_rate_limiter: HashMap<UserId, (u64, Instant)>,
     pub deposit_request_rate_limiter: HashMap<UserId, (u64, Instant)>,
     pub last_rates: HashMap<(Currency, Currency), Rate>,
+    pub last_rates_timestamp: u64,
     pub domain: String,
+    pub notification_templates: TemplateStore,
+    pub mailer: Option<Mailer>,
+    /// Swaps requested by DCA runs.
+    pub dca_swap_requests: HashMap<Uuid, Instant>,
+    /// Payments claiming an LNURL withdrawal.
+    pub lnurl_withdrawal_claims: HashMap<Uuid, Instant>,
+    pub insurance_fund_settings: Option<InsuranceFundSettings>,
 }
 
 impl BankEngine {
@@ -144,6 +177,16 @@ impl BankEngine {
         settings.logging_settings.name = String::from("Bank");
         let logger = init_log(&settings.logging_settings);
 
+        let notification_templates = match &settings.notification_templates_path {
+            Some(path) => TemplateStore::load(path)
+                .unwrap_or_else(|err| panic!("Failed to load notification templates from {path}: {err}")),
+            None => TemplateStore::new(),
+        };
+        let mailer = settings
+            .mailer_settings
+            .as_ref()
+            .map(|mailer_settings| Mailer::new(mailer_settings).expect("Failed to configure the mailer"));
+
         Self {
             lnd_node_info: LndNodeInfo::default(),
             bank_uid: BANK_UID,
@@ -169,7 +212,13 @@ impl BankEngine {
             payment_thread_sender,
             lnd_connector_settings,
             last_rates: HashMap::new(),
+            last_rates_timestamp: 0,
             domain: settings.domain,
+            notification_templates,
+            mailer,
+            dca_swap_requests: HashMap::new(),
+            lnurl_withdrawal_claims: HashMap::new(),
+            insurance_fund_settings: settings.insurance_fund_settings,
         }
     }
 
@@ -347,6 +396,15 @@ impl BankEngine {
         let dealer_accounts = self.fetch_dealer_accounts(&c);
         self.ledger.dealer_accounts.accounts = dealer_accounts;
 
+        let insurance_fund_accounts = self.fetch_accounts(&c, &mut accounts::Account::get_dealer_insurance_accounts);
+        match insurance_fund_accounts.first() {
+            Some(account) => self.ledger.insurance_fund_account = account.clone(),
+            None => {
+                let account = self.ledger.insurance_fund_account.clone();
+                self.update_account(&account, DEALER_UID);
+            }
+        }
+
         let accounts = match accounts::Account::get_non_internal_users_accounts(&c) {
             Ok(accs) => accs,
             Err(_) => return,
@@ -757,6 +815,91 @@ impl BankEngine {
         Ok(txid)
     }
 
+    /// Sends the notification of an event to the user on every channel they receive it on.
+    fn notify<F: FnMut(Message, ServiceIdentity)>(
+        &self,
+        uid: UserId,
+        event: NotificationEvent,
+        amounts: &[(&str, Money)],
+        invoice: Option<&Invoice>,
+        listener: &mut F,
+    ) {
+        // notifications are best effort, the main loop must not wait for a connection
+        let c = match self.conn_pool.as_ref().and_then(|pool| pool.try_get()) {
+            Some(psql_connection) => psql_connection,
+            _ => {
+                slog::error!(
+                    self.logger,
+                    "Couldn't get psql connection to send {:?} notification.",
+                    event
+                );
+                return;
+            }
+        };
+        let uid = uid as i32;
+        let profile = UserProfile::get_by_uid(&c, uid).ok();
+        let locale = profile
+            .as_ref()
+            .and_then(|profile| profile.notification_locale.as_deref())
+            .unwrap_or(DEFAULT_NOTIFICATION_LOCALE);
+        let args = amounts
+            .iter()
+            .map(|(name, money)| (*name, format_money(money, locale)))
+            .collect::<Vec<_>>();
+        let text = match self.notification_templates.render(locale, event, &args) {
+            Some(text) => text,
+            None => {
+                slog::error!(self.logger, "No notification template for {:?}", event);
+                return;
+            }
+        };
+        let text = match invoice {
+            Some(invoice) => with_payer_note(text, invoice),
+            None => text,
+        };
+
+        for channel in NotificationChannel::ALL {
+            match NotificationPreference::is_enabled(&c, profile.as_ref(), uid, event, channel) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(err) => {
+                    slog::error!(self.logger, "Couldn't get notification preference, error: {:?}", err);
+                    continue;
+                }
+            }
+            match channel {
+                NotificationChannel::Nostr => {
+                    if let Ok(pk) = NostrPublicKey::get_by_uid(&c, uid) {
+                        let nostr_private_msg = msgs::nostr::NostrPrivateMessage {
+                            pubkey: pk.pubkey,
+                            text: text.clone(),
+                            legacy_dms: pk.legacy_dms,
+                        };
+                        let msg = Message::Nostr(msgs::nostr::Nostr::NostrPrivateMessage(nostr_private_msg));
+                        listener(msg, ServiceIdentity::Nostr)
+                    }
+                }
+                NotificationChannel::Email => {
+                    let email = profile
+                        .as_ref()
+                        .filter(|profile| profile.is_email_verified == Some(true))
+                        .and_then(|profile| profile.email.clone());
+                    if let (Some(mailer), Some(email)) = (self.mailer.clone(), email) {
+                        let subject = self.notification_templates.email_subject(locale).unwrap_or_default();
+                        let body = text.clone();
+                        let logger = self.logger.clone();
+                        // Sending blocks on the SMTP server, it must not hold up the bank.
+                        tokio::task::spawn_blocking(move || {
+                            if let Err(err) = mailer.send(&email, &subject, &body) {
+                                slog::error!(logger, "Failed to send notification email, error: {:?}", err);
+                            }
+                        });
+                    }
+                }
+            }
+        }
+    }
+
     pub fn make_internal_tx<F: FnMut(Message, ServiceIdentity)>(
         &mut self,
         payment_request: PaymentRequest,
@@ -921,6 +1064,26 @@ impl BankEngine {
         payment_response.success = true;
         let msg = Message::Api(Api::PaymentResponse(payment_response));
         listener(msg, ServiceIdentity::Api);
+
+        let outbound_event = if self.lnurl_withdrawal_claims.remove(&payment_request.req_id).is_some() {
+            NotificationEvent::LnurlClaimed
+        } else {
+            NotificationEvent::OutgoingPayment
+        };
+        self.notify(
+            outbound_uid,
+            outbound_event,
+            &[("amount", outbound_amount)],
+            None,
+            listener,
+        );
+        self.notify(
+            inbound_uid,
+            NotificationEvent::Deposit,
+            &[("amount", outbound_amount)],
+            None,
+            listener,
+        );
         Ok(())
     }
 
@@ -930,6 +1093,7 @@ impl BankEngine {
                 Dealer::Health(dealer_health) => {
                     self.available_currencies = dealer_health.available_currencies;
                     self.last_rates = dealer_health.rates;
+                    self.last_rates_timestamp = dealer_health.timestamp;
                     if dealer_health.status == HealthStatus::Down {
                         if dealer_health.status == HealthStatus::Down {
                             slog::warn!(self.logger, "Dealer is disconnected from the exchange!");
@@ -993,8 +1157,6 @@ impl BankEngine {
                             None => return,
                         };
 
-                        let currency = msg.currency;
-
                         let (mut inbound_account, inbound_uid) = {
                             let user_account = self
                                 .ledger
@@ -1105,24 +1267,13 @@ impl BankEngine {
                         if invoice.update(&c).is_err() {
                             slog::error!(self.logger, "Unable to update invoice");
                         }
-                        if let Ok(user_profile) = UserProfile::get_by_uid(&c, invoice.uid) {
-                            if user_profile.nostr_notifications.unwrap() {
-                                if let Ok(pk) = NostrPublicKey::get_by_uid(&c, invoice.uid) {
-                                    let text = format!(
-                                        "💸 You just got paid {} {} into your Kollider Wallet! 💰",
-                                        fiat_value.value().round_dp_with_strategy(2, RoundingStrategy::ToZero),
-                                        currency
-                                    );
-                                    let nostr_private_msg = msgs::nostr::NostrPrivateMessage {
-                                        pubkey: pk.pubkey,
-                                        text,
-                                    };
-                                    let msg =
-                                        Message::Nostr(msgs::nostr::Nostr::NostrPrivateMessage(nostr_private_msg));
-                                    listener(msg, ServiceIdentity::Nostr)
-                                }
-                            }
-                        }
+                        self.notify(
+                            invoice.uid as UserId,
+                            NotificationEvent::Deposit,
+                            &[("amount", fiat_value)],
+                            Some(&invoice),
+                            listener,
+                        );
                     } else {
                         slog::error!(self.logger, "Couldn't find payment request. This should never happen.");
                     }
@@ -1306,22 +1457,13 @@ impl BankEngine {
                         slog::error!(self.logger, "Unable to update invoice");
                     }
 
-                    if let Ok(user_profile) = UserProfile::get_by_uid(&c, invoice.uid) {
-                        if user_profile.nostr_notifications.unwrap() {
-                            if let Ok(pk) = NostrPublicKey::get_by_uid(&c, invoice.uid) {
-                                let text = format!(
-                                    "💸 You just got paid {} Sats into your Kollider Wallet! 💰",
-                                    value.try_sats().unwrap().round_dp(0)
-                                );
-                                let nostr_private_msg = msgs::nostr::NostrPrivateMessage {
-                                    pubkey: pk.pubkey,
-                                    text,
-                                };
-                                let msg = Message::Nostr(msgs::nostr::Nostr::NostrPrivateMessage(nostr_private_msg));
-                                listener(msg, ServiceIdentity::Nostr)
-                            }
-                        }
-                    }
+                    self.notify(
+                        invoice.uid as UserId,
+                        NotificationEvent::Deposit,
+                        &[("amount", value)],
+                        Some(&invoice),
+                        listener,
+                    );
 
                     publish_if_zap_note(
                         &invoice,
@@ -1953,6 +2095,8 @@ impl BankEngine {
                             target_account_currency: None,
                             reference: None,
                             description: None,
+                            payer_comment: None,
+                            payer_data: None,
                         };
                         if let Err(err) = invoice.insert(&psql_connection) {
                             slog::error!(self.logger, "Error inserting Invoice {:?}", err);
@@ -2475,10 +2619,26 @@ impl BankEngine {
 
                     self.update_account(&outbound_dealer_account, DEALER_UID);
                     self.update_account(&inbound_dealer_account, DEALER_UID);
+                    self.record_spread_revenue(&swap_response);
+                    self.top_up_insurance_fund(&swap_response);
+
+                    let event = if self.dca_swap_requests.remove(&swap_response.req_id).is_some() {
+                        NotificationEvent::DcaRun
+                    } else {
+                        NotificationEvent::SwapExecuted
+                    };
 
                     let msg = Message::Api(Api::SwapResponse(swap_response));
                     listener(msg, ServiceIdentity::Api);
 
+                    self.notify(
+                        uid,
+                        event,
+                        &[("from_amount", value), ("to_amount", inbound_amount)],
+                        None,
+                        listener,
+                    );
+
                     // Updating the dealer of the new state of the bank.
                     let bank_state = self.get_bank_state();
                     let msg = Message::Dealer(Dealer::BankState(bank_state));
@@ -2687,6 +2847,7 @@ impl BankEngine {
                 Api::PayLnurlWithdrawalRequest(msg) => {
                     if let Some((_, mut payment_request)) = self.lnurl_withdrawal_requests.remove(&msg.req_id) {
                         payment_request.payment_request = Some(msg.payment_request);
+                        track_pending(&mut self.lnurl_withdrawal_claims, payment_request.req_id);
                         let msg = Message::Api(Api::PaymentRequest(payment_request));
                         listener(msg, ServiceIdentity::Loopback);
                         return;
@@ -2895,7 +3056,21 @@ impl BankEngine {
                         if invoice.update(&psql_connection).is_err() {
                             slog::error!(self.logger, "Error updating updating invoices!");
                         }
+
+                        let event = if self.lnurl_withdrawal_claims.remove(&payment_response.req_id).is_some() {
+                            NotificationEvent::LnurlClaimed
+                        } else {
+                            NotificationEvent::OutgoingPayment
+                        };
+                        // Payments are made in bitcoin, users paying from a fiat account get the fiat amount.
+                        let amount = if res.currency == Currency::BTC {
+                            payment_amount
+                        } else {
+                            payment_amount.exchange(&res.rate).unwrap_or(payment_amount)
+                        };
+                        self.notify(uid, event, &[("amount", amount)], None, listener);
                     } else {
+                        self.lnurl_withdrawal_claims.remove(&payment_response.req_id);
                         let refund = res.amount;
                         let rate = res.rate;
                         let refund_exchanged = refund.clone().exchange(&rate).unwrap();
@@ -3061,8 +3236,11 @@ impl BankEngine {
                         let amount = user.amount.to_string();
                         let amount = Decimal::from_str(&amount).unwrap();
 
+                        let req_id = Uuid::new_v4();
+                        track_pending(&mut self.dca_swap_requests, req_id);
+
                         let swap_request = SwapRequest {
-                            req_id: Uuid::new_v4(),
+                            req_id,
                             uid: user.uid as u64,
                             amount: Money::new(from_currency, amount),
                             from: from_currency,
@@ -3086,14 +3264,389 @@ impl BankEngine {
                 // just to pass some argument
                 listener(msg, ServiceIdentity::Api);
             }
+            Message::Cli(Cli::DealerPnlRequest(request)) => {
+                let report = self.dealer_pnl_report(&request);
+                let msg = Message::Cli(Cli::DealerPnlReport(report));
+                listener(msg, ServiceIdentity::Api);
+            }
+            Message::Cli(Cli::InsuranceFundRequest(request)) => {
+                let report = self.insurance_fund_report(&request);
+                let msg = Message::Cli(Cli::InsuranceFundReport(report));
+                listener(msg, ServiceIdentity::Api);
+            }
             Message::Nostr(Nostr::NostrProfilesRefetchRequest(req)) => {
                 let msg = Message::Nostr(Nostr::NostrProfilesRefetchRequest(req));
                 listener(msg, ServiceIdentity::Nostr);
             }
+            Message::Nostr(Nostr::NostrRelayUpdate(update)) => {
+                let msg = Message::Nostr(Nostr::NostrRelayUpdate(update));
+                listener(msg, ServiceIdentity::Nostr);
+            }
             _ => {}
         }
     }
 
+    /// Books the fees of an executed swap as the dealer's spread revenue.
+    fn record_spread_revenue(&self, swap_response: &SwapResponse) {
+        let fees = match swap_response.fees {
+            Some(fees) if !fees.value().is_zero() => fees,
+            _ => return,
+        };
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let amount = match BigDecimal::from_str(&fees.value().to_string()) {
+            Ok(amount) => amount,
+            Err(_) => {
+                slog::error!(
+                    self.logger,
+                    "Couldn't convert swap fees {} to a big decimal",
+                    fees.value()
+                );
+                return;
+            }
+        };
+        let entry = InsertableDealerPnlEntry {
+            created_at: utils::time::time_now() as i64,
+            kind: dealer_pnl_entries::SPREAD.to_string(),
+            currency: fees.currency().to_string(),
+            amount,
+            symbol: None,
+            reference: Some(swap_response.req_id.to_string()),
+        };
+        if let Err(err) = entry.insert(&c) {
+            slog::error!(
+                self.logger,
+                "Failed to book spread revenue of swap {}: {:?}",
+                swap_response.req_id,
+                err
+            );
+        }
+    }
+
+    fn dealer_pnl_report(&self, request: &DealerPnlRequest) -> DealerPnlReport {
+        let error_report = |error: &str| DealerPnlReport {
+            days: vec![],
+            error: Some(error.to_string()),
+        };
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => return error_report("No database provided."),
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => return error_report("Couldn't get psql connection."),
+        };
+
+        let today = utils::time::time_now() / DAY_MS * DAY_MS;
+        let since = today.saturating_sub(u64::from(request.days.saturating_sub(1)) * DAY_MS);
+        let currency = request.currency.map(|currency| currency.to_string());
+        match dealer_pnl_entries::DailyDealerPnl::fetch(&c, since as i64, currency.as_deref()) {
+            Ok(rows) => DealerPnlReport {
+                days: daily_dealer_pnl(rows),
+                error: None,
+            },
+            Err(err) => error_report(&format!("Failed to fetch dealer pnl: {err:?}")),
+        }
+    }
+
+    /// Last BTC rate of the currency the dealer reported, `None` once it is too old to be relied on.
+    fn get_last_rate(&self, currency: Currency) -> Option<&Rate> {
+        if utils::time::time_now().saturating_sub(self.last_rates_timestamp) > MAX_RATE_AGE_MS {
+            return None;
+        }
+        self.last_rates.get(&(Currency::BTC, currency))
+    }
+
+    /// BTC value of the users' fiat balances, `None` until the rates of all their currencies are known.
+    fn fiat_exposure(&self) -> Option<Decimal> {
+        self.get_bank_state()
+            .total_exposures
+            .into_iter()
+            .filter(|(currency, _)| *currency != Currency::BTC && *currency != Currency::KKP)
+            .try_fold(dec!(0), |total, (currency, exposure)| {
+                let rate = self.get_last_rate(currency)?;
+                let value = Money::new(currency, exposure).exchange(rate).ok()?;
+                Some(total + value.value())
+            })
+    }
+
+    fn insurance_fund_target(&self) -> Option<Decimal> {
+        let settings = self.insurance_fund_settings.as_ref()?;
+        self.fiat_exposure().map(|fiat_exposure| settings.target(fiat_exposure))
+    }
+
+    /// Part of `amount` the insurance fund may cover under its daily drawdown limit.
+    fn insurance_fund_drawdown(&self, amount: Decimal) -> Decimal {
+        let settings = match &self.insurance_fund_settings {
+            Some(settings) => settings,
+            None => return dec!(0),
+        };
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return dec!(0);
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return dec!(0);
+            }
+        };
+
+        let today = utils::time::time_now() / DAY_MS * DAY_MS;
+        let drawn_today = match insurance_fund_movements::InsuranceFundMovement::get_drawn_since(&c, today as i64) {
+            Ok(drawn) => Decimal::from_str(&drawn.to_string()).unwrap_or_default(),
+            Err(err) => {
+                slog::error!(
+                    self.logger,
+                    "Failed to fetch today's insurance fund drawdowns: {:?}",
+                    err
+                );
+                return dec!(0);
+            }
+        };
+        settings.drawdown(self.ledger.insurance_fund_account.balance, -drawn_today, amount)
+    }
+
+    fn record_insurance_fund_movement(&self, kind: &str, amount: Decimal, reference: Option<String>) {
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => {
+                slog::error!(self.logger, "No database provided.");
+                return;
+            }
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => {
+                slog::error!(self.logger, "Couldn't get psql connection.");
+                return;
+            }
+        };
+
+        let balance = self.ledger.insurance_fund_account.balance;
+        let (amount_bigdec, balance_bigdec) = match (
+            BigDecimal::from_str(&amount.to_string()),
+            BigDecimal::from_str(&balance.to_string()),
+        ) {
+            (Ok(amount), Ok(balance)) => (amount, balance),
+            _ => {
+                slog::error!(
+                    self.logger,
+                    "Couldn't convert insurance fund movement {} to a big decimal",
+                    amount
+                );
+                return;
+            }
+        };
+        let movement = InsertableInsuranceFundMovement {
+            created_at: utils::time::time_now() as i64,
+            kind: kind.to_string(),
+            amount: amount_bigdec,
+            balance: balance_bigdec,
+            reference,
+        };
+        if let Err(err) = movement.insert(&c) {
+            slog::error!(
+                self.logger,
+                "Failed to store insurance fund movement {:?}: {:?}",
+                movement,
+                err
+            );
+        }
+    }
+
+    /// Moves BTC between the dealer's own funds and the insurance fund, into the fund for positive amounts.
+    fn transfer_insurance_funds(
+        &mut self,
+        amount: Decimal,
+        kind: &str,
+        reference: Option<String>,
+    ) -> Result<(), BankError> {
+        let mut dealer_account = self
+            .ledger
+            .dealer_accounts
+            .get_default_account(Currency::BTC, Some(AccountType::Internal));
+        let mut insurance_fund_account = self.ledger.insurance_fund_account.clone();
+        let money = Money::new(Currency::BTC, amount.abs());
+
+        if amount.is_sign_positive() {
+            self.make_tx(
+                &mut dealer_account,
+                DEALER_UID,
+                &mut insurance_fund_account,
+                DEALER_UID,
+                money,
+            )?;
+        } else {
+            self.make_tx(
+                &mut insurance_fund_account,
+                DEALER_UID,
+                &mut dealer_account,
+                DEALER_UID,
+                money,
+            )?;
+        }
+
+        self.ledger
+            .dealer_accounts
+            .accounts
+            .insert(dealer_account.account_id, dealer_account.clone());
+        self.update_account(&dealer_account, DEALER_UID);
+        self.ledger.insurance_fund_account = insurance_fund_account.clone();
+        self.update_account(&insurance_fund_account, DEALER_UID);
+
+        let amount = if amount.is_sign_positive() {
+            money.value()
+        } else {
+            -money.value()
+        };
+        self.record_insurance_fund_movement(kind, amount, reference);
+        Ok(())
+    }
+
+    /// Moves part of the spread revenue of a swap from the dealer's BTC into the insurance fund while the fund is
+    /// below target.
+    fn top_up_insurance_fund(&mut self, swap_response: &SwapResponse) {
+        let settings = match &self.insurance_fund_settings {
+            Some(settings) => settings.clone(),
+            None => return,
+        };
+
+        let fees = match swap_response.fees {
+            Some(fees) => fees,
+            None => return,
+        };
+        // The swap rate only converts between the two swapped currencies, which needn't include BTC.
+        let revenue = match btc_value(fees, self.get_last_rate(fees.currency())) {
+            Some(revenue) => revenue,
+            None => return,
+        };
+
+        let target = match self.insurance_fund_target() {
+            Some(target) => target,
+            None => return,
+        };
+        let dealer_balance = self
+            .ledger
+            .dealer_accounts
+            .get_default_account(Currency::BTC, Some(AccountType::Internal))
+            .balance;
+        let amount = settings
+            .top_up(self.ledger.insurance_fund_account.balance, target, revenue)
+            .min(dealer_balance);
+        if amount <= dec!(0) {
+            return;
+        }
+
+        let reference = Some(swap_response.req_id.to_string());
+        if let Err(err) = self.transfer_insurance_funds(amount, insurance_fund_movements::TOP_UP, reference) {
+            slog::error!(
+                self.logger,
+                "Failed to top up the insurance fund by {} BTC: {:?}",
+                amount,
+                err
+            );
+        }
+    }
+
+    /// Covers a negative BTC balance of the dealer from the insurance fund, as far as its drawdown limit allows.
+    pub fn cover_dealer_losses(&mut self) {
+        let dealer_balance = self
+            .ledger
+            .dealer_accounts
+            .get_default_account(Currency::BTC, Some(AccountType::Internal))
+            .balance;
+        if dealer_balance >= dec!(0) {
+            return;
+        }
+
+        let amount = self.insurance_fund_drawdown(-dealer_balance);
+        if amount <= dec!(0) {
+            return;
+        }
+
+        match self.transfer_insurance_funds(-amount, insurance_fund_movements::LOSS, None) {
+            Ok(()) => slog::warn!(
+                self.logger,
+                "Covered {} BTC of dealer losses from the insurance fund, {} BTC left",
+                amount,
+                self.ledger.insurance_fund_account.balance
+            ),
+            Err(err) => slog::error!(
+                self.logger,
+                "Failed to cover {} BTC of dealer losses from the insurance fund: {:?}",
+                amount,
+                err
+            ),
+        }
+    }
+
+    fn insurance_fund_report(&self, request: &InsuranceFundRequest) -> InsuranceFundReport {
+        let balance = self.ledger.insurance_fund_account.balance;
+        let target = self.insurance_fund_target();
+        let error_report = |error: &str| InsuranceFundReport {
+            balance,
+            target,
+            movements: vec![],
+            error: Some(error.to_string()),
+        };
+
+        let conn = match &self.conn_pool {
+            Some(conn) => conn,
+            None => return error_report("No database provided."),
+        };
+
+        let c = match conn.get() {
+            Ok(psql_connection) => psql_connection,
+            Err(_) => return error_report("Couldn't get psql connection."),
+        };
+
+        let today = utils::time::time_now() / DAY_MS * DAY_MS;
+        let since = today.saturating_sub(u64::from(request.days.saturating_sub(1)) * DAY_MS);
+        match insurance_fund_movements::InsuranceFundMovement::get_since(&c, since as i64) {
+            Ok(rows) => InsuranceFundReport {
+                balance,
+                target,
+                movements: rows
+                    .into_iter()
+                    .map(|row| InsuranceFundMovement {
+                        created_at: row.created_at as u64,
+                        kind: row.kind,
+                        amount: Decimal::from_str(&row.amount.to_string()).unwrap_or_default(),
+                        balance: Decimal::from_str(&row.balance.to_string()).unwrap_or_default(),
+                        reference: row.reference,
+                    })
+                    .collect(),
+                error: None,
+            },
+            Err(err) => error_report(&format!("Failed to fetch insurance fund movements: {err:?}")),
+        }
+    }
+
     async fn handle_dealer_deposit(&mut self, deposit: Deposit) {
         slog::info!(self.logger, "Dealer deposit resceived!");
         // Dealer can transfer from an outside unknown wallet or between the external
@@ -3126,6 +3679,9 @@ impl BankEngine {
                 _ => return,
             };
 
+            // Margin released by Kollider and deposits from outside go to the insurance fund once it is set up.
+            let to_insurance_fund = !is_internal && self.insurance_fund_settings.is_some();
+
             let (mut inbound_dealer_account, mut outbound_account, outbound_uid) = if is_internal {
                 let inbound = self
                     .ledger
@@ -3137,10 +3693,13 @@ impl BankEngine {
                     .get_default_account(Currency::BTC, Some(AccountType::External));
                 (inbound, outbound, DEALER_UID)
             } else {
-                let inbound = self
-                    .ledger
-                    .dealer_accounts
-                    .get_default_account(Currency::BTC, Some(AccountType::Internal));
+                let inbound = if to_insurance_fund {
+                    self.ledger.insurance_fund_account.clone()
+                } else {
+                    self.ledger
+                        .dealer_accounts
+                        .get_default_account(Currency::BTC, Some(AccountType::Internal))
+                };
                 let outbound = self
                     .ledger
                     .bank_liabilities
@@ -3150,23 +3709,31 @@ impl BankEngine {
 
             let value = Money::from_sats(Decimal::new(invoice.value, 0));
 
-            if self
-                .make_tx(
-                    &mut outbound_account,
-                    outbound_uid,
-                    &mut inbound_dealer_account,
-                    DEALER_UID,
-                    value,
-                )
-                .is_err()
-            {
-                slog::error!(self.logger, "Failed to make deposit it dealer account");
+            let txid = match self.make_tx(
+                &mut outbound_account,
+                outbound_uid,
+                &mut inbound_dealer_account,
+                DEALER_UID,
+                value,
+            ) {
+                Ok(txid) => Some(txid),
+                Err(_) => {
+                    slog::error!(self.logger, "Failed to make deposit it dealer account");
+                    None
+                }
             };
 
-            self.ledger
-                .dealer_accounts
-                .accounts
-                .insert(inbound_dealer_account.account_id, inbound_dealer_account.clone());
+            if to_insurance_fund {
+                self.ledger.insurance_fund_account = inbound_dealer_account.clone();
+                if txid.is_some() {
+                    self.record_insurance_fund_movement(insurance_fund_movements::DEPOSIT, value.value(), txid);
+                }
+            } else {
+                self.ledger
+                    .dealer_accounts
+                    .accounts
+                    .insert(inbound_dealer_account.account_id, inbound_dealer_account.clone());
+            }
             self.update_account(&inbound_dealer_account, DEALER_UID);
 
             if is_internal {
@@ -3221,15 +3788,30 @@ impl BankEngine {
         {
             Ok(result) => {
                 slog::debug!(self.logger, "{:?}", result);
+                let amount = Money::from_sats(amount_in_sats);
+
+                // Margin calls are paid from the insurance fund if it can cover them in full.
+                let from_insurance_fund = is_external && self.insurance_fund_drawdown(amount.value()) == amount.value();
+                if is_external && !from_insurance_fund && self.insurance_fund_settings.is_some() {
+                    slog::warn!(
+                        self.logger,
+                        "Insurance fund can't cover margin call of {} sats, paying it from the dealer's funds",
+                        amount_in_sats
+                    );
+                }
+
                 let (mut outbound_account, mut inbound_account, inbound_uid) = if is_external {
                     let inbound_account = self
                         .ledger
                         .bank_liabilities
                         .get_default_account(Currency::BTC, Some(AccountType::External));
-                    let outbound_account = self
-                        .ledger
-                        .dealer_accounts
-                        .get_default_account(Currency::BTC, Some(AccountType::Internal));
+                    let outbound_account = if from_insurance_fund {
+                        self.ledger.insurance_fund_account.clone()
+                    } else {
+                        self.ledger
+                            .dealer_accounts
+                            .get_default_account(Currency::BTC, Some(AccountType::Internal))
+                    };
                     (outbound_account, inbound_account, BANK_UID)
                 } else {
                     let inbound_account = self
@@ -3243,20 +3825,16 @@ impl BankEngine {
                     (outbound_account, inbound_account, DEALER_UID)
                 };
 
-                let amount = Money::from_sats(amount_in_sats);
-
-                if self
-                    .make_tx(
-                        &mut outbound_account,
-                        DEALER_UID,
-                        &mut inbound_account,
-                        inbound_uid,
-                        amount,
-                    )
-                    .is_err()
-                {
-                    return;
-                }
+                let txid = match self.make_tx(
+                    &mut outbound_account,
+                    DEALER_UID,
+                    &mut inbound_account,
+                    inbound_uid,
+                    amount,
+                ) {
+                    Ok(txid) => txid,
+                    Err(_) => return,
+                };
 
                 if is_external {
                     self.update_account(&inbound_account, BANK_UID);
@@ -3266,10 +3844,19 @@ impl BankEngine {
                         .bank_liabilities
                         .accounts
                         .insert(inbound_account.account_id, inbound_account.clone());
-                    self.ledger
-                        .dealer_accounts
-                        .accounts
-                        .insert(outbound_account.account_id, outbound_account.clone());
+                    if from_insurance_fund {
+                        self.ledger.insurance_fund_account = outbound_account;
+                        self.record_insurance_fund_movement(
+                            insurance_fund_movements::MARGIN_CALL,
+                            -amount.value(),
+                            Some(txid),
+                        );
+                    } else {
+                        self.ledger
+                            .dealer_accounts
+                            .accounts
+                            .insert(outbound_account.account_id, outbound_account.clone());
+                    }
                 } else {
                     self.update_account(&inbound_account, DEALER_UID);
                     self.update_account(&outbound_account, DEALER_UID);
@@ -3416,6 +4003,8 @@ impl BankEngine {
             target_account_currency: None,
             reference: None,
             description: None,
+            payer_comment: None,
+            payer_data: None,
         };
         invoice.insert(&c).expect("Failed to insert psql connection");
         // let estimated_fee_in_sats = estimated_fee.try_sats().unwrap();
@@ -3538,6 +4127,12 @@ impl BankEngine {
             self.insert_into_ledger(&inbound_uid, inbound_account_id, inbound_account);
         };
 
+        if is_inbound_insurance_account {
+            self.record_insurance_fund_movement(insurance_fund_movements::DEPOSIT, amount.value(), None);
+        } else if is_outbound_insurance_account {
+            self.record_insurance_fund_movement(insurance_fund_movements::WITHDRAWAL, -amount.value(), None);
+        }
+
         Ok(())
     }
 
@@ -3615,7 +4210,7 @@ impl BankEngine {
                 } else if inbound_currency == Currency::BTC {
                     inbound_amount
                 } else {
-                    match self.last_rates.get(&(Currency::BTC, outbound_currency)) {
+                    match self.get_last_rate(outbound_currency) {
                         Some(rate) => {
                             let money = Money::new(outbound_currency, outbound_amount);
                             match money.exchange(rate) {
@@ -3695,6 +4290,32 @@ impl BankEngine {
     }
 }
 
+/// Remembers a request until its result comes in, forgetting the ones that never got one.
+fn track_pending(pending: &mut HashMap<Uuid, Instant>, req_id: Uuid) {
+    pending.retain(|_, requested_at| requested_at.elapsed() < PENDING_NOTIFICATION_TTL);
+    pending.insert(req_id, Instant::now());
+}
+
+/// Appends the payer's identifier and comment left on a lightning address payment to a notification.
+fn with_payer_note(mut text: String, invoice: &Invoice) -> String {
+    let payer = invoice
+        .payer_data
+        .as_ref()
+        .and_then(|payer_data| serde_json::from_str::<serde_json::Value>(payer_data).ok())
+        .and_then(|payer_data| {
+            ["name", "identifier", "email", "pubkey"]
+                .iter()
+                .find_map(|field| payer_data.get(*field).and_then(|v| v.as_str()).map(String::from))
+        });
+    if let Some(payer) = payer {
+        text.push_str(&format!("\nFrom: {payer}"));
+    }
+    if let Some(comment) = &invoice.payer_comment {
+        text.push_str(&format!("\n💬 {comment}"));
+    }
+    text
+}
+
 fn publish_if_zap_note<F: FnMut(Message, ServiceIdentity)>(
     invoice: &Invoice,
     description_hash: Option<String>,
@@ -3722,6 +4343,7 @@ fn publish_if_zap_note<F: FnMut(Message, ServiceIdentity)>(
                 bolt11: invoice.payment_request.clone(),
                 preimage,
                 settled_timestamp: settled_timestamp_seconds,
+                uid: Some(invoice.uid as UserId),
             };
             let msg = Message::Nostr(msgs::nostr::Nostr::NostrZapNote(nostr_zap));
             listener(msg, ServiceIdentity::Nostr)
@@ -3729,8 +4351,124 @@ fn publish_if_zap_note<F: FnMut(Message, ServiceIdentity)>(
     }
 }
 
+/// BTC value of `money`, converted with the BTC rate of its currency unless it is already in BTC.
+fn btc_value(money: Money, btc_rate: Option<&Rate>) -> Option<Decimal> {
+    if money.currency() == Currency::BTC {
+        return Some(money.value());
+    }
+    match money.exchange(btc_rate?) {
+        Ok(value) if value.currency() == Currency::BTC => Some(value.value()),
+        _ => None,
+    }
+}
+
+/// Puts the sums of each kind of a day and currency together, rows have to be ordered by day and currency.
+fn daily_dealer_pnl(rows: Vec<dealer_pnl_entries::DailyDealerPnl>) -> Vec<DailyDealerPnl> {
+    let mut days: Vec<DailyDealerPnl> = vec![];
+    for row in rows {
+        let currency = match Currency::from_str(&row.currency) {
+            Ok(currency) => currency,
+            Err(_) => continue,
+        };
+        let amount = Decimal::from_str(&row.amount.to_string()).unwrap_or_default();
+        let day = match days.last_mut() {
+            Some(day) if day.day == row.day && day.currency == currency => day,
+            _ => {
+                days.push(DailyDealerPnl {
+                    day: row.day,
+                    currency,
+                    spread: Decimal::ZERO,
+                    hedge: Decimal::ZERO,
+                    funding: Decimal::ZERO,
+                    fees: Decimal::ZERO,
+                });
+                days.last_mut().expect("A day was just added")
+            }
+        };
+        match row.kind.as_str() {
+            dealer_pnl_entries::SPREAD => day.spread += amount,
+            dealer_pnl_entries::HEDGE => day.hedge += amount,
+            dealer_pnl_entries::FUNDING => day.funding += amount,
+            dealer_pnl_entries::FEE => day.fees += amount,
+            _ => {}
+        }
+    }
+    days
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn test_daily_dealer_pnl() {
+        let row = |day: &str, currency: &str, kind: &str, amount: &str| dealer_pnl_entries::DailyDealerPnl {
+            day: day.to_string(),
+            currency: currency.to_string(),
+            kind: kind.to_string(),
+            amount: BigDecimal::from_str(amount).unwrap(),
+        };
+        let days = daily_dealer_pnl(vec![
+            row("2023-06-20", "BTC", dealer_pnl_entries::FEE, "-0.0001"),
+            row("2023-06-20", "BTC", dealer_pnl_entries::HEDGE, "0.002"),
+            row("2023-06-20", "USD", dealer_pnl_entries::SPREAD, "1.5"),
+            row("2023-06-21", "BTC", dealer_pnl_entries::FUNDING, "0.00001"),
+        ]);
+        assert_eq!(days.len(), 3);
+        assert_eq!((days[0].day.as_str(), days[0].currency), ("2023-06-20", Currency::BTC));
+        assert_eq!(days[0].hedge, dec!(0.002));
+        assert_eq!(days[0].fees, dec!(-0.0001));
+        assert_eq!(days[0].total(), dec!(0.0019));
+        assert_eq!((days[1].currency, days[1].spread), (Currency::USD, dec!(1.5)));
+        assert_eq!((days[2].day.as_str(), days[2].funding), ("2023-06-21", dec!(0.00001)));
+    }
+
+    #[test]
+    fn test_btc_value_of_fiat_swap_fees() {
+        // Fees of a USD -> EUR swap are in EUR, the EUR/USD swap rate can't convert them to BTC.
+        let fees = Money::new(Currency::EUR, dec!(2.5));
+        let swap_rate = Rate::new(Currency::USD, Currency::EUR, dec!(0.92));
+        assert_eq!(btc_value(fees, Some(&swap_rate)), None);
+        let btc_eur = Rate::new(Currency::BTC, Currency::EUR, dec!(25000));
+        assert_eq!(btc_value(fees, Some(&btc_eur)), Some(dec!(0.0001)));
+        assert_eq!(btc_value(fees, None), None);
+        assert_eq!(btc_value(Money::from_btc(dec!(0.001)), None), Some(dec!(0.001)));
+    }
+
     #[tokio::test]
     async fn test_create_bank_manager() {}
+
+    #[test]
+    fn test_with_payer_note() {
+        let mut invoice = Invoice {
+            payment_hash: String::from("hash"),
+            payment_request: String::from("lnbc1"),
+            created_at: 0,
+            value: 1000,
+            value_msat: 1000000,
+            expiry: 86400,
+            settled: true,
+            add_index: 1,
+            settled_date: 0,
+            account_id: Uuid::nil().to_string(),
+            uid: 1,
+            incoming: true,
+            owner: Some(1),
+            fees: None,
+            currency: None,
+            target_account_currency: None,
+            reference: None,
+            description: None,
+            payer_comment: None,
+            payer_data: None,
+        };
+        assert_eq!(with_payer_note(String::from("Paid"), &invoice), "Paid");
+
+        invoice.payer_comment = Some(String::from("Thanks for the coffee"));
+        invoice.payer_data = Some(String::from("{\"identifier\":\"bob@example.com\"}"));
+        assert_eq!(
+            with_payer_note(String::from("Paid"), &invoice),
+            "Paid\nFrom: bob@example.com\n💬 Thanks for the coffee"
+        );
+    }
 }
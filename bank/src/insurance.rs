@@ -0,0 +1,79 @@
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How large the dealer's insurance fund should be and how much of it may be drawn at once, amounts in BTC.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InsuranceFundSettings {
+    /// Smallest target, whatever the fiat balances.
+    pub min_target: Decimal,
+    /// Target as a share of the BTC value of the users' fiat balances.
+    pub target_ratio: Decimal,
+    /// Share of the spread revenue of a swap moved into the fund while it is below target.
+    pub top_up_share: Decimal,
+    /// Largest share of the fund margin calls and losses may take in a UTC day.
+    pub max_daily_drawdown_share: Decimal,
+}
+
+impl InsuranceFundSettings {
+    pub fn target(&self, fiat_exposure: Decimal) -> Decimal {
+        self.min_target.max(fiat_exposure * self.target_ratio)
+    }
+
+    /// Part of a revenue that goes into the fund, never beyond the target.
+    pub fn top_up(&self, balance: Decimal, target: Decimal, revenue: Decimal) -> Decimal {
+        if revenue <= Decimal::ZERO {
+            return Decimal::ZERO;
+        }
+        let shortfall = (target - balance).max(Decimal::ZERO);
+        (revenue * self.top_up_share).min(shortfall)
+    }
+
+    /// Part of `amount` the fund may still cover, given its balance and what was drawn from it today.
+    pub fn drawdown(&self, balance: Decimal, drawn_today: Decimal, amount: Decimal) -> Decimal {
+        let allowance = (balance + drawn_today).max(Decimal::ZERO) * self.max_daily_drawdown_share;
+        let available = (allowance - drawn_today).min(balance).max(Decimal::ZERO);
+        amount.max(Decimal::ZERO).min(available)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn settings() -> InsuranceFundSettings {
+        InsuranceFundSettings {
+            min_target: dec!(0.1),
+            target_ratio: dec!(0.05),
+            top_up_share: dec!(0.5),
+            max_daily_drawdown_share: dec!(0.25),
+        }
+    }
+
+    #[test]
+    fn test_target() {
+        let settings = settings();
+        assert_eq!(settings.target(dec!(1)), dec!(0.1));
+        assert_eq!(settings.target(dec!(10)), dec!(0.5));
+    }
+
+    #[test]
+    fn test_top_up() {
+        let settings = settings();
+        assert_eq!(settings.top_up(dec!(0), dec!(0.1), dec!(0.01)), dec!(0.005));
+        assert_eq!(settings.top_up(dec!(0.098), dec!(0.1), dec!(0.01)), dec!(0.002));
+        assert_eq!(settings.top_up(dec!(0.2), dec!(0.1), dec!(0.01)), dec!(0));
+        assert_eq!(settings.top_up(dec!(0), dec!(0.1), dec!(-0.01)), dec!(0));
+    }
+
+    #[test]
+    fn test_drawdown() {
+        let settings = settings();
+        assert_eq!(settings.drawdown(dec!(1), dec!(0), dec!(0.1)), dec!(0.1));
+        assert_eq!(settings.drawdown(dec!(1), dec!(0), dec!(0.5)), dec!(0.25));
+        // a quarter of the 1 BTC the fund held at the start of the day, of which 0.2 were drawn
+        assert_eq!(settings.drawdown(dec!(0.8), dec!(0.2), dec!(0.5)), dec!(0.05));
+        assert_eq!(settings.drawdown(dec!(0.7), dec!(0.3), dec!(0.5)), dec!(0));
+        assert_eq!(settings.drawdown(dec!(-1), dec!(0), dec!(0.5)), dec!(0));
+    }
+}
@@ -77,7 +77,7 @@ impl Ledger {
     pub fn new(owner: UserId, dealer: UserId) -> Self {
         Self {
             user_accounts: HashMap::new(),
-            insurance_fund_account: Account::new(Currency::BTC, AccountType::Internal, AccountClass::Cash),
+            insurance_fund_account: Account::new(Currency::BTC, AccountType::Internal, AccountClass::Insurance),
             fee_account: UserAccount::new(owner),
             bank_liabilities: UserAccount::new(owner),
             dealer_accounts: UserAccount::new(dealer),
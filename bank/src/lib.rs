@@ -3,7 +3,9 @@ extern crate core;
 pub mod accountant;
 pub mod bank_engine;
 pub mod ledger;
+pub mod notifications;
 pub mod dca;
+pub mod insurance;
 
 use bank_engine::*;
 use futures::prelude::*;
@@ -188,6 +190,7 @@ pub async fn start(
 
     let mut state_insertion_interval = Instant::now();
     let mut reconciliation_interval = Instant::now();
+    let mut insurance_fund_interval = Instant::now();
 
     insert_bank_state(&bank_engine, &influx_client, &settings.influx_bucket.clone()).await;
 
@@ -297,5 +300,10 @@ pub async fn start(
                 panic!("Reconciliation error! Shutting down.");
             }
         }
+
+        if insurance_fund_interval.elapsed().as_secs() > 60 {
+            insurance_fund_interval = Instant::now();
+            bank_engine.cover_dealer_losses();
+        }
     }
 }
@@ -0,0 +1,117 @@
+use core_types::{Currency, Money};
+use models::notification_preferences::{NotificationEvent, DEFAULT_NOTIFICATION_LOCALE};
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+
+const BUILTIN_TEMPLATES: &str = include_str!("../notification_templates.json");
+const EMAIL_SUBJECT_KEY: &str = "email_subject";
+
+/// Notification texts by locale and event type, with `{name}` placeholders.
+#[derive(Debug, Clone)]
+pub struct TemplateStore {
+    templates: HashMap<String, HashMap<String, String>>,
+}
+
+impl TemplateStore {
+    pub fn new() -> Self {
+        Self {
+            templates: serde_json::from_str(BUILTIN_TEMPLATES).expect("Built-in notification templates are valid"),
+        }
+    }
+
+    /// Built-in templates overridden by the ones in the file, which may add locales of their own.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let overrides: HashMap<String, HashMap<String, String>> =
+            serde_json::from_str(&contents).map_err(|err| err.to_string())?;
+        let mut store = Self::new();
+        for (locale, templates) in overrides {
+            store.templates.entry(locale).or_default().extend(templates);
+        }
+        Ok(store)
+    }
+
+    /// Looks up the template in the locale, then in its language ("de" for "de-AT") and then in english.
+    fn template(&self, locale: &str, key: &str) -> Option<&String> {
+        let language = locale.split(['-', '_']).next().unwrap_or(locale);
+        [locale, language, DEFAULT_NOTIFICATION_LOCALE]
+            .iter()
+            .find_map(|l| self.templates.get(*l).and_then(|t| t.get(key)))
+    }
+
+    pub fn email_subject(&self, locale: &str) -> Option<String> {
+        self.template(locale, EMAIL_SUBJECT_KEY).cloned()
+    }
+
+    pub fn render(&self, locale: &str, event: NotificationEvent, args: &[(&str, String)]) -> Option<String> {
+        let mut text = self.template(locale, event.as_str())?.clone();
+        for (name, value) in args {
+            text = text.replace(&format!("{{{name}}}"), value);
+        }
+        Some(text)
+    }
+}
+
+impl Default for TemplateStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn decimal_separator(locale: &str) -> char {
+    match locale.split(['-', '_']).next().unwrap_or(locale) {
+        "de" | "es" | "fr" | "it" | "nl" | "pt" => ',',
+        _ => '.',
+    }
+}
+
+/// Renders an amount in its account currency, sats for bitcoin and two decimals for fiat.
+pub fn format_money(money: &Money, locale: &str) -> String {
+    match money.currency() {
+        Currency::BTC => {
+            let sats = money.try_sats().unwrap_or_default().round_dp(0);
+            format!("{sats} Sats")
+        }
+        currency => {
+            let value = money.value().round_dp_with_strategy(2, RoundingStrategy::ToZero);
+            let value = format!("{value:.2}").replace('.', &decimal_separator(locale).to_string());
+            format!("{value} {currency}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_render_falls_back_to_language_and_english() {
+        let store = TemplateStore::new();
+        let args = [("amount", String::from("21 Sats"))];
+
+        assert_eq!(
+            store.render("en", NotificationEvent::Deposit, &args).unwrap(),
+            "💸 You just got paid 21 Sats into your Kollider Wallet! 💰"
+        );
+        assert_eq!(
+            store.render("de-AT", NotificationEvent::Deposit, &args),
+            store.render("de", NotificationEvent::Deposit, &args)
+        );
+        assert_eq!(
+            store.render("xx", NotificationEvent::Deposit, &args),
+            store.render("en", NotificationEvent::Deposit, &args)
+        );
+        for event in NotificationEvent::ALL {
+            assert!(store.render("de", event, &[]).is_some());
+        }
+        assert_eq!(store.email_subject("es-MX").unwrap(), "Notificación de Kollider Wallet");
+    }
+
+    #[test]
+    fn test_format_money() {
+        assert_eq!(format_money(&Money::from_sats(dec!(2100)), "de"), "2100 Sats");
+        assert_eq!(format_money(&Money::new(Currency::USD, dec!(12.5)), "en"), "12.50 USD");
+        assert_eq!(format_money(&Money::new(Currency::EUR, dec!(3.999)), "de"), "3,99 EUR");
+    }
+}
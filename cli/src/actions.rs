@@ -1,7 +1,7 @@
 use core_types::{Currency, UserId};
-use msgs::cli::{Cli, MakeTx};
+use msgs::cli::{Cli, DealerPnlRequest, InsuranceFundRequest, MakeTx};
 use msgs::dealer::{BankStateRequest, CreateInvoiceRequest, Dealer};
-use msgs::nostr::{Nostr, NostrProfilesRefetchRequest};
+use msgs::nostr::{Nostr, NostrProfilesRefetchRequest, NostrRelayAction, NostrRelayUpdate};
 use msgs::Message;
 use rust_decimal::Decimal;
 use structopt::StructOpt;
@@ -38,6 +38,26 @@ pub enum Action {
         #[structopt(short = "l", long = "limit")]
         limit: Option<usize>,
     },
+    AddNostrRelay {
+        #[structopt(long = "url")]
+        url: String,
+    },
+    RemoveNostrRelay {
+        #[structopt(long = "url")]
+        url: String,
+    },
+    /// Dealer pnl per day and currency.
+    DealerPnl {
+        #[structopt(short = "d", long = "days", default_value = "7")]
+        days: u32,
+        #[structopt(short = "c", long = "currency")]
+        currency: Option<Currency>,
+    },
+    /// Insurance fund balance, target and movements.
+    InsuranceFund {
+        #[structopt(short = "d", long = "days", default_value = "7")]
+        days: u32,
+    },
 }
 
 impl Action {
@@ -79,6 +99,18 @@ impl Action {
                 until_epoch_ms,
                 limit,
             })),
+            Self::AddNostrRelay { url } => Message::Nostr(Nostr::NostrRelayUpdate(NostrRelayUpdate {
+                url,
+                action: NostrRelayAction::Add,
+            })),
+            Self::RemoveNostrRelay { url } => Message::Nostr(Nostr::NostrRelayUpdate(NostrRelayUpdate {
+                url,
+                action: NostrRelayAction::Remove,
+            })),
+            Self::DealerPnl { days, currency } => {
+                Message::Cli(Cli::DealerPnlRequest(DealerPnlRequest { days, currency }))
+            }
+            Self::InsuranceFund { days } => Message::Cli(Cli::InsuranceFundRequest(InsuranceFundRequest { days })),
         }
     }
 }
@@ -1,6 +1,7 @@
 use crate::actions::Action;
+use msgs::cli::{Cli as CliMsg, DealerPnlReport, InsuranceFundReport};
 use msgs::nostr::Nostr;
-use msgs::{cli::Cli as CliMsg, dealer::Dealer, Message};
+use msgs::{dealer::Dealer, Message};
 use serde::{Deserialize, Serialize};
 use structopt::StructOpt;
 use utils::xzmq::ZmqSocket;
@@ -44,9 +45,14 @@ impl ResponseHandler {
                     Message::Cli(CliMsg::MakeTxResult(tx_result)) => {
                         println!("Received transaction result: {tx_result:?}");
                     }
+                    Message::Cli(CliMsg::DealerPnlReport(report)) => print_dealer_pnl(report),
+                    Message::Cli(CliMsg::InsuranceFundReport(report)) => print_insurance_fund(report),
                     Message::Nostr(Nostr::NostrProfilesRefetchRequest(delivery_receipt)) => {
                         println!("Received delivery receipt: {delivery_receipt:?}");
                     }
+                    Message::Nostr(Nostr::NostrRelayUpdate(delivery_receipt)) => {
+                        println!("Received delivery receipt: {delivery_receipt:?}");
+                    }
                     _ => {
                         println!("Received unhandled message: {msg:?}")
                     }
@@ -61,3 +67,52 @@ impl ResponseHandler {
         }
     }
 }
+
+fn print_dealer_pnl(report: DealerPnlReport) {
+    if let Some(error) = report.error {
+        eprintln!("Failed to get the dealer pnl: {error}");
+        return;
+    }
+    println!(
+        "{:<10} {:<8} {:>16} {:>16} {:>16} {:>16} {:>16}",
+        "day", "currency", "spread", "hedge", "funding", "fees", "total"
+    );
+    for day in report.days {
+        println!(
+            "{:<10} {:<8} {:>16} {:>16} {:>16} {:>16} {:>16}",
+            day.day,
+            day.currency.to_string(),
+            day.spread,
+            day.hedge,
+            day.funding,
+            day.fees,
+            day.total()
+        );
+    }
+}
+
+fn print_insurance_fund(report: InsuranceFundReport) {
+    println!("balance: {} BTC", report.balance);
+    match report.target {
+        Some(target) => println!("target: {target} BTC"),
+        None => println!("target: unknown"),
+    }
+    if let Some(error) = report.error {
+        eprintln!("Failed to get the insurance fund movements: {error}");
+        return;
+    }
+    println!(
+        "{:<14} {:<12} {:>16} {:>16} {}",
+        "created_at", "kind", "amount", "balance", "reference"
+    );
+    for movement in report.movements {
+        println!(
+            "{:<14} {:<12} {:>16} {:>16} {}",
+            movement.created_at,
+            movement.kind,
+            movement.amount,
+            movement.balance,
+            movement.reference.unwrap_or_default()
+        );
+    }
+}
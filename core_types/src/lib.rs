@@ -59,6 +59,7 @@ impl FromStr for AccountType {
 pub enum AccountClass {
     Cash,
     Fees,
+    Insurance,
 }
 
 impl fmt::Display for AccountClass {
@@ -66,6 +67,7 @@ impl fmt::Display for AccountClass {
         let sign = match self {
             Self::Cash => "Cash",
             Self::Fees => "Fee",
+            Self::Insurance => "Insurance",
         };
 
         write!(f, "{sign}")
@@ -79,6 +81,7 @@ impl FromStr for AccountClass {
         match account_type {
             "Cash" => Ok(AccountClass::Cash),
             "Fees" => Ok(AccountClass::Fees),
+            "Insurance" => Ok(AccountClass::Insurance),
             _ => Err("unknown account class".to_string()),
         }
     }
@@ -53,3 +53,6 @@ impl NostrProfile {
         self.nip05_verified = verified;
     }
 }
+
+/// Nostr Wallet Connect (NIP-47) methods supported by the nostr engine.
+pub const NWC_METHODS: [&str; 4] = ["pay_invoice", "make_invoice", "get_balance", "lookup_invoice"];
@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, HashMap};
+
+use core_types::Symbol;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use crossbeam::channel::Receiver;
+use dealer::dealer_engine::{DealerEngine, DealerEngineSettings};
+use dealer::execution::ExecutionSettings;
+use dealer::market_data::MarketDataSettings;
+use dealer::quotes::QuoteSettings;
+use msgs::kollider_client::{KolliderApiResponse, Level2State, TradableSymbol};
+use msgs::Message;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use simulated_exchange::{SimulatedExchange, SimulatedExchangeSettings};
+use utils::xlogging::LoggingSettings;
+
+const BOOK_DEPTH: u64 = 100;
+
+fn btcusd() -> Symbol {
+    Symbol::from("BTCUSD.PERP")
+}
+
+fn settings(quote_ladders: HashMap<String, Vec<u64>>) -> DealerEngineSettings {
+    DealerEngineSettings {
+        psql_url: String::new(),
+        dealer_bank_pull_address: String::new(),
+        dealer_bank_push_address: String::new(),
+        kollider_api_key: String::new(),
+        kollider_api_secret: String::new(),
+        kollider_api_passphrase: String::new(),
+        risk_tolerances: HashMap::new(),
+        kollider_ws_url: String::new(),
+        logging_settings: LoggingSettings {
+            stdout: false,
+            level: String::from("error"),
+            log_path: None,
+            name: String::new(),
+            slack_hook: String::new(),
+            slack_channel: String::new(),
+        },
+        influx_host: None,
+        influx_org: String::new(),
+        influx_bucket: String::new(),
+        influx_token: String::new(),
+        position_min_leverage: dec!(0.9999),
+        position_max_leverage: dec!(1.0001),
+        leverage_check_interval_ms: 1000,
+        spread: dec!(0.01),
+        dynamic_spread: None,
+        max_single_order_quantities: HashMap::new(),
+        execution: ExecutionSettings::default(),
+        quote_ladders,
+        market_data: MarketDataSettings::default(),
+        quotes: QuoteSettings::default(),
+        paper_trading: None,
+    }
+}
+
+/// Dealer quoting BTCUSD.PERP from a book of `BOOK_DEPTH` levels per side. The exchange only provides
+/// tradable symbols, its messages are never processed but the receiver has to outlive the dealer.
+fn dealer_engine(quote_ladders: HashMap<String, Vec<u64>>) -> (DealerEngine, Receiver<Message>) {
+    let (tx, rx) = crossbeam::channel::unbounded();
+    let exchange = SimulatedExchange::new(
+        SimulatedExchangeSettings {
+            initial_balance: dec!(100000000),
+            taker_fee: Decimal::ZERO,
+            slippage: Decimal::ZERO,
+            leverage: Decimal::ONE,
+        },
+        tx,
+    );
+    let contract = TradableSymbol {
+        symbol: btcusd(),
+        contract_size: dec!(1.0),
+        max_leverage: dec!(20.0),
+        base_margin: dec!(0.01),
+        liquidation_fee: dec!(0.004),
+        is_inverse_priced: true,
+        price_dp: 0,
+        underlying_symbol: Symbol::from(".BTCUSD"),
+        last_price: dec!(20000),
+        tick_size: dec!(1.0),
+        risk_limit: dec!(150000000.0),
+    };
+    exchange.set_tradable_symbols([(btcusd(), contract)].into_iter().collect());
+    let mut dealer_engine = DealerEngine::new(settings(quote_ladders), exchange);
+    dealer_engine.process_msg(level2_message(snapshot()), &mut |_msg| {});
+    (dealer_engine, rx)
+}
+
+fn snapshot() -> Level2State {
+    let bids: BTreeMap<Decimal, u64> = (1..=BOOK_DEPTH)
+        .map(|level| (Decimal::from(20000 - level), 1000 * level))
+        .collect();
+    let asks: BTreeMap<Decimal, u64> = (1..=BOOK_DEPTH)
+        .map(|level| (Decimal::from(20000 + level), 1000 * level))
+        .collect();
+    Level2State {
+        update_type: String::from("snapshot"),
+        seq_number: 0,
+        symbol: btcusd(),
+        bids,
+        asks,
+    }
+}
+
+/// Changes the volume of the best bid, the book stays the same when applied repeatedly.
+fn delta() -> Level2State {
+    Level2State {
+        update_type: String::from("delta"),
+        seq_number: 1,
+        symbol: btcusd(),
+        bids: [(dec!(19999), 500)].into_iter().collect(),
+        asks: BTreeMap::new(),
+    }
+}
+
+fn level2_message(level2_state: Level2State) -> Message {
+    Message::KolliderApiResponse(KolliderApiResponse::Level2State(level2_state))
+}
+
+fn update_quotes(c: &mut Criterion) {
+    let long_ladder = (1..=50).map(|step| step * 2_000).collect::<Vec<u64>>();
+    let ladders = [
+        ("default_ladder", HashMap::new()),
+        (
+            "long_ladder",
+            [(String::from("BTCUSD.PERP"), long_ladder)].into_iter().collect(),
+        ),
+    ];
+    for (name, quote_ladders) in ladders {
+        let (mut dealer_engine, _rx) = dealer_engine(quote_ladders);
+        let mut group = c.benchmark_group(name);
+        for (update, level2_state) in [("snapshot", snapshot()), ("delta", delta())] {
+            group.bench_function(update, |b| {
+                b.iter_batched(
+                    || level2_message(level2_state.clone()),
+                    |msg| dealer_engine.process_msg(msg, &mut |_msg| {}),
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, update_quotes);
+criterion_main!(benches);
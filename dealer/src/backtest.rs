@@ -0,0 +1,487 @@
+use crate::dealer_engine::{DealerEngine, DealerEngineSettings};
+use core_types::kollider_client::MarkPrice;
+use core_types::{Account, AccountClass, AccountType, Currency, Money, Symbol, UserId};
+use crossbeam::channel::{unbounded, Receiver};
+use msgs::api::{Api, SwapRequest};
+use msgs::dealer::{BankState, Dealer, FiatDepositRequest};
+use msgs::kollider_client::{KolliderApiResponse, Level2State, TradableSymbols};
+use msgs::Message;
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use simulated_exchange::{SimulatedExchange, SimulatedExchangeSettings};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+use utils::currencies::get_base_currency_from_symbol;
+use uuid::Uuid;
+use ws_client::WsClient;
+
+/// Risk is checked on the replay clock as often as the live dealer does.
+const RISK_CHECK_INTERVAL_MS: u64 = 10_000;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    TradableSymbols(TradableSymbols),
+    Level2State(Level2State),
+    MarkPrice(MarkPrice),
+    /// Funding settlement of a symbol, positive rates are paid by longs.
+    Funding {
+        symbol: Symbol,
+        rate: Decimal,
+    },
+    /// Swap at the dealer's current rate, the way swaps without a quote are done.
+    Swap {
+        uid: UserId,
+        amount: Money,
+        from: Currency,
+        to: Currency,
+    },
+    /// Bitcoin paid into a fiat account.
+    Deposit {
+        uid: UserId,
+        amount: Money,
+        currency: Currency,
+    },
+}
+
+/// A line of a recording, the timestamp is in milliseconds.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordedEvent {
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct CurrencyReport {
+    /// Fees charged on swaps and deposits paid out in the currency.
+    pub spread_revenue: Decimal,
+    /// Cost in sats of hedge orders filling away from the mid price.
+    pub hedge_slippage: Decimal,
+    /// Taker fees in sats paid on hedge orders.
+    pub hedge_fees: Decimal,
+    /// Funding in sats received on the hedge position.
+    pub funding_pnl: Decimal,
+    /// Time spent with the unhedged exposure beyond the risk tolerance.
+    pub time_unhedged_ms: u64,
+    /// Largest unhedged exposure in units of the currency.
+    pub worst_exposure: Decimal,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct BacktestReport {
+    pub swaps: u64,
+    pub failed_swaps: u64,
+    pub deposits: u64,
+    pub failed_deposits: u64,
+    pub currencies: HashMap<Currency, CurrencyReport>,
+}
+
+impl fmt::Display for BacktestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "swaps: {} ({} failed), deposits: {} ({} failed)",
+            self.swaps, self.failed_swaps, self.deposits, self.failed_deposits
+        )?;
+        writeln!(
+            f,
+            "{:<8} {:>16} {:>16} {:>16} {:>16} {:>16} {:>16}",
+            "currency",
+            "spread_revenue",
+            "hedge_slippage",
+            "hedge_fees",
+            "funding_pnl",
+            "unhedged_secs",
+            "worst_exposure"
+        )?;
+        let mut currencies = self.currencies.iter().collect::<Vec<_>>();
+        currencies.sort_by_key(|(currency, _)| currency.to_string());
+        for (currency, report) in currencies {
+            writeln!(
+                f,
+                "{:<8} {:>16} {:>16} {:>16} {:>16} {:>16} {:>16}",
+                currency.to_string(),
+                report.spread_revenue.round_dp(8),
+                report.hedge_slippage.round_dp(0),
+                report.hedge_fees.round_dp(0),
+                report.funding_pnl.round_dp(0),
+                report.time_unhedged_ms / 1000,
+                report.worst_exposure.round_dp(2),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Replays a recording through the dealer, standing in for the bank and hedging on a simulated exchange.
+///
+/// Swaps and deposits move the dealer accounts the way the bank books them and each is followed by
+/// a bank state, market data goes through the exchange so the dealer sees it as it would live.
+pub struct Backtest {
+    dealer: DealerEngine,
+    exchange: SimulatedExchange,
+    exchange_rx: Receiver<Message>,
+    clock: Rc<Cell<u64>>,
+    dealer_accounts: HashMap<Currency, Account>,
+    insurance_fund_account: Account,
+    risk_tolerances: HashMap<Currency, u64>,
+    unhedged: HashSet<Currency>,
+    last_timestamp: Option<u64>,
+    last_risk_check: u64,
+    report: BacktestReport,
+}
+
+impl Backtest {
+    pub fn new(settings: DealerEngineSettings, exchange_settings: SimulatedExchangeSettings) -> Self {
+        let (exchange_tx, exchange_rx) = unbounded();
+        let exchange = SimulatedExchange::new(exchange_settings, exchange_tx);
+        let risk_tolerances = settings
+            .risk_tolerances
+            .iter()
+            .filter_map(|(currency, tolerance)| Some((Currency::from_str(currency).ok()?, *tolerance)))
+            .collect();
+        let mut dealer = DealerEngine::new(settings, exchange.clone());
+        let clock = Rc::new(Cell::new(0));
+        let dealer_clock = clock.clone();
+        dealer.set_clock(move || dealer_clock.get());
+        let mut backtest = Self {
+            dealer,
+            exchange,
+            exchange_rx,
+            clock,
+            dealer_accounts: HashMap::new(),
+            insurance_fund_account: Account::new(Currency::BTC, AccountType::Internal, AccountClass::Cash),
+            risk_tolerances,
+            unhedged: HashSet::new(),
+            last_timestamp: None,
+            last_risk_check: 0,
+            report: BacktestReport::default(),
+        };
+        backtest.process_exchange_messages();
+        backtest
+    }
+
+    pub fn report(&self) -> &BacktestReport {
+        &self.report
+    }
+
+    /// Events have to be passed in the order they were recorded.
+    pub fn process_event(&mut self, recorded_event: RecordedEvent) {
+        let timestamp = recorded_event.timestamp;
+        if let Some(last_timestamp) = self.last_timestamp {
+            let elapsed = timestamp.saturating_sub(last_timestamp);
+            for currency in self.unhedged.iter() {
+                self.report.currencies.entry(*currency).or_default().time_unhedged_ms += elapsed;
+            }
+        }
+        self.last_timestamp = Some(timestamp);
+        self.clock.set(timestamp);
+
+        match recorded_event.event {
+            Event::TradableSymbols(tradable_symbols) => self.exchange.set_tradable_symbols(tradable_symbols.symbols),
+            Event::Level2State(level2_state) => self.exchange.process_level2_state(level2_state),
+            Event::MarkPrice(mark_price) => self.exchange.process_mark_price(mark_price),
+            Event::Funding { symbol, rate } => self.exchange.apply_funding(&symbol, rate),
+            Event::Swap { uid, amount, from, to } => self.swap(uid, amount, from, to),
+            Event::Deposit { uid, amount, currency } => self.deposit(uid, amount, currency),
+        }
+        self.dealer.execute_hedges();
+        self.process_exchange_messages();
+
+        if timestamp.saturating_sub(self.last_risk_check) > RISK_CHECK_INTERVAL_MS && self.dealer.has_bank_state() {
+            self.dealer.check_risk(&mut |_msg| {});
+            self.last_risk_check = timestamp;
+            self.process_exchange_messages();
+        }
+
+        self.update_exposures();
+    }
+
+    fn swap(&mut self, uid: UserId, amount: Money, from: Currency, to: Currency) {
+        let swap_request = SwapRequest {
+            req_id: Uuid::new_v4(),
+            uid,
+            amount,
+            from,
+            to,
+            quote_id: None,
+        };
+        let mut responses = vec![];
+        self.dealer
+            .process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
+                responses.push(msg)
+            });
+
+        self.report.swaps += 1;
+        let swap_response = responses.into_iter().find_map(|msg| match msg {
+            Message::Api(Api::SwapResponse(swap_response)) => Some(swap_response),
+            _ => None,
+        });
+        let (rate, fees) = match swap_response {
+            Some(swap_response) if swap_response.success && swap_response.error.is_none() => match swap_response.rate {
+                Some(rate) => (rate, swap_response.fees),
+                None => {
+                    self.report.failed_swaps += 1;
+                    return;
+                }
+            },
+            _ => {
+                self.report.failed_swaps += 1;
+                return;
+            }
+        };
+        let inbound_amount = match amount.exchange(&rate) {
+            Ok(converted) => converted,
+            Err(_) => {
+                self.report.failed_swaps += 1;
+                return;
+            }
+        };
+
+        self.dealer_account(from).balance += amount.value();
+        self.dealer_account(to).balance -= inbound_amount.value();
+        self.book_fees(fees);
+        self.send_bank_state();
+    }
+
+    fn deposit(&mut self, uid: UserId, amount: Money, currency: Currency) {
+        let fiat_deposit_request = FiatDepositRequest {
+            req_id: Uuid::new_v4(),
+            amount,
+            currency,
+            payment_request: String::new(),
+            uid,
+        };
+        let mut responses = vec![];
+        self.dealer.process_msg(
+            Message::Dealer(Dealer::FiatDepositRequest(fiat_deposit_request)),
+            &mut |msg| responses.push(msg),
+        );
+
+        self.report.deposits += 1;
+        let fiat_deposit_response = responses.into_iter().find_map(|msg| match msg {
+            Message::Dealer(Dealer::FiatDepositResponse(fiat_deposit_response)) => Some(fiat_deposit_response),
+            _ => None,
+        });
+        let (fiat_amount, fees) = match fiat_deposit_response {
+            Some(fiat_deposit_response) if fiat_deposit_response.error.is_none() => {
+                match fiat_deposit_response.rate.map(|rate| amount.exchange(&rate)) {
+                    Some(Ok(fiat_amount)) => (fiat_amount, fiat_deposit_response.fees),
+                    _ => {
+                        self.report.failed_deposits += 1;
+                        return;
+                    }
+                }
+            }
+            _ => {
+                self.report.failed_deposits += 1;
+                return;
+            }
+        };
+
+        self.dealer_account(Currency::BTC).balance += amount.value();
+        self.dealer_account(currency).balance -= fiat_amount.value();
+        self.book_fees(fees);
+        self.send_bank_state();
+    }
+
+    fn dealer_account(&mut self, currency: Currency) -> &mut Account {
+        self.dealer_accounts
+            .entry(currency)
+            .or_insert_with(|| Account::new(currency, AccountType::Internal, AccountClass::Cash))
+    }
+
+    fn book_fees(&mut self, fees: Option<Money>) {
+        if let Some(fees) = fees {
+            self.report
+                .currencies
+                .entry(fees.currency())
+                .or_default()
+                .spread_revenue += fees.value();
+        }
+    }
+
+    /// The bank sends its state to the dealer after every swap and deposit.
+    fn send_bank_state(&mut self) {
+        let bank_state = BankState {
+            total_exposures: HashMap::new(),
+            insurance_fund_account: self.insurance_fund_account.clone(),
+            fiat_exposures: self
+                .dealer_accounts
+                .values()
+                .map(|account| (account.account_id, account.clone()))
+                .collect(),
+        };
+        self.dealer
+            .process_msg(Message::Dealer(Dealer::BankState(bank_state)), &mut |_msg| {});
+        self.process_exchange_messages();
+    }
+
+    fn process_exchange_messages(&mut self) {
+        while let Ok(msg) = self.exchange_rx.try_recv() {
+            if let Message::KolliderApiResponse(KolliderApiResponse::FundingPayment(ref funding_payment)) = msg {
+                if let Ok(currency) = get_base_currency_from_symbol(funding_payment.symbol.clone()) {
+                    self.report.currencies.entry(currency).or_default().funding_pnl += funding_payment.amount;
+                }
+            }
+            self.dealer.process_msg(msg, &mut |_msg| {});
+        }
+
+        let tradable_symbols = self.exchange.get_tradable_symbols();
+        for fill in self.exchange.take_fills() {
+            let currency = match get_base_currency_from_symbol(fill.symbol.clone()) {
+                Ok(currency) => currency,
+                Err(_) => continue,
+            };
+            let contract_size = tradable_symbols
+                .get(&fill.symbol)
+                .map(|contract| contract.contract_size)
+                .unwrap_or(Decimal::ONE);
+            let currency_report = self.report.currencies.entry(currency).or_default();
+            currency_report.hedge_slippage += fill.slippage(contract_size);
+            currency_report.hedge_fees += fill.fee;
+        }
+    }
+
+    fn update_exposures(&mut self) {
+        for (currency, account) in self.dealer_accounts.iter() {
+            if matches!(currency, Currency::BTC | Currency::KKP) {
+                continue;
+            }
+            let hedged_quantity = match self.exchange.get_position_state(&Symbol::from(*currency)) {
+                Ok(Some(position_state)) => match position_state.side {
+                    Some(side) => Decimal::from(side.to_sign()) * position_state.quantity,
+                    None => Decimal::ZERO,
+                },
+                _ => Decimal::ZERO,
+            };
+            let unhedged = (account.balance - hedged_quantity).abs();
+
+            let currency_report = self.report.currencies.entry(*currency).or_default();
+            currency_report.worst_exposure = currency_report.worst_exposure.max(unhedged);
+
+            // Less than a contract can't be hedged.
+            let tolerance = self.risk_tolerances.get(currency).cloned().unwrap_or_default().max(1);
+            if unhedged >= Decimal::from(tolerance) {
+                self.unhedged.insert(*currency);
+            } else {
+                self.unhedged.remove(currency);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dealer_engine::tests::dealer_engine_settings;
+    use msgs::kollider_client::TradableSymbol;
+    use rust_decimal_macros::dec;
+
+    fn btcusd() -> Symbol {
+        Symbol::from("BTCUSD.PERP")
+    }
+
+    fn tradable_symbols() -> TradableSymbols {
+        let contract = TradableSymbol {
+            symbol: btcusd(),
+            contract_size: dec!(1.0),
+            max_leverage: dec!(20.0),
+            base_margin: dec!(0.01),
+            liquidation_fee: dec!(0.004),
+            is_inverse_priced: true,
+            price_dp: 0,
+            underlying_symbol: Symbol::from(".BTCUSD"),
+            last_price: dec!(19950),
+            tick_size: dec!(1.0),
+            risk_limit: dec!(150000000.0),
+        };
+        TradableSymbols {
+            symbols: [(btcusd(), contract)].into_iter().collect(),
+        }
+    }
+
+    fn snapshot(bids: &[(Decimal, u64)]) -> Event {
+        Event::Level2State(Level2State {
+            update_type: String::from("snapshot"),
+            seq_number: 0,
+            symbol: btcusd(),
+            bids: bids.iter().cloned().collect(),
+            asks: [(dec!(20000), 10000)].into_iter().collect(),
+        })
+    }
+
+    fn swap(value: Decimal) -> Event {
+        Event::Swap {
+            uid: 1003,
+            amount: Money::new(Currency::BTC, value),
+            from: Currency::BTC,
+            to: Currency::USD,
+        }
+    }
+
+    #[test]
+    fn test_recorded_event_from_json() {
+        let line = r#"{"timestamp": 1000, "type": "swap", "uid": 1, "amount": {"value": "0.001", "currency": "BTC"}, "from": "BTC", "to": "USD"}"#;
+        let recorded_event = serde_json::from_str::<RecordedEvent>(line).unwrap();
+        assert_eq!(recorded_event.timestamp, 1000);
+        assert!(matches!(
+            recorded_event.event,
+            Event::Swap {
+                uid: 1,
+                to: Currency::USD,
+                ..
+            }
+        ));
+
+        let line = r#"{"timestamp": 2000, "type": "level2_state", "update_type": "delta", "seq_number": 1, "symbol": "BTCUSD.PERP", "bids": {"19900": 0}, "asks": {}}"#;
+        match serde_json::from_str::<RecordedEvent>(line).unwrap().event {
+            Event::Level2State(level2_state) => assert_eq!(level2_state.bids[&dec!(19900)], 0),
+            other => panic!("Expected a level2 state, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_swaps_are_hedged_and_reported() {
+        let mut settings = dealer_engine_settings();
+        settings.risk_tolerances = [(String::from("USD"), 10)].into_iter().collect();
+        let exchange_settings = SimulatedExchangeSettings {
+            initial_balance: dec!(100000000),
+            taker_fee: Decimal::ZERO,
+            slippage: Decimal::ZERO,
+            leverage: Decimal::ONE,
+        };
+        let mut backtest = Backtest::new(settings, exchange_settings);
+
+        let events = vec![
+            (0, Event::TradableSymbols(tradable_symbols())),
+            (0, snapshot(&[(dec!(19900), 1000)])),
+            // 0.05 BTC for 990.025 USD at 19900 minus the half spread, hedged right away
+            (1000, swap(dec!(0.05))),
+            // the first hedge left 10 contracts on the simulated book, the rest of the next hedge
+            // waits until the book recovers and risk is checked again
+            (3000, swap(dec!(0.05))),
+            (33000, snapshot(&[(dec!(19900), 10000)])),
+        ];
+        for (timestamp, event) in events {
+            backtest.process_event(RecordedEvent { timestamp, event });
+        }
+
+        let position = backtest.exchange.get_position_state(&btcusd()).unwrap().unwrap();
+        assert_eq!(position.quantity, dec!(1980));
+
+        let report = backtest.report();
+        assert_eq!(report.swaps, 2);
+        assert_eq!(report.failed_swaps, 0);
+        let usd_report = &report.currencies[&Currency::USD];
+        assert_eq!(usd_report.spread_revenue, dec!(9.95));
+        assert_eq!(usd_report.time_unhedged_ms, 30000);
+        assert_eq!(usd_report.worst_exposure, dec!(980.05));
+        // the hedges sold 1980 contracts at 19900 against a 19950 mid
+        assert_eq!(usd_report.hedge_slippage.round(), dec!(24937));
+        assert_eq!(usd_report.hedge_fees, Decimal::ZERO);
+    }
+}
@@ -0,0 +1,40 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use dealer::backtest::{Backtest, RecordedEvent};
+use dealer::dealer_engine::DealerEngineSettings;
+
+fn main() {
+    let recording_path = std::env::args().nth(1).expect("Usage: backtest <recording.jsonl>");
+
+    let settings = utils::config::get_config_from_env::<DealerEngineSettings>().expect("Failed to load settings.");
+    let exchange_settings = settings
+        .paper_trading
+        .clone()
+        .expect("The [paper_trading] table configures the exchange hedges are backtested on.");
+
+    let recording = match File::open(&recording_path) {
+        Ok(file) => BufReader::new(file),
+        Err(err) => {
+            eprintln!("Failed to open {recording_path}, reason: {err:?}");
+            return;
+        }
+    };
+
+    let mut backtest = Backtest::new(settings, exchange_settings);
+    for (index, line) in recording.lines().enumerate() {
+        let line = line.expect("Failed to read the recording");
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<RecordedEvent>(&line) {
+            Ok(recorded_event) => backtest.process_event(recorded_event),
+            Err(err) => {
+                eprintln!("Failed to parse event on line {}, reason: {err}", index + 1);
+                return;
+            }
+        }
+    }
+
+    print!("{}", backtest.report());
+}
@@ -3,29 +3,38 @@ use std::cmp::Ordering;
 
 use msgs::api::{
     Api, AvailableCurrenciesResponse, InvoiceResponse, InvoiceResponseError, QuoteResponse, QuoteResponseError,
-    SwapRequest, SwapResponse, SwapResponseError,
+    SpreadComponents, SwapResponse, SwapResponseError,
 };
 use msgs::dealer::*;
 use msgs::kollider_client::*;
 use msgs::Message;
+use simulated_exchange::SimulatedExchangeSettings;
 use std::collections::{BTreeMap, HashMap, HashSet};
-use std::ops::{Add, Sub};
+use std::ops::Sub;
 use ws_client::WsClient;
 use xerror::dealer::*;
 
+use crate::execution::{ExecutionSettings, HedgeExecutor};
+use crate::market_data::{MarketDataMonitor, MarketDataSettings};
+use crate::orders::OrderTracker;
+use crate::pnl::PnlRecorder;
+use crate::quotes::{QuoteSettings, QuoteSigner};
+use crate::spread::{inventory_usage, size_magnitude, DynamicSpreadSettings, VolatilityTracker};
+
 use core_types::{kollider_client::*, *};
 
 use rust_decimal::prelude::*;
 use rust_decimal_macros::*;
 
-use std::time::{Duration, Instant, SystemTime};
+use std::time::{Duration, Instant};
 use utils::currencies::get_base_currency_from_symbol;
 use utils::time::time_now;
 use utils::xlogging::{init_log, LoggingSettings};
 use uuid::Uuid;
 use xerror::kollider_client::KolliderClientError;
 
-const QUOTE_TTL_MS: u64 = 5000;
+/// Quote sizes in contracts for symbols without a configured ladder.
+const DEFAULT_QUOTE_LADDER: [u64; 9] = [10, 100, 1_000, 2_000, 3_000, 5_000, 10_000, 100_000, 1_000_000];
 
 pub struct HedgeSettings {
     // The amount of unhedged value to tolerate before a an adjustment.
@@ -62,7 +71,26 @@ pub struct DealerEngineSettings {
     pub leverage_check_interval_ms: u64,
 
     pub spread: Decimal,
+    /// Widens `spread` with volatility, unhedged delta and quote size when set.
+    #[serde(default)]
+    pub dynamic_spread: Option<DynamicSpreadSettings>,
     pub max_single_order_quantities: HashMap<String, u64>,
+    /// How hedges are placed, a single market order per risk check unless configured.
+    #[serde(default)]
+    pub execution: ExecutionSettings,
+    /// Quote sizes in contracts per symbol, rates for sizes in between are interpolated.
+    #[serde(default)]
+    pub quote_ladders: HashMap<String, Vec<u64>>,
+    /// When quotes and swaps are suspended for stale, crossed or off-index market data.
+    #[serde(default)]
+    pub market_data: MarketDataSettings,
+    /// How long quotes are guaranteed for and the key their ids are signed with.
+    #[serde(default)]
+    pub quotes: QuoteSettings,
+
+    /// Hedges on a simulated exchange fed with Kollider market data instead of trading on Kollider.
+    #[serde(default)]
+    pub paper_trading: Option<SimulatedExchangeSettings>,
 }
 
 pub struct DealerEngine {
@@ -72,8 +100,7 @@ pub struct DealerEngine {
     bid_quotes: HashMap<Symbol, BTreeMap<u64, Decimal>>,
     ask_quotes: HashMap<Symbol, BTreeMap<u64, Decimal>>,
     risk_tolerances: HashMap<Currency, u64>,
-    // timestamp in microseconds is used as quote id
-    guaranteed_quotes: BTreeMap<u128, QuoteResponse>,
+    quotes: QuoteSigner,
     has_received_init_data: bool,
     has_received_symbols: bool,
     has_received_positions: bool,
@@ -87,16 +114,21 @@ pub struct DealerEngine {
     leverage_check_interval_ms: u64,
     last_leverage_check_timestamp: Instant,
     spread: Decimal,
+    dynamic_spread: Option<DynamicSpreadSettings>,
+    spread_floors: HashMap<Currency, Decimal>,
+    spread_caps: HashMap<Currency, Decimal>,
+    volatilities: HashMap<Symbol, VolatilityTracker>,
     funding_profit: Decimal,
-    max_single_order_quantities: HashMap<String, u64>,
+    quote_ladders: HashMap<String, Vec<u64>>,
+    execution: HedgeExecutor,
+    orders: OrderTracker,
+    pnl: PnlRecorder,
+    market_data: MarketDataMonitor,
+    clock: Box<dyn Fn() -> u64>,
 }
 
 impl DealerEngine {
-    pub fn new(
-        settings: DealerEngineSettings,
-        ws_client: impl WsClient + 'static,
-        initial_funding_pnl: Decimal,
-    ) -> Self {
+    pub fn new(settings: DealerEngineSettings, ws_client: impl WsClient + 'static) -> Self {
         let mut settings = settings;
 
         let risk_tolerances = settings
@@ -118,6 +150,26 @@ impl DealerEngine {
 
         let hedged_qtys = HashMap::new();
 
+        let execution = HedgeExecutor::new(settings.execution, settings.max_single_order_quantities, logger.clone());
+        let orders = OrderTracker::new(logger.clone());
+        let pnl = PnlRecorder::new(logger.clone());
+
+        let quote_ladders = settings
+            .quote_ladders
+            .into_iter()
+            .map(|(symbol, mut ladder)| {
+                ladder.retain(|size| *size > 0);
+                ladder.sort_unstable();
+                ladder.dedup();
+                (symbol.to_lowercase(), ladder)
+            })
+            .collect();
+
+        let (spread_floors, spread_caps) = match settings.dynamic_spread {
+            Some(ref dynamic_spread) => (currency_map(&dynamic_spread.floors), currency_map(&dynamic_spread.caps)),
+            None => (HashMap::new(), HashMap::new()),
+        };
+
         // making sure that leverage adjustment action is performed first time position state is received
         let last_leverage_check_timestamp =
             Instant::now().sub(Duration::from_millis(settings.leverage_check_interval_ms + 1));
@@ -134,7 +186,7 @@ impl DealerEngine {
             has_received_symbols: false,
             has_received_positions: false,
             is_kollider_authenticated: false,
-            guaranteed_quotes: BTreeMap::new(),
+            quotes: QuoteSigner::new(settings.quotes, logger.clone()),
             last_bank_state_timestamp: None,
             hedged_qtys,
             logger,
@@ -143,8 +195,17 @@ impl DealerEngine {
             leverage_check_interval_ms: settings.leverage_check_interval_ms,
             last_leverage_check_timestamp,
             spread: settings.spread,
-            funding_profit: initial_funding_pnl,
-            max_single_order_quantities: settings.max_single_order_quantities,
+            dynamic_spread: settings.dynamic_spread,
+            spread_floors,
+            spread_caps,
+            volatilities: HashMap::new(),
+            funding_profit: dec!(0),
+            quote_ladders,
+            execution,
+            orders,
+            pnl,
+            market_data: MarketDataMonitor::new(settings.market_data),
+            clock: Box::new(time_now),
         }
     }
 
@@ -152,6 +213,38 @@ impl DealerEngine {
         self.has_received_init_data
     }
 
+    /// Milliseconds hedge executions are timed with, the system time unless replaying a recording.
+    pub fn set_clock(&mut self, clock: impl Fn() -> u64 + 'static) {
+        self.clock = Box::new(clock);
+    }
+
+    /// Stores the orders sent, redeemed quotes and the pnl booked from now on, without it they are only tracked
+    /// in memory.
+    /// The funding pnl is picked up from what was booked before.
+    pub fn set_conn_pool(&mut self, conn_pool: DbPool) {
+        self.orders.set_conn_pool(conn_pool.clone());
+        self.quotes.set_conn_pool(conn_pool.clone());
+        self.pnl.set_conn_pool(conn_pool);
+        if let Some(funding_profit) = self.pnl.get_total_funding() {
+            self.funding_profit = funding_profit;
+        }
+    }
+
+    /// Whether no funding was booked yet, so the funding pnl tracked before has to be carried over.
+    pub fn needs_funding_seed(&self) -> bool {
+        self.pnl.has_funding() == Some(false)
+    }
+
+    /// Books the funding pnl in sats tracked before entries were kept.
+    pub fn seed_funding_profit(&mut self, funding_profit: Decimal, timestamp: u64) {
+        self.pnl.seed_funding(funding_profit, timestamp);
+        self.funding_profit = funding_profit;
+    }
+
+    pub fn get_order_tracker(&self) -> &OrderTracker {
+        &self.orders
+    }
+
     pub fn has_bank_state(&self) -> bool {
         self.last_bank_state.is_some()
     }
@@ -224,11 +317,10 @@ impl DealerEngine {
     pub fn check_health<F: FnMut(Message)>(&self, listener: &mut F) {
         slog::info!(self.logger, "Checking Dealer Health.");
         let is_authenticated = self.ws_client.is_authenticated();
-        let available_currencies = self
-            .ws_client
-            .get_tradable_symbols()
-            .into_iter()
-            .filter_map(|(symbol, _)| {
+        let tradable_symbols = self.ws_client.get_tradable_symbols();
+        let available_currencies = tradable_symbols
+            .keys()
+            .filter_map(|symbol| {
                 let base = &symbol[0..3];
                 let quote_currency_str = &symbol[3..6];
                 if let Ok(quote) = Currency::from_str(quote_currency_str) {
@@ -248,9 +340,21 @@ impl DealerEngine {
             })
             .collect::<HashSet<Currency>>();
 
+        let market_data = tradable_symbols
+            .into_keys()
+            .map(|symbol| {
+                let state = self.market_data_state(&symbol);
+                if state != MarketDataState::Live {
+                    slog::info!(self.logger, "Quotes on {} are suspended: {:?}", symbol, state);
+                }
+                (symbol, state)
+            })
+            .collect::<HashMap<_, _>>();
+
         let mut available_currencies = available_currencies.into_iter().collect::<Vec<_>>();
         let rates = available_currencies
             .iter()
+            .filter(|currency| self.has_live_markets(Currency::BTC, **currency))
             .filter_map(|currency| {
                 let money = Money::new(Currency::BTC, dec!(0.00000001));
                 let (rate, _fees) = self.get_rate(money, *currency);
@@ -269,6 +373,7 @@ impl DealerEngine {
             status,
             available_currencies,
             rates,
+            market_data,
             timestamp: time_now(),
         };
 
@@ -314,17 +419,8 @@ impl DealerEngine {
                 qty_contracts_required
             );
 
-            let currently_hedged_qty = match self.ws_client.get_position_state(&symbol) {
-                Ok(position_state) => match position_state {
-                    Some(p) => match p.side {
-                        None => dec!(0),
-                        Some(side) => {
-                            let side_sign = Decimal::new(side.to_sign(), 0);
-                            side_sign * p.quantity
-                        }
-                    },
-                    None => dec!(0),
-                },
+            let currently_hedged_qty = match self.signed_position(&symbol) {
+                Ok(position) => position,
                 Err(err) => {
                     slog::info!(
                         self.logger,
@@ -361,6 +457,7 @@ impl DealerEngine {
                     delta_qty,
                     risk_tolerance
                 );
+                self.execution.stop(&symbol);
                 continue;
             }
 
@@ -376,38 +473,58 @@ impl DealerEngine {
                 }
             };
 
-            let capped_order_quantity = match self.max_single_order_quantities.get(&symbol.to_lowercase()) {
-                Some(cap) => {
-                    if order_quantity > *cap {
-                        slog::info!(
-                            self.logger,
-                            "Capping order quantity for {} from {} to {}",
-                            symbol,
-                            order_quantity,
-                            cap
-                        );
-                        *cap
-                    } else {
-                        order_quantity
-                    }
-                }
-                None => order_quantity,
-            };
-
             slog::info!(
                 self.logger,
-                "Placing trade on side: {:?} of qty: {} for symbol: {}",
+                "Hedging on side: {:?} qty: {} for symbol: {}",
                 trade_side,
-                capped_order_quantity,
+                order_quantity,
                 symbol
             );
 
-            self.ws_client
-                .make_order(capped_order_quantity, symbol, trade_side)
-                .expect("Failed to create order");
+            self.execution.set_target(&symbol, trade_side, order_quantity);
+        }
+
+        self.execute_hedges();
+    }
+
+    /// Sends the hedge orders that are due and cancels limit orders that rested for too long.
+    pub fn execute_hedges(&mut self) {
+        let timestamp = (self.clock)();
+        let sent = self.execution.poll(timestamp, &*self.ws_client, &self.level2_data);
+        for (symbol, child) in sent {
+            let position = self.signed_position(&symbol).ok();
+            self.orders.track(&symbol, &child, position, timestamp);
         }
     }
 
+    /// Alerts when a position no longer matches the fills of the orders sent.
+    pub fn reconcile_positions(&mut self) {
+        for symbol in self.orders.tracked_symbols() {
+            match self.signed_position(&symbol) {
+                Ok(position) => {
+                    self.orders.reconcile(&symbol, position);
+                }
+                Err(err) => slog::info!(
+                    self.logger,
+                    "Position state is undefined: {:?} - skipping reconciliation for symbol: {}",
+                    err,
+                    symbol
+                ),
+            }
+        }
+    }
+
+    /// Contracts held, negative when short.
+    fn signed_position(&self, symbol: &Symbol) -> ws_client::Result<Decimal> {
+        let position = self.ws_client.get_position_state(symbol)?;
+        Ok(
+            match position.and_then(|position| position.side.map(|side| (side, position.quantity))) {
+                Some((side, quantity)) => Decimal::new(side.to_sign(), 0) * quantity,
+                None => dec!(0),
+            },
+        )
+    }
+
     pub fn process_msg<F: FnMut(Message)>(&mut self, msg: Message, listener: &mut F) {
         match msg {
             Message::Api(msg) => match msg {
@@ -430,20 +547,22 @@ impl DealerEngine {
                         listener(msg);
                         return;
                     }
-                    let time_now = SystemTime::now();
-                    let invalidated_quotes = time_now
-                        .sub(Duration::from_millis(QUOTE_TTL_MS))
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .expect("System time should not be set to earlier than epoch start")
-                        .as_micros();
-                    self.guaranteed_quotes = self.guaranteed_quotes.split_off(&invalidated_quotes);
+                    if !self.has_live_markets(swap_request.from, swap_request.to) {
+                        swap_response.success = false;
+                        swap_response.error = Some(SwapResponseError::MarketNotAvailable);
+                        let msg = Message::Api(Api::SwapResponse(swap_response));
+                        listener(msg);
+                        return;
+                    }
+                    let now = (self.clock)();
+                    self.quotes.remove_expired(now);
                     let is_linear = if swap_request.from == Currency::BTC || swap_request.to == Currency::BTC {
                         ConversionInfo::new(swap_request.from, swap_request.to).is_linear()
                     } else {
                         true
                     };
                     let (current_rate, fees) = self.get_rate(swap_request.amount, swap_request.to);
-                    match swap_request.quote_id {
+                    match swap_request.quote_id.as_deref() {
                         None => {
                             if current_rate.is_some() {
                                 swap_response.rate = current_rate;
@@ -453,23 +572,21 @@ impl DealerEngine {
                                 swap_response.error = Some(SwapResponseError::CurrencyNotAvailable);
                             }
                         }
-                        Some(quote_id) => match self.guaranteed_quotes.remove(&quote_id) {
+                        Some(quote_id) => match self.quotes.redeem(quote_id, &swap_request, now) {
+                            Some(quote) => {
+                                let best_rate = get_better_rate(Some(quote.rate), current_rate, is_linear);
+                                let best_fees = if best_rate == Some(quote.rate) {
+                                    quote.fees
+                                } else {
+                                    fees
+                                };
+                                swap_response.rate = best_rate;
+                                swap_response.fees = best_fees;
+                            }
                             None => {
                                 swap_response.success = false;
                                 swap_response.error = Some(SwapResponseError::InvalidQuoteId);
                             }
-                            Some(quote) => match validate_quote(&quote, &swap_request) {
-                                Ok(_) => {
-                                    let best_rate = get_better_rate(quote.rate, current_rate, is_linear);
-                                    let best_fees = if best_rate == quote.rate { quote.fees } else { fees };
-                                    swap_response.rate = best_rate;
-                                    swap_response.fees = best_fees;
-                                }
-                                Err(_) => {
-                                    swap_response.success = false;
-                                    swap_response.error = Some(SwapResponseError::InvalidQuoteId);
-                                }
-                            },
                         },
                     }
                     let msg = Message::Api(Api::SwapResponse(swap_response));
@@ -487,26 +604,24 @@ impl DealerEngine {
                         quote_id: None,
                         error: None,
                         fees: None,
+                        spread: None,
                     };
-                    let (rate, fees) = self.get_rate(quote_request.amount, quote_request.to);
+                    if !self.has_live_markets(quote_request.from, quote_request.to) {
+                        quote_response.error = Some(QuoteResponseError::MarketNotAvailable);
+                        let msg = Message::Api(Api::QuoteResponse(quote_response));
+                        listener(msg);
+                        return;
+                    }
+                    let (rate, fees, spread) = self.get_rate_with_spread(quote_request.amount, quote_request.to);
                     if rate.is_some() {
-                        let time_now = SystemTime::now();
-                        let quote_id = time_now
-                            .duration_since(SystemTime::UNIX_EPOCH)
-                            .expect("System time should not be set to earlier than epoch start")
-                            .as_micros();
-                        let valid_until = time_now
-                            .add(Duration::from_millis(QUOTE_TTL_MS))
-                            .duration_since(SystemTime::UNIX_EPOCH)
-                            .expect("System time should not be set to earlier than epoch start")
-                            .as_millis() as u64;
-                        quote_response.quote_id = Some(quote_id);
+                        let ttl_ms = self.quotes.ttl_ms(quote_request.from, quote_request.to);
                         quote_response.rate = rate;
-                        quote_response.valid_until = valid_until;
+                        quote_response.valid_until = (self.clock)() + ttl_ms;
                         quote_response.fees = fees;
-                        self.guaranteed_quotes.insert(quote_id, quote_response.clone());
+                        quote_response.spread = spread;
+                        quote_response.quote_id = self.quotes.sign(&quote_response);
                     } else {
-                        quote_response.error = Some(QuoteResponseError::CurrencyNotAvailable);
+                        quote_response.error = Some(self.get_quote_error(quote_request.amount, quote_request.to));
                     }
                     let msg = Message::Api(Api::QuoteResponse(quote_response));
                     listener(msg);
@@ -623,6 +738,11 @@ impl DealerEngine {
                             slog::info!(self.logger, "Successful Kollider authenticated!");
                             self.is_kollider_authenticated = true;
                             self.check_has_received_initial_data();
+                            // orders done while the dealer was down or disconnected are only found out this way
+                            self.orders.open_orders_requested((self.clock)());
+                            if let Err(err) = self.ws_client.fetch_open_orders() {
+                                slog::error!(self.logger, "Failed to fetch open orders, reason: {:?}", err);
+                            }
                         }
                         slog::info!(self.logger, "Re-subscribing to position states after authentication");
                         if let Err(err) = self.ws_client.subscribe(vec![Channel::PositionStates], None) {
@@ -674,6 +794,9 @@ impl DealerEngine {
                     KolliderApiResponse::Level2State(level2state) => {
                         self.process_orderbook_update(level2state);
                     }
+                    KolliderApiResponse::MarkPrices(mark_price) => {
+                        self.market_data.process_mark_price(&mark_price, (self.clock)());
+                    }
                     KolliderApiResponse::TradableSymbols(tradable_symbols) => {
                         slog::info!(self.logger, "Received Symbols");
                         let mut available_symbols = vec![];
@@ -731,6 +854,36 @@ impl DealerEngine {
                     }
                     KolliderApiResponse::FundingPayment(funding_payment) => {
                         self.update_funding_profit(funding_payment.amount);
+                        self.pnl.process_funding_payment(&funding_payment, (self.clock)());
+                    }
+                    KolliderApiResponse::Trade(trade) => {
+                        self.pnl.process_trade(&trade, (self.clock)());
+                    }
+                    KolliderApiResponse::Received(received) => {
+                        slog::info!(self.logger, "Order received {:?}", received);
+                        self.execution.process_received(&received);
+                        self.orders.process_received(&received, (self.clock)());
+                        self.execute_hedges();
+                    }
+                    KolliderApiResponse::Fill(fill) => {
+                        slog::info!(self.logger, "Order filled {:?}", fill);
+                        self.execution.process_fill(&fill);
+                        self.orders.process_fill(&fill, (self.clock)());
+                        self.execute_hedges();
+                    }
+                    KolliderApiResponse::Done(done) => {
+                        slog::info!(self.logger, "Order done {:?}", done);
+                        self.execution.process_done(&done);
+                        self.orders.process_done(&done, (self.clock)());
+                        self.execute_hedges();
+                    }
+                    KolliderApiResponse::OpenOrders(open_orders) => {
+                        self.orders.reconcile_open_orders(&open_orders, (self.clock)());
+                    }
+                    KolliderApiResponse::OrderRejection(rejection) => {
+                        slog::warn!(self.logger, "Order rejected {:?}", rejection);
+                        self.execution.process_rejection(&rejection, (self.clock)());
+                        self.orders.process_rejection(&rejection, (self.clock)());
                     }
                     KolliderApiResponse::Balances(balances) => {
                         let karma = balances.cash.get("KKP").cloned().unwrap_or_default();
@@ -825,12 +978,34 @@ impl DealerEngine {
             }
             _ => panic!("Unsupported level2 update"),
         }
+        if self.level2_data.contains_key(&symbol) {
+            self.market_data.process_book_update(&symbol, (self.clock)());
+        }
+        self.sample_volatility(&symbol);
         self.update_quotes(&symbol);
     }
 
-    fn update_quotes(&mut self, symbol: &Symbol) {
-        const QUANTITIES: [u64; 9] = [10, 100, 1_000, 2_000, 3_000, 5_000, 10_000, 100_000, 1_000_000];
+    fn sample_volatility(&mut self, symbol: &Symbol) {
+        let settings = match self.dynamic_spread {
+            Some(ref settings) => settings,
+            None => return,
+        };
+        let book = match self.level2_data.get(symbol) {
+            Some(l2_data) => l2_data,
+            None => return,
+        };
+        if let (Some((best_bid, _)), Some((best_ask, _))) = (book.bids.iter().next_back(), book.asks.iter().next()) {
+            let mid_price = (best_bid + best_ask) / Decimal::TWO;
+            self.volatilities.entry(symbol.clone()).or_default().sample(
+                mid_price,
+                time_now(),
+                settings.volatility_sample_interval_ms,
+                settings.volatility_window,
+            );
+        }
+    }
 
+    fn update_quotes(&mut self, symbol: &Symbol) {
         let tradable_symbols = self.ws_client.get_tradable_symbols();
         let contract = match tradable_symbols.get(symbol) {
             Some(c) => c,
@@ -844,134 +1019,166 @@ impl DealerEngine {
             None => return,
         };
 
-        let mut bid_quantity_at_level = 0;
-        let mut bid_price_at_level = dec!(0);
-        let mut bid_quantity_so_far = 0;
-        let mut bid_num = dec!(0);
-        let mut bid_levels = book.bids.iter().rev();
-
-        let mut ask_quantity_at_level = 0;
-        let mut ask_price_at_level = dec!(0);
-        let mut ask_quantity_so_far = 0;
-        let mut ask_num = dec!(0);
-        let mut ask_levels = book.asks.iter();
-
-        for qty in QUANTITIES {
-            let mut bid_to_match = qty - bid_quantity_so_far;
-            while bid_to_match > 0 {
-                if bid_to_match <= bid_quantity_at_level {
-                    bid_quantity_at_level -= bid_to_match;
-                    bid_quantity_so_far += bid_to_match;
-                    bid_num += bid_price_at_level * Decimal::new(bid_to_match as i64, 0);
-                    bid_to_match = 0;
-                } else {
-                    bid_quantity_so_far += bid_quantity_at_level;
-                    bid_num += bid_price_at_level * Decimal::new(bid_quantity_at_level as i64, 0);
-                    bid_to_match -= bid_quantity_at_level;
-                    match bid_levels.next() {
-                        Some((price, volume)) => {
-                            bid_price_at_level = *price;
-                            bid_quantity_at_level = *volume;
-                        }
-                        None => {
-                            bid_price_at_level = dec!(0);
-                            bid_quantity_at_level = 0;
-                            break;
-                        }
-                    }
-                }
-            }
-            let mut ask_to_match = qty - ask_quantity_so_far;
-            while ask_to_match > 0 {
-                if ask_to_match <= ask_quantity_at_level {
-                    ask_quantity_at_level -= ask_to_match;
-                    ask_quantity_so_far += ask_to_match;
-                    ask_num += ask_price_at_level * Decimal::new(ask_to_match as i64, 0);
-                    ask_to_match = 0;
-                } else {
-                    ask_quantity_so_far += ask_quantity_at_level;
-                    ask_num += ask_price_at_level * Decimal::new(ask_quantity_at_level as i64, 0);
-                    ask_to_match -= ask_quantity_at_level;
-                    match ask_levels.next() {
-                        Some((price, volume)) => {
-                            ask_price_at_level = *price;
-                            ask_quantity_at_level = *volume;
-                        }
-                        None => {
-                            ask_price_at_level = dec!(0);
-                            ask_quantity_at_level = 0;
-                            break;
-                        }
-                    }
-                }
-            }
-            if bid_to_match == 0 {
-                let mut price = (bid_num / Decimal::new(bid_quantity_so_far as i64, 0)).ceil();
-                price.set_scale(dp).expect("Could not set scale");
-                let quotes = self.bid_quotes.entry(symbol.clone()).or_default();
-                quotes.insert(qty, price);
-            }
-            if ask_to_match == 0 {
-                let mut price = (ask_num / Decimal::new(ask_quantity_so_far as i64, 0)).floor();
-                price.set_scale(dp).expect("Could not set scale");
-                let quotes = self.ask_quotes.entry(symbol.clone()).or_default();
-                quotes.insert(qty, price);
-            }
+        let ladder = match self.quote_ladders.get(&symbol.to_lowercase()) {
+            Some(ladder) => ladder.as_slice(),
+            None => &DEFAULT_QUOTE_LADDER,
+        };
+
+        let bid_quotes = quote_ladder(book.bids.iter().rev(), ladder, RoundingStrategy::ToPositiveInfinity, dp);
+        let ask_quotes = quote_ladder(book.asks.iter(), ladder, RoundingStrategy::ToNegativeInfinity, dp);
+        self.bid_quotes.insert(symbol.clone(), bid_quotes);
+        self.ask_quotes.insert(symbol.clone(), ask_quotes);
+    }
+
+    /// Tells currencies without a market apart from amounts the books are too thin for.
+    fn get_quote_error(&self, amount: Money, destination_currency: Currency) -> QuoteResponseError {
+        let conversions = if amount.currency() == Currency::BTC || destination_currency == Currency::BTC {
+            vec![ConversionInfo::new(amount.currency(), destination_currency)]
+        } else {
+            vec![
+                ConversionInfo::new(amount.currency(), Currency::BTC),
+                ConversionInfo::new(Currency::BTC, destination_currency),
+            ]
+        };
+        let has_markets = conversions.iter().all(|conversion_info| {
+            let maybe_quotes = match conversion_info.side {
+                Side::Bid => self.ask_quotes.get(&conversion_info.symbol),
+                Side::Ask => self.bid_quotes.get(&conversion_info.symbol),
+            };
+            maybe_quotes.map_or(false, |quotes| !quotes.is_empty())
+        });
+        if has_markets {
+            QuoteResponseError::InsufficientLiquidity
+        } else {
+            QuoteResponseError::CurrencyNotAvailable
         }
     }
 
-    #[inline]
-    fn get_spread(&self) -> Decimal {
-        self.spread
+    fn market_data_state(&self, symbol: &Symbol) -> MarketDataState {
+        self.market_data
+            .state(symbol, self.level2_data.get(symbol), (self.clock)())
     }
 
-    #[inline]
-    fn get_half_spread(&self) -> Decimal {
-        self.get_spread() / Decimal::TWO
+    /// Whether the markets a conversion between the currencies goes through can be quoted on. Currencies
+    /// without a tradable symbol are left to the rate lookup to reject.
+    fn has_live_markets(&self, from: Currency, to: Currency) -> bool {
+        let tradable_symbols = self.ws_client.get_tradable_symbols();
+        [from, to]
+            .into_iter()
+            .filter(|currency| *currency != Currency::BTC)
+            .map(Symbol::from)
+            .filter(|symbol| tradable_symbols.contains_key(symbol))
+            .all(|symbol| self.market_data_state(&symbol) == MarketDataState::Live)
     }
 
-    #[inline]
-    fn get_linear_modifier(&self) -> Decimal {
-        Decimal::ONE - self.get_half_spread()
+    /// Spread charged for converting `quantity` units of the fiat currency.
+    fn get_spread(&self, conversion_info: &ConversionInfo, quantity: u64) -> SpreadComponents {
+        let base = self.spread;
+        let settings = match self.dynamic_spread {
+            Some(ref settings) => settings,
+            None => {
+                return SpreadComponents {
+                    base,
+                    total: base,
+                    ..Default::default()
+                }
+            }
+        };
+        let currency = conversion_info.quote;
+
+        let volatility = self
+            .volatilities
+            .get(&conversion_info.symbol)
+            .map(|tracker| tracker.volatility())
+            .unwrap_or_default()
+            * settings.volatility_factor;
+
+        // Users selling fiat grow the dealer's fiat balance.
+        let direction = if conversion_info.from == currency {
+            Decimal::ONE
+        } else {
+            Decimal::NEGATIVE_ONE
+        };
+        let inventory = match self.risk_tolerances.get(&currency) {
+            Some(risk_tolerance) => {
+                inventory_usage(self.get_unhedged_delta(currency), direction, *risk_tolerance)
+                    * settings.inventory_factor
+            }
+            None => Decimal::ZERO,
+        };
+
+        let size = Decimal::from(size_magnitude(quantity)) * settings.size_factor;
+
+        let mut total = base + volatility + inventory + size;
+        if let Some(floor) = self.spread_floors.get(&currency) {
+            total = total.max(*floor);
+        }
+        if let Some(cap) = self.spread_caps.get(&currency) {
+            total = total.min(*cap);
+        }
+
+        SpreadComponents {
+            base,
+            volatility,
+            inventory,
+            size,
+            total,
+        }
     }
 
-    #[inline]
-    fn get_inverse_modifier(&self) -> Decimal {
-        Decimal::ONE + self.get_half_spread()
+    /// Dealer balance in the currency that isn't offset by the hedge position.
+    fn get_unhedged_delta(&self, currency: Currency) -> Decimal {
+        let balance = match self.last_bank_state {
+            Some(ref bank_state) => bank_state
+                .fiat_exposures
+                .values()
+                .filter(|account| account.currency == currency)
+                .map(|account| account.balance)
+                .sum::<Decimal>(),
+            None => return Decimal::ZERO,
+        };
+        let hedged_qty = match self.ws_client.get_position_state(&Symbol::from(currency)) {
+            Ok(Some(position_state)) => match position_state.side {
+                Some(side) => Decimal::new(side.to_sign(), 0) * position_state.quantity,
+                None => dec!(0),
+            },
+            _ => dec!(0),
+        };
+        balance - hedged_qty
     }
 
     #[inline]
-    fn get_linear_rate(&self, price: Decimal, charge_spread: bool) -> Decimal {
-        let modifier = if charge_spread {
-            self.get_linear_modifier()
-        } else {
-            Decimal::ONE
-        };
-        price * modifier
+    fn get_linear_rate(&self, price: Decimal, spread: Decimal) -> Decimal {
+        price * (Decimal::ONE - spread / Decimal::TWO)
     }
 
     #[inline]
-    fn get_inverse_rate(&self, price: Decimal, charge_spread: bool) -> Decimal {
-        let modifier = if charge_spread {
-            self.get_inverse_modifier()
-        } else {
-            Decimal::ONE
-        };
-        Decimal::ONE / (price * modifier)
+    fn get_inverse_rate(&self, price: Decimal, spread: Decimal) -> Decimal {
+        Decimal::ONE / (price * (Decimal::ONE + spread / Decimal::TWO))
     }
 
     pub fn get_rate(&self, amount: Money, destination_currency: Currency) -> (Option<Rate>, Option<Money>) {
+        let (rate, fees, _spread) = self.get_rate_with_spread(amount, destination_currency);
+        (rate, fees)
+    }
+
+    pub fn get_rate_with_spread(
+        &self,
+        amount: Money,
+        destination_currency: Currency,
+    ) -> (Option<Rate>, Option<Money>, Option<SpreadComponents>) {
         if amount.currency() == Currency::BTC || destination_currency == Currency::BTC {
             let conversion = ConversionInfo::new(amount.currency(), destination_currency);
             self.get_btc_cross_rate(amount, conversion, true)
         } else {
             let first_conversion = ConversionInfo::new(amount.currency(), Currency::BTC);
-            let (first_rate, _first_fees) = self.get_btc_cross_rate(amount, first_conversion, false);
+            let (first_rate, _first_fees, _first_spread) = self.get_btc_cross_rate(amount, first_conversion, false);
             match first_rate {
                 Some(to_btc_rate) => {
                     let converted_money = Money::new(Currency::BTC, amount.value() * to_btc_rate.value());
                     let second_conversion = ConversionInfo::new(Currency::BTC, destination_currency);
-                    let (second_rate, second_fee) = self.get_btc_cross_rate(converted_money, second_conversion, true);
+                    let (second_rate, second_fee, second_spread) =
+                        self.get_btc_cross_rate(converted_money, second_conversion, true);
                     match second_rate {
                         Some(to_target_rate) => {
                             let final_rate = Rate::new(
@@ -979,12 +1186,12 @@ impl DealerEngine {
                                 destination_currency,
                                 to_btc_rate.value() * to_target_rate.value(),
                             );
-                            (Some(final_rate), second_fee)
+                            (Some(final_rate), second_fee, second_spread)
                         }
-                        None => (None, None),
+                        None => (None, None, None),
                     }
                 }
-                None => (None, None),
+                None => (None, None, None),
             }
         }
     }
@@ -994,7 +1201,7 @@ impl DealerEngine {
         amount: Money,
         conversion_info: ConversionInfo,
         charge_spread: bool,
-    ) -> (Option<Rate>, Option<Money>) {
+    ) -> (Option<Rate>, Option<Money>, Option<SpreadComponents>) {
         // Example 1:
         // from: BTC
         // to: USD
@@ -1017,13 +1224,13 @@ impl DealerEngine {
         };
 
         match maybe_quotes {
-            None => (None, None),
+            None => (None, None, None),
             Some(quotes) => {
                 let best_price = if conversion_info.from != conversion_info.quote {
                     if let Some((_volume, price)) = quotes.range(0..u64::MAX).next() {
                         *price
                     } else {
-                        return (None, None);
+                        return (None, None, None);
                     }
                 } else {
                     dec!(1.0)
@@ -1032,32 +1239,38 @@ impl DealerEngine {
                 let value_in_fiat = amount.value() * best_price;
 
                 if let Some(lookup_quantity) = value_in_fiat.to_u64() {
-                    match quotes.range(lookup_quantity..u64::MAX).next() {
-                        None => (None, None),
-                        Some((_level_vol, price)) => {
+                    match interpolate_quote(quotes, lookup_quantity) {
+                        None => (None, None, None),
+                        Some(ref price) => {
+                            let spread = if charge_spread {
+                                Some(self.get_spread(&conversion_info, lookup_quantity))
+                            } else {
+                                None
+                            };
+                            let total_spread = spread.as_ref().map(|spread| spread.total).unwrap_or_default();
                             if conversion_info.is_linear() {
-                                let user_rate = self.get_linear_rate(*price, charge_spread);
+                                let user_rate = self.get_linear_rate(*price, total_spread);
                                 // Fees are paid in the target currency.
                                 let value = (price - user_rate) / price * value_in_fiat;
                                 let currency = conversion_info.to;
                                 let fees = Money::new(currency, value);
                                 let rate = Rate::new(conversion_info.from, conversion_info.to, user_rate);
-                                (Some(rate), Some(fees))
+                                (Some(rate), Some(fees), spread)
                             } else {
                                 let no_fee_inverse_rate = Decimal::ONE / price;
-                                let user_inverse_rate = self.get_inverse_rate(*price, charge_spread);
+                                let user_inverse_rate = self.get_inverse_rate(*price, total_spread);
                                 let rate = Rate::new(conversion_info.from, conversion_info.to, user_inverse_rate);
                                 // Fees are paid in the target currency.
                                 let value = (no_fee_inverse_rate - user_inverse_rate) / no_fee_inverse_rate
                                     * (value_in_fiat / price);
                                 let currency = conversion_info.to;
                                 let fees = Money::new(currency, value);
-                                (Some(rate), Some(fees))
+                                (Some(rate), Some(fees), spread)
                             }
                         }
                     }
                 } else {
-                    (None, None)
+                    (None, None, None)
                 }
             }
         }
@@ -1075,11 +1288,12 @@ impl DealerEngine {
                 let value_in_fiat = amount.value().round_dp_with_strategy(0, RoundingStrategy::AwayFromZero);
 
                 if let Some(lookup_quantity) = value_in_fiat.to_u64() {
-                    match quotes.range(lookup_quantity..u64::MAX).next() {
+                    match interpolate_quote(quotes, lookup_quantity) {
                         None => (None, None),
-                        Some((_level_vol, price)) => {
+                        Some(ref price) => {
+                            let spread = self.get_spread(&conversion_info, lookup_quantity).total;
                             if conversion_info.is_linear() {
-                                let user_rate = self.get_linear_rate(*price, true);
+                                let user_rate = self.get_linear_rate(*price, spread);
                                 // Fees are paid in the target currency.
                                 let value = (price - user_rate) / price * value_in_fiat;
                                 let currency = conversion_info.to;
@@ -1088,7 +1302,7 @@ impl DealerEngine {
                                 (Some(rate), Some(fees))
                             } else {
                                 let no_fee_inverse_rate = Decimal::ONE / price;
-                                let user_inverse_rate = self.get_inverse_rate(*price, true);
+                                let user_inverse_rate = self.get_inverse_rate(*price, spread);
                                 let rate = Rate::new(conversion_info.from, conversion_info.to, user_inverse_rate);
                                 // Fees are paid in the target currency.
                                 let value = (no_fee_inverse_rate - user_inverse_rate) / no_fee_inverse_rate
@@ -1110,11 +1324,14 @@ impl DealerEngine {
         self.level2_data = HashMap::new();
         self.bid_quotes = HashMap::new();
         self.ask_quotes = HashMap::new();
+        self.volatilities = HashMap::new();
+        self.market_data.clear();
+        self.execution.clear();
+        self.orders.reset_positions();
         self.has_received_init_data = false;
         self.has_received_symbols = false;
         self.has_received_positions = false;
         self.is_kollider_authenticated = false;
-        self.guaranteed_quotes = BTreeMap::new();
         self.hedged_qtys = HashMap::new();
         self.last_bank_state = None;
         self.last_bank_state_timestamp = None;
@@ -1176,15 +1393,78 @@ impl DealerEngine {
     }
 }
 
-fn validate_quote(quote: &QuoteResponse, swap_request: &SwapRequest) -> Result<(), ()> {
-    if quote.from != swap_request.from
-        || quote.to != swap_request.to
-        || quote.amount.value() != swap_request.amount.value()
-        || quote.uid != swap_request.uid
-    {
-        return Err(());
+/// Average prices of filling each ladder size against the levels, best level first. If the book
+/// runs out, its whole depth is quoted instead and larger sizes are left out.
+fn quote_ladder<'a>(
+    levels: impl Iterator<Item = (&'a Decimal, &'a u64)>,
+    ladder: &[u64],
+    rounding: RoundingStrategy,
+    dp: u32,
+) -> BTreeMap<u64, Decimal> {
+    let mut quotes = BTreeMap::new();
+    let mut levels = levels.map(|(price, volume)| (*price, *volume));
+    let mut level = levels.next();
+    let mut filled = 0;
+    let mut notional = dec!(0);
+    for size in ladder {
+        while filled < *size {
+            let (price, volume) = match level.as_mut() {
+                Some(level) => level,
+                None => break,
+            };
+            let taken = (*volume).min(size - filled);
+            filled += taken;
+            notional += *price * Decimal::from(taken);
+            *volume -= taken;
+            if *volume == 0 {
+                level = levels.next();
+            }
+        }
+        if filled == 0 {
+            break;
+        }
+        let price = (notional / Decimal::from(filled)).round_dp_with_strategy(dp, rounding);
+        quotes.insert(filled, price);
+        if filled < *size {
+            break;
+        }
     }
-    Ok(())
+    quotes
+}
+
+/// Average price of the quantity, from the notional of filling it interpolated between the ladder
+/// sizes around it and rounded towards the price of the larger size. Interpolating the average prices
+/// instead would quote better than the book fills. Quantities below the smallest size get its price,
+/// deeper ones none.
+fn interpolate_quote(quotes: &BTreeMap<u64, Decimal>, quantity: u64) -> Option<Decimal> {
+    let (upper_size, upper_price) = quotes.range(quantity..).next()?;
+    let (lower_size, lower_price) = match quotes.range(..quantity).next_back() {
+        Some(lower) => lower,
+        None => return Some(*upper_price),
+    };
+    let lower_notional = *lower_price * Decimal::from(*lower_size);
+    let upper_notional = *upper_price * Decimal::from(*upper_size);
+    let weight = Decimal::from(quantity - lower_size) / Decimal::from(upper_size - lower_size);
+    let notional = lower_notional + (upper_notional - lower_notional) * weight;
+    let price = notional / Decimal::from(quantity);
+    let rounding = if upper_price < lower_price {
+        RoundingStrategy::ToNegativeInfinity
+    } else {
+        RoundingStrategy::ToPositiveInfinity
+    };
+    Some(price.round_dp_with_strategy(lower_price.scale().max(upper_price.scale()), rounding))
+}
+
+fn currency_map<T: Copy>(settings_map: &HashMap<String, T>) -> HashMap<Currency, T> {
+    settings_map
+        .iter()
+        .map(|(c, value)| match Currency::from_str(c) {
+            Ok(currency) => (currency, *value),
+            Err(err) => {
+                panic!("Failed to convert a settings item {c} into a currency, reason: {err:?}");
+            }
+        })
+        .collect()
 }
 
 fn get_better_rate(rate1: Option<Rate>, rate2: Option<Rate>, is_linear: bool) -> Option<Rate> {
@@ -1209,7 +1489,7 @@ fn get_better_rate(rate1: Option<Rate>, rate2: Option<Rate>, is_linear: bool) ->
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use core_types::{Money, Rate};
     use msgs::kollider_client::Channel;
 
@@ -1344,6 +1624,10 @@ mod tests {
             Ok(())
         }
 
+        fn fetch_open_orders(&self) -> ws_client::Result<()> {
+            Ok(())
+        }
+
         fn get_all_balances(&self) -> Option<Balances> {
             Some(self.balances.borrow().clone())
         }
@@ -1366,7 +1650,22 @@ mod tests {
             }
         }
 
-        fn make_order(&self, _quantity: u64, _symbol: Symbol, _side: Side) -> ws_client::Result<()> {
+        fn make_order(&self, _quantity: u64, _symbol: Symbol, _side: Side) -> ws_client::Result<ExtOrderId> {
+            Ok(Uuid::new_v4())
+        }
+
+        fn make_limit_order(
+            &self,
+            _quantity: u64,
+            _symbol: Symbol,
+            _side: Side,
+            _price: u64,
+            _is_post_only: bool,
+        ) -> ws_client::Result<ExtOrderId> {
+            Ok(Uuid::new_v4())
+        }
+
+        fn cancel_order(&self, _order_id: OrderId, _symbol: Symbol) -> ws_client::Result<()> {
             Ok(())
         }
 
@@ -1391,25 +1690,34 @@ mod tests {
         }
     }
 
-    use crate::dealer_engine::QUOTE_TTL_MS;
+    use crate::execution::{ExecutionSettings, OrderStyle};
+    use crate::market_data::MarketDataSettings;
+    use crate::quotes::{QuoteSettings, DEFAULT_QUOTE_TTL_MS};
+    use crate::spread::DynamicSpreadSettings;
     use crate::{DealerEngine, DealerEngineSettings};
-    use core_types::kollider_client::{Balances, MarkPrice, PositionState, Side};
-    use core_types::{Currency, Symbol, SATS_IN_BITCOIN};
-    use msgs::api::{Api, QuoteRequest, QuoteResponseError, SwapRequest, SwapResponseError};
+    use core_types::kollider_client::{Balances, ExtOrderId, MarkPrice, OrderId, PositionState, Side};
+    use core_types::{Account, AccountClass, AccountType, Currency, Symbol, SATS_IN_BITCOIN};
+    use msgs::api::{
+        Api, QuoteRequest, QuoteResponse, QuoteResponseError, SpreadComponents, SwapRequest, SwapResponseError,
+    };
+    use msgs::dealer::{BankState, Dealer, MarketDataState};
     use msgs::kollider_client::{KolliderApiResponse, Level2State, TradableSymbol};
     use msgs::Message;
     use rust_decimal::Decimal;
     use rust_decimal_macros::dec;
-    use std::cell::RefCell;
+    use simulated_exchange::{SimulatedExchange, SimulatedExchangeSettings};
+    use std::cell::{Cell, RefCell};
     use std::collections::{BTreeMap, HashMap, VecDeque};
+    use std::rc::Rc;
     use std::time::Duration;
+    use utils::time::time_now;
     use utils::xlogging::*;
     use uuid::Uuid;
     use ws_client::WsClient;
     use xerror::kollider_client::KolliderClientError;
 
-    fn initialise_dealer_engine() -> DealerEngine {
-        let settings = DealerEngineSettings {
+    pub(crate) fn dealer_engine_settings() -> DealerEngineSettings {
+        DealerEngineSettings {
             psql_url: "".to_string(),
             dealer_bank_pull_address: "".to_string(),
             dealer_bank_push_address: "".to_string(),
@@ -1434,10 +1742,23 @@ mod tests {
             position_max_leverage: dec!(1.0001),
             leverage_check_interval_ms: 1000,
             spread: dec!(0.01),
+            dynamic_spread: None,
             max_single_order_quantities: HashMap::new(),
-        };
+            execution: ExecutionSettings::default(),
+            quote_ladders: HashMap::new(),
+            market_data: MarketDataSettings::default(),
+            quotes: QuoteSettings::default(),
+            paper_trading: None,
+        }
+    }
+
+    fn initialise_dealer_engine() -> DealerEngine {
+        initialise_dealer_engine_with_settings(dealer_engine_settings())
+    }
+
+    fn initialise_dealer_engine_with_settings(settings: DealerEngineSettings) -> DealerEngine {
         let ws_client = MockWsClient::new();
-        let mut dealer = DealerEngine::new(settings, ws_client, Decimal::ZERO);
+        let mut dealer = DealerEngine::new(settings, ws_client);
 
         // BTC/USD
         let mut bids = BTreeMap::new();
@@ -1480,6 +1801,17 @@ mod tests {
             Message::KolliderApiResponse(KolliderApiResponse::Level2State(level2state)),
             &mut |_msg| {},
         );
+
+        for (symbol, price) in [("BTCUSD.PERP", dec!(35000)), ("BTCEUR.PERP", dec!(31500))] {
+            let mark_price = MarkPrice {
+                price,
+                symbol: Symbol::from(symbol),
+            };
+            dealer.process_msg(
+                Message::KolliderApiResponse(KolliderApiResponse::MarkPrices(mark_price)),
+                &mut |_msg| {},
+            );
+        }
         dealer
     }
 
@@ -1558,7 +1890,9 @@ mod tests {
                 assert_eq!(quote_response.uid, uid);
                 assert_eq!(quote_response.from, Currency::BTC);
                 assert_eq!(quote_response.to, Currency::USD);
-                assert_eq!(quote_response.rate.map(|rate| rate.value()), Some(dec!(23217.33)));
+                // 2625 contracts fill 1000 at 30000 and 1625 at 20000, 23809.52 on average. The ladder
+                // rounds its 3000 contract price up, which quotes 23810 before the spread.
+                assert_eq!(quote_response.rate.map(|rate| rate.value()), Some(dec!(23690.95)));
                 assert!(quote_response.quote_id.is_some());
                 assert!(quote_response.error.is_none());
                 break;
@@ -1580,9 +1914,11 @@ mod tests {
                 assert_eq!(quote_response.uid, uid);
                 assert_eq!(quote_response.from, Currency::USD);
                 assert_eq!(quote_response.to, Currency::BTC);
+                // 3500 contracts fill at 48571.43 on average, the ladder rounds its 3000 contract price
+                // down, which quotes 48571 before the spread.
                 assert_eq!(
                     quote_response.rate.map(|rate| rate.value()),
-                    Some(Rate::normalized_value(dec!(1) / dec!(52260)))
+                    Some(Rate::normalized_value(dec!(1) / dec!(48813.855)))
                 );
                 assert!(quote_response.quote_id.is_some());
                 assert!(quote_response.error.is_none());
@@ -1614,7 +1950,7 @@ mod tests {
                 assert!(quote_response.quote_id.is_none());
                 assert!(matches!(
                     quote_response.error,
-                    Some(QuoteResponseError::CurrencyNotAvailable)
+                    Some(QuoteResponseError::InsufficientLiquidity)
                 ));
                 break;
             }
@@ -1637,7 +1973,7 @@ mod tests {
                 assert!(quote_response.quote_id.is_none());
                 assert!(matches!(
                     quote_response.error,
-                    Some(QuoteResponseError::CurrencyNotAvailable)
+                    Some(QuoteResponseError::InsufficientLiquidity)
                 ));
                 break;
             }
@@ -1719,7 +2055,7 @@ mod tests {
                 assert_eq!(swap_response.uid, uid);
                 assert_eq!(swap_response.from, Currency::BTC);
                 assert_eq!(swap_response.to, Currency::USD);
-                assert_eq!(swap_response.rate.map(|rate| rate.value()), Some(dec!(23217.33)));
+                assert_eq!(swap_response.rate.map(|rate| rate.value()), Some(dec!(23690.95)));
                 assert!(swap_response.error.is_none());
                 break;
             }
@@ -1743,7 +2079,7 @@ mod tests {
                 assert_eq!(swap_response.to, Currency::BTC);
                 assert_eq!(
                     swap_response.rate.map(|rate| rate.value()),
-                    Some(Rate::normalized_value(dec!(1) / dec!(52260)))
+                    Some(Rate::normalized_value(dec!(1) / dec!(48813.855)))
                 );
                 assert!(swap_response.error.is_none());
                 break;
@@ -1773,7 +2109,7 @@ mod tests {
                 assert_eq!(quote_response.uid, uid);
                 assert_eq!(quote_response.from, Currency::BTC);
                 assert_eq!(quote_response.to, Currency::USD);
-                assert_eq!(quote_response.rate.map(|rate| rate.value()), Some(dec!(23217.33)));
+                assert_eq!(quote_response.rate.map(|rate| rate.value()), Some(dec!(23690.95)));
                 assert!(quote_response.quote_id.is_some());
                 assert!(quote_response.error.is_none());
                 quote = Some(quote_response);
@@ -1787,7 +2123,7 @@ mod tests {
             amount: quote.amount,
             from: quote.from,
             to: quote.to,
-            quote_id: quote.quote_id,
+            quote_id: quote.quote_id.clone(),
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1816,7 +2152,7 @@ mod tests {
             amount: money,
             from: Currency::BTC,
             to: Currency::USD,
-            quote_id: Some(12345),
+            quote_id: Some(String::from("12345")),
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1838,7 +2174,7 @@ mod tests {
             amount: money,
             from: Currency::USD,
             to: Currency::BTC,
-            quote_id: Some(67890),
+            quote_id: Some(String::from("67890")),
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1877,7 +2213,7 @@ mod tests {
                 assert_eq!(quote_response.uid, uid);
                 assert_eq!(quote_response.from, Currency::BTC);
                 assert_eq!(quote_response.to, Currency::USD);
-                assert_eq!(quote_response.rate.map(|rate| rate.value()), Some(dec!(23217.33)));
+                assert_eq!(quote_response.rate.map(|rate| rate.value()), Some(dec!(23690.95)));
                 assert!(quote_response.quote_id.is_some());
                 assert!(quote_response.error.is_none());
                 quote = Some(quote_response);
@@ -1885,14 +2221,14 @@ mod tests {
             }
         }
         let quote = quote.expect("Expected a valid quote");
-        std::thread::sleep(Duration::from_millis(QUOTE_TTL_MS + 50));
+        std::thread::sleep(Duration::from_millis(DEFAULT_QUOTE_TTL_MS + 50));
         let swap_request = SwapRequest {
             req_id: Uuid::new_v4(),
             uid: quote.uid,
             amount: quote.amount,
             from: quote.from,
             to: quote.to,
-            quote_id: quote.quote_id,
+            quote_id: quote.quote_id.clone(),
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1931,7 +2267,7 @@ mod tests {
                 assert_eq!(quote_response.uid, uid);
                 assert_eq!(quote_response.from, Currency::BTC);
                 assert_eq!(quote_response.to, Currency::USD);
-                assert_eq!(quote_response.rate.map(|rate| rate.value()), Some(dec!(23217.33)));
+                assert_eq!(quote_response.rate.map(|rate| rate.value()), Some(dec!(23690.95)));
                 assert!(quote_response.quote_id.is_some());
                 assert!(quote_response.error.is_none());
                 quote = Some(quote_response);
@@ -1946,7 +2282,7 @@ mod tests {
             amount: quote.amount,
             from: quote.from,
             to: quote.to,
-            quote_id: quote.quote_id,
+            quote_id: quote.quote_id.clone(),
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1969,7 +2305,7 @@ mod tests {
             amount: money,
             from: quote.from,
             to: quote.to,
-            quote_id: quote.quote_id,
+            quote_id: quote.quote_id.clone(),
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -1991,7 +2327,7 @@ mod tests {
             amount: quote.amount,
             from: Currency::BTC,
             to: Currency::GBP,
-            quote_id: quote.quote_id,
+            quote_id: quote.quote_id.clone(),
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
             out_msg.push_back(msg);
@@ -2030,7 +2366,7 @@ mod tests {
                 assert_eq!(quote_response.uid, uid);
                 assert_eq!(quote_response.from, Currency::BTC);
                 assert_eq!(quote_response.to, Currency::USD);
-                assert_eq!(quote_response.rate.map(|rate| rate.value()), Some(dec!(23217.33)));
+                assert_eq!(quote_response.rate.map(|rate| rate.value()), Some(dec!(23690.95)));
                 assert!(quote_response.quote_id.is_some());
                 assert!(quote_response.error.is_none());
                 quote = Some(quote_response);
@@ -2044,7 +2380,7 @@ mod tests {
             amount: quote.amount,
             from: quote.from,
             to: quote.to,
-            quote_id: quote.quote_id,
+            quote_id: quote.quote_id.clone(),
         };
         dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request.clone())), &mut |msg| {
             out_msg.push_back(msg);
@@ -2074,6 +2410,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn swap_with_quote_after_restart() {
+        let mut settings = dealer_engine_settings();
+        settings.quotes.secret = Some(String::from("secret"));
+        settings.quotes.ttls = [(String::from("BTCUSD"), 60000)].into_iter().collect();
+        let mut dealer_engine = initialise_dealer_engine_with_settings(settings.clone());
+        let quote = request_quote(
+            &mut dealer_engine,
+            Money::new(Currency::BTC, dec!(0.0875)),
+            Currency::USD,
+        );
+        assert!(quote.valid_until > time_now() + DEFAULT_QUOTE_TTL_MS);
+
+        // the quote id carries the quote, a dealer started with the same secret honours it
+        let mut dealer_engine = initialise_dealer_engine_with_settings(settings);
+        let swap_request = SwapRequest {
+            req_id: Uuid::new_v4(),
+            uid: quote.uid,
+            amount: quote.amount,
+            from: quote.from,
+            to: quote.to,
+            quote_id: quote.quote_id.clone(),
+        };
+        let mut swap_response = None;
+        dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
+            if let Message::Api(Api::SwapResponse(response)) = msg {
+                swap_response = Some(response);
+            }
+        });
+        let swap_response = swap_response.expect("Dealer should respond to a swap request");
+        assert!(swap_response.error.is_none());
+        assert_eq!(swap_response.rate, quote.rate);
+    }
+
     #[test]
     fn triangular_swap() {
         let mut dealer_engine = initialise_dealer_engine();
@@ -2242,4 +2612,363 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn hedge_on_simulated_exchange() {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let exchange = SimulatedExchange::new(
+            SimulatedExchangeSettings {
+                initial_balance: dec!(100000000),
+                taker_fee: Decimal::ZERO,
+                slippage: Decimal::ZERO,
+                leverage: Decimal::ONE,
+            },
+            tx,
+        );
+        let mut settings = dealer_engine_settings();
+        settings.risk_tolerances = [(String::from("USD"), 10)].into_iter().collect();
+        let mut dealer_engine = DealerEngine::new(settings, exchange.clone());
+        let process_responses = |dealer_engine: &mut DealerEngine| {
+            while let Ok(msg) = rx.try_recv() {
+                dealer_engine.process_msg(msg, &mut |_msg| {});
+            }
+        };
+
+        exchange.set_tradable_symbols(MockWsClient::new().tradable_symbols);
+        process_responses(&mut dealer_engine);
+        assert!(dealer_engine.is_ready());
+
+        let level2state = Level2State {
+            update_type: "snapshot".to_string(),
+            seq_number: 0,
+            symbol: Symbol::from("BTCUSD.PERP"),
+            bids: [(dec!(19900), 10000)].into_iter().collect(),
+            asks: [(dec!(20000), 10000)].into_iter().collect(),
+        };
+        exchange.process_level2_state(level2state);
+        process_responses(&mut dealer_engine);
+
+        let mut usd_account = Account::new(Currency::USD, AccountType::Internal, AccountClass::Cash);
+        usd_account.balance = dec!(-1000);
+        let bank_state = BankState {
+            total_exposures: HashMap::new(),
+            fiat_exposures: [(usd_account.account_id, usd_account)].into_iter().collect(),
+            insurance_fund_account: Account::new(Currency::BTC, AccountType::Internal, AccountClass::Cash),
+        };
+        for _ in 0..2 {
+            dealer_engine.process_msg(Message::Dealer(Dealer::BankState(bank_state.clone())), &mut |_msg| {});
+            process_responses(&mut dealer_engine);
+        }
+
+        let position = exchange
+            .get_position_state(&Symbol::from("BTCUSD.PERP"))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(position.side, Some(Side::Ask)));
+        assert_eq!(position.quantity, dec!(1000));
+        assert_eq!(position.entry_price, dec!(19900));
+        assert_eq!(
+            dealer_engine.get_hedged_quantity(Symbol::from("BTCUSD.PERP")).unwrap(),
+            dec!(1000)
+        );
+    }
+
+    #[test]
+    fn hedge_with_post_only_slices() {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let exchange = SimulatedExchange::new(
+            SimulatedExchangeSettings {
+                initial_balance: dec!(100000000),
+                taker_fee: Decimal::ZERO,
+                slippage: Decimal::ZERO,
+                leverage: Decimal::ONE,
+            },
+            tx,
+        );
+        let mut settings = dealer_engine_settings();
+        settings.risk_tolerances = [(String::from("USD"), 10)].into_iter().collect();
+        settings.max_single_order_quantities = [(String::from("btcusd.perp"), 600)].into_iter().collect();
+        settings.execution = ExecutionSettings {
+            order_style: OrderStyle::PostOnly,
+            order_timeout_ms: 5000,
+            ..Default::default()
+        };
+        let mut dealer_engine = DealerEngine::new(settings, exchange.clone());
+        let clock = Rc::new(Cell::new(0));
+        let dealer_clock = clock.clone();
+        dealer_engine.set_clock(move || dealer_clock.get());
+        let process_responses = |dealer_engine: &mut DealerEngine| {
+            while let Ok(msg) = rx.try_recv() {
+                dealer_engine.process_msg(msg, &mut |_msg| {});
+            }
+        };
+        let snapshot = |best_bid| Level2State {
+            update_type: "snapshot".to_string(),
+            seq_number: 0,
+            symbol: Symbol::from("BTCUSD.PERP"),
+            bids: [(best_bid, 10000)].into_iter().collect(),
+            asks: [(dec!(20000), 10000)].into_iter().collect(),
+        };
+
+        exchange.set_tradable_symbols(MockWsClient::new().tradable_symbols);
+        process_responses(&mut dealer_engine);
+        exchange.process_level2_state(snapshot(dec!(19900)));
+        process_responses(&mut dealer_engine);
+
+        let mut usd_account = Account::new(Currency::USD, AccountType::Internal, AccountClass::Cash);
+        usd_account.balance = dec!(-1000);
+        let bank_state = BankState {
+            total_exposures: HashMap::new(),
+            fiat_exposures: [(usd_account.account_id, usd_account)].into_iter().collect(),
+            insurance_fund_account: Account::new(Currency::BTC, AccountType::Internal, AccountClass::Cash),
+        };
+        dealer_engine.process_msg(Message::Dealer(Dealer::BankState(bank_state)), &mut |_msg| {});
+        process_responses(&mut dealer_engine);
+        // the first slice rests at the 19950 mid
+        assert!(exchange
+            .get_position_state(&Symbol::from("BTCUSD.PERP"))
+            .unwrap()
+            .is_none());
+
+        // bids moving up fill it and the second slice rests at the new 19980 mid
+        exchange.process_level2_state(snapshot(dec!(19960)));
+        process_responses(&mut dealer_engine);
+        let position = exchange
+            .get_position_state(&Symbol::from("BTCUSD.PERP"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(position.quantity, dec!(600));
+
+        // once it times out the rest crosses the spread
+        clock.set(5000);
+        dealer_engine.execute_hedges();
+        process_responses(&mut dealer_engine);
+        let position = exchange
+            .get_position_state(&Symbol::from("BTCUSD.PERP"))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(position.side, Some(Side::Ask)));
+        assert_eq!(position.quantity, dec!(1000));
+        let fills = exchange
+            .take_fills()
+            .into_iter()
+            .map(|fill| (fill.quantity, fill.price))
+            .collect::<Vec<_>>();
+        assert_eq!(fills, vec![(600, dec!(19950)), (400, dec!(19960))]);
+        assert_eq!(
+            dealer_engine
+                .get_order_tracker()
+                .get_expected_position(&Symbol::from("BTCUSD.PERP")),
+            Some(dec!(-1000))
+        );
+    }
+
+    fn request_quote(dealer_engine: &mut DealerEngine, amount: Money, to: Currency) -> QuoteResponse {
+        let quote_request = QuoteRequest {
+            req_id: Uuid::new_v4(),
+            uid: 1003,
+            amount,
+            from: amount.currency(),
+            to,
+        };
+        let mut quote_response = None;
+        dealer_engine.process_msg(Message::Api(Api::QuoteRequest(quote_request)), &mut |msg| {
+            if let Message::Api(Api::QuoteResponse(response)) = msg {
+                quote_response = Some(response);
+            }
+        });
+        quote_response.expect("Dealer should respond to a quote request")
+    }
+
+    #[test]
+    fn dynamic_spread_components() {
+        let mut settings = dealer_engine_settings();
+        settings.risk_tolerances = [(String::from("USD"), 100)].into_iter().collect();
+        settings.dynamic_spread = Some(DynamicSpreadSettings {
+            volatility_factor: Decimal::ZERO,
+            volatility_window: 60,
+            volatility_sample_interval_ms: 1000,
+            inventory_factor: dec!(0.01),
+            size_factor: dec!(0.001),
+            floors: [(String::from("USD"), dec!(0.012))].into_iter().collect(),
+            caps: [(String::from("USD"), dec!(0.02))].into_iter().collect(),
+        });
+        let mut dealer_engine = initialise_dealer_engine_with_settings(settings);
+
+        // 3 USD fall in the smallest bucket, only the floor widens the spread
+        let quote_response = request_quote(
+            &mut dealer_engine,
+            Money::new(Currency::BTC, dec!(0.0001)),
+            Currency::USD,
+        );
+        assert_eq!(
+            quote_response.spread,
+            Some(SpreadComponents {
+                base: dec!(0.01),
+                total: dec!(0.012),
+                ..Default::default()
+            })
+        );
+        assert_eq!(quote_response.rate.map(|rate| rate.value()), Some(dec!(29820)));
+
+        // the dealer owes 50 unhedged dollars, half the risk tolerance
+        let mut usd_account = Account::new(Currency::USD, AccountType::Internal, AccountClass::Cash);
+        usd_account.balance = dec!(-50);
+        let bank_state = BankState {
+            total_exposures: HashMap::new(),
+            fiat_exposures: [(usd_account.account_id, usd_account)].into_iter().collect(),
+            insurance_fund_account: Account::new(Currency::BTC, AccountType::Internal, AccountClass::Cash),
+        };
+        dealer_engine.process_msg(Message::Dealer(Dealer::BankState(bank_state)), &mut |_msg| {});
+
+        // 300 USD are two tenfolds above the smallest bucket
+        let quote_response = request_quote(&mut dealer_engine, Money::new(Currency::BTC, dec!(0.01)), Currency::USD);
+        assert_eq!(
+            quote_response.spread,
+            Some(SpreadComponents {
+                base: dec!(0.01),
+                volatility: Decimal::ZERO,
+                inventory: dec!(0.005),
+                size: dec!(0.002),
+                total: dec!(0.017),
+            })
+        );
+        assert_eq!(quote_response.rate.map(|rate| rate.value()), Some(dec!(29745)));
+
+        // selling dollars shrinks the delta
+        let quote_response = request_quote(&mut dealer_engine, Money::new(Currency::USD, dec!(5)), Currency::BTC);
+        assert_eq!(
+            quote_response.spread.map(|spread| spread.inventory),
+            Some(Decimal::ZERO)
+        );
+    }
+
+    #[test]
+    fn custom_quote_ladder() {
+        let mut settings = dealer_engine_settings();
+        settings.quote_ladders = [(String::from("BTCUSD.PERP"), vec![10000, 1000, 0, 4000])]
+            .into_iter()
+            .collect();
+        let mut dealer_engine = initialise_dealer_engine_with_settings(settings);
+
+        // bids only add up to 8000 contracts, the deepest quote covers all of them
+        let bid_quotes = &dealer_engine.bid_quotes[&Symbol::from("BTCUSD.PERP")];
+        assert_eq!(
+            bid_quotes.iter().collect::<Vec<_>>(),
+            vec![(&1000, &dec!(30000)), (&4000, &dec!(20000)), (&8000, &dec!(15000))]
+        );
+
+        // 1500 USD fill 38.33M of notional interpolated between the 1000 and 4000 contract fills, rounded
+        // towards the price of the larger size
+        let quote_response = request_quote(&mut dealer_engine, Money::new(Currency::BTC, dec!(0.05)), Currency::USD);
+        assert_eq!(quote_response.rate.map(|rate| rate.value()), Some(dec!(25427.225)));
+
+        // 6000 USD fill exactly as the book does, 100M of notional at 16666.67
+        let quote_response = request_quote(&mut dealer_engine, Money::new(Currency::BTC, dec!(0.2)), Currency::USD);
+        assert_eq!(quote_response.rate.map(|rate| rate.value()), Some(dec!(16582.67)));
+
+        let quote_response = request_quote(&mut dealer_engine, Money::new(Currency::BTC, dec!(0.3)), Currency::USD);
+        assert!(quote_response.rate.is_none());
+        assert!(matches!(
+            quote_response.error,
+            Some(QuoteResponseError::InsufficientLiquidity)
+        ));
+    }
+
+    #[test]
+    fn suspend_quotes_on_bad_market_data() {
+        let mut dealer_engine = initialise_dealer_engine();
+        let now = Rc::new(Cell::new(time_now()));
+        let clock = now.clone();
+        dealer_engine.set_clock(move || clock.get());
+        let btc = Money::new(Currency::BTC, dec!(0.0001));
+
+        let quote_response = request_quote(&mut dealer_engine, btc, Currency::USD);
+        assert!(quote_response.error.is_none());
+
+        // no book update for longer than the default 10 seconds
+        now.set(now.get() + 10_001);
+        let quote_response = request_quote(&mut dealer_engine, btc, Currency::USD);
+        assert!(matches!(
+            quote_response.error,
+            Some(QuoteResponseError::MarketNotAvailable)
+        ));
+        let swap_request = SwapRequest {
+            req_id: Uuid::new_v4(),
+            uid: 1003,
+            amount: Money::new(Currency::EUR, dec!(10)),
+            from: Currency::EUR,
+            to: Currency::USD,
+            quote_id: None,
+        };
+        let mut swap_response = None;
+        dealer_engine.process_msg(Message::Api(Api::SwapRequest(swap_request)), &mut |msg| {
+            if let Message::Api(Api::SwapResponse(response)) = msg {
+                swap_response = Some(response);
+            }
+        });
+        let swap_response = swap_response.expect("Dealer should respond to a swap request");
+        assert!(!swap_response.success);
+        assert!(matches!(
+            swap_response.error,
+            Some(SwapResponseError::MarketNotAvailable)
+        ));
+
+        let update_book = |dealer_engine: &mut DealerEngine, bid: Decimal, ask: Decimal| {
+            let level2state = Level2State {
+                update_type: "snapshot".to_string(),
+                seq_number: 1,
+                symbol: Symbol::from("BTCUSD.PERP"),
+                bids: [(bid, 1000)].into_iter().collect(),
+                asks: [(ask, 1000)].into_iter().collect(),
+            };
+            dealer_engine.process_msg(
+                Message::KolliderApiResponse(KolliderApiResponse::Level2State(level2state)),
+                &mut |_msg| {},
+            );
+        };
+        let mark_price = MarkPrice {
+            price: dec!(35000),
+            symbol: Symbol::from("BTCUSD.PERP"),
+        };
+        dealer_engine.process_msg(
+            Message::KolliderApiResponse(KolliderApiResponse::MarkPrices(mark_price)),
+            &mut |_msg| {},
+        );
+        update_book(&mut dealer_engine, dec!(35000), dec!(35000));
+        let quote_response = request_quote(&mut dealer_engine, btc, Currency::USD);
+        assert!(matches!(
+            quote_response.error,
+            Some(QuoteResponseError::MarketNotAvailable)
+        ));
+
+        // a mid of 36000 is 2.9% away from the mark price
+        update_book(&mut dealer_engine, dec!(35900), dec!(36100));
+        let quote_response = request_quote(&mut dealer_engine, btc, Currency::USD);
+        assert!(matches!(
+            quote_response.error,
+            Some(QuoteResponseError::MarketNotAvailable)
+        ));
+
+        let mut dealer_health = None;
+        dealer_engine.check_health(&mut |msg| {
+            if let Message::Dealer(Dealer::Health(health)) = msg {
+                dealer_health = Some(health);
+            }
+        });
+        let dealer_health = dealer_health.expect("Dealer should report its health");
+        assert_eq!(
+            dealer_health.market_data[&Symbol::from("BTCUSD.PERP")],
+            MarketDataState::IndexDeviation
+        );
+        assert_eq!(
+            dealer_health.market_data[&Symbol::from("BTCEUR.PERP")],
+            MarketDataState::StaleBook
+        );
+        assert!(dealer_health.rates.is_empty());
+
+        update_book(&mut dealer_engine, dec!(34900), dec!(35100));
+        let quote_response = request_quote(&mut dealer_engine, btc, Currency::USD);
+        assert!(quote_response.error.is_none());
+    }
 }
@@ -0,0 +1,495 @@
+use core_types::kollider_client::{ExtOrderId, OrderId, Side};
+use core_types::Symbol;
+use msgs::kollider_client::{Done, Fill, Level2State, OrderRejection, Received};
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use ws_client::WsClient;
+
+fn default_order_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_retry_interval_ms() -> u64 {
+    1000
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStyle {
+    #[default]
+    Market,
+    /// Limit orders near mid, taking whatever liquidity they cross.
+    Limit,
+    /// Limit orders near mid that are kept off the other side of the book.
+    PostOnly,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExecutionSettings {
+    #[serde(default)]
+    pub order_style: OrderStyle,
+    /// Distance of limit orders from mid away from the other side of the book, as a fraction of the price.
+    #[serde(default)]
+    pub limit_offset: Decimal,
+    /// Time a limit order rests before it is cancelled and its unfilled part crosses the spread.
+    #[serde(default = "default_order_timeout_ms")]
+    pub order_timeout_ms: u64,
+    /// Pause between slices of a hedge larger than the symbol's `max_single_order_quantities`.
+    #[serde(default)]
+    pub slice_interval_ms: u64,
+    #[serde(default = "default_retry_interval_ms")]
+    pub retry_interval_ms: u64,
+    /// Failed or rejected orders retried before the hedge is left to the next risk check.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+}
+
+impl Default for ExecutionSettings {
+    fn default() -> Self {
+        Self {
+            order_style: OrderStyle::default(),
+            limit_offset: Decimal::ZERO,
+            order_timeout_ms: default_order_timeout_ms(),
+            slice_interval_ms: 0,
+            retry_interval_ms: default_retry_interval_ms(),
+            max_retries: default_max_retries(),
+        }
+    }
+}
+
+/// Order sent to the exchange for a slice of a hedge.
+#[derive(Debug, Clone)]
+pub struct ChildOrder {
+    pub ext_order_id: ExtOrderId,
+    /// Known once the exchange has received the order.
+    pub order_id: Option<OrderId>,
+    pub side: Side,
+    pub quantity: u64,
+    pub filled: u64,
+    /// Limit price in the symbol's price decimal places, `None` for market orders.
+    pub price: Option<u64>,
+    pub placed_at: u64,
+    is_cancelling: bool,
+    /// Cancelled because it rested for too long rather than because the hedge changed.
+    has_timed_out: bool,
+}
+
+impl ChildOrder {
+    pub fn new(ext_order_id: ExtOrderId, side: Side, quantity: u64, price: Option<u64>, placed_at: u64) -> Self {
+        Self {
+            ext_order_id,
+            order_id: None,
+            side,
+            quantity,
+            filled: 0,
+            price,
+            placed_at,
+            is_cancelling: false,
+            has_timed_out: false,
+        }
+    }
+
+    pub fn unfilled(&self) -> u64 {
+        self.quantity.saturating_sub(self.filled)
+    }
+}
+
+/// Hedge of a symbol, sent one child order at a time.
+#[derive(Debug)]
+pub struct Execution {
+    pub side: Side,
+    /// Contracts to trade on top of the position when the hedge was last set.
+    pub target: u64,
+    /// Contracts not sent in a child order yet.
+    pub remaining: u64,
+    pub child: Option<ChildOrder>,
+    /// Part of `remaining` left by timed out limit orders, sent as market orders.
+    cross_quantity: u64,
+    next_order_timestamp: u64,
+    retries: u32,
+}
+
+impl Execution {
+    fn new(side: Side) -> Self {
+        Self {
+            side,
+            target: 0,
+            remaining: 0,
+            child: None,
+            cross_quantity: 0,
+            next_order_timestamp: 0,
+            retries: 0,
+        }
+    }
+
+    fn is_child_needed(&self) -> bool {
+        self.target > 0
+            && self
+                .child
+                .as_ref()
+                .map_or(false, |child| child.side.to_sign() == self.side.to_sign())
+    }
+
+    /// Takes back what a finished child order left unfilled if the hedge still needs it.
+    fn finish_child(&mut self) -> Option<ChildOrder> {
+        let is_needed = self.is_child_needed();
+        let child = self.child.take()?;
+        if is_needed {
+            self.remaining += child.unfilled();
+            if child.has_timed_out {
+                self.cross_quantity += child.unfilled();
+            }
+        }
+        Some(child)
+    }
+}
+
+/// Works hedges set by the risk checks, slicing them and falling back to market orders when limit
+/// orders do not fill in time.
+pub struct HedgeExecutor {
+    settings: ExecutionSettings,
+    max_slice_quantities: HashMap<String, u64>,
+    executions: HashMap<Symbol, Execution>,
+    logger: slog::Logger,
+}
+
+impl HedgeExecutor {
+    pub fn new(settings: ExecutionSettings, max_slice_quantities: HashMap<String, u64>, logger: slog::Logger) -> Self {
+        Self {
+            settings,
+            max_slice_quantities,
+            executions: HashMap::new(),
+            logger,
+        }
+    }
+
+    pub fn get_execution(&self, symbol: &Symbol) -> Option<&Execution> {
+        self.executions.get(symbol)
+    }
+
+    /// Sets the contracts to trade on top of the current position, the unfilled part of an open
+    /// child order in the same direction counts as traded already.
+    pub fn set_target(&mut self, symbol: &Symbol, side: Side, quantity: u64) {
+        let execution = self
+            .executions
+            .entry(symbol.clone())
+            .or_insert_with(|| Execution::new(side));
+        if execution.side.to_sign() != side.to_sign() {
+            *execution = Execution {
+                child: execution.child.take(),
+                ..Execution::new(side)
+            };
+        }
+        let in_flight = match execution.child {
+            Some(ref child) if child.side.to_sign() == side.to_sign() => child.unfilled(),
+            _ => 0,
+        };
+        execution.target = quantity;
+        execution.remaining = quantity.saturating_sub(in_flight);
+        execution.cross_quantity = execution.cross_quantity.min(execution.remaining);
+    }
+
+    /// Stops sending slices for the symbol, a resting limit order is cancelled.
+    pub fn stop(&mut self, symbol: &Symbol) {
+        if let Some(execution) = self.executions.get_mut(symbol) {
+            execution.target = 0;
+            execution.remaining = 0;
+            execution.cross_quantity = 0;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.executions.clear();
+    }
+
+    /// Cancels limit orders that rested for too long or are no longer needed and sends the slices that are due,
+    /// returning the orders sent.
+    pub fn poll(
+        &mut self,
+        timestamp: u64,
+        ws_client: &dyn WsClient,
+        books: &HashMap<Symbol, Level2State>,
+    ) -> Vec<(Symbol, ChildOrder)> {
+        let mut sent = vec![];
+        let symbols = self.executions.keys().cloned().collect::<Vec<_>>();
+        for symbol in symbols {
+            if self.executions[&symbol].child.is_some() {
+                self.check_child_order(&symbol, timestamp, ws_client);
+            } else if let Some(child) = self.send_child_order(&symbol, timestamp, ws_client, books.get(&symbol)) {
+                sent.push((symbol, child));
+            }
+        }
+        self.executions
+            .retain(|_symbol, execution| execution.child.is_some() || execution.remaining > 0);
+        sent
+    }
+
+    fn check_child_order(&mut self, symbol: &Symbol, timestamp: u64, ws_client: &dyn WsClient) {
+        let order_timeout_ms = self.settings.order_timeout_ms;
+        let execution = match self.executions.get_mut(symbol) {
+            Some(execution) => execution,
+            None => return,
+        };
+        let is_needed = execution.is_child_needed();
+        let child = match execution.child {
+            Some(ref mut child) => child,
+            None => return,
+        };
+        let has_timed_out = timestamp >= child.placed_at + order_timeout_ms;
+        if child.price.is_none() {
+            // Market orders complete right away, one without a response is left to the next risk check.
+            if has_timed_out {
+                slog::warn!(
+                    self.logger,
+                    "{} market order {} did not complete in time",
+                    symbol,
+                    child.ext_order_id
+                );
+                execution.child = None;
+            }
+            return;
+        }
+        if child.is_cancelling || (is_needed && !has_timed_out) {
+            return;
+        }
+        let order_id = match child.order_id {
+            Some(order_id) => order_id,
+            None => return,
+        };
+        match ws_client.cancel_order(order_id, symbol.clone()) {
+            Ok(()) => {
+                child.is_cancelling = true;
+                child.has_timed_out = is_needed;
+            }
+            Err(err) => {
+                slog::warn!(
+                    self.logger,
+                    "Failed to cancel {} order {}, reason: {:?}",
+                    symbol,
+                    order_id,
+                    err
+                );
+            }
+        }
+    }
+
+    fn send_child_order(
+        &mut self,
+        symbol: &Symbol,
+        timestamp: u64,
+        ws_client: &dyn WsClient,
+        book: Option<&Level2State>,
+    ) -> Option<ChildOrder> {
+        let execution = self.executions.get_mut(symbol)?;
+        if execution.remaining == 0 || timestamp < execution.next_order_timestamp {
+            return None;
+        }
+        let is_crossing = execution.cross_quantity > 0;
+        let mut quantity = if is_crossing {
+            execution.cross_quantity
+        } else {
+            execution.remaining
+        };
+        if let Some(max_quantity) = self.max_slice_quantities.get(&symbol.to_lowercase()) {
+            quantity = quantity.min(*max_quantity);
+        }
+        let side = execution.side;
+
+        let limit_price = match self.settings.order_style {
+            OrderStyle::Market => None,
+            _ if is_crossing => None,
+            order_style => book.and_then(|book| {
+                let contract = ws_client.get_tradable_symbols().remove(symbol)?;
+                limit_price(
+                    book,
+                    side,
+                    self.settings.limit_offset,
+                    order_style == OrderStyle::PostOnly,
+                    contract.tick_size,
+                    contract.price_dp,
+                )
+            }),
+        };
+        slog::info!(
+            self.logger,
+            "Placing {:?} {} order of {} {} contracts",
+            side,
+            limit_price.map_or(String::from("market"), |price| format!("limit at {price}")),
+            quantity,
+            symbol
+        );
+        let result = match limit_price {
+            Some(price) => ws_client.make_limit_order(
+                quantity,
+                symbol.clone(),
+                side,
+                price,
+                self.settings.order_style == OrderStyle::PostOnly,
+            ),
+            None => ws_client.make_order(quantity, symbol.clone(), side),
+        };
+        match result {
+            Ok(ext_order_id) => {
+                execution.remaining -= quantity;
+                if is_crossing {
+                    execution.cross_quantity -= quantity;
+                }
+                execution.next_order_timestamp = timestamp + self.settings.slice_interval_ms;
+                let child = ChildOrder::new(ext_order_id, side, quantity, limit_price, timestamp);
+                execution.child = Some(child.clone());
+                Some(child)
+            }
+            Err(err) => {
+                slog::warn!(self.logger, "Failed to place {} order, reason: {:?}", symbol, err);
+                self.schedule_retry(symbol, timestamp);
+                None
+            }
+        }
+    }
+
+    fn schedule_retry(&mut self, symbol: &Symbol, timestamp: u64) {
+        let execution = match self.executions.get_mut(symbol) {
+            Some(execution) => execution,
+            None => return,
+        };
+        execution.retries += 1;
+        if execution.retries > self.settings.max_retries {
+            slog::error!(
+                self.logger,
+                "Giving up hedging {} after {} retries, left to the next risk check",
+                symbol,
+                self.settings.max_retries
+            );
+            execution.remaining = 0;
+            execution.cross_quantity = 0;
+            return;
+        }
+        execution.next_order_timestamp = timestamp + self.settings.retry_interval_ms;
+    }
+
+    fn child_execution(&mut self, symbol: &Symbol, is_child: impl Fn(&ChildOrder) -> bool) -> Option<&mut Execution> {
+        self.executions
+            .get_mut(symbol)
+            .filter(|execution| execution.child.as_ref().map_or(false, &is_child))
+    }
+
+    pub fn process_received(&mut self, received: &Received) {
+        if let Some(execution) =
+            self.child_execution(&received.symbol, |child| child.ext_order_id == received.ext_order_id)
+        {
+            if let Some(ref mut child) = execution.child {
+                child.order_id = Some(received.order_id);
+            }
+        }
+    }
+
+    pub fn process_fill(&mut self, fill: &Fill) {
+        if let Some(execution) = self.child_execution(&fill.symbol, |child| child.ext_order_id == fill.ext_order_id) {
+            let is_filled = match execution.child {
+                Some(ref mut child) => {
+                    child.filled += fill.quantity;
+                    child.filled >= child.quantity
+                }
+                None => false,
+            };
+            if is_filled {
+                execution.child = None;
+                execution.retries = 0;
+            }
+        }
+    }
+
+    pub fn process_done(&mut self, done: &Done) {
+        if let Some(execution) = self.child_execution(&done.symbol, |child| child.order_id == Some(done.order_id)) {
+            execution.finish_child();
+        }
+    }
+
+    pub fn process_rejection(&mut self, rejection: &OrderRejection, timestamp: u64) {
+        let execution = self.child_execution(&rejection.symbol, |child| child.ext_order_id == rejection.ext_order_id);
+        if let Some(execution) = execution {
+            execution.finish_child();
+            self.schedule_retry(&rejection.symbol, timestamp);
+        }
+    }
+}
+
+/// Price of a limit order near mid as an integer of the price decimal places, rounded to a tick away
+/// from the other side of the book. Post-only orders are kept a tick off the other side.
+fn limit_price(
+    book: &Level2State,
+    side: Side,
+    offset: Decimal,
+    is_post_only: bool,
+    tick_size: Decimal,
+    price_dp: u32,
+) -> Option<u64> {
+    let best_bid = *book.bids.keys().next_back()?;
+    let best_ask = *book.asks.keys().next()?;
+    let tick_size = if tick_size > Decimal::ZERO {
+        tick_size
+    } else {
+        Decimal::new(1, price_dp)
+    };
+    let mid = (best_bid + best_ask) / Decimal::TWO;
+    let price = mid * (Decimal::ONE - Decimal::from(side.to_sign()) * offset) / tick_size;
+    let mut price = match side {
+        Side::Bid => price.floor(),
+        Side::Ask => price.ceil(),
+    } * tick_size;
+    if is_post_only {
+        price = match side {
+            Side::Bid => price.min(best_ask - tick_size),
+            Side::Ask => price.max(best_bid + tick_size),
+        };
+    }
+    (price * Decimal::from(10u64.pow(price_dp))).round().to_u64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_limit_price() {
+        let book = Level2State {
+            update_type: String::from("snapshot"),
+            seq_number: 0,
+            symbol: Symbol::from("BTCUSD.PERP"),
+            bids: [(dec!(19990), 100)].into_iter().collect(),
+            asks: [(dec!(19991), 100)].into_iter().collect(),
+        };
+        // 19990.5 mid
+        assert_eq!(
+            limit_price(&book, Side::Bid, Decimal::ZERO, false, dec!(0.5), 1),
+            Some(199905)
+        );
+        assert_eq!(
+            limit_price(&book, Side::Bid, dec!(0.001), false, dec!(0.5), 1),
+            Some(199705)
+        );
+        assert_eq!(
+            limit_price(&book, Side::Ask, dec!(0.001), false, dec!(0.5), 1),
+            Some(200105)
+        );
+        // post-only orders stay off the other side
+        assert_eq!(
+            limit_price(&book, Side::Bid, dec!(-0.001), false, dec!(1), 0),
+            Some(20010)
+        );
+        assert_eq!(
+            limit_price(&book, Side::Bid, dec!(-0.001), true, dec!(1), 0),
+            Some(19990)
+        );
+        assert_eq!(
+            limit_price(&book, Side::Ask, Decimal::ZERO, true, dec!(1), 0),
+            Some(19991)
+        );
+    }
+}
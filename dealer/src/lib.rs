@@ -1,14 +1,25 @@
+pub mod backtest;
 pub mod dealer_engine;
+pub mod execution;
+pub mod market_data;
+pub mod orders;
+pub mod pnl;
+pub mod quotes;
+pub mod spread;
 
 use chrono::{DateTime, FixedOffset};
 use crossbeam::channel::bounded;
 use dealer_engine::*;
+use diesel::{r2d2::ConnectionManager, PgConnection};
 use msgs::dealer::{BankStateRequest, Dealer};
 use msgs::*;
 use std::time::Instant;
 use uuid::Uuid;
 
 use kollider_hedging::KolliderHedgingClient;
+use msgs::kollider_client::{Channel, KolliderApiResponse};
+use simulated_exchange::SimulatedExchange;
+use ws_client::WsClient;
 
 use core_types::*;
 use futures::prelude::*;
@@ -99,6 +110,49 @@ async fn store_quotes(dealer: &DealerEngine, client: &Client, bucket: &str) {
     }
 }
 
+async fn store_spreads(dealer: &DealerEngine, client: &Client, bucket: &str) {
+    let some_sats = Money::new(Currency::BTC, dec!(0.000005000));
+    let one_usd = Money::new(Currency::USD, dec!(1.0));
+    let one_eur = Money::new(Currency::EUR, dec!(1.0));
+    let quotes = vec![
+        ("btc_usd", some_sats, Currency::USD),
+        ("btc_eur", some_sats, Currency::EUR),
+        ("usd_btc", one_usd, Currency::BTC),
+        ("eur_btc", one_eur, Currency::BTC),
+    ];
+
+    let builder = quotes.into_iter().fold(
+        influxdb2::models::DataPoint::builder("swap_spreads"),
+        |builder, (pair, amount, destination_currency)| {
+            let (_rate, _fees, spread) = dealer.get_rate_with_spread(amount, destination_currency);
+            let spread = match spread {
+                Some(spread) => spread,
+                None => return builder,
+            };
+            let components = [
+                ("base", spread.base),
+                ("volatility", spread.volatility),
+                ("inventory", spread.inventory),
+                ("size", spread.size),
+                ("total", spread.total),
+            ];
+            components
+                .into_iter()
+                .fold(builder, |builder, (component, value)| match value.to_f64() {
+                    Some(converted) => builder.field(format!("{pair}_{component}"), converted),
+                    None => builder,
+                })
+        },
+    );
+
+    if let Ok(data_point) = builder.build() {
+        let points = vec![data_point];
+        if let Err(err) = client.write(bucket, stream::iter(points)).await {
+            eprintln!("Failed to write swap spreads data point to Influx. Err: {err}");
+        }
+    }
+}
+
 async fn store_pnl(dealer: &DealerEngine, client: &Client, bucket: &str) {
     let DealerPnl { total_pnl, funding_pnl } = dealer.get_pnl();
     let fields = vec![("total_pnl", total_pnl), ("funding_pnl", funding_pnl)];
@@ -125,7 +179,8 @@ async fn store_pnl(dealer: &DealerEngine, client: &Client, bucket: &str) {
     }
 }
 
-async fn retrieve_funding_profit(client: &Client, bucket: &str) -> Decimal {
+/// Last funding pnl stored in influx together with when it was stored, from before it was booked in psql.
+async fn retrieve_funding_profit(client: &Client, bucket: &str) -> Option<(Decimal, u64)> {
     let qs = format!(
         "from(bucket: \"{bucket}\")
         |> range(start: -52w)
@@ -133,24 +188,59 @@ async fn retrieve_funding_profit(client: &Client, bucket: &str) -> Decimal {
         |> last()"
     );
     let query = influxdb2::models::Query::new(qs);
-    let result = client.query::<FundingPnlDataPoint>(Some(query)).await;
-    let funding_pnl = result
-        .unwrap_or_default()
-        .first()
-        .map(|data_point| data_point.funding_pnl)
-        .unwrap_or_default();
-    Decimal::from_f64(funding_pnl).unwrap_or_default()
+    let result = client.query::<FundingPnlDataPoint>(Some(query)).await.ok()?;
+    let data_point = result.first()?;
+    Some((
+        Decimal::from_f64(data_point.funding_pnl)?,
+        data_point.time.timestamp_millis() as u64,
+    ))
+}
+
+/// Feeds the simulated exchange with the market data of the Kollider client in paper trading mode.
+fn forward_market_data(market_data_client: &KolliderHedgingClient, exchange: &SimulatedExchange, message: Message) {
+    match message {
+        Message::KolliderApiResponse(KolliderApiResponse::TradableSymbols(tradable_symbols)) => {
+            let symbols = tradable_symbols.symbols.keys().cloned().collect::<Vec<_>>();
+            exchange.set_tradable_symbols(tradable_symbols.symbols);
+            if let Err(err) = market_data_client.subscribe(vec![Channel::OrderbookLevel2], symbols) {
+                eprintln!("Failed to subscribe L2 order book updates for paper trading, reason: {err:?}");
+            }
+        }
+        Message::KolliderApiResponse(KolliderApiResponse::Reconnected(_)) => {
+            // re-initialises the client, which brings the tradable symbols again
+            if let Err(err) = WsClient::subscribe(market_data_client, vec![Channel::OrderbookLevel2], None) {
+                eprintln!("Failed to re-subscribe L2 order book updates for paper trading, reason: {err:?}");
+            }
+        }
+        Message::KolliderApiResponse(KolliderApiResponse::Level2State(level2_state)) => {
+            exchange.process_level2_state(level2_state);
+        }
+        _ => {}
+    }
 }
 
 pub async fn start(settings: DealerEngineSettings, bank_sender: ZmqSocket, bank_recv: ZmqSocket) {
     let (kollider_client_tx, kollider_client_rx) = bounded(2024);
+    let (market_data_tx, market_data_rx) = bounded(2024);
+
+    let paper_exchange = settings
+        .paper_trading
+        .clone()
+        .map(|paper_settings| SimulatedExchange::new(paper_settings, kollider_client_tx.clone()));
+
+    // When paper trading the Kollider client is only used for market data.
+    let kollider_callback = if paper_exchange.is_some() {
+        market_data_tx
+    } else {
+        kollider_client_tx
+    };
 
     let ws_client = match KolliderHedgingClient::connect(
         &settings.kollider_ws_url,
         &settings.kollider_api_key,
         &settings.kollider_api_secret,
         &settings.kollider_api_passphrase,
-        kollider_client_tx,
+        kollider_callback,
     ) {
         Ok(connected) => connected,
         Err(err) => {
@@ -171,12 +261,32 @@ pub async fn start(settings: DealerEngineSettings, bank_sender: ZmqSocket, bank_
         None => None,
     };
 
-    let initial_funding_pnl = match influx_client {
-        Some(ref client) => retrieve_funding_profit(client, &settings.influx_bucket).await,
-        None => Decimal::ZERO,
+    let (mut synth_dealer, paper_trading) = match paper_exchange {
+        Some(exchange) => (
+            DealerEngine::new(settings.clone(), exchange.clone()),
+            Some((ws_client, exchange)),
+        ),
+        None => (DealerEngine::new(settings.clone(), ws_client), None),
     };
 
-    let mut synth_dealer = DealerEngine::new(settings.clone(), ws_client, initial_funding_pnl);
+    // Paper orders and pnl are not stored.
+    if paper_trading.is_none() {
+        let pool = r2d2::Pool::builder()
+            .build(ConnectionManager::<PgConnection>::new(settings.psql_url.clone()))
+            .expect("Failed to create pool.");
+        synth_dealer.set_conn_pool(pool);
+
+        // The funding pnl was only kept in influx before, it is carried over once.
+        if let Some(ref client) = influx_client {
+            if synth_dealer.needs_funding_seed() {
+                if let Some((funding_profit, timestamp)) =
+                    retrieve_funding_profit(client, &settings.influx_bucket).await
+                {
+                    synth_dealer.seed_funding_profit(funding_profit, timestamp);
+                }
+            }
+        }
+    }
 
     let mut listener = |msg: Message| {
         utils::xzmq::send_as_bincode(&bank_sender, &msg);
@@ -186,6 +296,7 @@ pub async fn start(settings: DealerEngineSettings, bank_sender: ZmqSocket, bank_
     let mut last_house_keeping = Instant::now();
     let mut last_risk_check = Instant::now();
     let mut last_influx_quotes = Instant::now();
+    let mut last_hedge_execution = Instant::now();
 
     loop {
         // Before we proceed we have to have received a bank state message
@@ -209,12 +320,19 @@ pub async fn start(settings: DealerEngineSettings, bank_sender: ZmqSocket, bank_
             };
         }
 
+        if let Some((ref market_data_client, ref exchange)) = paper_trading {
+            if let Ok(message) = market_data_rx.try_recv() {
+                forward_market_data(market_data_client, exchange, message);
+            }
+        }
+
         if let Ok(message) = kollider_client_rx.try_recv() {
             synth_dealer.process_msg(message, &mut listener);
         }
 
         if last_risk_check.elapsed().as_secs() > 10 {
             if synth_dealer.has_bank_state() {
+                synth_dealer.reconcile_positions();
                 synth_dealer.check_risk(&mut listener);
                 last_risk_check = Instant::now();
             }
@@ -224,12 +342,18 @@ pub async fn start(settings: DealerEngineSettings, bank_sender: ZmqSocket, bank_
             }
         }
 
+        if last_hedge_execution.elapsed().as_millis() > 100 {
+            synth_dealer.execute_hedges();
+            last_hedge_execution = Instant::now();
+        }
+
         if last_health_check.elapsed().as_secs() > 5 {
             synth_dealer.check_health(&mut listener);
             last_health_check = Instant::now();
         }
 
-        if last_house_keeping.elapsed().as_secs() > 30 {
+        // Paper balances are not real funds to be swept.
+        if last_house_keeping.elapsed().as_secs() > 30 && paper_trading.is_none() {
             last_house_keeping = Instant::now();
             synth_dealer.sweep_excess_funds(&mut listener);
         }
@@ -237,6 +361,7 @@ pub async fn start(settings: DealerEngineSettings, bank_sender: ZmqSocket, bank_
         if last_influx_quotes.elapsed().as_secs() > 1 {
             if let Some(ref client) = influx_client {
                 store_quotes(&synth_dealer, client, &settings.influx_bucket).await;
+                store_spreads(&synth_dealer, client, &settings.influx_bucket).await;
                 last_influx_quotes = Instant::now();
             }
         }
@@ -1,5 +1,3 @@
-pub mod dealer_engine;
-
 use utils::xzmq::SocketContext;
 
 use dealer::dealer_engine::*;
@@ -0,0 +1,190 @@
+use core_types::kollider_client::MarkPrice;
+use core_types::Symbol;
+use msgs::dealer::MarketDataState;
+use msgs::kollider_client::Level2State;
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+fn default_max_book_age_ms() -> u64 {
+    10_000
+}
+
+fn default_max_mark_price_age_ms() -> u64 {
+    30_000
+}
+
+fn default_max_index_deviation() -> Decimal {
+    Decimal::new(2, 2)
+}
+
+/// Limits beyond which the market data of a symbol is not quoted on.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MarketDataSettings {
+    #[serde(default = "default_max_book_age_ms")]
+    pub max_book_age_ms: u64,
+    #[serde(default = "default_max_mark_price_age_ms")]
+    pub max_mark_price_age_ms: u64,
+    /// Largest distance of the mid price from the mark price, as a fraction of the mark price.
+    #[serde(default = "default_max_index_deviation")]
+    pub max_index_deviation: Decimal,
+}
+
+impl Default for MarketDataSettings {
+    fn default() -> Self {
+        Self {
+            max_book_age_ms: default_max_book_age_ms(),
+            max_mark_price_age_ms: default_max_mark_price_age_ms(),
+            max_index_deviation: default_max_index_deviation(),
+        }
+    }
+}
+
+/// Keeps track of when the books and mark prices were last updated, timestamps in milliseconds.
+pub struct MarketDataMonitor {
+    settings: MarketDataSettings,
+    book_updates: HashMap<Symbol, u64>,
+    mark_prices: HashMap<Symbol, (Decimal, u64)>,
+}
+
+impl MarketDataMonitor {
+    pub fn new(settings: MarketDataSettings) -> Self {
+        Self {
+            settings,
+            book_updates: HashMap::new(),
+            mark_prices: HashMap::new(),
+        }
+    }
+
+    pub fn process_book_update(&mut self, symbol: &Symbol, timestamp: u64) {
+        self.book_updates.insert(symbol.clone(), timestamp);
+    }
+
+    pub fn process_mark_price(&mut self, mark_price: &MarkPrice, timestamp: u64) {
+        self.mark_prices
+            .insert(mark_price.symbol.clone(), (mark_price.price, timestamp));
+    }
+
+    pub fn clear(&mut self) {
+        self.book_updates.clear();
+        self.mark_prices.clear();
+    }
+
+    /// Whether `book`, the current book of `symbol`, and its mark price can be quoted on at `now`.
+    pub fn state(&self, symbol: &Symbol, book: Option<&Level2State>, now: u64) -> MarketDataState {
+        let (book, updated_at) = match (book, self.book_updates.get(symbol)) {
+            (Some(book), Some(updated_at)) => (book, *updated_at),
+            _ => return MarketDataState::NoBook,
+        };
+        if now.saturating_sub(updated_at) > self.settings.max_book_age_ms {
+            return MarketDataState::StaleBook;
+        }
+        let (best_bid, best_ask) = match (book.bids.keys().next_back(), book.asks.keys().next()) {
+            (Some(best_bid), Some(best_ask)) => (*best_bid, *best_ask),
+            _ => return MarketDataState::NoBook,
+        };
+        if best_bid >= best_ask {
+            return MarketDataState::CrossedBook;
+        }
+        let (mark_price, marked_at) = match self.mark_prices.get(symbol) {
+            Some(mark_price) => *mark_price,
+            None => return MarketDataState::NoMarkPrice,
+        };
+        if now.saturating_sub(marked_at) > self.settings.max_mark_price_age_ms {
+            return MarketDataState::StaleMarkPrice;
+        }
+        if mark_price <= Decimal::ZERO {
+            return MarketDataState::NoMarkPrice;
+        }
+        let mid_price = (best_bid + best_ask) / Decimal::TWO;
+        if (mid_price - mark_price).abs() / mark_price > self.settings.max_index_deviation {
+            return MarketDataState::IndexDeviation;
+        }
+        MarketDataState::Live
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn book(bid: Decimal, ask: Decimal) -> Level2State {
+        Level2State {
+            update_type: "snapshot".to_string(),
+            seq_number: 0,
+            symbol: Symbol::from("BTCUSD.PERP"),
+            bids: [(bid, 1000)].into_iter().collect(),
+            asks: [(ask, 1000)].into_iter().collect(),
+        }
+    }
+
+    fn mark_price(price: Decimal) -> MarkPrice {
+        MarkPrice {
+            price,
+            symbol: Symbol::from("BTCUSD.PERP"),
+        }
+    }
+
+    #[test]
+    fn test_market_data_state() {
+        let symbol = Symbol::from("BTCUSD.PERP");
+        let mut monitor = MarketDataMonitor::new(MarketDataSettings::default());
+        let live_book = book(dec!(19900), dec!(20100));
+        assert_eq!(monitor.state(&symbol, Some(&live_book), 0), MarketDataState::NoBook);
+
+        monitor.process_book_update(&symbol, 1000);
+        assert_eq!(monitor.state(&symbol, None, 1000), MarketDataState::NoBook);
+        assert_eq!(
+            monitor.state(&symbol, Some(&live_book), 1000),
+            MarketDataState::NoMarkPrice
+        );
+
+        monitor.process_mark_price(&mark_price(dec!(20000)), 1000);
+        assert_eq!(monitor.state(&symbol, Some(&live_book), 11_000), MarketDataState::Live);
+        assert_eq!(
+            monitor.state(&symbol, Some(&live_book), 11_001),
+            MarketDataState::StaleBook
+        );
+
+        monitor.process_book_update(&symbol, 40_000);
+        assert_eq!(
+            monitor.state(&symbol, Some(&live_book), 40_000),
+            MarketDataState::StaleMarkPrice
+        );
+
+        let mut one_sided_book = live_book.clone();
+        one_sided_book.asks.clear();
+        assert_eq!(
+            monitor.state(&symbol, Some(&one_sided_book), 40_000),
+            MarketDataState::NoBook
+        );
+        let crossed_book = book(dec!(20100), dec!(20100));
+        assert_eq!(
+            monitor.state(&symbol, Some(&crossed_book), 40_000),
+            MarketDataState::CrossedBook
+        );
+    }
+
+    #[test]
+    fn test_index_deviation() {
+        let symbol = Symbol::from("BTCUSD.PERP");
+        let mut monitor = MarketDataMonitor::new(MarketDataSettings::default());
+        monitor.process_book_update(&symbol, 0);
+        monitor.process_mark_price(&mark_price(dec!(20000)), 0);
+
+        // mid prices 2% above, 2.5% above and 2.5% below the mark price
+        assert_eq!(
+            monitor.state(&symbol, Some(&book(dec!(20390), dec!(20410))), 0),
+            MarketDataState::Live
+        );
+        assert_eq!(
+            monitor.state(&symbol, Some(&book(dec!(20490), dec!(20510))), 0),
+            MarketDataState::IndexDeviation
+        );
+        assert_eq!(
+            monitor.state(&symbol, Some(&book(dec!(19490), dec!(19510))), 0),
+            MarketDataState::IndexDeviation
+        );
+    }
+}
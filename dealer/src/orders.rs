@@ -0,0 +1,532 @@
+use crate::execution::ChildOrder;
+use core_types::kollider_client::{ExtOrderId, OrderId, Side};
+use core_types::{DbPool, Symbol};
+use models::dealer_orders::{DealerOrder, InsertableDealerOrder};
+use msgs::kollider_client::{Done, Fill, OpenOrders, OrderRejection, Received};
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrderState {
+    /// Sent but not acknowledged by the exchange yet.
+    Pending,
+    Open,
+    Filled,
+    /// Done before filling completely, either cancelled or out of liquidity.
+    Cancelled,
+    Rejected,
+}
+
+impl OrderState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderState::Pending => "pending",
+            OrderState::Open => "open",
+            OrderState::Filled => "filled",
+            OrderState::Cancelled => "cancelled",
+            OrderState::Rejected => "rejected",
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        matches!(self, OrderState::Pending | OrderState::Open)
+    }
+}
+
+impl FromStr for OrderState {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(OrderState::Pending),
+            "open" => Ok(OrderState::Open),
+            "filled" => Ok(OrderState::Filled),
+            "cancelled" => Ok(OrderState::Cancelled),
+            "rejected" => Ok(OrderState::Rejected),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackedOrder {
+    pub ext_order_id: ExtOrderId,
+    pub order_id: Option<OrderId>,
+    pub symbol: Symbol,
+    pub side: Side,
+    pub quantity: u64,
+    pub price: Option<u64>,
+    pub filled: u64,
+    pub state: OrderState,
+    pub rejection_reason: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl TrackedOrder {
+    fn from_model(order: DealerOrder) -> Option<Self> {
+        let side = match order.side.as_str() {
+            "Bid" => Side::Bid,
+            "Ask" => Side::Ask,
+            _ => return None,
+        };
+        Some(Self {
+            ext_order_id: order.ext_order_id,
+            order_id: order.order_id.map(|order_id| order_id as u64),
+            symbol: order.symbol,
+            side,
+            quantity: order.quantity as u64,
+            price: order.price.map(|price| price as u64),
+            filled: order.filled as u64,
+            state: OrderState::from_str(&order.state).ok()?,
+            rejection_reason: order.rejection_reason,
+            created_at: order.created_at as u64,
+            updated_at: order.updated_at as u64,
+        })
+    }
+
+    fn to_model(&self) -> InsertableDealerOrder {
+        InsertableDealerOrder {
+            ext_order_id: self.ext_order_id,
+            order_id: self.order_id.map(|order_id| order_id as i64),
+            symbol: self.symbol.clone(),
+            side: format!("{:?}", self.side),
+            quantity: self.quantity as i64,
+            price: self.price.map(|price| price as i64),
+            filled: self.filled as i64,
+            state: self.state.as_str().to_string(),
+            rejection_reason: self.rejection_reason.clone(),
+            created_at: self.created_at as i64,
+            updated_at: self.updated_at as i64,
+        }
+    }
+}
+
+/// Keeps the state of the orders the dealer sent, persisting every change when a database is set, and checks
+/// that the fills add up to the exchange positions.
+///
+/// Positions are expected to move by the fills received since the first order of a symbol was sent,
+/// a difference seen on two reconciliations in a row is alerted.
+pub struct OrderTracker {
+    /// Orders the exchange is not done with yet.
+    orders: HashMap<ExtOrderId, TrackedOrder>,
+    /// Done messages only carry the exchange order id.
+    order_ids: HashMap<OrderId, ExtOrderId>,
+    expected_positions: HashMap<Symbol, Decimal>,
+    /// Last difference between the exchange and the expected position and whether it was alerted.
+    mismatches: HashMap<Symbol, (Decimal, bool)>,
+    /// When the exchange was last asked for its open orders, only orders sent before are reconciled.
+    open_orders_requested_at: Option<u64>,
+    conn_pool: Option<DbPool>,
+    logger: slog::Logger,
+}
+
+impl OrderTracker {
+    pub fn new(logger: slog::Logger) -> Self {
+        Self {
+            orders: HashMap::new(),
+            order_ids: HashMap::new(),
+            expected_positions: HashMap::new(),
+            mismatches: HashMap::new(),
+            open_orders_requested_at: None,
+            conn_pool: None,
+            logger,
+        }
+    }
+
+    /// Persists orders from now on and picks up the ones left open by a previous run, they are reconciled with
+    /// the exchange's open orders once it sent them.
+    pub fn set_conn_pool(&mut self, conn_pool: DbPool) {
+        let open_states = [OrderState::Pending.as_str(), OrderState::Open.as_str()];
+        let open_orders = match conn_pool.get() {
+            Ok(conn) => DealerOrder::get_by_states(&conn, &open_states),
+            Err(err) => {
+                slog::error!(self.logger, "Couldn't get psql connection: {:?}", err);
+                Ok(vec![])
+            }
+        };
+        match open_orders {
+            Ok(open_orders) => {
+                for order in open_orders.into_iter().filter_map(TrackedOrder::from_model) {
+                    if let Some(order_id) = order.order_id {
+                        self.order_ids.insert(order_id, order.ext_order_id);
+                    }
+                    self.orders.insert(order.ext_order_id, order);
+                }
+                slog::info!(self.logger, "Loaded {} open orders", self.orders.len());
+            }
+            Err(err) => slog::error!(self.logger, "Failed to load open orders: {:?}", err),
+        }
+        self.conn_pool = Some(conn_pool);
+    }
+
+    pub fn get_order(&self, ext_order_id: &ExtOrderId) -> Option<&TrackedOrder> {
+        self.orders.get(ext_order_id)
+    }
+
+    pub fn get_expected_position(&self, symbol: &Symbol) -> Option<Decimal> {
+        self.expected_positions.get(symbol).copied()
+    }
+
+    /// Starts tracking a sent order, the position of its symbol before it is needed for the first one.
+    pub fn track(&mut self, symbol: &Symbol, child: &ChildOrder, position: Option<Decimal>, timestamp: u64) {
+        if let Some(position) = position {
+            self.expected_positions.entry(symbol.clone()).or_insert(position);
+        }
+        let order = TrackedOrder {
+            ext_order_id: child.ext_order_id,
+            order_id: None,
+            symbol: symbol.clone(),
+            side: child.side,
+            quantity: child.quantity,
+            price: child.price,
+            filled: 0,
+            state: OrderState::Pending,
+            rejection_reason: None,
+            created_at: timestamp,
+            updated_at: timestamp,
+        };
+        self.persist(&order);
+        self.orders.insert(order.ext_order_id, order);
+    }
+
+    pub fn process_received(&mut self, received: &Received, timestamp: u64) {
+        let order = match self.orders.get_mut(&received.ext_order_id) {
+            Some(order) => order,
+            None => {
+                slog::warn!(self.logger, "Received untracked order {}", received.ext_order_id);
+                return;
+            }
+        };
+        order.order_id = Some(received.order_id);
+        if order.state == OrderState::Pending {
+            order.state = OrderState::Open;
+        }
+        order.updated_at = timestamp;
+        self.order_ids.insert(received.order_id, received.ext_order_id);
+        let order = order.clone();
+        self.persist(&order);
+    }
+
+    pub fn process_fill(&mut self, fill: &Fill, timestamp: u64) {
+        if let Some(expected_position) = self.expected_positions.get_mut(&fill.symbol) {
+            *expected_position += Decimal::from(fill.side.to_sign()) * Decimal::from(fill.quantity);
+        }
+        let order = match self.orders.get_mut(&fill.ext_order_id) {
+            Some(order) => order,
+            None => {
+                slog::warn!(
+                    self.logger,
+                    "Fill of {} {} contracts for untracked order {}",
+                    fill.quantity,
+                    fill.symbol,
+                    fill.ext_order_id
+                );
+                return;
+            }
+        };
+        order.filled += fill.quantity;
+        if order.filled >= order.quantity {
+            order.state = OrderState::Filled;
+        }
+        order.updated_at = timestamp;
+        let order = order.clone();
+        self.persist(&order);
+    }
+
+    pub fn process_done(&mut self, done: &Done, timestamp: u64) {
+        let mut order = match self
+            .order_ids
+            .remove(&done.order_id)
+            .and_then(|ext_order_id| self.orders.remove(&ext_order_id))
+        {
+            Some(order) => order,
+            None => return,
+        };
+        if order.state.is_open() {
+            order.state = OrderState::Cancelled;
+        }
+        order.updated_at = timestamp;
+        self.persist(&order);
+    }
+
+    pub fn process_rejection(&mut self, rejection: &OrderRejection, timestamp: u64) {
+        let mut order = match self.orders.remove(&rejection.ext_order_id) {
+            Some(order) => order,
+            None => return,
+        };
+        if let Some(order_id) = order.order_id {
+            self.order_ids.remove(&order_id);
+        }
+        order.state = OrderState::Rejected;
+        order.rejection_reason = Some(format!("{:?}", rejection.reason));
+        order.updated_at = timestamp;
+        self.persist(&order);
+    }
+
+    pub fn open_orders_requested(&mut self, timestamp: u64) {
+        self.open_orders_requested_at = Some(timestamp);
+    }
+
+    /// Cancels the orders the exchange no longer has open, their `Done` was missed while the dealer was down or
+    /// disconnected. Orders sent after the open orders were requested may not have reached the exchange yet.
+    pub fn reconcile_open_orders(&mut self, open_orders: &OpenOrders, timestamp: u64) {
+        let requested_at = match self.open_orders_requested_at.take() {
+            Some(requested_at) => requested_at,
+            None => return,
+        };
+        let exchange_orders = open_orders
+            .open_orders
+            .values()
+            .flatten()
+            .map(|order| (order.ext_order_id, order.order_id))
+            .collect::<HashMap<_, _>>();
+        let missing = self
+            .orders
+            .values()
+            .filter(|order| order.created_at < requested_at && !exchange_orders.contains_key(&order.ext_order_id))
+            .map(|order| order.ext_order_id)
+            .collect::<Vec<_>>();
+        for ext_order_id in missing {
+            let mut order = match self.orders.remove(&ext_order_id) {
+                Some(order) => order,
+                None => continue,
+            };
+            if let Some(order_id) = order.order_id {
+                self.order_ids.remove(&order_id);
+            }
+            slog::warn!(self.logger, "Order {} is no longer open on the exchange", ext_order_id);
+            if order.state.is_open() {
+                order.state = OrderState::Cancelled;
+            }
+            order.updated_at = timestamp;
+            self.persist(&order);
+        }
+        // orders whose `Received` was missed
+        for (ext_order_id, order_id) in exchange_orders {
+            let order = match self.orders.get_mut(&ext_order_id) {
+                Some(order) if order.order_id.is_none() => order,
+                _ => continue,
+            };
+            order.order_id = Some(order_id);
+            order.state = OrderState::Open;
+            order.updated_at = timestamp;
+            self.order_ids.insert(order_id, ext_order_id);
+            let order = order.clone();
+            self.persist(&order);
+        }
+    }
+
+    /// Symbols with an expected position to reconcile.
+    pub fn tracked_symbols(&self) -> Vec<Symbol> {
+        self.expected_positions.keys().cloned().collect()
+    }
+
+    /// Compares the exchange position with the fills, returns the difference.
+    pub fn reconcile(&mut self, symbol: &Symbol, position: Decimal) -> Decimal {
+        let expected_position = match self.expected_positions.get(symbol) {
+            Some(expected_position) => *expected_position,
+            None => return Decimal::ZERO,
+        };
+        let difference = position - expected_position;
+        if difference.is_zero() {
+            if let Some((_difference, true)) = self.mismatches.remove(symbol) {
+                slog::info!(self.logger, "{} position matches the fills again", symbol);
+            }
+            return difference;
+        }
+        match self.mismatches.get_mut(symbol) {
+            Some((last_difference, is_alerted)) if *last_difference == difference => {
+                if !*is_alerted {
+                    slog::error!(
+                        self.logger,
+                        "{} position of {} does not match the fills, expected {}",
+                        symbol,
+                        position,
+                        expected_position
+                    );
+                    *is_alerted = true;
+                }
+            }
+            _ => {
+                self.mismatches.insert(symbol.clone(), (difference, false));
+            }
+        }
+        difference
+    }
+
+    /// Forgets the expected positions, fills missed while disconnected would show up as mismatches.
+    pub fn reset_positions(&mut self) {
+        self.expected_positions.clear();
+        self.mismatches.clear();
+    }
+
+    fn persist(&self, order: &TrackedOrder) {
+        let conn_pool = match self.conn_pool {
+            Some(ref conn_pool) => conn_pool,
+            None => return,
+        };
+        let conn = match conn_pool.get() {
+            Ok(conn) => conn,
+            Err(err) => {
+                slog::error!(self.logger, "Couldn't get psql connection: {:?}", err);
+                return;
+            }
+        };
+        if let Err(err) = order.to_model().upsert(&conn) {
+            slog::error!(self.logger, "Failed to store order {}: {:?}", order.ext_order_id, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_types::kollider_client::{MarginType, OrderType};
+    use msgs::kollider_client::RejectionReason;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    fn btcusd() -> Symbol {
+        Symbol::from("BTCUSD.PERP")
+    }
+
+    fn fill(ext_order_id: ExtOrderId, order_id: OrderId, side: Side, quantity: u64) -> Fill {
+        Fill {
+            order_id,
+            ext_order_id,
+            price: 20000,
+            quantity,
+            partial: false,
+            symbol: btcusd(),
+            leverage: 100,
+            side,
+            is_maker: false,
+            margin_type: MarginType::Isolated,
+            is_selftrade: false,
+            timestamp: 0,
+        }
+    }
+
+    fn received(ext_order_id: ExtOrderId, order_id: OrderId, quantity: u64) -> Received {
+        Received {
+            timestamp: 0,
+            order_id,
+            ext_order_id,
+            uid: 0,
+            symbol: btcusd(),
+            quantity,
+            order_type: OrderType::Limit,
+            price: 20000,
+            leverage: 100,
+        }
+    }
+
+    #[test]
+    fn test_order_lifecycle() {
+        let mut tracker = OrderTracker::new(slog::Logger::root(slog::Discard, slog::o!()));
+        let ext_order_id = Uuid::new_v4();
+        let child = ChildOrder::new(ext_order_id, Side::Ask, 100, Some(20000), 0);
+        tracker.track(&btcusd(), &child, Some(dec!(-50)), 0);
+        assert_eq!(tracker.get_order(&ext_order_id).unwrap().state, OrderState::Pending);
+
+        tracker.process_received(&received(ext_order_id, 1, 100), 1);
+        tracker.process_fill(&fill(ext_order_id, 1, Side::Ask, 40), 2);
+        let order = tracker.get_order(&ext_order_id).unwrap();
+        assert_eq!(order.state, OrderState::Open);
+        assert_eq!(order.order_id, Some(1));
+        assert_eq!(order.filled, 40);
+
+        tracker.process_done(
+            &Done {
+                timestamp: 3,
+                order_id: 1,
+                symbol: btcusd(),
+            },
+            3,
+        );
+        assert!(tracker.get_order(&ext_order_id).is_none());
+        assert_eq!(tracker.get_expected_position(&btcusd()), Some(dec!(-90)));
+
+        let rejected_order_id = Uuid::new_v4();
+        let child = ChildOrder::new(rejected_order_id, Side::Bid, 100, None, 4);
+        tracker.track(&btcusd(), &child, Some(dec!(-90)), 4);
+        tracker.process_rejection(
+            &OrderRejection {
+                symbol: btcusd(),
+                ext_order_id: rejected_order_id,
+                reason: RejectionReason::NoMarket,
+            },
+            5,
+        );
+        assert!(tracker.get_order(&rejected_order_id).is_none());
+        assert_eq!(tracker.get_expected_position(&btcusd()), Some(dec!(-90)));
+    }
+
+    #[test]
+    fn test_reconcile_open_orders() {
+        let mut tracker = OrderTracker::new(slog::Logger::root(slog::Discard, slog::o!()));
+        let (missing, resting, unacknowledged, late) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        for (ext_order_id, timestamp) in [(missing, 0), (resting, 1), (unacknowledged, 2), (late, 11)] {
+            tracker.track(
+                &btcusd(),
+                &ChildOrder::new(ext_order_id, Side::Bid, 100, Some(20000), 0),
+                None,
+                timestamp,
+            );
+        }
+        tracker.process_received(&received(missing, 1, 100), 3);
+        tracker.process_received(&received(resting, 2, 100), 3);
+
+        let open_order = |ext_order_id, order_id| msgs::kollider_client::OpenOrder {
+            order_id,
+            ext_order_id,
+            symbol: btcusd(),
+            side: Side::Bid,
+            quantity: 100,
+            filled: 0,
+            price: 20000,
+        };
+        let open_orders = OpenOrders {
+            open_orders: [(btcusd(), vec![open_order(resting, 2), open_order(unacknowledged, 3)])]
+                .into_iter()
+                .collect(),
+        };
+        // nothing is reconciled unless the open orders were requested
+        tracker.reconcile_open_orders(&open_orders, 12);
+        assert!(tracker.get_order(&missing).is_some());
+
+        tracker.open_orders_requested(10);
+        tracker.reconcile_open_orders(&open_orders, 12);
+        assert!(tracker.get_order(&missing).is_none());
+        assert!(!tracker.order_ids.contains_key(&1));
+        assert_eq!(tracker.get_order(&resting).unwrap().state, OrderState::Open);
+        let order = tracker.get_order(&unacknowledged).unwrap();
+        assert_eq!((order.state, order.order_id), (OrderState::Open, Some(3)));
+        assert_eq!(tracker.get_order(&late).unwrap().state, OrderState::Pending);
+    }
+
+    #[test]
+    fn test_reconciliation() {
+        let mut tracker = OrderTracker::new(slog::Logger::root(slog::Discard, slog::o!()));
+        assert_eq!(tracker.reconcile(&btcusd(), dec!(100)), Decimal::ZERO);
+
+        let ext_order_id = Uuid::new_v4();
+        let child = ChildOrder::new(ext_order_id, Side::Bid, 100, None, 0);
+        tracker.track(&btcusd(), &child, Some(dec!(100)), 0);
+        tracker.process_fill(&fill(ext_order_id, 1, Side::Bid, 100), 1);
+        assert_eq!(tracker.get_order(&ext_order_id).unwrap().state, OrderState::Filled);
+        assert_eq!(tracker.reconcile(&btcusd(), dec!(200)), Decimal::ZERO);
+
+        // fills of orders the dealer did not send still move the expected position
+        tracker.process_fill(&fill(Uuid::new_v4(), 2, Side::Ask, 30), 2);
+        assert_eq!(tracker.reconcile(&btcusd(), dec!(170)), Decimal::ZERO);
+
+        assert_eq!(tracker.reconcile(&btcusd(), dec!(150)), dec!(-20));
+        assert_eq!(tracker.mismatches.get(&btcusd()), Some(&(dec!(-20), false)));
+        assert_eq!(tracker.reconcile(&btcusd(), dec!(150)), dec!(-20));
+        assert_eq!(tracker.mismatches.get(&btcusd()), Some(&(dec!(-20), true)));
+        assert_eq!(tracker.reconcile(&btcusd(), dec!(170)), Decimal::ZERO);
+        assert!(tracker.mismatches.is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+use bigdecimal::BigDecimal;
+use core_types::{Currency, DbPool, Symbol, SATS_IN_BITCOIN};
+use models::dealer_pnl_entries::{self, DealerPnlEntry, InsertableDealerPnlEntry};
+use msgs::kollider_client::{FundingPayment, Trade};
+use rust_decimal::prelude::*;
+
+/// Books the hedging pnl, funding and fees the exchange reports in sats as BTC entries. Spread revenue is
+/// booked by the bank once it executed a swap.
+pub struct PnlRecorder {
+    conn_pool: Option<DbPool>,
+    logger: slog::Logger,
+}
+
+impl PnlRecorder {
+    pub fn new(logger: slog::Logger) -> Self {
+        Self {
+            conn_pool: None,
+            logger,
+        }
+    }
+
+    pub fn set_conn_pool(&mut self, conn_pool: DbPool) {
+        self.conn_pool = Some(conn_pool);
+    }
+
+    /// Funding booked so far in sats, `None` if it could not be loaded.
+    pub fn get_total_funding(&self) -> Option<Decimal> {
+        let conn = self.conn_pool.as_ref()?.get().ok()?;
+        match DealerPnlEntry::get_total(&conn, dealer_pnl_entries::FUNDING, &Currency::BTC.to_string()) {
+            Ok(total) => Decimal::from_str(&total.to_string())
+                .ok()
+                .map(|total| total * SATS_IN_BITCOIN),
+            Err(err) => {
+                slog::error!(self.logger, "Failed to load the funding pnl: {:?}", err);
+                None
+            }
+        }
+    }
+
+    /// Whether funding was booked before, `None` if it could not be checked.
+    pub fn has_funding(&self) -> Option<bool> {
+        let conn = self.conn_pool.as_ref()?.get().ok()?;
+        match DealerPnlEntry::has_kind(&conn, dealer_pnl_entries::FUNDING) {
+            Ok(has_funding) => Some(has_funding),
+            Err(err) => {
+                slog::error!(self.logger, "Failed to check for funding pnl: {:?}", err);
+                None
+            }
+        }
+    }
+
+    /// Books the funding collected before entries were kept as a single entry.
+    pub fn seed_funding(&self, sats: Decimal, timestamp: u64) {
+        self.record(
+            dealer_pnl_entries::FUNDING,
+            sats,
+            None,
+            Some(String::from("influx")),
+            timestamp,
+        );
+    }
+
+    pub fn process_trade(&self, trade: &Trade, timestamp: u64) {
+        let reference = Some(trade.order_id.to_string());
+        if !trade.rpnl.is_zero() {
+            self.record(
+                dealer_pnl_entries::HEDGE,
+                trade.rpnl,
+                Some(&trade.symbol),
+                reference.clone(),
+                timestamp,
+            );
+        }
+        if !trade.fees.is_zero() {
+            self.record(
+                dealer_pnl_entries::FEE,
+                -trade.fees,
+                Some(&trade.symbol),
+                reference,
+                timestamp,
+            );
+        }
+    }
+
+    pub fn process_funding_payment(&self, funding_payment: &FundingPayment, timestamp: u64) {
+        self.record(
+            dealer_pnl_entries::FUNDING,
+            funding_payment.amount,
+            Some(&funding_payment.symbol),
+            None,
+            timestamp,
+        );
+    }
+
+    fn record(&self, kind: &str, sats: Decimal, symbol: Option<&Symbol>, reference: Option<String>, timestamp: u64) {
+        let conn_pool = match self.conn_pool {
+            Some(ref conn_pool) => conn_pool,
+            None => return,
+        };
+        let conn = match conn_pool.get() {
+            Ok(conn) => conn,
+            Err(err) => {
+                slog::error!(self.logger, "Couldn't get psql connection: {:?}", err);
+                return;
+            }
+        };
+        let amount = match BigDecimal::from_str(&(sats / SATS_IN_BITCOIN).to_string()) {
+            Ok(amount) => amount,
+            Err(_) => return,
+        };
+        let entry = InsertableDealerPnlEntry {
+            created_at: timestamp as i64,
+            kind: kind.to_string(),
+            currency: Currency::BTC.to_string(),
+            amount,
+            symbol: symbol.cloned(),
+            reference,
+        };
+        if let Err(err) = entry.insert(&conn) {
+            slog::error!(
+                self.logger,
+                "Failed to book {:?} {} pnl of {}: {:?}",
+                symbol,
+                kind,
+                sats,
+                err
+            );
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use core_types::{Currency, DbPool, Money, Rate, UserId};
+use hmac::{Hmac, Mac};
+use models::dealer_redeemed_quotes::{self, InsertableDealerRedeemedQuote};
+use msgs::api::{QuoteResponse, SwapRequest};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const DEFAULT_QUOTE_TTL_MS: u64 = 5000;
+const REMOVE_EXPIRED_INTERVAL_MS: u64 = 60000;
+
+fn default_ttl_ms() -> u64 {
+    DEFAULT_QUOTE_TTL_MS
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QuoteSettings {
+    /// Key quote ids are signed with. Dealers sharing it and the database honour each other's quotes, also
+    /// across restarts, without it a random key is used that is lost when the dealer stops.
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default = "default_ttl_ms")]
+    pub ttl_ms: u64,
+    /// Time quotes are guaranteed for per currency pair such as `BTCUSD`, in both directions.
+    #[serde(default)]
+    pub ttls: HashMap<String, u64>,
+}
+
+impl Default for QuoteSettings {
+    fn default() -> Self {
+        Self {
+            secret: None,
+            ttl_ms: default_ttl_ms(),
+            ttls: HashMap::new(),
+        }
+    }
+}
+
+/// What a quote id guarantees, carried in the id itself.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SignedQuote {
+    pub uid: UserId,
+    pub amount: Money,
+    pub from: Currency,
+    pub to: Currency,
+    pub rate: Rate,
+    pub fees: Option<Money>,
+    /// Epoch in ms.
+    pub valid_until: u64,
+    nonce: Uuid,
+}
+
+impl SignedQuote {
+    pub fn matches(&self, swap_request: &SwapRequest) -> bool {
+        self.from == swap_request.from
+            && self.to == swap_request.to
+            && self.amount.value() == swap_request.amount.value()
+            && self.uid == swap_request.uid
+    }
+}
+
+/// Issues quote ids as HMAC signed tokens that can be validated without keeping the quotes, only ids of
+/// redeemed quotes are kept until they expire so that a quote is honoured once. They are stored in the
+/// database once there is one, in memory of this dealer otherwise.
+pub struct QuoteSigner {
+    key: Vec<u8>,
+    ttl_ms: u64,
+    ttls: HashMap<String, u64>,
+    redeemed: HashMap<String, u64>,
+    conn_pool: Option<DbPool>,
+    last_removal: u64,
+    logger: slog::Logger,
+}
+
+impl QuoteSigner {
+    pub fn new(settings: QuoteSettings, logger: slog::Logger) -> Self {
+        let key = match settings.secret {
+            Some(secret) => secret.into_bytes(),
+            None => Uuid::new_v4().as_bytes().to_vec(),
+        };
+        let ttls = settings
+            .ttls
+            .into_iter()
+            .map(|(pair, ttl_ms)| (pair.to_lowercase(), ttl_ms))
+            .collect();
+        Self {
+            key,
+            ttl_ms: settings.ttl_ms,
+            ttls,
+            redeemed: HashMap::new(),
+            conn_pool: None,
+            last_removal: 0,
+            logger,
+        }
+    }
+
+    pub fn set_conn_pool(&mut self, conn_pool: DbPool) {
+        self.conn_pool = Some(conn_pool);
+    }
+
+    pub fn ttl_ms(&self, from: Currency, to: Currency) -> u64 {
+        let pair = format!("{from}{to}").to_lowercase();
+        let reversed_pair = format!("{to}{from}").to_lowercase();
+        self.ttls
+            .get(&pair)
+            .or_else(|| self.ttls.get(&reversed_pair))
+            .copied()
+            .unwrap_or(self.ttl_ms)
+    }
+
+    /// Id guaranteeing the rate and fees of the quote until its `valid_until`, `None` without a rate.
+    pub fn sign(&self, quote: &QuoteResponse) -> Option<String> {
+        let signed_quote = SignedQuote {
+            uid: quote.uid,
+            amount: quote.amount,
+            from: quote.from,
+            to: quote.to,
+            rate: quote.rate?,
+            fees: quote.fees,
+            valid_until: quote.valid_until,
+            nonce: Uuid::new_v4(),
+        };
+        let payload = serde_json::to_vec(&signed_quote).ok()?;
+        let payload = base64::encode_config(payload, base64::URL_SAFE_NO_PAD);
+        let signature = base64::encode_config(self.mac(&payload).finalize().into_bytes(), base64::URL_SAFE_NO_PAD);
+        Some(format!("{payload}.{signature}"))
+    }
+
+    /// The quote behind the id, `None` if it was not signed with this key, has expired, doesn't match the swap or
+    /// was redeemed before. Only a matching quote is marked as redeemed.
+    pub fn redeem(&mut self, quote_id: &str, swap_request: &SwapRequest, now: u64) -> Option<SignedQuote> {
+        let (payload, signature) = quote_id.split_once('.')?;
+        let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD).ok()?;
+        self.mac(payload).verify_slice(&signature).ok()?;
+        let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+        let signed_quote = serde_json::from_slice::<SignedQuote>(&payload).ok()?;
+        if signed_quote.valid_until < now
+            || !signed_quote.matches(swap_request)
+            || !self.mark_redeemed(quote_id, signed_quote.valid_until)
+        {
+            return None;
+        }
+        Some(signed_quote)
+    }
+
+    /// Forgets redeemed quotes that have expired since, they are rejected for their expiry anyway.
+    pub fn remove_expired(&mut self, now: u64) {
+        self.redeemed.retain(|_, valid_until| *valid_until >= now);
+        if now.saturating_sub(self.last_removal) < REMOVE_EXPIRED_INTERVAL_MS {
+            return;
+        }
+        let conn = match self.conn_pool.as_ref().map(|conn_pool| conn_pool.get()) {
+            Some(Ok(conn)) => conn,
+            Some(Err(err)) => {
+                slog::error!(self.logger, "Couldn't get psql connection: {:?}", err);
+                return;
+            }
+            None => return,
+        };
+        self.last_removal = now;
+        if let Err(err) = dealer_redeemed_quotes::remove_expired(&conn, now as i64) {
+            slog::error!(self.logger, "Failed to remove expired redeemed quotes: {:?}", err);
+        }
+    }
+
+    /// `false` if the quote was redeemed before. Quotes are rejected if that can't be checked.
+    fn mark_redeemed(&mut self, quote_id: &str, valid_until: u64) -> bool {
+        let conn_pool = match self.conn_pool {
+            Some(ref conn_pool) => conn_pool,
+            None => return self.redeemed.insert(quote_id.to_string(), valid_until).is_none(),
+        };
+        let conn = match conn_pool.get() {
+            Ok(conn) => conn,
+            Err(err) => {
+                slog::error!(self.logger, "Couldn't get psql connection: {:?}", err);
+                return false;
+            }
+        };
+        let redeemed_quote = InsertableDealerRedeemedQuote {
+            quote_id: quote_id.to_string(),
+            valid_until: valid_until as i64,
+        };
+        match redeemed_quote.insert(&conn) {
+            Ok(inserted) => inserted,
+            Err(err) => {
+                slog::error!(self.logger, "Failed to store redeemed quote {}: {:?}", quote_id, err);
+                false
+            }
+        }
+    }
+
+    fn mac(&self, payload: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn quote(valid_until: u64) -> QuoteResponse {
+        QuoteResponse {
+            req_id: Uuid::new_v4(),
+            uid: 1003,
+            amount: Money::new(Currency::BTC, dec!(0.01)),
+            from: Currency::BTC,
+            to: Currency::USD,
+            valid_until,
+            rate: Some(Rate::new(Currency::BTC, Currency::USD, dec!(20000))),
+            quote_id: None,
+            error: None,
+            fees: None,
+            spread: None,
+        }
+    }
+
+    fn swap_request() -> SwapRequest {
+        SwapRequest {
+            req_id: Uuid::new_v4(),
+            uid: 1003,
+            amount: Money::new(Currency::BTC, dec!(0.01)),
+            from: Currency::BTC,
+            to: Currency::USD,
+            quote_id: None,
+        }
+    }
+
+    fn logger() -> slog::Logger {
+        slog::Logger::root(slog::Discard, slog::o!())
+    }
+
+    fn settings(secret: &str) -> QuoteSettings {
+        QuoteSettings {
+            secret: Some(secret.to_string()),
+            ttl_ms: 5000,
+            ttls: [(String::from("BTCEUR"), 10000)].into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn test_redeem_once() {
+        let mut signer = QuoteSigner::new(settings("secret"), logger());
+        let quote_id = signer.sign(&quote(1000)).unwrap();
+
+        // a dealer started with the same secret honours the quote
+        let mut other_signer = QuoteSigner::new(settings("secret"), logger());
+        // a swap that doesn't match leaves the quote to the one that does
+        let other_swap_request = SwapRequest {
+            amount: Money::new(Currency::BTC, dec!(0.02)),
+            ..swap_request()
+        };
+        assert!(other_signer.redeem(&quote_id, &other_swap_request, 1000).is_none());
+        let signed_quote = other_signer.redeem(&quote_id, &swap_request(), 1000).unwrap();
+        assert_eq!(signed_quote.rate.value(), dec!(20000));
+        assert_eq!(signed_quote.amount.value(), dec!(0.01));
+        assert!(other_signer.redeem(&quote_id, &swap_request(), 1000).is_none());
+
+        assert!(signer.redeem(&quote_id, &swap_request(), 1001).is_none());
+        assert!(QuoteSigner::new(settings("other secret"), logger())
+            .redeem(&quote_id, &swap_request(), 0)
+            .is_none());
+    }
+
+    #[test]
+    fn test_tampered_quote() {
+        let mut signer = QuoteSigner::new(settings("secret"), logger());
+        let quote_id = signer.sign(&quote(1000)).unwrap();
+        let (_, signature) = quote_id.split_once('.').unwrap();
+
+        let other_quote_id = signer.sign(&quote(2000)).unwrap();
+        let (other_payload, _) = other_quote_id.split_once('.').unwrap();
+        assert!(signer
+            .redeem(&format!("{other_payload}.{signature}"), &swap_request(), 0)
+            .is_none());
+        assert!(signer.redeem("12345", &swap_request(), 0).is_none());
+        assert!(signer.redeem(&other_quote_id, &swap_request(), 0).is_some());
+    }
+
+    #[test]
+    fn test_ttls_and_expiry() {
+        let mut signer = QuoteSigner::new(settings("secret"), logger());
+        assert_eq!(signer.ttl_ms(Currency::BTC, Currency::USD), 5000);
+        assert_eq!(signer.ttl_ms(Currency::BTC, Currency::EUR), 10000);
+        assert_eq!(signer.ttl_ms(Currency::EUR, Currency::BTC), 10000);
+
+        let quote_id = signer.sign(&quote(1000)).unwrap();
+        assert!(signer.redeem(&quote_id, &swap_request(), 500).is_some());
+        signer.remove_expired(1000);
+        assert_eq!(signer.redeemed.len(), 1);
+        signer.remove_expired(1001);
+        assert!(signer.redeemed.is_empty());
+    }
+}
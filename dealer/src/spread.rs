@@ -0,0 +1,140 @@
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Quote size the size component starts growing from.
+const SMALLEST_QUOTE_SIZE: u64 = 10;
+
+fn default_volatility_window() -> usize {
+    60
+}
+
+fn default_volatility_sample_interval_ms() -> u64 {
+    1000
+}
+
+/// Widens the configured `spread` with market conditions, all factors are fractions of the price.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DynamicSpreadSettings {
+    /// Spread added per unit of realised volatility, the standard deviation of mid price returns between samples.
+    #[serde(default)]
+    pub volatility_factor: Decimal,
+    /// Number of mid price samples volatility is computed over.
+    #[serde(default = "default_volatility_window")]
+    pub volatility_window: usize,
+    #[serde(default = "default_volatility_sample_interval_ms")]
+    pub volatility_sample_interval_ms: u64,
+    /// Spread added on quotes growing an unhedged delta as large as the risk tolerance, less for smaller deltas.
+    #[serde(default)]
+    pub inventory_factor: Decimal,
+    /// Spread added for every tenfold of the quote size.
+    #[serde(default)]
+    pub size_factor: Decimal,
+    /// Minimum total spread per currency.
+    #[serde(default)]
+    pub floors: HashMap<String, Decimal>,
+    /// Maximum total spread per currency.
+    #[serde(default)]
+    pub caps: HashMap<String, Decimal>,
+}
+
+/// Mid prices of a symbol sampled at a fixed interval.
+#[derive(Debug, Default)]
+pub struct VolatilityTracker {
+    samples: VecDeque<Decimal>,
+    last_sample_timestamp: Option<u64>,
+}
+
+impl VolatilityTracker {
+    pub fn sample(&mut self, mid_price: Decimal, timestamp: u64, interval_ms: u64, window: usize) {
+        if let Some(last_sample_timestamp) = self.last_sample_timestamp {
+            if timestamp < last_sample_timestamp + interval_ms {
+                return;
+            }
+        }
+        self.last_sample_timestamp = Some(timestamp);
+        self.samples.push_back(mid_price);
+        while self.samples.len() > window.max(2) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Standard deviation of the returns between samples, zero until there are enough of them.
+    pub fn volatility(&self) -> Decimal {
+        let returns = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .filter_map(|(previous, current)| {
+                if previous.is_zero() {
+                    return None;
+                }
+                (current / previous - Decimal::ONE).to_f64()
+            })
+            .collect::<Vec<_>>();
+        if returns.len() < 2 {
+            return Decimal::ZERO;
+        }
+        let count = returns.len() as f64;
+        let mean = returns.iter().sum::<f64>() / count;
+        let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (count - 1.0);
+        Decimal::from_f64(variance.sqrt()).unwrap_or_default()
+    }
+}
+
+/// Number of tenfolds `quantity` is above the smallest quote size.
+pub fn size_magnitude(quantity: u64) -> u32 {
+    let mut magnitude = 0;
+    let mut size = SMALLEST_QUOTE_SIZE;
+    while size < quantity {
+        size = size.saturating_mul(10);
+        magnitude += 1;
+    }
+    magnitude
+}
+
+/// Share of the risk tolerance a quote adds to the unhedged delta, between zero and one.
+/// `direction` is the sign the quote moves the delta in.
+pub fn inventory_usage(unhedged_delta: Decimal, direction: Decimal, risk_tolerance: u64) -> Decimal {
+    let growing_delta = unhedged_delta * direction;
+    if growing_delta <= Decimal::ZERO {
+        return Decimal::ZERO;
+    }
+    if risk_tolerance == 0 {
+        return Decimal::ONE;
+    }
+    (growing_delta / Decimal::from(risk_tolerance)).min(Decimal::ONE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_volatility_sampling() {
+        let mut tracker = VolatilityTracker::default();
+        for (timestamp, price) in [(0, dec!(100)), (500, dec!(200)), (1000, dec!(101)), (2000, dec!(100))] {
+            tracker.sample(price, timestamp, 1000, 3);
+        }
+        // the 200 sample is skipped, returns are +1% and -0.99%
+        assert_eq!(tracker.volatility().round_dp(4), dec!(0.0141));
+
+        for timestamp in [3000, 4000, 5000] {
+            tracker.sample(dec!(100), timestamp, 1000, 3);
+        }
+        assert_eq!(tracker.volatility(), Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_size_and_inventory() {
+        assert_eq!(size_magnitude(1), 0);
+        assert_eq!(size_magnitude(10), 0);
+        assert_eq!(size_magnitude(11), 1);
+        assert_eq!(size_magnitude(1_000_000), 5);
+
+        assert_eq!(inventory_usage(dec!(-50), dec!(-1), 100), dec!(0.5));
+        assert_eq!(inventory_usage(dec!(-500), dec!(-1), 100), Decimal::ONE);
+        assert_eq!(inventory_usage(dec!(-500), Decimal::ONE, 100), Decimal::ZERO);
+    }
+}
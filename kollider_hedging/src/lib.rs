@@ -382,9 +382,46 @@ impl WsClient for KolliderHedgingClient {
         self.checked_send_request(&withdrawal_request)
     }
 
-    fn make_order(&self, quantity: u64, symbol: Symbol, side: Side) -> Result<()> {
-        let order = Request::Order(Order::new(side, quantity, symbol, Some(Uuid::new_v4())));
-        self.checked_send_request(&order)
+    fn make_order(&self, quantity: u64, symbol: Symbol, side: Side) -> Result<ExtOrderId> {
+        let ext_order_id = Uuid::new_v4();
+        let order = Request::Order(Order::new(side, quantity, symbol, Some(ext_order_id)));
+        self.checked_send_request(&order)?;
+        Ok(ext_order_id)
+    }
+
+    fn make_limit_order(
+        &self,
+        quantity: u64,
+        symbol: Symbol,
+        side: Side,
+        price: u64,
+        is_post_only: bool,
+    ) -> Result<ExtOrderId> {
+        let ext_order_id = Uuid::new_v4();
+        let order = Request::Order(Order::limit(
+            side,
+            quantity,
+            symbol,
+            price,
+            is_post_only,
+            Some(ext_order_id),
+        ));
+        self.checked_send_request(&order)?;
+        Ok(ext_order_id)
+    }
+
+    fn cancel_order(&self, order_id: OrderId, symbol: Symbol) -> Result<()> {
+        let cancel_order = Request::CancelOrder(CancelOrder {
+            order_id,
+            symbol,
+            settlement_type: SettlementType::Instant,
+        });
+        self.checked_send_request(&cancel_order)
+    }
+
+    fn fetch_open_orders(&self) -> Result<()> {
+        let fetch_open_orders = Request::FetchOpenOrders;
+        self.checked_send_request(&fetch_open_orders)
     }
 
     fn buy(&self, quantity: u64, currency: Currency) -> Result<()> {
@@ -482,8 +519,10 @@ fn process_incoming_message(
         KolliderApiResponse::MarkPrices(mark_price) => {
             get_locked_state(shared_state)
                 .mark_prices
-                .insert(mark_price.symbol.clone(), mark_price);
+                .insert(mark_price.symbol.clone(), mark_price.clone());
             shared_state_changed.notify_one();
+            let msg = Message::KolliderApiResponse(KolliderApiResponse::MarkPrices(mark_price));
+            send_to_callback(callback, msg);
         }
         KolliderApiResponse::OrderInvoice(_order_invoice) => {
             let msg = Message::KolliderApiResponse(response);
@@ -519,6 +558,15 @@ fn process_incoming_message(
             let msg = Message::KolliderApiResponse(response);
             send_to_callback(callback, msg);
         }
+        KolliderApiResponse::Received(_)
+        | KolliderApiResponse::Fill(_)
+        | KolliderApiResponse::Done(_)
+        | KolliderApiResponse::OrderRejection(_)
+        | KolliderApiResponse::OpenOrders(_)
+        | KolliderApiResponse::Trade(_) => {
+            let msg = Message::KolliderApiResponse(response);
+            send_to_callback(callback, msg);
+        }
         _ => {}
     }
 }
@@ -147,6 +147,8 @@ impl LndConnector {
                 target_account_currency: None,
                 reference: Some(memo),
                 description: metadata,
+                payer_comment: None,
+                payer_data: None,
             };
             return Ok(invoice);
         }
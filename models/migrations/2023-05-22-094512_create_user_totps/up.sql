@@ -0,0 +1,9 @@
+CREATE TABLE user_totps (
+	uid integer references "users" (uid) NOT NULL PRIMARY KEY,
+	encrypted_secret TEXT NOT NULL,
+	enabled BOOLEAN NOT NULL DEFAULT false,
+	threshold decimal NOT NULL DEFAULT 0,
+	threshold_currency TEXT NOT NULL DEFAULT 'BTC',
+	backup_codes TEXT[] NOT NULL DEFAULT '{}',
+	last_used_step BIGINT NOT NULL DEFAULT 0
+);
@@ -0,0 +1 @@
+DROP TABLE lnurl_auth_keys;
\ No newline at end of file
@@ -0,0 +1,8 @@
+CREATE TABLE lnurl_auth_keys (
+created_at TIMESTAMP default now(),
+linking_key TEXT PRIMARY KEY,
+uid integer NOT NULL,
+CONSTRAINT fk_id
+FOREIGN KEY (uid)
+REFERENCES users(uid)
+);
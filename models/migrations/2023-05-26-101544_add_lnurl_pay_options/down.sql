@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE invoices DROP COLUMN payer_data;
+ALTER TABLE invoices DROP COLUMN payer_comment;
+ALTER TABLE user_profiles DROP COLUMN lnurl_success_url;
+ALTER TABLE user_profiles DROP COLUMN lnurl_success_message;
+ALTER TABLE user_profiles DROP COLUMN lnurl_payer_data;
+ALTER TABLE user_profiles DROP COLUMN lnurl_comment_allowed;
\ No newline at end of file
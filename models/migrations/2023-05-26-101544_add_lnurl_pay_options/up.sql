@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE user_profiles ADD COLUMN lnurl_comment_allowed INTEGER;
+ALTER TABLE user_profiles ADD COLUMN lnurl_payer_data TEXT[];
+ALTER TABLE user_profiles ADD COLUMN lnurl_success_message TEXT;
+ALTER TABLE user_profiles ADD COLUMN lnurl_success_url TEXT;
+ALTER TABLE invoices ADD COLUMN payer_comment TEXT;
+ALTER TABLE invoices ADD COLUMN payer_data TEXT;
\ No newline at end of file
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE nwc_connections (
+created_at TIMESTAMP default now(),
+pubkey TEXT PRIMARY KEY,
+uid integer NOT NULL,
+name TEXT NOT NULL,
+allowed_methods TEXT[] NOT NULL,
+budget_msat BIGINT,
+budget_renewal_secs BIGINT,
+budget_spent_msat BIGINT NOT NULL DEFAULT 0,
+budget_period_start BIGINT NOT NULL,
+revoked BOOLEAN NOT NULL DEFAULT false,
+CONSTRAINT fk_id
+FOREIGN KEY (uid)
+REFERENCES users(uid)
+);
@@ -0,0 +1,2 @@
+DROP TABLE nostr_dm_deliveries;
+ALTER TABLE nostr_public_keys DROP COLUMN IF EXISTS legacy_dms;
\ No newline at end of file
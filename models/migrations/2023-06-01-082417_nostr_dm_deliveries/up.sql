@@ -0,0 +1,12 @@
+ALTER TABLE nostr_public_keys ADD COLUMN IF NOT EXISTS legacy_dms BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE nostr_dm_deliveries (
+id SERIAL NOT NULL PRIMARY KEY,
+created_at BIGINT NOT NULL,
+event_id TEXT NOT NULL,
+pubkey TEXT NOT NULL,
+protocol TEXT NOT NULL,
+relay_url TEXT NOT NULL,
+success BOOLEAN NOT NULL,
+error TEXT
+);
@@ -0,0 +1 @@
+DROP TABLE nostr_relays;
\ No newline at end of file
@@ -0,0 +1,13 @@
+CREATE TABLE nostr_relays (
+url TEXT NOT NULL PRIMARY KEY,
+source TEXT NOT NULL,
+enabled BOOLEAN NOT NULL DEFAULT true,
+connected BOOLEAN NOT NULL DEFAULT false,
+latency_ms BIGINT,
+publish_attempts BIGINT NOT NULL DEFAULT 0,
+publish_successes BIGINT NOT NULL DEFAULT 0,
+consecutive_failures INTEGER NOT NULL DEFAULT 0,
+backoff_until BIGINT NOT NULL DEFAULT 0,
+last_used_at BIGINT NOT NULL DEFAULT 0,
+updated_at BIGINT NOT NULL
+);
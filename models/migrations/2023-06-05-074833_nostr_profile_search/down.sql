@@ -0,0 +1,7 @@
+DROP INDEX IF EXISTS nostr_profile_records_received_at_idx;
+DROP INDEX IF EXISTS nostr_profile_records_lud16_trgm_idx;
+DROP INDEX IF EXISTS nostr_profile_records_nip05_trgm_idx;
+DROP INDEX IF EXISTS nostr_profile_records_display_name_trgm_idx;
+DROP INDEX IF EXISTS nostr_profile_records_name_trgm_idx;
+DROP INDEX IF EXISTS nostr_profile_records_search_vector_idx;
+DROP FUNCTION IF EXISTS nostr_profile_search_vector(TEXT, TEXT, TEXT, TEXT);
@@ -0,0 +1,23 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE OR REPLACE FUNCTION nostr_profile_search_vector(name TEXT, display_name TEXT, nip05 TEXT, lud16 TEXT)
+RETURNS tsvector
+LANGUAGE SQL IMMUTABLE
+AS $$
+SELECT setweight(to_tsvector('simple'::regconfig, coalesce(name, '') || ' ' || coalesce(display_name, '')), 'A') ||
+    setweight(to_tsvector('simple'::regconfig, coalesce(nip05, '') || ' ' || coalesce(lud16, '')), 'B')
+$$;
+
+CREATE INDEX nostr_profile_records_search_vector_idx ON nostr_profile_records
+USING GIN (nostr_profile_search_vector(name, display_name, nip05, lud16));
+
+CREATE INDEX nostr_profile_records_name_trgm_idx ON nostr_profile_records
+USING GIN (lower(coalesce(name, '')) gin_trgm_ops);
+CREATE INDEX nostr_profile_records_display_name_trgm_idx ON nostr_profile_records
+USING GIN (lower(coalesce(display_name, '')) gin_trgm_ops);
+CREATE INDEX nostr_profile_records_nip05_trgm_idx ON nostr_profile_records
+USING GIN (lower(coalesce(nip05, '')) gin_trgm_ops);
+CREATE INDEX nostr_profile_records_lud16_trgm_idx ON nostr_profile_records
+USING GIN (lower(coalesce(lud16, '')) gin_trgm_ops);
+
+CREATE INDEX nostr_profile_records_received_at_idx ON nostr_profile_records (received_at DESC);
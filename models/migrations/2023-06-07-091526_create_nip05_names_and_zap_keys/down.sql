@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE nostr_zap_keys;
+DROP TABLE nostr_nip05_names;
\ No newline at end of file
@@ -0,0 +1,30 @@
+-- Your SQL goes here
+CREATE TABLE nostr_nip05_names (
+created_at TIMESTAMP default now(),
+name TEXT PRIMARY KEY,
+uid integer NOT NULL,
+pubkey TEXT NOT NULL,
+relays TEXT[] NOT NULL DEFAULT '{}',
+disabled BOOLEAN NOT NULL DEFAULT false,
+CONSTRAINT fk_id
+FOREIGN KEY (uid)
+REFERENCES users(uid)
+);
+
+CREATE INDEX nostr_nip05_names_uid_idx ON nostr_nip05_names (uid);
+
+-- usernames with a linked pubkey were the only NIP-05 names so far
+INSERT INTO nostr_nip05_names (name, uid, pubkey)
+SELECT lower(users.username), users.uid, nostr_public_keys.pubkey
+FROM users INNER JOIN nostr_public_keys ON nostr_public_keys.uid = users.uid
+ON CONFLICT DO NOTHING;
+
+CREATE TABLE nostr_zap_keys (
+created_at TIMESTAMP default now(),
+uid integer PRIMARY KEY,
+pubkey TEXT NOT NULL UNIQUE,
+secret_key TEXT NOT NULL,
+CONSTRAINT fk_id
+FOREIGN KEY (uid)
+REFERENCES users(uid)
+);
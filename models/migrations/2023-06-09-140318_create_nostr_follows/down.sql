@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX summary_transactions_inbound_uid_outbound_username_idx;
+DROP INDEX summary_transactions_outbound_uid_inbound_username_idx;
+DROP TABLE nostr_follows;
+DROP TABLE nostr_contact_lists;
\ No newline at end of file
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+CREATE TABLE nostr_contact_lists (
+pubkey TEXT PRIMARY KEY,
+created_at BIGINT NOT NULL,
+received_at BIGINT NOT NULL,
+follows_count INTEGER NOT NULL
+);
+
+CREATE TABLE nostr_follows (
+pubkey TEXT NOT NULL,
+followed_pubkey TEXT NOT NULL,
+relay TEXT,
+petname TEXT,
+position INTEGER NOT NULL,
+PRIMARY KEY (pubkey, followed_pubkey)
+);
+
+-- interactions with a follow are counted from payments to and from their lightning address
+CREATE INDEX summary_transactions_outbound_uid_inbound_username_idx
+ON summary_transactions (outbound_uid, lower(inbound_username));
+CREATE INDEX summary_transactions_inbound_uid_outbound_username_idx
+ON summary_transactions (inbound_uid, lower(outbound_username));
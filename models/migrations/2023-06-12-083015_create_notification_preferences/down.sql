@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE notification_preferences;
+ALTER TABLE user_profiles DROP COLUMN notification_locale;
\ No newline at end of file
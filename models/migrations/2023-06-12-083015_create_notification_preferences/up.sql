@@ -0,0 +1,13 @@
+-- Your SQL goes here
+ALTER TABLE user_profiles ADD COLUMN notification_locale TEXT;
+
+CREATE TABLE notification_preferences (
+uid integer NOT NULL,
+event_type TEXT NOT NULL,
+channel TEXT NOT NULL,
+enabled BOOLEAN NOT NULL,
+PRIMARY KEY (uid, event_type, channel),
+CONSTRAINT fk_id
+FOREIGN KEY (uid)
+REFERENCES users(uid)
+);
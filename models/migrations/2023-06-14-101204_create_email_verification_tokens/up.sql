@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE email_verification_tokens (
+token_hash TEXT PRIMARY KEY,
+uid integer NOT NULL,
+email TEXT NOT NULL,
+created_at BIGINT NOT NULL,
+expires_at BIGINT NOT NULL,
+CONSTRAINT fk_id
+FOREIGN KEY (uid)
+REFERENCES users(uid)
+);
+
+CREATE INDEX email_verification_tokens_uid_idx ON email_verification_tokens (uid);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE pre_signups DROP COLUMN invite_code;
+DROP TABLE invite_codes;
\ No newline at end of file
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE invite_codes (
+code TEXT PRIMARY KEY,
+created_at TIMESTAMP default now(),
+created_by integer,
+max_uses integer NOT NULL,
+uses integer NOT NULL DEFAULT 0,
+expires_at BIGINT,
+CONSTRAINT fk_id
+FOREIGN KEY (created_by)
+REFERENCES users(uid)
+);
+
+-- waitlist entries get their own invite code once invited
+ALTER TABLE pre_signups ADD COLUMN invite_code TEXT REFERENCES invite_codes(code);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE dealer_orders (
+ext_order_id UUID PRIMARY KEY,
+order_id BIGINT,
+symbol TEXT NOT NULL,
+side TEXT NOT NULL,
+quantity BIGINT NOT NULL,
+price BIGINT,
+filled BIGINT NOT NULL DEFAULT 0,
+state TEXT NOT NULL,
+rejection_reason TEXT,
+created_at BIGINT NOT NULL,
+updated_at BIGINT NOT NULL
+);
+
+CREATE INDEX dealer_orders_state_idx ON dealer_orders(state);
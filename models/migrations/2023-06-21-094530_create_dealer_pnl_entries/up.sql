@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE dealer_pnl_entries (
+id SERIAL PRIMARY KEY,
+created_at BIGINT NOT NULL,
+kind TEXT NOT NULL,
+currency TEXT NOT NULL,
+amount NUMERIC NOT NULL,
+symbol TEXT,
+reference TEXT
+);
+
+CREATE INDEX dealer_pnl_entries_created_at_idx ON dealer_pnl_entries(created_at);
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE insurance_fund_movements (
+id SERIAL PRIMARY KEY,
+created_at BIGINT NOT NULL,
+kind TEXT NOT NULL,
+amount NUMERIC NOT NULL,
+balance NUMERIC NOT NULL,
+reference TEXT
+);
+
+CREATE INDEX insurance_fund_movements_created_at_idx ON insurance_fund_movements(created_at);
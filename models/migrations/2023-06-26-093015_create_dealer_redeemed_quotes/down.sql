@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE dealer_redeemed_quotes;
\ No newline at end of file
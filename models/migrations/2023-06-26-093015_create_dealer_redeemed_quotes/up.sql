@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE dealer_redeemed_quotes (
+quote_id TEXT PRIMARY KEY,
+valid_until BIGINT NOT NULL
+);
+
+CREATE INDEX dealer_redeemed_quotes_valid_until_idx ON dealer_redeemed_quotes(valid_until);
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE nwc_requests (
+event_id TEXT PRIMARY KEY,
+pubkey TEXT NOT NULL,
+created_at BIGINT NOT NULL,
+req_id UUID,
+spent_msat BIGINT
+);
+
+CREATE INDEX nwc_requests_req_id_idx ON nwc_requests(req_id);
+CREATE INDEX nwc_requests_created_at_idx ON nwc_requests(created_at);
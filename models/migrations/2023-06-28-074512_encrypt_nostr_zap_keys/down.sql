@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM nostr_zap_keys;
+ALTER TABLE nostr_zap_keys RENAME COLUMN encrypted_secret_key TO secret_key;
\ No newline at end of file
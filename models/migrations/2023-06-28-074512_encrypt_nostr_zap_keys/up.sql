@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- Zap keys used to be stored in plain text and can't be encrypted here. They are dropped, their owners get a new
+-- one once they set their nostr pubkey again and zap receipts are signed with the server key until then.
+DELETE FROM nostr_zap_keys;
+ALTER TABLE nostr_zap_keys RENAME COLUMN secret_key TO encrypted_secret_key;
@@ -126,6 +126,10 @@ impl Account {
         Ok(internal_accounts)
     }
 
+    pub fn get_dealer_insurance_accounts(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        Self::get_accounts(conn, 52172712, "dealer", "Internal", "Insurance")
+    }
+
     pub fn get_bank_liabilities(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
         Self::get_accounts(conn, 23193913, "bank", "External", "Cash")
     }
@@ -0,0 +1,61 @@
+use crate::schema::dealer_orders;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use uuid::Uuid;
+
+/// Hedge order sent by the dealer, prices and quantities as sent to the exchange.
+#[derive(Queryable, Identifiable, Debug, Clone)]
+#[primary_key(ext_order_id)]
+pub struct DealerOrder {
+    pub ext_order_id: Uuid,
+    /// Assigned by the exchange once it received the order.
+    pub order_id: Option<i64>,
+    pub symbol: String,
+    pub side: String,
+    pub quantity: i64,
+    /// Limit price in the symbol's price decimal places, `None` for market orders.
+    pub price: Option<i64>,
+    pub filled: i64,
+    pub state: String,
+    pub rejection_reason: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl DealerOrder {
+    pub fn get_by_states(conn: &diesel::PgConnection, states: &[&str]) -> Result<Vec<Self>, DieselError> {
+        dealer_orders::dsl::dealer_orders
+            .filter(dealer_orders::state.eq_any(states.to_vec()))
+            .order(dealer_orders::created_at.asc())
+            .load::<Self>(conn)
+    }
+}
+
+#[derive(Insertable, AsChangeset, Debug)]
+#[table_name = "dealer_orders"]
+#[primary_key(ext_order_id)]
+pub struct InsertableDealerOrder {
+    pub ext_order_id: Uuid,
+    pub order_id: Option<i64>,
+    pub symbol: String,
+    pub side: String,
+    pub quantity: i64,
+    pub price: Option<i64>,
+    pub filled: i64,
+    pub state: String,
+    pub rejection_reason: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl InsertableDealerOrder {
+    /// Inserts the order or overwrites its stored state.
+    pub fn upsert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(dealer_orders::table)
+            .values(self)
+            .on_conflict(dealer_orders::ext_order_id)
+            .do_update()
+            .set(self)
+            .execute(conn)
+    }
+}
@@ -0,0 +1,94 @@
+use crate::schema::dealer_pnl_entries;
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::{BigInt, Nullable, Numeric, Text};
+
+pub const SPREAD: &str = "spread";
+pub const HEDGE: &str = "hedge";
+pub const FUNDING: &str = "funding";
+pub const FEE: &str = "fee";
+
+/// Profit or loss of the dealer, `kind` tells where it comes from. Amounts are in units of the currency,
+/// negative for losses.
+#[derive(Queryable, Identifiable, Debug)]
+#[table_name = "dealer_pnl_entries"]
+pub struct DealerPnlEntry {
+    pub id: i32,
+    pub created_at: i64,
+    pub kind: String,
+    pub currency: String,
+    pub amount: BigDecimal,
+    pub symbol: Option<String>,
+    /// Swap request or exchange order the entry was booked for.
+    pub reference: Option<String>,
+}
+
+impl DealerPnlEntry {
+    pub fn get_total(conn: &diesel::PgConnection, kind: &str, currency: &str) -> Result<BigDecimal, DieselError> {
+        dealer_pnl_entries::dsl::dealer_pnl_entries
+            .filter(dealer_pnl_entries::kind.eq(kind))
+            .filter(dealer_pnl_entries::currency.eq(currency))
+            .select(diesel::dsl::sum(dealer_pnl_entries::amount))
+            .first::<Option<BigDecimal>>(conn)
+            .map(|total| total.unwrap_or_else(|| BigDecimal::from(0)))
+    }
+
+    pub fn has_kind(conn: &diesel::PgConnection, kind: &str) -> Result<bool, DieselError> {
+        diesel::select(diesel::dsl::exists(
+            dealer_pnl_entries::dsl::dealer_pnl_entries.filter(dealer_pnl_entries::kind.eq(kind)),
+        ))
+        .get_result(conn)
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "dealer_pnl_entries"]
+pub struct InsertableDealerPnlEntry {
+    pub created_at: i64,
+    pub kind: String,
+    pub currency: String,
+    pub amount: BigDecimal,
+    pub symbol: Option<String>,
+    pub reference: Option<String>,
+}
+
+impl InsertableDealerPnlEntry {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(dealer_pnl_entries::table)
+            .values(self)
+            .execute(conn)
+    }
+}
+
+/// Sum of the entries of a kind booked in a currency on a UTC day.
+#[derive(QueryableByName, Debug)]
+pub struct DailyDealerPnl {
+    /// Formatted as `YYYY-MM-DD`.
+    #[sql_type = "Text"]
+    pub day: String,
+    #[sql_type = "Text"]
+    pub currency: String,
+    #[sql_type = "Text"]
+    pub kind: String,
+    #[sql_type = "Numeric"]
+    pub amount: BigDecimal,
+}
+
+impl DailyDealerPnl {
+    /// Days from `since` (milliseconds since epoch) on, optionally of a single currency.
+    pub fn fetch(conn: &diesel::PgConnection, since: i64, currency: Option<&str>) -> QueryResult<Vec<Self>> {
+        let query = "\
+            SELECT to_char(to_timestamp(created_at / 1000) AT TIME ZONE 'UTC', 'YYYY-MM-DD') AS day, \
+                currency, kind, sum(amount) AS amount \
+            FROM dealer_pnl_entries \
+            WHERE created_at >= $1 AND ($2::text IS NULL OR currency = $2) \
+            GROUP BY day, currency, kind \
+            ORDER BY day, currency, kind\
+        ";
+        diesel::sql_query(query)
+            .bind::<BigInt, _>(since)
+            .bind::<Nullable<Text>, _>(currency)
+            .load(conn)
+    }
+}
@@ -0,0 +1,27 @@
+use crate::schema::dealer_redeemed_quotes;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+/// Quote id a swap was executed with, kept until the quote expires so every dealer honours it only once.
+#[derive(Insertable, Debug)]
+#[table_name = "dealer_redeemed_quotes"]
+pub struct InsertableDealerRedeemedQuote {
+    pub quote_id: String,
+    /// Epoch in ms.
+    pub valid_until: i64,
+}
+
+impl InsertableDealerRedeemedQuote {
+    /// `false` if the quote was redeemed before.
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<bool, DieselError> {
+        diesel::insert_into(dealer_redeemed_quotes::table)
+            .values(self)
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .map(|inserted| inserted == 1)
+    }
+}
+
+pub fn remove_expired(conn: &diesel::PgConnection, now: i64) -> Result<usize, DieselError> {
+    diesel::delete(dealer_redeemed_quotes::table.filter(dealer_redeemed_quotes::valid_until.lt(now))).execute(conn)
+}
@@ -0,0 +1,50 @@
+use crate::schema::email_verification_tokens;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+/// Pending verification of an email address, only the hash of the token sent by email is stored.
+#[derive(Queryable, Insertable, Debug, Clone)]
+#[table_name = "email_verification_tokens"]
+pub struct EmailVerificationToken {
+    pub token_hash: String,
+    pub uid: i32,
+    pub email: String,
+    /// Milliseconds since epoch.
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+impl EmailVerificationToken {
+    pub fn get_by_token_hash(conn: &diesel::PgConnection, token_hash: &str) -> Result<Option<Self>, DieselError> {
+        email_verification_tokens::dsl::email_verification_tokens
+            .filter(email_verification_tokens::token_hash.eq(token_hash))
+            .first::<Self>(conn)
+            .optional()
+    }
+
+    /// Most recently issued token of the user.
+    pub fn get_latest_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Option<Self>, DieselError> {
+        email_verification_tokens::dsl::email_verification_tokens
+            .filter(email_verification_tokens::uid.eq(uid))
+            .order(email_verification_tokens::created_at.desc())
+            .first::<Self>(conn)
+            .optional()
+    }
+
+    /// Replaces any token issued to the user before, only the latest one stays valid.
+    pub fn replace(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        conn.transaction(|| {
+            Self::delete_by_uid(conn, self.uid)?;
+            diesel::insert_into(email_verification_tokens::table)
+                .values(self)
+                .execute(conn)
+        })
+    }
+
+    pub fn delete_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<usize, DieselError> {
+        diesel::delete(
+            email_verification_tokens::dsl::email_verification_tokens.filter(email_verification_tokens::uid.eq(uid)),
+        )
+        .execute(conn)
+    }
+}
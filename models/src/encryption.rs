@@ -0,0 +1,55 @@
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// Key for secrets stored encrypted, derived from the env `var`. `None` if it isn't set.
+pub fn key_from_env(var: &str) -> Option<[u8; 32]> {
+    match std::env::var(var) {
+        Ok(key) if !key.is_empty() => {
+            let mut derived = [0u8; 32];
+            derived.copy_from_slice(digest::digest(&digest::SHA256, key.as_bytes()).as_ref());
+            Some(derived)
+        }
+        _ => None,
+    }
+}
+
+/// Encrypts with AES-256-GCM, returns the base64 encoded nonce followed by the cipher text.
+pub fn seal(key: &[u8; 32], plain: &[u8]) -> Option<String> {
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key).ok()?);
+    let mut nonce_bytes = vec![0u8; NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce_bytes).ok()?;
+    let nonce = Nonce::try_assume_unique_for_key(&nonce_bytes).ok()?;
+    let mut in_out = plain.to_vec();
+    key.seal_in_place_append_tag(nonce, Aad::empty(), &mut in_out).ok()?;
+    let mut sealed = nonce_bytes;
+    sealed.extend_from_slice(&in_out);
+    Some(base64::encode(sealed))
+}
+
+pub fn open(key: &[u8; 32], sealed: &str) -> Option<Vec<u8>> {
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key).ok()?);
+    let sealed = base64::decode(sealed).ok()?;
+    if sealed.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce_bytes, cipher_text) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce_bytes).ok()?;
+    let mut in_out = cipher_text.to_vec();
+    let plain = key.open_in_place(nonce, Aad::empty(), &mut in_out).ok()?;
+    Some(plain.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open() {
+        let key = [7u8; 32];
+        let sealed = seal(&key, b"secret").unwrap();
+        assert_eq!(open(&key, &sealed).unwrap(), b"secret");
+        assert!(open(&[8u8; 32], &sealed).is_none());
+        assert!(open(&key, "c2hvcnQ=").is_none());
+    }
+}
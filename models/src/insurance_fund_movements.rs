@@ -0,0 +1,61 @@
+use crate::schema::insurance_fund_movements;
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+pub const DEPOSIT: &str = "deposit";
+pub const WITHDRAWAL: &str = "withdrawal";
+pub const TOP_UP: &str = "top_up";
+pub const MARGIN_CALL: &str = "margin_call";
+pub const LOSS: &str = "loss";
+
+/// Change of the insurance fund balance, in BTC. Drawdowns are negative, `balance` is the one after the movement.
+#[derive(Queryable, Identifiable, Debug)]
+#[table_name = "insurance_fund_movements"]
+pub struct InsuranceFundMovement {
+    pub id: i32,
+    pub created_at: i64,
+    pub kind: String,
+    pub amount: BigDecimal,
+    pub balance: BigDecimal,
+    /// Swap request or bank transaction the movement was made for.
+    pub reference: Option<String>,
+}
+
+impl InsuranceFundMovement {
+    /// Movements from `since` (milliseconds since epoch) on, the latest first.
+    pub fn get_since(conn: &diesel::PgConnection, since: i64) -> Result<Vec<Self>, DieselError> {
+        insurance_fund_movements::dsl::insurance_fund_movements
+            .filter(insurance_fund_movements::created_at.ge(since))
+            .order(insurance_fund_movements::created_at.desc())
+            .load::<Self>(conn)
+    }
+
+    /// Sum of the margin calls and losses covered from `since` on, zero or negative.
+    pub fn get_drawn_since(conn: &diesel::PgConnection, since: i64) -> Result<BigDecimal, DieselError> {
+        insurance_fund_movements::dsl::insurance_fund_movements
+            .filter(insurance_fund_movements::created_at.ge(since))
+            .filter(insurance_fund_movements::kind.eq_any(vec![MARGIN_CALL, LOSS]))
+            .select(diesel::dsl::sum(insurance_fund_movements::amount))
+            .first::<Option<BigDecimal>>(conn)
+            .map(|total| total.unwrap_or_else(|| BigDecimal::from(0)))
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "insurance_fund_movements"]
+pub struct InsertableInsuranceFundMovement {
+    pub created_at: i64,
+    pub kind: String,
+    pub amount: BigDecimal,
+    pub balance: BigDecimal,
+    pub reference: Option<String>,
+}
+
+impl InsertableInsuranceFundMovement {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(insurance_fund_movements::table)
+            .values(self)
+            .execute(conn)
+    }
+}
@@ -0,0 +1,70 @@
+use crate::schema::invite_codes;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::Serialize;
+
+#[derive(Queryable, Debug, Clone, Serialize)]
+pub struct InviteCode {
+    pub code: String,
+    pub created_at: Option<std::time::SystemTime>,
+    /// Admin that generated the code.
+    pub created_by: Option<i32>,
+    pub max_uses: i32,
+    pub uses: i32,
+    /// Milliseconds since epoch, `None` if the code never expires.
+    pub expires_at: Option<i64>,
+}
+
+impl InviteCode {
+    pub fn get_by_code(conn: &diesel::PgConnection, code: &str) -> Result<Option<Self>, DieselError> {
+        invite_codes::dsl::invite_codes
+            .filter(invite_codes::code.eq(code))
+            .first::<Self>(conn)
+            .optional()
+    }
+
+    pub fn get_all(conn: &diesel::PgConnection) -> Result<Vec<Self>, DieselError> {
+        invite_codes::dsl::invite_codes
+            .order(invite_codes::created_at.desc())
+            .load::<Self>(conn)
+    }
+
+    /// Takes one use of the code if it is neither used up nor expired, returns whether it did.
+    pub fn redeem(conn: &diesel::PgConnection, code: &str, now: i64) -> Result<bool, DieselError> {
+        let updated = diesel::update(
+            invite_codes::dsl::invite_codes
+                .filter(invite_codes::code.eq(code))
+                .filter(invite_codes::uses.lt(invite_codes::max_uses))
+                .filter(invite_codes::expires_at.is_null().or(invite_codes::expires_at.gt(now))),
+        )
+        .set(invite_codes::uses.eq(invite_codes::uses + 1))
+        .execute(conn)?;
+        Ok(updated == 1)
+    }
+
+    /// Gives back a use taken by `redeem` when the account could not be created.
+    pub fn release(conn: &diesel::PgConnection, code: &str) -> Result<usize, DieselError> {
+        diesel::update(
+            invite_codes::dsl::invite_codes
+                .filter(invite_codes::code.eq(code))
+                .filter(invite_codes::uses.gt(0)),
+        )
+        .set(invite_codes::uses.eq(invite_codes::uses - 1))
+        .execute(conn)
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "invite_codes"]
+pub struct InsertableInviteCode {
+    pub code: String,
+    pub created_by: Option<i32>,
+    pub max_uses: i32,
+    pub expires_at: Option<i64>,
+}
+
+impl InsertableInviteCode {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(invite_codes::table).values(self).execute(conn)
+    }
+}
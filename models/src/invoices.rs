@@ -25,6 +25,10 @@ pub struct Invoice {
     pub target_account_currency: Option<String>,
     pub reference: Option<String>,
     pub description: Option<String>,
+    /// LUD-12 comment left by the payer
+    pub payer_comment: Option<String>,
+    /// LUD-18 payer data as sent by the payer's wallet
+    pub payer_data: Option<String>,
 }
 
 impl Invoice {
@@ -56,6 +60,20 @@ impl Invoice {
             .set(self)
             .execute(conn)
     }
+
+    pub fn set_payer_info(
+        conn: &diesel::PgConnection,
+        payment_hash: String,
+        payer_comment: Option<String>,
+        payer_data: Option<String>,
+    ) -> Result<usize, DieselError> {
+        diesel::update(invoices::dsl::invoices.filter(invoices::payment_hash.eq(payment_hash)))
+            .set((
+                invoices::payer_comment.eq(payer_comment),
+                invoices::payer_data.eq(payer_data),
+            ))
+            .execute(conn)
+    }
 }
 
 #[derive(Insertable, Debug, Deserialize)]
@@ -79,6 +97,8 @@ pub struct InsertableInvoice {
     pub target_account_currency: Option<String>,
     pub reference: Option<String>,
     pub description: Option<String>,
+    pub payer_comment: Option<String>,
+    pub payer_data: Option<String>,
 }
 
 impl InsertableInvoice {
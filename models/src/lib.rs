@@ -5,19 +5,36 @@ extern crate diesel_migrations;
 
 pub mod accounts;
 pub mod conversions;
+pub mod dealer_orders;
+pub mod dealer_pnl_entries;
+pub mod dealer_redeemed_quotes;
 pub mod deezy_stuff;
+pub mod email_verification_tokens;
+pub mod encryption;
+pub mod insurance_fund_movements;
 mod error;
 pub mod internal_user_mappings;
+pub mod invite_codes;
 pub mod invoices;
 pub mod ln_addresses;
+pub mod lnurl_auth_keys;
+pub mod nostr_dm_deliveries;
+pub mod nostr_follows;
+pub mod nostr_nip05_names;
 pub mod nostr_profile_indexer_times;
 pub mod nostr_profiles;
 pub mod nostr_public_keys;
+pub mod nostr_relays;
+pub mod nostr_zap_keys;
+pub mod notification_preferences;
+pub mod nwc_connections;
+pub mod nwc_requests;
 pub mod pre_signups;
 mod schema;
 pub mod summary_transactions;
 pub mod transactions;
 pub mod user_profiles;
+pub mod user_totps;
 pub mod users;
 pub mod dca;
 
@@ -0,0 +1,43 @@
+use crate::schema::lnurl_auth_keys;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::Deserialize;
+
+/// LNURL-auth (LUD-04) linking key of a wallet that can log into the account.
+#[derive(Queryable, Identifiable, Debug)]
+#[primary_key(linking_key)]
+pub struct LnurlAuthKey {
+    pub created_at: Option<std::time::SystemTime>,
+    pub linking_key: String,
+    pub uid: i32,
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "lnurl_auth_keys"]
+pub struct InsertableLnurlAuthKey {
+    pub linking_key: String,
+    pub uid: i32,
+}
+
+impl LnurlAuthKey {
+    pub fn get_by_linking_key(conn: &diesel::PgConnection, linking_key: &str) -> Result<Self, DieselError> {
+        lnurl_auth_keys::dsl::lnurl_auth_keys
+            .filter(lnurl_auth_keys::linking_key.eq(linking_key))
+            .first::<Self>(conn)
+    }
+
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        lnurl_auth_keys::dsl::lnurl_auth_keys
+            .filter(lnurl_auth_keys::uid.eq(uid))
+            .load::<Self>(conn)
+    }
+}
+
+impl InsertableLnurlAuthKey {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<String, DieselError> {
+        diesel::insert_into(lnurl_auth_keys::table)
+            .values(self)
+            .returning(lnurl_auth_keys::linking_key)
+            .get_result(conn)
+    }
+}
@@ -0,0 +1,36 @@
+use crate::schema::nostr_dm_deliveries;
+use diesel::{QueryResult, RunQueryDsl};
+
+/// Outcome of publishing a private message event to a single relay.
+#[derive(Queryable, Identifiable, Debug)]
+#[table_name = "nostr_dm_deliveries"]
+pub struct NostrDmDelivery {
+    pub id: i32,
+    pub created_at: i64,
+    pub event_id: String,
+    pub pubkey: String,
+    pub protocol: String,
+    pub relay_url: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "nostr_dm_deliveries"]
+pub struct InsertableNostrDmDelivery {
+    pub created_at: i64,
+    pub event_id: String,
+    pub pubkey: String,
+    pub protocol: String,
+    pub relay_url: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl InsertableNostrDmDelivery {
+    pub fn insert_many(conn: &diesel::PgConnection, deliveries: &[Self]) -> QueryResult<usize> {
+        diesel::insert_into(nostr_dm_deliveries::table)
+            .values(deliveries)
+            .execute(conn)
+    }
+}
@@ -0,0 +1,113 @@
+use crate::schema::{nostr_contact_lists, nostr_follows};
+use diesel::sql_types::{BigInt, Integer, Nullable, Text};
+use diesel::{Connection, ExpressionMethods, OptionalExtension, QueryDsl, QueryResult, RunQueryDsl};
+
+/// Postgres limits the number of bind parameters of a single statement.
+const INSERT_CHUNK_SIZE: usize = 1000;
+
+/// Latest kind 3 contact list seen for a pubkey, older lists are ignored as the event is replaceable.
+#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug)]
+#[table_name = "nostr_contact_lists"]
+#[primary_key(pubkey)]
+pub struct NostrContactList {
+    pub pubkey: String,
+    pub created_at: i64,
+    pub received_at: i64,
+    pub follows_count: i32,
+}
+
+#[derive(Queryable, Insertable, Debug, Clone)]
+#[table_name = "nostr_follows"]
+pub struct NostrFollow {
+    pub pubkey: String,
+    pub followed_pubkey: String,
+    pub relay: Option<String>,
+    pub petname: Option<String>,
+    /// Position in the contact list, newer follows come last
+    pub position: i32,
+}
+
+/// Followed pubkey with a validated lightning address, ranked by payments exchanged with the follower.
+#[derive(QueryableByName, Debug)]
+pub struct SuggestedContact {
+    #[sql_type = "Text"]
+    pub pubkey: String,
+    #[sql_type = "Nullable<Text>"]
+    pub petname: Option<String>,
+    #[sql_type = "Nullable<Text>"]
+    pub name: Option<String>,
+    #[sql_type = "Nullable<Text>"]
+    pub display_name: Option<String>,
+    #[sql_type = "Nullable<Text>"]
+    pub nip05: Option<String>,
+    #[sql_type = "Nullable<Text>"]
+    pub lud16: Option<String>,
+    #[sql_type = "Text"]
+    pub lnurl_pay_req: String,
+    #[sql_type = "BigInt"]
+    pub interactions: i64,
+}
+
+impl NostrContactList {
+    pub fn get_by_pubkey(conn: &diesel::PgConnection, pubkey: &str) -> QueryResult<Option<Self>> {
+        nostr_contact_lists::dsl::nostr_contact_lists
+            .filter(nostr_contact_lists::pubkey.eq(pubkey))
+            .first::<Self>(conn)
+            .optional()
+    }
+
+    /// Replaces the stored follows unless a newer contact list was stored already.
+    /// Returns whether the follows were replaced.
+    pub fn replace(&self, conn: &diesel::PgConnection, follows: &[NostrFollow]) -> QueryResult<bool> {
+        conn.transaction(|| {
+            if let Some(stored) = Self::get_by_pubkey(conn, &self.pubkey)? {
+                if stored.created_at >= self.created_at {
+                    return Ok(false);
+                }
+            }
+            diesel::insert_into(nostr_contact_lists::table)
+                .values(self)
+                .on_conflict(nostr_contact_lists::pubkey)
+                .do_update()
+                .set(self)
+                .execute(conn)?;
+            diesel::delete(nostr_follows::dsl::nostr_follows.filter(nostr_follows::pubkey.eq(&self.pubkey)))
+                .execute(conn)?;
+            for chunk in follows.chunks(INSERT_CHUNK_SIZE) {
+                diesel::insert_into(nostr_follows::table).values(chunk).execute(conn)?;
+            }
+            Ok(true)
+        })
+    }
+}
+
+impl SuggestedContact {
+    pub fn fetch(
+        conn: &diesel::PgConnection,
+        pubkey: &str,
+        uid: i32,
+        limit: u64,
+        offset: u64,
+    ) -> QueryResult<Vec<Self>> {
+        let query = "\
+            SELECT f.followed_pubkey AS pubkey, f.petname, p.name, p.display_name, p.nip05, p.lud16, \
+                p.lnurl_pay_req, coalesce(i.interactions, 0) AS interactions \
+            FROM nostr_follows f \
+            INNER JOIN nostr_profile_records p ON p.pubkey = f.followed_pubkey \
+            LEFT JOIN LATERAL (\
+                SELECT count(*) AS interactions FROM summary_transactions t \
+                WHERE (t.outbound_uid = $2 AND lower(t.inbound_username) = lower(p.lud16)) \
+                    OR (t.inbound_uid = $2 AND lower(t.outbound_username) = lower(p.lud16))\
+            ) i ON true \
+            WHERE f.pubkey = $1 AND p.lnurl_pay_req IS NOT NULL \
+            ORDER BY interactions DESC, f.position DESC \
+            LIMIT $3 OFFSET $4\
+        ";
+        diesel::sql_query(query)
+            .bind::<Text, _>(pubkey)
+            .bind::<Integer, _>(uid)
+            .bind::<BigInt, _>(limit as i64)
+            .bind::<BigInt, _>(offset as i64)
+            .load(conn)
+    }
+}
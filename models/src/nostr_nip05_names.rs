@@ -0,0 +1,83 @@
+use crate::schema::nostr_nip05_names;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::Deserialize;
+
+/// NIP-05 name under our domain, a user can hold several names pointing to different pubkeys.
+#[derive(Queryable, Identifiable, Debug, Clone)]
+#[primary_key(name)]
+pub struct Nip05Name {
+    pub created_at: Option<std::time::SystemTime>,
+    pub name: String,
+    pub uid: i32,
+    pub pubkey: String,
+    /// Relays the pubkey can be found on, served in the `relays` map of `nostr.json`
+    pub relays: Vec<String>,
+    /// Set by admins to take a name offline
+    pub disabled: bool,
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "nostr_nip05_names"]
+pub struct InsertableNip05Name {
+    pub name: String,
+    pub uid: i32,
+    pub pubkey: String,
+    pub relays: Vec<String>,
+}
+
+impl Nip05Name {
+    pub fn get_by_name(conn: &diesel::PgConnection, name: &str) -> Result<Self, DieselError> {
+        nostr_nip05_names::dsl::nostr_nip05_names
+            .filter(nostr_nip05_names::name.eq(name))
+            .first::<Self>(conn)
+    }
+
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        nostr_nip05_names::dsl::nostr_nip05_names
+            .filter(nostr_nip05_names::uid.eq(uid))
+            .order(nostr_nip05_names::name.asc())
+            .load::<Self>(conn)
+    }
+
+    /// Points every name of the user at the new pubkey that pointed at the old one.
+    pub fn update_pubkey(
+        conn: &diesel::PgConnection,
+        uid: i32,
+        old_pubkey: &str,
+        new_pubkey: &str,
+    ) -> Result<usize, DieselError> {
+        diesel::update(
+            nostr_nip05_names::dsl::nostr_nip05_names
+                .filter(nostr_nip05_names::uid.eq(uid))
+                .filter(nostr_nip05_names::pubkey.eq(old_pubkey)),
+        )
+        .set(nostr_nip05_names::pubkey.eq(new_pubkey))
+        .execute(conn)
+    }
+
+    pub fn set_disabled(conn: &diesel::PgConnection, name: &str, disabled: bool) -> Result<usize, DieselError> {
+        diesel::update(nostr_nip05_names::dsl::nostr_nip05_names.filter(nostr_nip05_names::name.eq(name)))
+            .set(nostr_nip05_names::disabled.eq(disabled))
+            .execute(conn)
+    }
+
+    /// Only deletes the name if it belongs to the given user.
+    pub fn delete(conn: &diesel::PgConnection, uid: i32, name: &str) -> Result<usize, DieselError> {
+        diesel::delete(
+            nostr_nip05_names::dsl::nostr_nip05_names
+                .filter(nostr_nip05_names::name.eq(name))
+                .filter(nostr_nip05_names::uid.eq(uid)),
+        )
+        .execute(conn)
+    }
+}
+
+impl InsertableNip05Name {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<String, DieselError> {
+        diesel::insert_into(nostr_nip05_names::table)
+            .values(self)
+            .returning(nostr_nip05_names::name)
+            .get_result(conn)
+    }
+}
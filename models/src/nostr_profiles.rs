@@ -1,8 +1,12 @@
 use crate::schema::nostr_profile_records;
-use diesel::{
-    BoolExpressionMethods, ExpressionMethods, PgSortExpressionMethods, PgTextExpressionMethods, QueryDsl, QueryResult,
-    RunQueryDsl,
-};
+use diesel::sql_types::{BigInt, Nullable, Text};
+use diesel::{ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl};
+
+const PROFILE_COLUMNS: &str =
+    "pubkey, created_at, received_at, name, display_name, nip05, lud16, nip05_verified, content, lnurl_pay_req";
+
+/// Has to match the expression of the full text search index
+const SEARCH_VECTOR: &str = "nostr_profile_search_vector(name, display_name, nip05, lud16)";
 
 fn escaped_text(text: &str) -> String {
     text.replace('\'', "''")
@@ -26,8 +30,18 @@ fn nullable_bool(value: &Option<bool>) -> String {
     }
 }
 
-#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug)]
+/// Prefix matching query over the words of the text, e.g. `jack dor` becomes `jack:* & dor:*`.
+fn prefix_tsquery(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("{}:*", word.to_lowercase()))
+        .collect::<Vec<_>>()
+        .join(" & ")
+}
+
+#[derive(Queryable, QueryableByName, Identifiable, Insertable, AsChangeset, Debug)]
 #[changeset_options(treat_none_as_null = "true")]
+#[table_name = "nostr_profile_records"]
 #[primary_key(pubkey)]
 pub struct NostrProfileRecord {
     pub pubkey: String,
@@ -43,8 +57,12 @@ pub struct NostrProfileRecord {
 }
 
 impl NostrProfileRecord {
-    pub fn fetch_all(conn: &diesel::PgConnection) -> QueryResult<Vec<Self>> {
-        nostr_profile_records::dsl::nostr_profile_records.load(conn)
+    /// Most recently received profiles, used to warm up the profile cache.
+    pub fn fetch_recent(conn: &diesel::PgConnection, limit: usize) -> QueryResult<Vec<Self>> {
+        nostr_profile_records::dsl::nostr_profile_records
+            .order(nostr_profile_records::dsl::received_at.desc())
+            .limit(limit as i64)
+            .load(conn)
     }
 
     pub fn upsert(&self, conn: &diesel::PgConnection) -> QueryResult<usize> {
@@ -131,56 +149,81 @@ impl NostrProfileRecord {
             .execute(conn)
     }
 
+    /// Ranks profiles matching the text by full text and trigram similarity over names and internet
+    /// identifiers, boosting verified NIP-05 identifiers and validated lnurl pay requests.
     pub fn search_by_text(
         conn: &diesel::PgConnection,
         pubkey: Option<String>,
         text: Option<String>,
         limit: Option<u64>,
+        offset: Option<u64>,
     ) -> QueryResult<Vec<Self>> {
-        if pubkey.is_none() && text.is_none() {
-            return Ok(Vec::new());
-        }
-
-        let maybe_pubkey_expr = pubkey.map(|pkey| nostr_profile_records::dsl::pubkey.eq(pkey));
-        let maybe_text_expr = text.map(|txt| {
-            let escaped_lowered = txt.replace('%', "\\%").replace('_', "\\_").to_lowercase();
-            let name_pattern = format!("%{escaped_lowered}%");
-            let internet_identifier = match escaped_lowered.split_once('@') {
-                Some((local_part, domain)) => format!("%{local_part}@{domain}%"),
-                None => format!("{name_pattern}@%"),
-            };
-            nostr_profile_records::dsl::name
-                .ilike(name_pattern.clone())
-                .or(nostr_profile_records::dsl::display_name.ilike(name_pattern))
-                .or(nostr_profile_records::dsl::nip05.ilike(internet_identifier.clone()))
-                .or(nostr_profile_records::dsl::lud16.ilike(internet_identifier))
-        });
-
-        let query = nostr_profile_records::dsl::nostr_profile_records
-            .order(nostr_profile_records::dsl::nip05_verified.desc().nulls_last());
-
-        if let Some(pubkey_expr) = maybe_pubkey_expr {
-            let query = query.filter(pubkey_expr);
-            if let Some(text_expr) = maybe_text_expr {
-                let query = query.filter(text_expr);
-                let query_result = match limit {
-                    Some(num_records) => query.limit(num_records as i64).load(conn),
-                    None => query.load(conn),
-                };
-                return query_result;
+        let limit = limit.map(|num_records| num_records as i64);
+        let offset = offset.unwrap_or_default() as i64;
+        let text = match text {
+            Some(text) => text,
+            None => {
+                return match pubkey {
+                    Some(pkey) => nostr_profile_records::dsl::nostr_profile_records
+                        .filter(nostr_profile_records::dsl::pubkey.eq(pkey))
+                        .limit(limit.unwrap_or(i64::MAX))
+                        .offset(offset)
+                        .load(conn),
+                    None => Ok(Vec::new()),
+                }
             }
-            match limit {
-                Some(num_records) => query.limit(num_records as i64).load(conn),
-                None => query.load(conn),
-            }
-        } else if let Some(text_expr) = maybe_text_expr {
-            let query = query.filter(text_expr);
-            match limit {
-                Some(num_records) => query.limit(num_records as i64).load(conn),
-                None => query.load(conn),
-            }
-        } else {
-            Ok(Vec::new())
-        }
+        };
+
+        let lowered = text.trim().to_lowercase();
+        let escaped_lowered = lowered.replace('%', "\\%").replace('_', "\\_");
+        let internet_identifier = match escaped_lowered.split_once('@') {
+            Some((local_part, domain)) => format!("%{local_part}@{domain}%"),
+            None => format!("%{escaped_lowered}%@%"),
+        };
+        let search_query = format!(
+            "\
+                SELECT {PROFILE_COLUMNS} FROM nostr_profile_records \
+                WHERE ($1 IS NULL OR pubkey = $1) AND (\
+                    ($2 <> '' AND {SEARCH_VECTOR} @@ to_tsquery('simple', $2)) \
+                    OR $3 <% lower(coalesce(name, '')) \
+                    OR $3 <% lower(coalesce(display_name, '')) \
+                    OR lower(coalesce(nip05, '')) LIKE $4 \
+                    OR lower(coalesce(lud16, '')) LIKE $4\
+                ) \
+                ORDER BY \
+                    CASE WHEN $2 <> '' THEN ts_rank({SEARCH_VECTOR}, to_tsquery('simple', $2)) ELSE 0 END \
+                    + greatest(\
+                        word_similarity($3, lower(coalesce(name, ''))), \
+                        word_similarity($3, lower(coalesce(display_name, ''))), \
+                        similarity($3, lower(coalesce(nip05, ''))), \
+                        similarity($3, lower(coalesce(lud16, '')))\
+                    ) \
+                    + CASE WHEN nip05_verified THEN 0.5 ELSE 0 END \
+                    + CASE WHEN lnurl_pay_req IS NOT NULL THEN 0.25 ELSE 0 END DESC, \
+                    received_at DESC \
+                LIMIT $5 OFFSET $6\
+            "
+        );
+        diesel::sql_query(search_query)
+            .bind::<Nullable<Text>, _>(pubkey)
+            .bind::<Text, _>(prefix_tsquery(&lowered))
+            .bind::<Text, _>(lowered)
+            .bind::<Text, _>(internet_identifier)
+            .bind::<Nullable<BigInt>, _>(limit)
+            .bind::<BigInt, _>(offset)
+            .load(conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_tsquery() {
+        assert_eq!(prefix_tsquery("Jack Dor"), "jack:* & dor:*");
+        assert_eq!(prefix_tsquery("bob@kollider.xyz"), "bob:* & kollider:* & xyz:*");
+        assert_eq!(prefix_tsquery("it's (a) | !test:*"), "it:* & s:* & a:* & test:*");
+        assert_eq!(prefix_tsquery(" @ "), "");
     }
 }
@@ -11,6 +11,8 @@ pub struct NostrPublicKey {
     pub created_at: Option<std::time::SystemTime>,
     pub pubkey: String,
     pub uid: i32,
+    /// Notifications are sent as NIP-04 direct messages for clients without NIP-17 support
+    pub legacy_dms: bool,
 }
 
 #[derive(Insertable, Debug, Deserialize)]
@@ -18,12 +20,14 @@ pub struct NostrPublicKey {
 pub struct InsertableNostrPublicKey {
     pub pubkey: String,
     pub uid: i32,
+    pub legacy_dms: bool,
 }
 
 #[derive(Default, AsChangeset, Debug, Deserialize)]
 #[table_name = "nostr_public_keys"]
 pub struct UpdateNostrPublicKey {
     pub pubkey: String,
+    pub legacy_dms: Option<bool>,
 }
 
 impl NostrPublicKey {
@@ -32,6 +36,12 @@ impl NostrPublicKey {
             .filter(nostr_public_keys::pubkey.eq(pubkey))
             .first::<Self>(conn)
     }
+    /// Pubkeys linked to any user, their contact lists are indexed.
+    pub fn fetch_all_pubkeys(conn: &diesel::PgConnection) -> Result<Vec<String>, DieselError> {
+        nostr_public_keys::dsl::nostr_public_keys
+            .select(nostr_public_keys::pubkey)
+            .load::<String>(conn)
+    }
     pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Self, DieselError> {
         nostr_public_keys::dsl::nostr_public_keys
             .filter(nostr_public_keys::uid.eq(uid))
@@ -44,6 +54,7 @@ impl NostrPublicKey {
                 nostr_public_keys::created_at,
                 nostr_public_keys::pubkey,
                 nostr_public_keys::uid,
+                nostr_public_keys::legacy_dms,
             ))
             .filter(users::username.eq(username))
             .first::<Self>(conn)
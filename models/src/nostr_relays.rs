@@ -0,0 +1,39 @@
+use crate::schema::nostr_relays;
+use diesel::{QueryResult, RunQueryDsl};
+
+/// Relay known to the nostr engine together with its health statistics.
+#[derive(Queryable, Identifiable, Insertable, AsChangeset, Debug, Clone)]
+#[changeset_options(treat_none_as_null = "true")]
+#[primary_key(url)]
+pub struct NostrRelay {
+    pub url: String,
+    /// Where the relay came from: config, cli or zap
+    pub source: String,
+    /// Disabled relays were removed by an operator and are not connected to
+    pub enabled: bool,
+    pub connected: bool,
+    /// Moving average of the time it takes the relay to acknowledge a published event
+    pub latency_ms: Option<i64>,
+    pub publish_attempts: i64,
+    pub publish_successes: i64,
+    pub consecutive_failures: i32,
+    /// The relay is not published to until this time in epoch ms
+    pub backoff_until: i64,
+    pub last_used_at: i64,
+    pub updated_at: i64,
+}
+
+impl NostrRelay {
+    pub fn fetch_all(conn: &diesel::PgConnection) -> QueryResult<Vec<Self>> {
+        nostr_relays::dsl::nostr_relays.load(conn)
+    }
+
+    pub fn upsert(&self, conn: &diesel::PgConnection) -> QueryResult<usize> {
+        diesel::insert_into(nostr_relays::table)
+            .values(self)
+            .on_conflict(nostr_relays::url)
+            .do_update()
+            .set(self)
+            .execute(conn)
+    }
+}
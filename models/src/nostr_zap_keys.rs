@@ -0,0 +1,62 @@
+use crate::encryption;
+use crate::schema::nostr_zap_keys;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::Deserialize;
+
+/// Env of the key zap secret keys are encrypted with at rest. Users don't get zap keys without it.
+pub const ZAP_KEY_ENCRYPTION_KEY_ENV: &str = "ZAP_KEY_ENCRYPTION_KEY";
+
+/// Key a user's zap receipts are signed with, advertised as `nostrPubkey` on their lightning address.
+#[derive(Queryable, Identifiable, Debug, Clone)]
+#[primary_key(uid)]
+pub struct NostrZapKey {
+    pub created_at: Option<std::time::SystemTime>,
+    pub uid: i32,
+    pub pubkey: String,
+    pub encrypted_secret_key: String,
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "nostr_zap_keys"]
+pub struct InsertableNostrZapKey {
+    pub uid: i32,
+    pub pubkey: String,
+    pub encrypted_secret_key: String,
+}
+
+impl NostrZapKey {
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Self, DieselError> {
+        nostr_zap_keys::dsl::nostr_zap_keys
+            .filter(nostr_zap_keys::uid.eq(uid))
+            .first::<Self>(conn)
+    }
+
+    /// Hex encoded secret key, `None` if the encryption key is missing or doesn't match.
+    pub fn secret_key(&self) -> Option<String> {
+        let key = encryption::key_from_env(ZAP_KEY_ENCRYPTION_KEY_ENV)?;
+        String::from_utf8(encryption::open(&key, &self.encrypted_secret_key)?).ok()
+    }
+}
+
+impl InsertableNostrZapKey {
+    /// Encrypts the secret key, `None` if no encryption key is configured.
+    pub fn new(uid: i32, pubkey: String, secret_key: &str) -> Option<Self> {
+        let key = encryption::key_from_env(ZAP_KEY_ENCRYPTION_KEY_ENV)?;
+        Some(Self {
+            uid,
+            pubkey,
+            encrypted_secret_key: encryption::seal(&key, secret_key.as_bytes())?,
+        })
+    }
+
+    /// Keeps the existing key if the user already has one and returns the stored key.
+    pub fn insert_or_get(&self, conn: &diesel::PgConnection) -> Result<NostrZapKey, DieselError> {
+        diesel::insert_into(nostr_zap_keys::table)
+            .values(self)
+            .on_conflict(nostr_zap_keys::uid)
+            .do_nothing()
+            .execute(conn)?;
+        NostrZapKey::get_by_uid(conn, self.uid)
+    }
+}
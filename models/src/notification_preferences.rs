@@ -0,0 +1,181 @@
+use crate::schema::notification_preferences;
+use crate::user_profiles::UserProfile;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Explicit choice of a user to receive, or not, an event type on a channel.
+#[derive(Queryable, Insertable, Debug, Clone, Serialize, Deserialize)]
+#[table_name = "notification_preferences"]
+pub struct NotificationPreference {
+    pub uid: i32,
+    pub event_type: String,
+    pub channel: String,
+    pub enabled: bool,
+}
+
+impl NotificationPreference {
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        notification_preferences::dsl::notification_preferences
+            .filter(notification_preferences::uid.eq(uid))
+            .load::<Self>(conn)
+    }
+
+    pub fn get(
+        conn: &diesel::PgConnection,
+        uid: i32,
+        event_type: &str,
+        channel: &str,
+    ) -> Result<Option<Self>, DieselError> {
+        notification_preferences::dsl::notification_preferences
+            .filter(notification_preferences::uid.eq(uid))
+            .filter(notification_preferences::event_type.eq(event_type))
+            .filter(notification_preferences::channel.eq(channel))
+            .first::<Self>(conn)
+            .optional()
+    }
+
+    pub fn upsert(&self, conn: &diesel::PgConnection) -> Result<usize, DieselError> {
+        diesel::insert_into(notification_preferences::table)
+            .values(self)
+            .on_conflict((
+                notification_preferences::uid,
+                notification_preferences::event_type,
+                notification_preferences::channel,
+            ))
+            .do_update()
+            .set(notification_preferences::enabled.eq(self.enabled))
+            .execute(conn)
+    }
+
+    /// Whether the user receives the event on the channel, falling back to the channel default.
+    pub fn is_enabled(
+        conn: &diesel::PgConnection,
+        profile: Option<&UserProfile>,
+        uid: i32,
+        event: NotificationEvent,
+        channel: NotificationChannel,
+    ) -> Result<bool, DieselError> {
+        Ok(match Self::get(conn, uid, event.as_str(), channel.as_str())? {
+            Some(preference) => preference.enabled,
+            None => channel.enabled_by_default(event, profile),
+        })
+    }
+}
+
+pub const DEFAULT_NOTIFICATION_LOCALE: &str = "en";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NotificationEvent {
+    Deposit,
+    OutgoingPayment,
+    SwapExecuted,
+    DcaRun,
+    LnurlClaimed,
+}
+
+impl NotificationEvent {
+    pub const ALL: [NotificationEvent; 5] = [
+        NotificationEvent::Deposit,
+        NotificationEvent::OutgoingPayment,
+        NotificationEvent::SwapExecuted,
+        NotificationEvent::DcaRun,
+        NotificationEvent::LnurlClaimed,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationEvent::Deposit => "deposit",
+            NotificationEvent::OutgoingPayment => "outgoing_payment",
+            NotificationEvent::SwapExecuted => "swap_executed",
+            NotificationEvent::DcaRun => "dca_run",
+            NotificationEvent::LnurlClaimed => "lnurl_claimed",
+        }
+    }
+}
+
+impl FromStr for NotificationEvent {
+    type Err = String;
+
+    fn from_str(event_type: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|event| event.as_str() == event_type)
+            .ok_or_else(|| format!("unknown notification event {event_type}"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NotificationChannel {
+    Nostr,
+    Email,
+}
+
+impl NotificationChannel {
+    pub const ALL: [NotificationChannel; 2] = [NotificationChannel::Nostr, NotificationChannel::Email];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationChannel::Nostr => "nostr",
+            NotificationChannel::Email => "email",
+        }
+    }
+
+    /// Whether the channel is used for an event the user has no stored preference for. Deposits were
+    /// notified before preferences existed and keep following the profile flags, other events are opt-in.
+    pub fn enabled_by_default(&self, event: NotificationEvent, profile: Option<&UserProfile>) -> bool {
+        if event != NotificationEvent::Deposit {
+            return false;
+        }
+        match self {
+            NotificationChannel::Nostr => profile.and_then(|p| p.nostr_notifications).unwrap_or(false),
+            NotificationChannel::Email => profile.and_then(|p| p.email_notifications).unwrap_or(false),
+        }
+    }
+}
+
+impl FromStr for NotificationChannel {
+    type Err = String;
+
+    fn from_str(channel: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|c| c.as_str() == channel)
+            .ok_or_else(|| format!("unknown notification channel {channel}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_deposits_follow_profile_flags() {
+        let profile = UserProfile {
+            uid: 1,
+            email: None,
+            nostr_notifications: Some(true),
+            email_notifications: Some(false),
+            img_url: None,
+            twitter_handle: None,
+            is_twitter_verified: None,
+            is_email_verified: None,
+            lnurl_comment_allowed: None,
+            lnurl_payer_data: None,
+            lnurl_success_message: None,
+            lnurl_success_url: None,
+            notification_locale: None,
+        };
+        let nostr = NotificationChannel::Nostr;
+        assert!(nostr.enabled_by_default(NotificationEvent::Deposit, Some(&profile)));
+        assert!(!nostr.enabled_by_default(NotificationEvent::Deposit, None));
+        assert!(!NotificationChannel::Email.enabled_by_default(NotificationEvent::Deposit, Some(&profile)));
+        for event in NotificationEvent::ALL
+            .into_iter()
+            .filter(|e| *e != NotificationEvent::Deposit)
+        {
+            assert!(!nostr.enabled_by_default(event, Some(&profile)));
+        }
+    }
+}
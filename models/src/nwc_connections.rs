@@ -0,0 +1,81 @@
+use crate::schema::nwc_connections;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::Deserialize;
+
+/// Nostr Wallet Connect (NIP-47) connection, identified by the public key of the client app secret.
+#[derive(Queryable, Identifiable, Debug, Clone)]
+#[primary_key(pubkey)]
+pub struct NwcConnection {
+    pub created_at: Option<std::time::SystemTime>,
+    pub pubkey: String,
+    pub uid: i32,
+    pub name: String,
+    /// NIP-47 methods the client app is allowed to call
+    pub allowed_methods: Vec<String>,
+    /// Maximum amount spent per budget period, unlimited if not set
+    pub budget_msat: Option<i64>,
+    /// Length of a budget period, the budget never renews if not set
+    pub budget_renewal_secs: Option<i64>,
+    pub budget_spent_msat: i64,
+    /// Start of the current budget period in epoch seconds
+    pub budget_period_start: i64,
+    pub revoked: bool,
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "nwc_connections"]
+pub struct InsertableNwcConnection {
+    pub pubkey: String,
+    pub uid: i32,
+    pub name: String,
+    pub allowed_methods: Vec<String>,
+    pub budget_msat: Option<i64>,
+    pub budget_renewal_secs: Option<i64>,
+    pub budget_period_start: i64,
+}
+
+#[derive(Default, AsChangeset, Debug, Deserialize)]
+#[table_name = "nwc_connections"]
+pub struct UpdateNwcConnection {
+    pub budget_spent_msat: Option<i64>,
+    pub budget_period_start: Option<i64>,
+    pub revoked: Option<bool>,
+}
+
+impl NwcConnection {
+    pub fn get_by_pubkey(conn: &diesel::PgConnection, pubkey: &str) -> Result<Self, DieselError> {
+        nwc_connections::dsl::nwc_connections
+            .filter(nwc_connections::pubkey.eq(pubkey))
+            .first::<Self>(conn)
+    }
+
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Vec<Self>, DieselError> {
+        nwc_connections::dsl::nwc_connections
+            .filter(nwc_connections::uid.eq(uid))
+            .filter(nwc_connections::revoked.eq(false))
+            .load::<Self>(conn)
+    }
+}
+
+impl InsertableNwcConnection {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<String, DieselError> {
+        diesel::insert_into(nwc_connections::table)
+            .values(self)
+            .returning(nwc_connections::pubkey)
+            .get_result(conn)
+    }
+}
+
+impl UpdateNwcConnection {
+    /// Only updates the connection if it belongs to the given user.
+    pub fn update(&self, conn: &diesel::PgConnection, uid: i32, pubkey: &str) -> Result<usize, DieselError> {
+        diesel::update(
+            nwc_connections::dsl::nwc_connections
+                .filter(nwc_connections::pubkey.eq(pubkey))
+                .filter(nwc_connections::uid.eq(uid)),
+        )
+        .set(self)
+        .execute(conn)
+    }
+}
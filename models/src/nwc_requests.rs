@@ -0,0 +1,50 @@
+use crate::schema::nwc_requests;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use uuid::Uuid;
+
+/// Wallet connect request event that was handled, kept to reject the copies relays deliver again.
+#[derive(Insertable, Debug)]
+#[table_name = "nwc_requests"]
+pub struct InsertableNwcRequest {
+    pub event_id: String,
+    pub pubkey: String,
+    /// Epoch in seconds of the request event.
+    pub created_at: i64,
+}
+
+impl InsertableNwcRequest {
+    /// `false` if the request was handled before.
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<bool, DieselError> {
+        diesel::insert_into(nwc_requests::table)
+            .values(self)
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .map(|inserted| inserted == 1)
+    }
+}
+
+/// Links the request to the bank request it was forwarded as.
+pub fn set_req_id(conn: &diesel::PgConnection, event_id: &str, req_id: Uuid) -> Result<usize, DieselError> {
+    diesel::update(nwc_requests::table.filter(nwc_requests::event_id.eq(event_id)))
+        .set(nwc_requests::req_id.eq(req_id))
+        .execute(conn)
+}
+
+/// Records the amount spent by the bank request once, returns the pubkey of the connection it is spent by.
+/// `None` if the bank request isn't a wallet connect payment or its spend was recorded before.
+pub fn record_spent(conn: &diesel::PgConnection, req_id: Uuid, spent_msat: i64) -> Result<Option<String>, DieselError> {
+    diesel::update(
+        nwc_requests::table
+            .filter(nwc_requests::req_id.eq(req_id))
+            .filter(nwc_requests::spent_msat.is_null()),
+    )
+    .set(nwc_requests::spent_msat.eq(spent_msat))
+    .returning(nwc_requests::pubkey)
+    .get_result(conn)
+    .optional()
+}
+
+pub fn remove_older_than(conn: &diesel::PgConnection, created_at: i64) -> Result<usize, DieselError> {
+    diesel::delete(nwc_requests::table.filter(nwc_requests::created_at.lt(created_at))).execute(conn)
+}
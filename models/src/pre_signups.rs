@@ -12,6 +12,31 @@ pub struct PreSignup {
     pub created_at: Option<std::time::SystemTime>,
     /// Username for this row
     pub email: String,
+    /// Code sent to this email once invited from the waitlist
+    pub invite_code: Option<String>,
+}
+
+impl PreSignup {
+    /// Oldest waitlist entries that haven't been invited yet.
+    pub fn get_uninvited(conn: &diesel::PgConnection, limit: i64) -> Result<Vec<Self>, DieselError> {
+        pre_signups::dsl::pre_signups
+            .filter(pre_signups::invite_code.is_null())
+            .order(pre_signups::created_at.asc())
+            .limit(limit)
+            .load::<Self>(conn)
+    }
+
+    /// Sets the code unless the entry was invited before, returns whether it did.
+    pub fn mark_invited(conn: &diesel::PgConnection, uid: i32, code: &str) -> Result<bool, DieselError> {
+        let updated = diesel::update(
+            pre_signups::dsl::pre_signups
+                .filter(pre_signups::uid.eq(uid))
+                .filter(pre_signups::invite_code.is_null()),
+        )
+        .set(pre_signups::invite_code.eq(code))
+        .execute(conn)?;
+        Ok(updated == 1)
+    }
 }
 
 #[derive(Insertable, Debug, Deserialize)]
@@ -21,6 +21,41 @@ table! {
     }
 }
 
+table! {
+    dealer_orders (ext_order_id) {
+        ext_order_id -> Uuid,
+        order_id -> Nullable<Int8>,
+        symbol -> Text,
+        side -> Text,
+        quantity -> Int8,
+        price -> Nullable<Int8>,
+        filled -> Int8,
+        state -> Text,
+        rejection_reason -> Nullable<Text>,
+        created_at -> Int8,
+        updated_at -> Int8,
+    }
+}
+
+table! {
+    dealer_pnl_entries (id) {
+        id -> Int4,
+        created_at -> Int8,
+        kind -> Text,
+        currency -> Text,
+        amount -> Numeric,
+        symbol -> Nullable<Text>,
+        reference -> Nullable<Text>,
+    }
+}
+
+table! {
+    dealer_redeemed_quotes (quote_id) {
+        quote_id -> Text,
+        valid_until -> Int8,
+    }
+}
+
 table! {
     deezy_btc_ln_swaps (id) {
         id -> Int4,
@@ -42,6 +77,27 @@ table! {
     }
 }
 
+table! {
+    email_verification_tokens (token_hash) {
+        token_hash -> Text,
+        uid -> Int4,
+        email -> Text,
+        created_at -> Int8,
+        expires_at -> Int8,
+    }
+}
+
+table! {
+    insurance_fund_movements (id) {
+        id -> Int4,
+        created_at -> Int8,
+        kind -> Text,
+        amount -> Numeric,
+        balance -> Numeric,
+        reference -> Nullable<Text>,
+    }
+}
+
 table! {
     internal_user_mappings (username) {
         username -> Text,
@@ -49,6 +105,17 @@ table! {
     }
 }
 
+table! {
+    invite_codes (code) {
+        code -> Text,
+        created_at -> Nullable<Timestamp>,
+        created_by -> Nullable<Int4>,
+        max_uses -> Int4,
+        uses -> Int4,
+        expires_at -> Nullable<Int8>,
+    }
+}
+
 table! {
     invoices (payment_hash) {
         payment_hash -> Text,
@@ -69,6 +136,8 @@ table! {
         target_account_currency -> Nullable<Text>,
         reference -> Nullable<Text>,
         description -> Nullable<Text>,
+        payer_comment -> Nullable<Text>,
+        payer_data -> Nullable<Text>,
     }
 }
 
@@ -81,6 +150,57 @@ table! {
     }
 }
 
+table! {
+    lnurl_auth_keys (linking_key) {
+        created_at -> Nullable<Timestamp>,
+        linking_key -> Text,
+        uid -> Int4,
+    }
+}
+
+table! {
+    nostr_contact_lists (pubkey) {
+        pubkey -> Text,
+        created_at -> Int8,
+        received_at -> Int8,
+        follows_count -> Int4,
+    }
+}
+
+table! {
+    nostr_dm_deliveries (id) {
+        id -> Int4,
+        created_at -> Int8,
+        event_id -> Text,
+        pubkey -> Text,
+        protocol -> Text,
+        relay_url -> Text,
+        success -> Bool,
+        error -> Nullable<Text>,
+    }
+}
+
+table! {
+    nostr_follows (pubkey, followed_pubkey) {
+        pubkey -> Text,
+        followed_pubkey -> Text,
+        relay -> Nullable<Text>,
+        petname -> Nullable<Text>,
+        position -> Int4,
+    }
+}
+
+table! {
+    nostr_nip05_names (name) {
+        created_at -> Nullable<Timestamp>,
+        name -> Text,
+        uid -> Int4,
+        pubkey -> Text,
+        relays -> Array<Text>,
+        disabled -> Bool,
+    }
+}
+
 table! {
     nostr_profile_indexer_times (id) {
         id -> Int4,
@@ -103,11 +223,71 @@ table! {
     }
 }
 
+table! {
+    nostr_relays (url) {
+        url -> Text,
+        source -> Text,
+        enabled -> Bool,
+        connected -> Bool,
+        latency_ms -> Nullable<Int8>,
+        publish_attempts -> Int8,
+        publish_successes -> Int8,
+        consecutive_failures -> Int4,
+        backoff_until -> Int8,
+        last_used_at -> Int8,
+        updated_at -> Int8,
+    }
+}
+
 table! {
     nostr_public_keys (pubkey) {
         created_at -> Nullable<Timestamp>,
         pubkey -> Text,
         uid -> Int4,
+        legacy_dms -> Bool,
+    }
+}
+
+table! {
+    nostr_zap_keys (uid) {
+        created_at -> Nullable<Timestamp>,
+        uid -> Int4,
+        pubkey -> Text,
+        encrypted_secret_key -> Text,
+    }
+}
+
+table! {
+    notification_preferences (uid, event_type, channel) {
+        uid -> Int4,
+        event_type -> Text,
+        channel -> Text,
+        enabled -> Bool,
+    }
+}
+
+table! {
+    nwc_connections (pubkey) {
+        created_at -> Nullable<Timestamp>,
+        pubkey -> Text,
+        uid -> Int4,
+        name -> Text,
+        allowed_methods -> Array<Text>,
+        budget_msat -> Nullable<Int8>,
+        budget_renewal_secs -> Nullable<Int8>,
+        budget_spent_msat -> Int8,
+        budget_period_start -> Int8,
+        revoked -> Bool,
+    }
+}
+
+table! {
+    nwc_requests (event_id) {
+        event_id -> Text,
+        pubkey -> Text,
+        created_at -> Int8,
+        req_id -> Nullable<Uuid>,
+        spent_msat -> Nullable<Int8>,
     }
 }
 
@@ -116,6 +296,7 @@ table! {
         uid -> Int4,
         created_at -> Nullable<Timestamp>,
         email -> Text,
+        invite_code -> Nullable<Text>,
     }
 }
 
@@ -171,6 +352,23 @@ table! {
         twitter_handle -> Nullable<Text>,
         is_twitter_verified -> Nullable<Bool>,
         is_email_verified -> Nullable<Bool>,
+        lnurl_comment_allowed -> Nullable<Int4>,
+        lnurl_payer_data -> Nullable<Array<Text>>,
+        lnurl_success_message -> Nullable<Text>,
+        lnurl_success_url -> Nullable<Text>,
+        notification_locale -> Nullable<Text>,
+    }
+}
+
+table! {
+    user_totps (uid) {
+        uid -> Int4,
+        encrypted_secret -> Text,
+        enabled -> Bool,
+        threshold -> Numeric,
+        threshold_currency -> Text,
+        backup_codes -> Array<Text>,
+        last_used_step -> Int8,
     }
 }
 
@@ -187,24 +385,50 @@ table! {
 
 joinable!(accounts -> users (uid));
 joinable!(dca_settings -> users (uid));
+joinable!(email_verification_tokens -> users (uid));
 joinable!(internal_user_mappings -> users (uid));
+joinable!(invite_codes -> users (created_by));
+joinable!(lnurl_auth_keys -> users (uid));
+joinable!(nostr_nip05_names -> users (uid));
 joinable!(nostr_public_keys -> users (uid));
+joinable!(nostr_zap_keys -> users (uid));
+joinable!(notification_preferences -> users (uid));
+joinable!(nwc_connections -> users (uid));
+joinable!(pre_signups -> invite_codes (invite_code));
 joinable!(user_profiles -> users (uid));
+joinable!(user_totps -> users (uid));
 
 allow_tables_to_appear_in_same_query!(
     accounts,
     dca_settings,
+    dealer_orders,
+    dealer_pnl_entries,
+    dealer_redeemed_quotes,
     deezy_btc_ln_swaps,
     deezy_secret_keys,
+    email_verification_tokens,
+    insurance_fund_movements,
     internal_user_mappings,
+    invite_codes,
     invoices,
     ln_addresses,
+    lnurl_auth_keys,
+    nostr_contact_lists,
+    nostr_dm_deliveries,
+    nostr_follows,
+    nostr_nip05_names,
     nostr_profile_indexer_times,
     nostr_profile_records,
     nostr_public_keys,
+    nostr_relays,
+    nostr_zap_keys,
+    notification_preferences,
+    nwc_connections,
+    nwc_requests,
     pre_signups,
     summary_transactions,
     transactions,
     user_profiles,
+    user_totps,
     users,
 );
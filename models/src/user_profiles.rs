@@ -15,6 +15,11 @@ pub struct UserProfile {
     pub twitter_handle: Option<String>,
     pub is_twitter_verified: Option<bool>,
     pub is_email_verified: Option<bool>,
+    pub lnurl_comment_allowed: Option<i32>,
+    pub lnurl_payer_data: Option<Vec<String>>,
+    pub lnurl_success_message: Option<String>,
+    pub lnurl_success_url: Option<String>,
+    pub notification_locale: Option<String>,
 }
 
 #[derive(Insertable, Debug, Deserialize, Serialize)]
@@ -28,6 +33,11 @@ pub struct InsertableUserProfile {
     pub twitter_handle: Option<String>,
     pub is_twitter_verified: Option<bool>,
     pub is_email_verified: Option<bool>,
+    pub lnurl_comment_allowed: Option<i32>,
+    pub lnurl_payer_data: Option<Vec<String>>,
+    pub lnurl_success_message: Option<String>,
+    pub lnurl_success_url: Option<String>,
+    pub notification_locale: Option<String>,
 }
 
 #[derive(Default, AsChangeset, Debug, Deserialize)]
@@ -40,6 +50,11 @@ pub struct UpdateUserProfile {
     pub twitter_handle: Option<String>,
     pub is_twitter_verified: Option<bool>,
     pub is_email_verified: Option<bool>,
+    pub lnurl_comment_allowed: Option<i32>,
+    pub lnurl_payer_data: Option<Vec<String>>,
+    pub lnurl_success_message: Option<String>,
+    pub lnurl_success_url: Option<String>,
+    pub notification_locale: Option<String>,
 }
 
 impl UserProfile {
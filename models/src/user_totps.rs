@@ -0,0 +1,96 @@
+use crate::schema::user_totps;
+
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::{Integer, Text};
+use serde::Deserialize;
+
+#[derive(Queryable, Identifiable, Debug)]
+#[primary_key(uid)]
+pub struct UserTotp {
+    pub uid: i32,
+    /// TOTP secret encrypted by the api, never stored in plain text
+    pub encrypted_secret: String,
+    /// Set once the user has confirmed enrolment with a valid code
+    pub enabled: bool,
+    /// Transfers above this amount require a code
+    pub threshold: BigDecimal,
+    pub threshold_currency: String,
+    /// Hashes of the remaining single use backup codes
+    pub backup_codes: Vec<String>,
+    /// Last accepted time step, used to reject replayed codes
+    pub last_used_step: i64,
+}
+
+#[derive(Insertable, Debug, Deserialize)]
+#[table_name = "user_totps"]
+pub struct InsertableUserTotp {
+    pub uid: i32,
+    pub encrypted_secret: String,
+    pub enabled: bool,
+    pub threshold: BigDecimal,
+    pub threshold_currency: String,
+    pub backup_codes: Vec<String>,
+}
+
+#[derive(Default, AsChangeset, Debug, Deserialize)]
+#[table_name = "user_totps"]
+pub struct UpdateUserTotp {
+    pub enabled: Option<bool>,
+    pub threshold: Option<BigDecimal>,
+    pub threshold_currency: Option<String>,
+    pub backup_codes: Option<Vec<String>>,
+    pub last_used_step: Option<i64>,
+}
+
+impl UserTotp {
+    pub fn get_by_uid(conn: &diesel::PgConnection, uid: i32) -> Result<Self, DieselError> {
+        user_totps::dsl::user_totps
+            .filter(user_totps::uid.eq(uid))
+            .first::<Self>(conn)
+    }
+
+    /// Marks the time step as used unless it or a later one already was, returns the rows updated.
+    pub fn use_step(conn: &diesel::PgConnection, uid: i32, step: i64) -> Result<usize, DieselError> {
+        diesel::update(
+            user_totps::dsl::user_totps
+                .filter(user_totps::uid.eq(uid))
+                .filter(user_totps::last_used_step.lt(step)),
+        )
+        .set(user_totps::last_used_step.eq(step))
+        .execute(conn)
+    }
+
+    /// Removes the hashed backup code if it is still there, returns the rows updated.
+    pub fn use_backup_code(conn: &diesel::PgConnection, uid: i32, hashed_code: &str) -> Result<usize, DieselError> {
+        diesel::sql_query(
+            "UPDATE user_totps SET backup_codes = array_remove(backup_codes, $2) \
+             WHERE uid = $1 AND $2 = ANY(backup_codes)",
+        )
+        .bind::<Integer, _>(uid)
+        .bind::<Text, _>(hashed_code)
+        .execute(conn)
+    }
+
+    pub fn delete(conn: &diesel::PgConnection, uid: i32) -> Result<usize, DieselError> {
+        diesel::delete(user_totps::dsl::user_totps.filter(user_totps::uid.eq(uid))).execute(conn)
+    }
+}
+
+impl InsertableUserTotp {
+    pub fn insert(&self, conn: &diesel::PgConnection) -> Result<i32, DieselError> {
+        diesel::insert_into(user_totps::table)
+            .values(self)
+            .returning(user_totps::uid)
+            .get_result(conn)
+    }
+}
+
+impl UpdateUserTotp {
+    pub fn update(&self, conn: &diesel::PgConnection, uid: i32) -> Result<usize, DieselError> {
+        diesel::update(user_totps::dsl::user_totps.filter(user_totps::uid.eq(uid)))
+            .set(self)
+            .execute(conn)
+    }
+}
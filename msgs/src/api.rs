@@ -38,6 +38,7 @@ pub enum PayLnurlWithdrawalError {
 pub enum SwapResponseError {
     Invalid,
     CurrencyNotAvailable,
+    MarketNotAvailable,
     InvalidQuoteId,
     NotEnoughAvailableBalance,
     BTCNotFromTo,
@@ -53,6 +54,7 @@ pub enum QuoteResponseError {
     CurrencyNotAvailable,
     MarketNotAvailable,
     BTCNotFromTo,
+    InsufficientLiquidity,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -183,7 +185,7 @@ pub struct SwapRequest {
     pub amount: Money,
     pub from: Currency,
     pub to: Currency,
-    pub quote_id: Option<u128>,
+    pub quote_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -235,9 +237,21 @@ pub struct QuoteResponse {
     // epoch in ms
     pub valid_until: u64,
     pub rate: Option<Rate>,
-    pub quote_id: Option<u128>,
+    pub quote_id: Option<String>,
     pub error: Option<QuoteResponseError>,
     pub fees: Option<Money>,
+    pub spread: Option<SpreadComponents>,
+}
+
+/// What the spread charged on a quote is made of, as fractions of the price.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpreadComponents {
+    pub base: Decimal,
+    pub volatility: Decimal,
+    pub inventory: Decimal,
+    pub size: Decimal,
+    /// Sum of the components kept within the currency's floor and cap.
+    pub total: Decimal,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -344,6 +358,7 @@ pub struct NostrProfileSearchRequest {
     pub text: Option<String>,
     pub pubkey: Option<String>,
     pub limit: Option<u64>,
+    pub offset: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
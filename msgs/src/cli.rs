@@ -6,6 +6,10 @@ use serde::{Deserialize, Serialize};
 pub enum Cli {
     MakeTx(MakeTx),
     MakeTxResult(MakeTxResult),
+    DealerPnlRequest(DealerPnlRequest),
+    DealerPnlReport(DealerPnlReport),
+    InsuranceFundRequest(InsuranceFundRequest),
+    InsuranceFundReport(InsuranceFundReport),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,3 +27,57 @@ pub struct MakeTxResult {
     pub tx: MakeTx,
     pub result: String,
 }
+
+/// Dealer pnl of the last `days` UTC days, including today.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DealerPnlRequest {
+    pub days: u32,
+    pub currency: Option<Currency>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DealerPnlReport {
+    pub days: Vec<DailyDealerPnl>,
+    pub error: Option<String>,
+}
+
+/// Pnl booked in a currency on a day, in units of the currency.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyDealerPnl {
+    pub day: String,
+    pub currency: Currency,
+    pub spread: Decimal,
+    pub hedge: Decimal,
+    pub funding: Decimal,
+    pub fees: Decimal,
+}
+
+impl DailyDealerPnl {
+    pub fn total(&self) -> Decimal {
+        self.spread + self.hedge + self.funding + self.fees
+    }
+}
+
+/// Insurance fund state with its movements of the last `days` days.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InsuranceFundRequest {
+    pub days: u32,
+}
+
+/// Amounts in BTC, `target` is missing without insurance fund settings or while fiat rates are unknown.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InsuranceFundReport {
+    pub balance: Decimal,
+    pub target: Option<Decimal>,
+    pub movements: Vec<InsuranceFundMovement>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InsuranceFundMovement {
+    pub created_at: u64,
+    pub kind: String,
+    pub amount: Decimal,
+    pub balance: Decimal,
+    pub reference: Option<String>,
+}
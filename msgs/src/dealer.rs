@@ -53,11 +53,26 @@ pub enum HealthStatus {
     Down,
 }
 
+/// Whether the market data of a symbol can be quoted on, and if not why.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub enum MarketDataState {
+    Live,
+    /// No order book yet, or one of its sides is empty.
+    NoBook,
+    StaleBook,
+    CrossedBook,
+    NoMarkPrice,
+    StaleMarkPrice,
+    /// The mid price is too far from the mark price, which follows the index.
+    IndexDeviation,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DealerHealth {
     pub status: HealthStatus,
     pub available_currencies: Vec<Currency>,
     pub rates: HashMap<(Currency, Currency), Rate>,
+    pub market_data: HashMap<Symbol, MarketDataState>,
     pub timestamp: u64,
 }
 
@@ -26,8 +26,10 @@ pub enum Request {
     FetchBalances,
     FetchTradableSymbols,
     FetchPositions,
+    FetchOpenOrders,
     Subscribe(Subscribe),
     Order(Order),
+    CancelOrder(CancelOrder),
     WithdrawalRequest(WithdrawalRequest),
     ChangeMargin(ChangeMargin),
 }
@@ -57,6 +59,18 @@ pub struct Order {
     margin_type: MarginType,
     settlement_type: SettlementType,
     origin: TradeOrigin,
+    /// Limit price as an integer of the symbol's price decimal places.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    price: Option<u64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    is_post_only: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CancelOrder {
+    pub order_id: OrderId,
+    pub symbol: Symbol,
+    pub settlement_type: SettlementType,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -87,6 +101,25 @@ impl Order {
             margin_type: MarginType::Isolated,
             settlement_type: SettlementType::Instant,
             origin: TradeOrigin::Sdk,
+            price: None,
+            is_post_only: false,
+        }
+    }
+
+    /// Post-only orders are rejected instead of taking liquidity.
+    pub fn limit(
+        side: Side,
+        quantity: u64,
+        symbol: Symbol,
+        price: u64,
+        is_post_only: bool,
+        ext_order_id: Option<Uuid>,
+    ) -> Self {
+        Self {
+            order_type: OrderType::Limit,
+            price: Some(price),
+            is_post_only,
+            ..Self::new(side, quantity, symbol, ext_order_id)
         }
     }
 }
@@ -103,6 +136,9 @@ pub enum KolliderApiResponse {
     MarkPrices(MarkPrice),
     Received(Received),
     Fill(Fill),
+    Done(Done),
+    OrderRejection(OrderRejection),
+    OpenOrders(OpenOrders),
     Trade(Trade),
     SettlementRequest(SettlementRequest),
     OrderInvoice(OrderInvoice),
@@ -133,6 +169,10 @@ pub struct Authenticate {
 }
 
 impl Authenticate {
+    pub fn new(message: String) -> Self {
+        Self { message }
+    }
+
     pub fn success(&self) -> bool {
         self.message == "success"
     }
@@ -178,6 +218,39 @@ pub struct Received {
     pub leverage: u64,
 }
 
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Done {
+    pub timestamp: u64,
+    pub order_id: OrderId,
+    pub symbol: Symbol,
+}
+
+/// Order resting on the exchange, `price` as an integer of the symbol's price decimal places.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct OpenOrder {
+    pub order_id: OrderId,
+    pub ext_order_id: ExtOrderId,
+    pub symbol: Symbol,
+    pub side: Side,
+    pub quantity: u64,
+    #[serde(default)]
+    pub filled: u64,
+    pub price: u64,
+}
+
+/// Answer to `FetchOpenOrders`, by symbol.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct OpenOrders {
+    pub open_orders: HashMap<Symbol, Vec<OpenOrder>>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct OrderRejection {
+    pub symbol: Symbol,
+    pub ext_order_id: ExtOrderId,
+    pub reason: RejectionReason,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct Fill {
     pub order_id: OrderId,
@@ -1,9 +1,12 @@
+use core_types::UserId;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NostrPrivateMessage {
     pub pubkey: String,
     pub text: String,
+    /// Send as a NIP-04 direct message instead of a NIP-17 gift wrap
+    pub legacy_dms: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,6 +17,9 @@ pub struct NostrZapNote {
     pub bolt11: String,
     pub preimage: Option<String>,
     pub settled_timestamp: u64,
+    /// Receiver of the zap, the receipt is signed with their zap key if they have one
+    #[serde(default)]
+    pub uid: Option<UserId>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,9 +30,23 @@ pub struct NostrProfilesRefetchRequest {
     pub limit: Option<usize>,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum NostrRelayAction {
+    Add,
+    Remove,
+}
+
+/// Adds a relay to or removes a relay from the nostr engine pool at runtime.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NostrRelayUpdate {
+    pub url: String,
+    pub action: NostrRelayAction,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Nostr {
     NostrPrivateMessage(NostrPrivateMessage),
     NostrZapNote(NostrZapNote),
     NostrProfilesRefetchRequest(NostrProfilesRefetchRequest),
+    NostrRelayUpdate(NostrRelayUpdate),
 }
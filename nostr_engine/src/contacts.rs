@@ -0,0 +1,101 @@
+use models::nostr_follows::NostrFollow;
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::prelude::{Kind, SubscriptionFilter};
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// Relays reject overly large filters, so linked pubkeys are spread over several of them.
+const MAX_AUTHORS_PER_FILTER: usize = 500;
+const MAX_FOLLOWS: usize = 10_000;
+const MAX_PETNAME_LEN: usize = 64;
+const MAX_RELAY_URL_LEN: usize = 256;
+
+/// Contact lists (kind 3) of the given pubkeys, relays only keep the latest one of each author.
+pub fn create_contacts_filters(pubkeys: &[String]) -> Vec<SubscriptionFilter> {
+    let authors = pubkeys
+        .iter()
+        .filter_map(|pubkey| XOnlyPublicKey::from_str(pubkey).ok())
+        .collect::<Vec<_>>();
+    authors
+        .chunks(MAX_AUTHORS_PER_FILTER)
+        .map(|chunk| {
+            SubscriptionFilter::new()
+                .kind(Kind::ContactList)
+                .authors(chunk.to_vec())
+        })
+        .collect()
+}
+
+/// Follows from the `p` tags of a contact list (NIP-02), invalid and repeated pubkeys are skipped.
+pub fn parse_follows(pubkey: &str, tags: &[Vec<String>]) -> Vec<NostrFollow> {
+    let mut seen = HashSet::new();
+    tags.iter()
+        .filter(|tag| tag.first().map(|kind| kind == "p").unwrap_or(false))
+        .filter_map(|tag| {
+            let followed_pubkey = XOnlyPublicKey::from_str(tag.get(1)?).ok()?.to_string();
+            let relay = tag
+                .get(2)
+                .filter(|relay| !relay.is_empty() && relay.len() <= MAX_RELAY_URL_LEN)
+                .cloned();
+            let petname = tag
+                .get(3)
+                .filter(|petname| !petname.is_empty() && petname.len() <= MAX_PETNAME_LEN)
+                .cloned();
+            Some((followed_pubkey, relay, petname))
+        })
+        .filter(|(followed_pubkey, _, _)| seen.insert(followed_pubkey.clone()))
+        .take(MAX_FOLLOWS)
+        .enumerate()
+        .map(|(position, (followed_pubkey, relay, petname))| NostrFollow {
+            pubkey: pubkey.to_string(),
+            followed_pubkey,
+            relay,
+            petname,
+            position: position as i32,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::prelude::Keys;
+
+    #[test]
+    fn test_parse_follows() {
+        let alice = Keys::generate().public_key().to_string();
+        let bob = Keys::generate().public_key().to_string();
+        let tags = vec![
+            vec![
+                String::from("p"),
+                alice.clone(),
+                String::from("wss://nos.lol"),
+                String::from("alice"),
+            ],
+            vec![String::from("e"), bob.clone()],
+            vec![String::from("p"), String::from("not a pubkey")],
+            vec![String::from("p"), bob.clone(), String::new()],
+            vec![String::from("p"), alice.clone()],
+        ];
+
+        let follows = parse_follows("me", &tags);
+        assert_eq!(follows.len(), 2);
+        assert_eq!(follows[0].followed_pubkey, alice);
+        assert_eq!(follows[0].relay.as_deref(), Some("wss://nos.lol"));
+        assert_eq!(follows[0].petname.as_deref(), Some("alice"));
+        assert_eq!(follows[1].followed_pubkey, bob);
+        assert_eq!(follows[1].relay, None);
+        assert_eq!(follows[1].position, 1);
+        assert!(follows.iter().all(|follow| follow.pubkey == "me"));
+    }
+
+    #[test]
+    fn test_create_contacts_filters() {
+        let pubkeys = (0..MAX_AUTHORS_PER_FILTER + 1)
+            .map(|_| Keys::generate().public_key().to_string())
+            .chain(std::iter::once(String::from("invalid")))
+            .collect::<Vec<_>>();
+        assert_eq!(create_contacts_filters(&pubkeys).len(), 2);
+        assert!(create_contacts_filters(&[]).is_empty());
+    }
+}
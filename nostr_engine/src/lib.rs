@@ -1,13 +1,23 @@
+mod contacts;
+mod nip17;
+mod nip44;
 mod nostr_engine;
+mod nwc;
+mod profile_cache;
+mod relay_manager;
 
 use crate::nostr_engine::NostrEngine;
+pub use crate::relay_manager::RelayManager;
 use core_types::nostr::NostrProfile;
 use diesel::r2d2::ConnectionManager;
 use diesel::PgConnection;
 use lazy_static::lazy_static;
-use msgs::api::ShareableNostrProfile;
+use models::nostr_public_keys::NostrPublicKey;
+use models::nostr_relays::NostrRelay;
+use msgs::api::{Api, NostrResponseError, ShareableNostrProfile};
 use msgs::Message;
-use nostr_sdk::prelude::{Event, FromPkStr, Keys, Kind, SubscriptionFilter, Timestamp};
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::prelude::{Event, EventBuilder, FromPkStr, Keys, Kind, RelayMessage, SubscriptionFilter, Timestamp};
 use nostr_sdk::{Client, RelayPoolNotification};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
@@ -19,6 +29,8 @@ use utils::xlogging::LoggingSettings;
 
 const REQUEST_USER_PROFILE_TIMEOUT: u64 = 30_000;
 const API_PROFILE_TIMEOUT: u64 = 5_000;
+const RELAY_HEALTH_CHECK_INTERVAL: u64 = 60;
+const DEFAULT_PROFILE_CACHE_SIZE: usize = 10_000;
 
 type DbPool = diesel::r2d2::Pool<ConnectionManager<PgConnection>>;
 
@@ -31,6 +43,12 @@ pub struct NostrEngineSettings {
     pub nostr_engine_logging_settings: LoggingSettings,
     pub nostr_relays_urls: Vec<String>,
     pub nostr_historical_profile_indexer: bool,
+    /// Bank publish address, Nostr Wallet Connect is only served when set.
+    #[serde(default)]
+    pub nostr_bank_subscribe_address: Option<String>,
+    /// Maximum number of profiles kept in memory, search goes to the database.
+    #[serde(default)]
+    pub nostr_profile_cache_size: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -38,6 +56,19 @@ pub enum NostrEngineEvent {
     NostrProfileUpdate(Box<NostrProfileUpdate>),
     LndhubxMessage(Message),
     InternalNostrProfileRequest(InternalNostrProfileRequest),
+    NwcRequest(Box<Event>),
+    ContactList(Box<Event>),
+    RelayPublishResult(RelayPublishResult),
+    RelayHealthCheck,
+}
+
+/// Acknowledgement of a published event by a relay (NIP-20).
+#[derive(Debug)]
+pub struct RelayPublishResult {
+    pub url: String,
+    pub event_id: String,
+    pub accepted: bool,
+    pub message: String,
 }
 
 #[derive(Clone, Debug)]
@@ -85,9 +116,24 @@ struct LnUrlPayResponse {
     tag: String,
 }
 
-/// Returns relays for normal subscription and relays for which index should be rebuilt
-pub fn get_relays(settings: &NostrEngineSettings) -> Vec<(String, Option<SocketAddr>)> {
-    into_relays(settings.nostr_relays_urls.clone())
+/// Merges the configured relays with the ones persisted by the relay manager.
+pub fn load_relay_manager(settings: &NostrEngineSettings, db_pool: &DbPool, logger: &Logger) -> RelayManager {
+    let persisted = match db_pool.try_get() {
+        Some(conn) => NostrRelay::fetch_all(&conn).unwrap_or_else(|err| {
+            log::error!(logger, "Failed to fetch nostr relays, error: {:?}", err);
+            Vec::new()
+        }),
+        None => {
+            log::error!(logger, "Failed to get a DB connection to fetch nostr relays");
+            Vec::new()
+        }
+    };
+    RelayManager::new(&settings.nostr_relays_urls, persisted)
+}
+
+/// Returns relays the client should connect to on startup
+pub fn get_relays(relay_manager: &RelayManager) -> Vec<(String, Option<SocketAddr>)> {
+    into_relays(relay_manager.pool_relays())
 }
 
 fn into_relays<T: IntoIterator>(urls: T) -> Vec<(String, Option<SocketAddr>)>
@@ -100,6 +146,7 @@ where
 pub fn spawn_profile_indexer(
     nostr_client: Client,
     indexer_start: Option<u64>,
+    nwc_wallet_pubkey: Option<XOnlyPublicKey>,
     events_tx: tokio::sync::mpsc::Sender<NostrEngineEvent>,
     db_pool: DbPool,
     logger: Logger,
@@ -162,7 +209,19 @@ pub fn spawn_profile_indexer(
                 Some(filter) => filter,
                 None => return,
             };
-            nostr_client.subscribe(vec![subscription_filter]).await;
+            // subscribing replaces the previous filters, so wallet connect requests have to be part of it
+            let nwc_filter = nwc_wallet_pubkey.map(|pubkey| nwc::create_request_filter(pubkey, since_epoch_seconds));
+            // newly linked pubkeys have their contact lists picked up with the next subscription
+            let contacts_filters = create_contacts_filters(&db_pool, &logger);
+            nostr_client
+                .subscribe(
+                    vec![subscription_filter]
+                        .into_iter()
+                        .chain(nwc_filter)
+                        .chain(contacts_filters)
+                        .collect(),
+                )
+                .await;
             tokio::time::sleep(tokio::time::Duration::from_secs(utils::time::SECONDS_IN_HOUR)).await;
             store_last_check(&db_pool, since_epoch_seconds, &logger);
         }
@@ -178,41 +237,137 @@ fn spawn_profile_subscriber(
         loop {
             let mut notifications = nostr_client.notifications();
             while let Ok(notification) = notifications.recv().await {
-                if let RelayPoolNotification::Event(_url, event) = notification {
-                    if event.kind == Kind::Metadata {
-                        if let Some(profile_update) = try_profile_update_from_event(&event).await {
-                            let msg = NostrEngineEvent::NostrProfileUpdate(Box::new(profile_update));
-                            if let Err(err) = events_tx.try_send(msg) {
+                match notification {
+                    RelayPoolNotification::Event(_url, event) => {
+                        if event.kind == Kind::Custom(nwc::NWC_REQUEST_KIND) {
+                            if let Err(err) = events_tx.try_send(NostrEngineEvent::NwcRequest(Box::new(event))) {
                                 log::error!(
                                     logger,
-                                    "Failed to send nostr profile update to events channel, error: {:?}",
+                                    "Failed to send wallet connect request to events channel, error: {:?}",
                                     err
                                 );
                             }
+                        } else if event.kind == Kind::ContactList {
+                            if let Err(err) = events_tx.try_send(NostrEngineEvent::ContactList(Box::new(event))) {
+                                log::error!(
+                                    logger,
+                                    "Failed to send contact list to events channel, error: {:?}",
+                                    err
+                                );
+                            }
+                        } else if event.kind == Kind::Metadata {
+                            if let Some(profile_update) = try_profile_update_from_event(&event).await {
+                                let msg = NostrEngineEvent::NostrProfileUpdate(Box::new(profile_update));
+                                if let Err(err) = events_tx.try_send(msg) {
+                                    log::error!(
+                                        logger,
+                                        "Failed to send nostr profile update to events channel, error: {:?}",
+                                        err
+                                    );
+                                }
+                            }
                         }
                     }
+                    RelayPoolNotification::Message(
+                        url,
+                        RelayMessage::Ok {
+                            event_id,
+                            status,
+                            message,
+                        },
+                    ) => {
+                        let result = RelayPublishResult {
+                            url: url.to_string(),
+                            event_id: event_id.to_string(),
+                            accepted: status,
+                            message,
+                        };
+                        if let Err(err) = events_tx.try_send(NostrEngineEvent::RelayPublishResult(result)) {
+                            log::error!(
+                                logger,
+                                "Failed to send relay publish result to events channel, error: {:?}",
+                                err
+                            );
+                        }
+                    }
+                    _ => {}
                 }
             }
         }
     });
 }
 
+/// Periodically makes the events handler check the relays, persist their health and prune failing ones.
+pub fn spawn_relay_health_checker(events_tx: tokio::sync::mpsc::Sender<NostrEngineEvent>, logger: Logger) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(RELAY_HEALTH_CHECK_INTERVAL)).await;
+            if let Err(err) = events_tx.try_send(NostrEngineEvent::RelayHealthCheck) {
+                log::error!(
+                    logger,
+                    "Failed to send relay health check to events channel, error: {:?}",
+                    err
+                );
+            }
+        }
+    });
+}
+
 pub fn spawn_events_handler(
     nostr_client: Client,
     mut events_rx: tokio::sync::mpsc::Receiver<NostrEngineEvent>,
     bank_tx_sender: tokio::sync::mpsc::Sender<Message>,
+    relay_manager: RelayManager,
+    profile_cache_size: Option<usize>,
     db_pool: DbPool,
     logger: Logger,
 ) {
     tokio::spawn(async move {
-        let mut nostr_engine = NostrEngine::new(nostr_client, bank_tx_sender, db_pool, logger).await;
-        nostr_engine.initialize_profile_cache().await;
+        let profile_cache_size = profile_cache_size.unwrap_or(DEFAULT_PROFILE_CACHE_SIZE);
+        let mut nostr_engine = NostrEngine::new(
+            nostr_client,
+            bank_tx_sender,
+            relay_manager,
+            profile_cache_size,
+            db_pool,
+            logger,
+        )
+        .await;
+        nostr_engine.initialize_profile_cache(profile_cache_size).await;
         while let Some(event) = events_rx.recv().await {
             nostr_engine.process_event(&event).await;
         }
     });
 }
 
+/// Only bank responses to wallet connect requests are of interest to the nostr engine.
+pub fn is_nwc_response(message: &Message) -> bool {
+    matches!(
+        message,
+        Message::Api(Api::PaymentResponse(_)) | Message::Api(Api::InvoiceResponse(_)) | Message::Api(Api::Balances(_))
+    )
+}
+
+/// Starts listening for wallet connect requests and announces the supported methods (NIP-47 info event).
+/// The profile indexer keeps the request filter as part of its own subscription afterwards.
+pub async fn start_nwc(client: &Client, wallet_pubkey: XOnlyPublicKey, logger: &Logger) {
+    let since = utils::time::time_now() / 1000;
+    client
+        .subscribe(vec![nwc::create_request_filter(wallet_pubkey, since)])
+        .await;
+    let content = core_types::nostr::NWC_METHODS.join(" ");
+    match EventBuilder::new(Kind::Custom(nwc::NWC_INFO_KIND), content, &[]).to_event(&client.keys()) {
+        Ok(event) => {
+            if let Err(err) = client.send_event(event).await {
+                log::error!(logger, "Failed to publish wallet connect info event, error: {:?}", err);
+            }
+        }
+        Err(err) => {
+            log::error!(logger, "Failed to create wallet connect info event, error: {:?}", err);
+        }
+    }
+}
+
 async fn request_user_profile(client: &Client, request: &InternalNostrProfileRequest) {
     let subscription = match create_profile_filter(request) {
         Some(filter) => filter,
@@ -222,9 +377,31 @@ async fn request_user_profile(client: &Client, request: &InternalNostrProfileReq
     client.req_events_of(vec![subscription], Some(timeout)).await;
 }
 
-async fn send_nostr_private_msg(client: &Client, pubkey: &str, text: &str) {
-    let keys = Keys::from_pk_str(pubkey).unwrap();
-    client.send_direct_msg(keys.public_key(), text).await.unwrap();
+#[derive(Debug)]
+struct RelayDelivery {
+    relay_url: String,
+    error: Option<String>,
+}
+
+/// Creates a NIP-17 gift wrapped message, or a NIP-04 direct message for legacy clients.
+fn create_private_msg(
+    client: &Client,
+    pubkey: &str,
+    text: &str,
+    legacy_dms: bool,
+) -> Result<Event, NostrResponseError> {
+    let receiver = Keys::from_pk_str(pubkey)
+        .map_err(|_| NostrResponseError::ErrorSendingPrivateMessage)?
+        .public_key();
+    let keys = client.keys();
+    if legacy_dms {
+        EventBuilder::new_encrypted_direct_msg(&keys, receiver, text)
+            .map_err(|_| NostrResponseError::ErrorSendingPrivateMessage)?
+            .to_event(&keys)
+            .map_err(|_| NostrResponseError::ErrorSendingPrivateMessage)
+    } else {
+        nip17::gift_wrap(&keys, &receiver, text).map_err(|_| NostrResponseError::ErrorSendingPrivateMessage)
+    }
 }
 
 async fn verify_nip05(pubkey: String, nip05: String) -> Option<bool> {
@@ -325,6 +502,23 @@ fn create_profile_filter(request: &InternalNostrProfileRequest) -> Option<Subscr
     Some(filter)
 }
 
+fn create_contacts_filters(db_pool: &DbPool, logger: &Logger) -> Vec<SubscriptionFilter> {
+    let conn = match db_pool.try_get() {
+        Some(conn) => conn,
+        None => {
+            log::error!(logger, "Indexer failed to get a DB connection to load linked pubkeys");
+            return Vec::new();
+        }
+    };
+    match NostrPublicKey::fetch_all_pubkeys(&conn) {
+        Ok(pubkeys) => contacts::create_contacts_filters(&pubkeys),
+        Err(err) => {
+            log::error!(logger, "Failed to fetch linked pubkeys, error: {:?}", err);
+            Vec::new()
+        }
+    }
+}
+
 fn store_last_check(db_pool: &DbPool, last_check: u64, logger: &Logger) {
     if let Some(conn) = db_pool.try_get() {
         if let Err(err) =
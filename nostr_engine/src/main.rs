@@ -1,7 +1,10 @@
 use diesel::r2d2::{ConnectionManager, Pool};
 use diesel::PgConnection;
 use msgs::*;
-use nostr_engine::{get_relays, spawn_events_handler, spawn_profile_indexer, NostrEngineEvent, NostrEngineSettings};
+use nostr_engine::{
+    get_relays, is_nwc_response, load_relay_manager, spawn_events_handler, spawn_profile_indexer,
+    spawn_relay_health_checker, start_nwc, NostrEngineEvent, NostrEngineSettings,
+};
 use nostr_sdk::prelude::{FromSkStr, Keys};
 use nostr_sdk::{Client, Options};
 use slog as log;
@@ -18,14 +21,15 @@ async fn main() {
 
     let nostr_engine_keys = Keys::from_sk_str(&settings.nostr_private_key).unwrap();
 
-    let relays = get_relays(&settings);
-
     let (events_tx, events_rx) = tokio::sync::mpsc::channel(2048);
 
     let db_pool = Pool::builder()
-        .build(ConnectionManager::<PgConnection>::new(settings.psql_url))
+        .build(ConnectionManager::<PgConnection>::new(settings.psql_url.clone()))
         .expect("Failed to create pool.");
 
+    let relay_manager = load_relay_manager(&settings, &db_pool, &logger);
+    let relays = get_relays(&relay_manager);
+
     log::info!(logger, "Waiting to connect with relays: {:?}", relays,);
     let options = Options::new().wait_for_connection(true);
     let nostr_client = Client::new_with_opts(&nostr_engine_keys, options);
@@ -33,6 +37,33 @@ async fn main() {
     nostr_client.connect().await;
     log::info!(logger, "Connected");
 
+    let nwc_wallet_pubkey = match settings.nostr_bank_subscribe_address {
+        Some(ref address) => {
+            let bank_subscriber = context.create_subscriber(address);
+            let nwc_events_tx = events_tx.clone();
+            let nwc_logger = logger.clone();
+            std::thread::spawn(move || {
+                while let Ok(frames) = bank_subscriber.recv_multipart(0) {
+                    let message = match frames.get(2).map(|frame| bincode::deserialize::<Message>(frame)) {
+                        Some(Ok(message)) if is_nwc_response(&message) => message,
+                        _ => continue,
+                    };
+                    if let Err(err) = nwc_events_tx.try_send(NostrEngineEvent::LndhubxMessage(message)) {
+                        log::error!(
+                            nwc_logger,
+                            "Failed to send bank response to events channel, error: {:?}",
+                            err
+                        );
+                    }
+                }
+            });
+            start_nwc(&nostr_client, nostr_engine_keys.public_key(), &logger).await;
+            log::info!(logger, "Serving Nostr Wallet Connect requests");
+            Some(nostr_engine_keys.public_key())
+        }
+        None => None,
+    };
+
     let indexer_start = if settings.nostr_historical_profile_indexer {
         if let Some(conn) = db_pool.try_get() {
             match models::nostr_profile_indexer_times::NostrProfileIndexerTime::get_last_check(&conn) {
@@ -68,11 +99,21 @@ async fn main() {
         nostr_client.clone(),
         events_rx,
         bank_tx_sender,
+        relay_manager,
+        settings.nostr_profile_cache_size,
         db_pool.clone(),
         logger.clone(),
     );
+    spawn_relay_health_checker(events_tx.clone(), logger.clone());
 
-    spawn_profile_indexer(nostr_client, indexer_start, events_tx.clone(), db_pool, logger.clone());
+    spawn_profile_indexer(
+        nostr_client,
+        indexer_start,
+        nwc_wallet_pubkey,
+        events_tx.clone(),
+        db_pool,
+        logger.clone(),
+    );
 
     std::thread::spawn(move || {
         while let Some(message) = bank_tx_receiver.blocking_recv() {
@@ -0,0 +1,127 @@
+use crate::nip44;
+use nostr_sdk::nostr::secp256k1::{KeyPair, Message, Secp256k1, XOnlyPublicKey};
+use nostr_sdk::nostr::Event;
+use nostr_sdk::prelude::Keys;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+const SEAL_KIND: u64 = 13;
+const PRIVATE_DIRECT_MESSAGE_KIND: u64 = 14;
+const GIFT_WRAP_KIND: u64 = 1059;
+
+/// Seal and gift wrap timestamps are shifted up to two days into the past to hide the real send time.
+const MAX_TIMESTAMP_TWEAK_SECS: u64 = 2 * 24 * 60 * 60;
+
+#[derive(Debug)]
+pub enum GiftWrapError {
+    MissingSecretKey,
+    Encryption(nip44::Nip44Error),
+    Signing,
+    NoRandomness,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn event_id(pubkey: &str, created_at: u64, kind: u64, tags: &Value, content: &str) -> [u8; 32] {
+    let serialized = json!([0, pubkey, created_at, kind, tags, content]).to_string();
+    Sha256::digest(serialized.as_bytes()).into()
+}
+
+fn tweaked_timestamp(now: u64) -> Result<u64, GiftWrapError> {
+    let mut random = [0u8; 8];
+    getrandom::getrandom(&mut random).map_err(|_| GiftWrapError::NoRandomness)?;
+    Ok(now - u64::from_be_bytes(random) % MAX_TIMESTAMP_TWEAK_SECS)
+}
+
+fn sign_event(keys: &Keys, created_at: u64, kind: u64, tags: Value, content: String) -> Result<Event, GiftWrapError> {
+    let secret_key = keys.secret_key().map_err(|_| GiftWrapError::MissingSecretKey)?;
+    let pubkey = keys.public_key().to_string();
+    let id = event_id(&pubkey, created_at, kind, &tags, &content);
+
+    let mut aux_rand = [0u8; 32];
+    getrandom::getrandom(&mut aux_rand).map_err(|_| GiftWrapError::NoRandomness)?;
+    let secp = Secp256k1::new();
+    let key_pair = KeyPair::from_secret_key(&secp, &secret_key);
+    let message = Message::from_slice(&id).map_err(|_| GiftWrapError::Signing)?;
+    let sig = secp.sign_schnorr_with_aux_rand(&message, &key_pair, &aux_rand);
+
+    let event = json!({
+        "id": to_hex(&id),
+        "pubkey": pubkey,
+        "created_at": created_at,
+        "kind": kind,
+        "tags": tags,
+        "content": content,
+        "sig": sig.to_string(),
+    });
+    Event::from_json(event.to_string()).map_err(|_| GiftWrapError::Signing)
+}
+
+/// Wraps a private direct message (NIP-17): an unsigned kind 14 rumor, sealed by the sender
+/// and gift wrapped with a throwaway key so relays only learn the receiver.
+pub fn gift_wrap(sender: &Keys, receiver: &XOnlyPublicKey, text: &str) -> Result<Event, GiftWrapError> {
+    let sender_secret_key = sender.secret_key().map_err(|_| GiftWrapError::MissingSecretKey)?;
+    let now = utils::time::time_now() / 1000;
+
+    let sender_pubkey = sender.public_key().to_string();
+    let rumor_tags = json!([["p", receiver.to_string()]]);
+    let rumor_id = event_id(&sender_pubkey, now, PRIVATE_DIRECT_MESSAGE_KIND, &rumor_tags, text);
+    let rumor = json!({
+        "id": to_hex(&rumor_id),
+        "pubkey": sender_pubkey,
+        "created_at": now,
+        "kind": PRIVATE_DIRECT_MESSAGE_KIND,
+        "tags": rumor_tags,
+        "content": text,
+    });
+
+    let sealed_rumor =
+        nip44::encrypt(&sender_secret_key, receiver, &rumor.to_string()).map_err(GiftWrapError::Encryption)?;
+    let seal = sign_event(sender, tweaked_timestamp(now)?, SEAL_KIND, json!([]), sealed_rumor)?;
+
+    let wrapper = Keys::generate();
+    let wrapper_secret_key = wrapper.secret_key().map_err(|_| GiftWrapError::MissingSecretKey)?;
+    let wrapped_seal =
+        nip44::encrypt(&wrapper_secret_key, receiver, &seal.as_json()).map_err(GiftWrapError::Encryption)?;
+    sign_event(
+        &wrapper,
+        tweaked_timestamp(now)?,
+        GIFT_WRAP_KIND,
+        json!([["p", receiver.to_string()]]),
+        wrapped_seal,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::nostr::Kind;
+
+    #[test]
+    fn test_gift_wrap_unwraps_to_rumor() {
+        let sender = Keys::generate();
+        let receiver = Keys::generate();
+        let receiver_secret_key = receiver.secret_key().unwrap();
+
+        let wrap = gift_wrap(&sender, &receiver.public_key(), "⚡ hello").unwrap();
+        assert_eq!(wrap.kind, Kind::Custom(GIFT_WRAP_KIND));
+        assert_ne!(wrap.pubkey, sender.public_key());
+        assert!(wrap.created_at.as_u64() <= utils::time::time_now() / 1000);
+
+        let seal_json = nip44::decrypt(&receiver_secret_key, &wrap.pubkey, &wrap.content).unwrap();
+        let seal = Event::from_json(seal_json).unwrap();
+        assert_eq!(seal.kind, Kind::Custom(SEAL_KIND));
+        assert_eq!(seal.pubkey, sender.public_key());
+        assert!(seal.tags.is_empty());
+
+        let rumor_json = nip44::decrypt(&receiver_secret_key, &seal.pubkey, &seal.content).unwrap();
+        let rumor = serde_json::from_str::<Value>(&rumor_json).unwrap();
+        assert_eq!(rumor["kind"], PRIVATE_DIRECT_MESSAGE_KIND);
+        assert_eq!(rumor["content"], "⚡ hello");
+        assert_eq!(rumor["pubkey"], sender.public_key().to_string());
+        assert_eq!(rumor["tags"][0][1], receiver.public_key().to_string());
+        assert!(rumor.get("sig").is_none());
+    }
+}
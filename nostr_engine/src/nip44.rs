@@ -0,0 +1,240 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::ChaCha20;
+use hmac::{Hmac, Mac};
+use nostr_sdk::nostr::secp256k1::{ecdh, PublicKey, SecretKey, XOnlyPublicKey};
+use sha2::Sha256;
+use std::str::FromStr;
+
+const VERSION: u8 = 2;
+const SALT: &[u8] = b"nip44-v2";
+const MIN_PLAINTEXT_LEN: usize = 1;
+const MAX_PLAINTEXT_LEN: usize = 65535;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Nip44Error {
+    InvalidPublicKey,
+    InvalidPlaintextLength,
+    InvalidPayload,
+    UnknownVersion,
+    InvalidMac,
+    InvalidPadding,
+    NoRandomness,
+}
+
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+/// Shared key between two parties, the same in both directions (HKDF-extract of the ECDH x coordinate).
+pub fn conversation_key(secret_key: &SecretKey, public_key: &XOnlyPublicKey) -> Result<[u8; 32], Nip44Error> {
+    let public_key = PublicKey::from_str(&format!("02{public_key}")).map_err(|_| Nip44Error::InvalidPublicKey)?;
+    let shared_point = ecdh::shared_secret_point(&public_key, secret_key);
+    Ok(hmac_sha256(SALT, &[&shared_point[..32]]))
+}
+
+/// Chacha key, chacha nonce and hmac key derived for a single message (HKDF-expand to 76 bytes).
+fn message_keys(conversation_key: &[u8; 32], nonce: &[u8; 32]) -> ([u8; 32], [u8; 12], [u8; 32]) {
+    let mut okm = Vec::with_capacity(96);
+    let mut block: Vec<u8> = Vec::new();
+    for counter in 1..=3u8 {
+        block = hmac_sha256(conversation_key, &[block.as_slice(), &nonce[..], &[counter]]).to_vec();
+        okm.extend_from_slice(&block);
+    }
+    let mut chacha_key = [0u8; 32];
+    let mut chacha_nonce = [0u8; 12];
+    let mut hmac_key = [0u8; 32];
+    chacha_key.copy_from_slice(&okm[0..32]);
+    chacha_nonce.copy_from_slice(&okm[32..44]);
+    hmac_key.copy_from_slice(&okm[44..76]);
+    (chacha_key, chacha_nonce, hmac_key)
+}
+
+pub fn calc_padded_len(unpadded_len: usize) -> usize {
+    if unpadded_len <= 32 {
+        return 32;
+    }
+    let next_power = 1usize << (usize::BITS - (unpadded_len - 1).leading_zeros());
+    let chunk = if next_power <= 256 { 32 } else { next_power / 8 };
+    chunk * ((unpadded_len - 1) / chunk + 1)
+}
+
+fn pad(plaintext: &str) -> Result<Vec<u8>, Nip44Error> {
+    let unpadded = plaintext.as_bytes();
+    if unpadded.len() < MIN_PLAINTEXT_LEN || unpadded.len() > MAX_PLAINTEXT_LEN {
+        return Err(Nip44Error::InvalidPlaintextLength);
+    }
+    let mut padded = Vec::with_capacity(2 + calc_padded_len(unpadded.len()));
+    padded.extend_from_slice(&(unpadded.len() as u16).to_be_bytes());
+    padded.extend_from_slice(unpadded);
+    padded.resize(2 + calc_padded_len(unpadded.len()), 0);
+    Ok(padded)
+}
+
+fn unpad(padded: &[u8]) -> Result<String, Nip44Error> {
+    if padded.len() < 2 {
+        return Err(Nip44Error::InvalidPadding);
+    }
+    let unpadded_len = u16::from_be_bytes([padded[0], padded[1]]) as usize;
+    if unpadded_len < MIN_PLAINTEXT_LEN || padded.len() != 2 + calc_padded_len(unpadded_len) {
+        return Err(Nip44Error::InvalidPadding);
+    }
+    String::from_utf8(padded[2..2 + unpadded_len].to_vec()).map_err(|_| Nip44Error::InvalidPadding)
+}
+
+pub fn encrypt_with_nonce(
+    conversation_key: &[u8; 32],
+    plaintext: &str,
+    nonce: &[u8; 32],
+) -> Result<String, Nip44Error> {
+    let (chacha_key, chacha_nonce, hmac_key) = message_keys(conversation_key, nonce);
+    let mut ciphertext = pad(plaintext)?;
+    ChaCha20::new(&chacha_key.into(), &chacha_nonce.into()).apply_keystream(&mut ciphertext);
+    let mac = hmac_sha256(&hmac_key, &[&nonce[..], ciphertext.as_slice()]);
+
+    let mut payload = Vec::with_capacity(1 + 32 + ciphertext.len() + 32);
+    payload.push(VERSION);
+    payload.extend_from_slice(nonce);
+    payload.extend_from_slice(&ciphertext);
+    payload.extend_from_slice(&mac);
+    Ok(BASE64.encode(payload))
+}
+
+pub fn encrypt(secret_key: &SecretKey, public_key: &XOnlyPublicKey, plaintext: &str) -> Result<String, Nip44Error> {
+    let mut nonce = [0u8; 32];
+    getrandom::getrandom(&mut nonce).map_err(|_| Nip44Error::NoRandomness)?;
+    encrypt_with_nonce(&conversation_key(secret_key, public_key)?, plaintext, &nonce)
+}
+
+pub fn decrypt_with_conversation_key(conversation_key: &[u8; 32], payload: &str) -> Result<String, Nip44Error> {
+    if payload.starts_with('#') {
+        return Err(Nip44Error::UnknownVersion);
+    }
+    if payload.len() < 132 || payload.len() > 87472 {
+        return Err(Nip44Error::InvalidPayload);
+    }
+    let data = BASE64.decode(payload).map_err(|_| Nip44Error::InvalidPayload)?;
+    if data.len() < 99 || data.len() > 65603 {
+        return Err(Nip44Error::InvalidPayload);
+    }
+    if data[0] != VERSION {
+        return Err(Nip44Error::UnknownVersion);
+    }
+
+    let mut nonce = [0u8; 32];
+    nonce.copy_from_slice(&data[1..33]);
+    let (ciphertext, mac) = data[33..].split_at(data.len() - 33 - 32);
+    let (chacha_key, chacha_nonce, hmac_key) = message_keys(conversation_key, &nonce);
+
+    let mut verifier = Hmac::<Sha256>::new_from_slice(&hmac_key).expect("HMAC accepts keys of any length");
+    verifier.update(&nonce);
+    verifier.update(ciphertext);
+    verifier.verify_slice(mac).map_err(|_| Nip44Error::InvalidMac)?;
+
+    let mut padded = ciphertext.to_vec();
+    ChaCha20::new(&chacha_key.into(), &chacha_nonce.into()).apply_keystream(&mut padded);
+    unpad(&padded)
+}
+
+pub fn decrypt(secret_key: &SecretKey, public_key: &XOnlyPublicKey, payload: &str) -> Result<String, Nip44Error> {
+    decrypt_with_conversation_key(&conversation_key(secret_key, public_key)?, payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::prelude::Keys;
+
+    fn secret_key(last_byte: u8) -> SecretKey {
+        let mut bytes = [0u8; 32];
+        bytes[31] = last_byte;
+        SecretKey::from_slice(&bytes).unwrap()
+    }
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    #[test]
+    fn test_calc_padded_len() {
+        let expected = [
+            (16, 32),
+            (32, 32),
+            (33, 64),
+            (37, 64),
+            (45, 64),
+            (49, 64),
+            (64, 64),
+            (65, 96),
+            (100, 128),
+            (111, 128),
+            (200, 224),
+            (250, 256),
+            (320, 320),
+            (383, 384),
+            (384, 384),
+            (400, 448),
+            (500, 512),
+            (512, 512),
+            (515, 640),
+            (700, 768),
+            (800, 896),
+            (900, 1024),
+            (1020, 1024),
+            (65536, 65536),
+        ];
+        for (unpadded, padded) in expected {
+            assert_eq!(calc_padded_len(unpadded), padded, "unpadded length {unpadded}");
+        }
+    }
+
+    #[test]
+    fn test_encrypt_vector() {
+        // First valid vector from the NIP-44 specification.
+        let public_key_2 = Keys::new(secret_key(2)).public_key();
+        let key = conversation_key(&secret_key(1), &public_key_2).unwrap();
+        assert_eq!(
+            to_hex(&key),
+            "c41c775356fd92eadc63ff5a0dc1da211b268cbea22316767095b2871ea1412d"
+        );
+
+        let mut nonce = [0u8; 32];
+        nonce[31] = 1;
+        let payload = encrypt_with_nonce(&key, "a", &nonce).unwrap();
+        assert_eq!(
+            payload,
+            "AgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABee0G5VSK0/9YypIObAtDKfYEAjD35uVkHyB0F4DwrcNaCXlCWZKaArsGrY6M9wnuTMxWfp1RTN9Xga8no+kF5Vsb"
+        );
+        assert_eq!(decrypt_with_conversation_key(&key, &payload).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_roundtrip_and_tampering() {
+        let public_key_1 = Keys::new(secret_key(1)).public_key();
+        let public_key_2 = Keys::new(secret_key(2)).public_key();
+        let text = "💸 You just got paid 21 Sats into your Kollider Wallet! 💰";
+
+        let payload = encrypt(&secret_key(1), &public_key_2, text).unwrap();
+        assert_eq!(decrypt(&secret_key(2), &public_key_1, &payload).unwrap(), text);
+
+        let mut tampered = BASE64.decode(&payload).unwrap();
+        tampered[40] ^= 1;
+        assert_eq!(
+            decrypt(&secret_key(2), &public_key_1, &BASE64.encode(tampered)),
+            Err(Nip44Error::InvalidMac)
+        );
+        assert_eq!(
+            decrypt(&secret_key(2), &public_key_1, "#invalid"),
+            Err(Nip44Error::UnknownVersion)
+        );
+        assert_eq!(
+            encrypt(&secret_key(1), &public_key_2, ""),
+            Err(Nip44Error::InvalidPlaintextLength)
+        );
+    }
+}
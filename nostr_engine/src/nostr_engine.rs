@@ -1,26 +1,45 @@
+use crate::contacts::parse_follows;
+use crate::nwc::{self, NwcError, NwcErrorCode, NwcPending};
+use crate::profile_cache::LruCache;
+use crate::relay_manager::{normalize_url, RelayAction, RelayManager, RelaySource};
 use crate::{
-    into_relays, request_user_profile, send_nostr_private_msg, DbPool, InternalNostrProfileRequest, NostrEngineEvent,
-    NostrProfileUpdate, API_PROFILE_TIMEOUT,
+    create_private_msg, request_user_profile, DbPool, InternalNostrProfileRequest, NostrEngineEvent,
+    NostrProfileUpdate, RelayDelivery, API_PROFILE_TIMEOUT,
 };
 use core_types::nostr::NostrProfile;
-use diesel::QueryResult;
+use core_types::Currency;
+use diesel::{Connection, QueryResult};
+use models::invoices::Invoice;
+use models::nostr_dm_deliveries::InsertableNostrDmDelivery;
+use models::nostr_follows::NostrContactList;
 use models::nostr_profiles::NostrProfileRecord;
-use msgs::api::{NostrResponseError, ShareableNostrProfile};
-use msgs::nostr::NostrZapNote;
+use models::nostr_public_keys::NostrPublicKey;
+use models::nostr_zap_keys::NostrZapKey;
+use models::nwc_connections::{NwcConnection, UpdateNwcConnection};
+use models::nwc_requests::{self, InsertableNwcRequest};
+use msgs::api::{Api, GetBalances, InvoiceRequest, NostrResponseError, PaymentRequest, ShareableNostrProfile};
+use msgs::nostr::{NostrPrivateMessage, NostrRelayAction, NostrRelayUpdate, NostrZapNote};
 use msgs::Message;
-use nostr_sdk::nostr::{Event, Url};
-use nostr_sdk::{Client, Options};
+use nostr_sdk::nostr::nips::nip04;
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::{Event, EventBuilder, EventId, Keys, Kind, Tag};
+use nostr_sdk::prelude::FromSkStr;
+use nostr_sdk::{Client, RelayStatus};
+use serde_json::Value;
 use slog as log;
 use slog::Logger;
-use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 pub struct NostrEngine {
-    nostr_profile_cache: HashMap<String, NostrProfileUpdate>,
+    nostr_profile_cache: LruCache<NostrProfileUpdate>,
     nostr_profile_pending: HashMap<String, (u64, Uuid)>,
+    nwc_pending: HashMap<Uuid, NwcPending>,
+    nwc_handled: LruCache<()>,
+    last_nwc_prune: u64,
     bank_tx_sender: tokio::sync::mpsc::Sender<Message>,
     nostr_client: Client,
+    relay_manager: RelayManager,
     db_pool: DbPool,
     logger: Logger,
 }
@@ -29,14 +48,20 @@ impl NostrEngine {
     pub async fn new(
         nostr_client: Client,
         bank_tx_sender: tokio::sync::mpsc::Sender<Message>,
+        relay_manager: RelayManager,
+        profile_cache_size: usize,
         db_pool: DbPool,
         logger: Logger,
     ) -> Self {
         Self {
-            nostr_profile_cache: HashMap::new(),
+            nostr_profile_cache: LruCache::new(profile_cache_size),
             nostr_profile_pending: HashMap::new(),
+            nwc_pending: HashMap::new(),
+            nwc_handled: LruCache::new(nwc::NWC_HANDLED_REQUESTS_CAPACITY),
+            last_nwc_prune: 0,
             bank_tx_sender,
             nostr_client,
+            relay_manager,
             db_pool,
             logger,
         }
@@ -54,6 +79,32 @@ impl NostrEngine {
             NostrEngineEvent::InternalNostrProfileRequest(request) => {
                 request_user_profile(&self.nostr_client, request).await;
             }
+            NostrEngineEvent::NwcRequest(event) => {
+                self.process_nwc_request(event).await;
+            }
+            NostrEngineEvent::ContactList(event) => {
+                self.store_contact_list(event);
+            }
+            NostrEngineEvent::RelayPublishResult(result) => {
+                if !result.accepted {
+                    log::warn!(
+                        self.logger,
+                        "Relay {} rejected event {}: {}",
+                        result.url,
+                        result.event_id,
+                        result.message
+                    );
+                }
+                self.relay_manager.record_ok(
+                    &result.event_id,
+                    &result.url,
+                    result.accepted,
+                    utils::time::time_now() as i64,
+                );
+            }
+            NostrEngineEvent::RelayHealthCheck => {
+                self.check_relays().await;
+            }
         }
     }
 
@@ -65,8 +116,8 @@ impl NostrEngine {
                     None => return,
                 };
 
-                if let Some(cached_profile_update) = self.nostr_profile_cache.get(pubkey) {
-                    let sharable_profile = ShareableNostrProfile::from(cached_profile_update);
+                if let Some(cached_profile_update) = self.get_cached_profile(pubkey) {
+                    let sharable_profile = ShareableNostrProfile::from(&cached_profile_update);
                     let resp = msgs::api::NostrProfileSearchResponse {
                         req_id: req.req_id,
                         data: vec![sharable_profile],
@@ -87,10 +138,11 @@ impl NostrEngine {
                 }
             }
             Message::Api(msgs::api::Api::NostrProfileSearchRequest(req)) => {
-                let (data, error) = match self.search_profile_by_text(req.pubkey.clone(), req.text.clone(), req.limit) {
-                    Ok(profiles) => (profiles, None),
-                    Err(_) => (Vec::new(), Some(NostrResponseError::ProfileNotFound)),
-                };
+                let (data, error) =
+                    match self.search_profile_by_text(req.pubkey.clone(), req.text.clone(), req.limit, req.offset) {
+                        Ok(profiles) => (profiles, None),
+                        Err(_) => (Vec::new(), Some(NostrResponseError::ProfileNotFound)),
+                    };
                 let resp = msgs::api::NostrProfileSearchResponse {
                     req_id: req.req_id,
                     data,
@@ -109,48 +161,440 @@ impl NostrEngine {
                 request_user_profile(&self.nostr_client, &internal_request).await;
             }
             Message::Nostr(msgs::nostr::Nostr::NostrPrivateMessage(req)) => {
-                send_nostr_private_msg(&self.nostr_client, &req.pubkey, &req.text).await;
+                self.send_private_msg(req).await;
             }
             Message::Nostr(msgs::nostr::Nostr::NostrZapNote(zap)) => {
                 self.send_zap_note(zap).await;
             }
+            Message::Nostr(msgs::nostr::Nostr::NostrRelayUpdate(update)) => {
+                self.update_relay(update).await;
+            }
+            Message::Api(Api::PaymentResponse(_))
+            | Message::Api(Api::InvoiceResponse(_))
+            | Message::Api(Api::Balances(_)) => {
+                self.process_nwc_response(message).await;
+            }
             _ => {}
         }
     }
 
-    async fn store_profile(&mut self, profile_update: &NostrProfileUpdate) {
-        let inserted = match self.nostr_profile_cache.entry(profile_update.pubkey.clone()) {
-            Entry::Occupied(mut entry) => {
-                let existing_created_time_ms = entry.get().created_at_epoch_ms;
-                let existing_nip05_verified_status = entry.get().nostr_profile.nip05_verified();
-                if existing_created_time_ms < profile_update.created_at_epoch_ms
-                    || (existing_created_time_ms == profile_update.created_at_epoch_ms
-                        && existing_nip05_verified_status != profile_update.nostr_profile.nip05_verified())
-                {
-                    entry.insert(profile_update.clone());
-                    true
-                } else {
-                    false
+    async fn send_private_msg(&mut self, req: &NostrPrivateMessage) {
+        let event = match create_private_msg(&self.nostr_client, &req.pubkey, &req.text, req.legacy_dms) {
+            Ok(event) => event,
+            Err(err) => {
+                log::error!(
+                    self.logger,
+                    "Failed to send private message to {}, error: {:?}",
+                    req.pubkey,
+                    err
+                );
+                return;
+            }
+        };
+        let relays = self.relay_manager.publish_relays(utils::time::time_now() as i64);
+        let deliveries = self.publish_event(&event, &relays).await;
+        let event_id = event.id.to_string();
+        if deliveries.iter().all(|delivery| delivery.error.is_some()) {
+            log::error!(
+                self.logger,
+                "No relay accepted private message {} to {}, deliveries: {:?}",
+                event_id,
+                req.pubkey,
+                deliveries
+            );
+        }
+
+        let protocol = if req.legacy_dms { "nip04" } else { "nip17" };
+        let created_at = utils::time::time_now() as i64;
+        let records = deliveries
+            .into_iter()
+            .map(|delivery| InsertableNostrDmDelivery {
+                created_at,
+                event_id: event_id.clone(),
+                pubkey: req.pubkey.clone(),
+                protocol: protocol.to_string(),
+                relay_url: delivery.relay_url,
+                success: delivery.error.is_none(),
+                error: delivery.error,
+            })
+            .collect::<Vec<_>>();
+        match self.db_pool.try_get() {
+            Some(conn) => {
+                if let Err(err) = InsertableNostrDmDelivery::insert_many(&conn, &records) {
+                    log::error!(
+                        self.logger,
+                        "Failed to record deliveries of private message {}, error: {:?}",
+                        event_id,
+                        err
+                    );
                 }
             }
-            Entry::Vacant(entry) => {
-                entry.insert(profile_update.clone());
-                true
+            None => {
+                log::error!(
+                    self.logger,
+                    "Failed to get a DB connection to record deliveries of private message {}",
+                    event_id
+                );
+            }
+        }
+    }
+
+    async fn process_nwc_request(&mut self, event: &Event) {
+        // every relay delivers its own copy and replays requests on reconnect
+        let tags = event.tags.iter().map(|tag| tag.as_vec()).collect::<Vec<_>>();
+        let now_secs = utils::time::time_now() / 1000;
+        if nwc::is_expired(&tags, event.created_at.as_u64(), now_secs) || !self.mark_nwc_request_handled(event) {
+            return;
+        }
+
+        let keys = self.nostr_client.keys();
+        let secret_key = match keys.secret_key() {
+            Ok(secret_key) => secret_key,
+            Err(err) => {
+                log::error!(
+                    self.logger,
+                    "Cannot serve wallet connect without a secret key, error: {:?}",
+                    err
+                );
+                return;
             }
         };
-        if inserted {
-            self.reply_if_pending(profile_update).await;
+        let content = match nip04::decrypt(&secret_key, &event.pubkey, &event.content) {
+            Ok(content) => content,
+            Err(err) => {
+                log::warn!(
+                    self.logger,
+                    "Failed to decrypt wallet connect request: {}, error: {:?}",
+                    event.id,
+                    err
+                );
+                return;
+            }
+        };
+
+        let time_now_ms = utils::time::time_now();
+        self.nwc_pending
+            .retain(|_req_id, pending| time_now_ms - pending.created_at < nwc::NWC_REQUEST_TIMEOUT);
+
+        let (method, result) = match nwc::parse_request(&content) {
+            Ok(request) => {
+                let result = self.forward_nwc_request(event, &request).await;
+                (request.method, result)
+            }
+            Err(err) => (String::new(), Err(err)),
+        };
+
+        // Requests forwarded to the bank are answered once its response arrives.
+        match result {
+            Ok(Some(result)) => {
+                self.send_nwc_response(event.id, event.pubkey, &method, Ok(result))
+                    .await
+            }
+            Ok(None) => {}
+            Err(err) => self.send_nwc_response(event.id, event.pubkey, &method, Err(err)).await,
         }
-        if let Some(conn) = self.db_pool.try_get() {
-            if let Err(err) = insert_profile_update(&conn, profile_update) {
+    }
+
+    /// `false` if the request was handled before or that can't be checked.
+    fn mark_nwc_request_handled(&mut self, event: &Event) -> bool {
+        let event_id = event.id.to_string();
+        if self.nwc_handled.get(&event_id).is_some() {
+            return false;
+        }
+        let conn = match self.db_pool.try_get() {
+            Some(conn) => conn,
+            None => {
+                log::error!(
+                    self.logger,
+                    "Failed to get a DB connection to check wallet connect request {}",
+                    event_id
+                );
+                return false;
+            }
+        };
+        let now_secs = utils::time::time_now() / 1000;
+        if now_secs.saturating_sub(self.last_nwc_prune) >= utils::time::SECONDS_IN_HOUR {
+            let max_age = now_secs.saturating_sub(nwc::NWC_REQUEST_MAX_AGE_SECS) as i64;
+            match nwc_requests::remove_older_than(&conn, max_age) {
+                Ok(_) => self.last_nwc_prune = now_secs,
+                Err(err) => log::error!(self.logger, "Failed to remove old wallet connect requests: {:?}", err),
+            }
+        }
+        let request = InsertableNwcRequest {
+            event_id: event_id.clone(),
+            pubkey: event.pubkey.to_string(),
+            created_at: event.created_at.as_u64() as i64,
+        };
+        match request.insert(&conn) {
+            Ok(inserted) => {
+                self.nwc_handled.insert(event_id, ());
+                inserted
+            }
+            Err(err) => {
+                log::error!(
+                    self.logger,
+                    "Failed to store wallet connect request {}, error: {:?}",
+                    event_id,
+                    err
+                );
+                false
+            }
+        }
+    }
+
+    async fn forward_nwc_request(
+        &mut self,
+        event: &Event,
+        request: &nwc::NwcRequest,
+    ) -> Result<Option<Value>, NwcError> {
+        let message = {
+            let conn = self
+                .db_pool
+                .try_get()
+                .ok_or((NwcErrorCode::Internal, String::from("Database unavailable.")))?;
+            let connection = NwcConnection::get_by_pubkey(&conn, &event.pubkey.to_string())
+                .map_err(|_| (NwcErrorCode::Unauthorized, String::from("Unknown connection.")))?;
+            nwc::authorize(&connection, &request.method)?;
+
+            let req_id = Uuid::new_v4();
+            let uid = connection.uid as u64;
+            let (message, reserved_msat) = match request.method.as_str() {
+                "get_balance" => (Api::GetBalances(GetBalances { req_id, uid }), 0),
+                "make_invoice" => {
+                    let params = nwc::parse_params::<nwc::MakeInvoiceParams>(request)?;
+                    if params.amount == 0 {
+                        return Err((NwcErrorCode::Other, String::from("Amount is required.")));
+                    }
+                    let invoice_request = InvoiceRequest {
+                        req_id,
+                        uid,
+                        amount: nwc::msat_to_money(params.amount as i64),
+                        meta: params.description.unwrap_or_default(),
+                        metadata: None,
+                        currency: Currency::BTC,
+                        account_id: None,
+                        target_account_currency: None,
+                    };
+                    (Api::InvoiceRequest(invoice_request), 0)
+                }
+                "pay_invoice" => {
+                    let params = nwc::parse_params::<nwc::PayInvoiceParams>(request)?;
+                    let (amount, amount_msat) = nwc::pay_invoice_amount(&params)?;
+                    let reserved_msat = self
+                        .nwc_pending
+                        .values()
+                        .filter(|pending| pending.client_pubkey == event.pubkey)
+                        .map(|pending| pending.reserved_msat)
+                        .sum();
+                    let now_secs = (utils::time::time_now() / 1000) as i64;
+                    nwc::check_budget(&connection, amount_msat, reserved_msat, now_secs)?;
+                    // the spend is recorded by the request id, also if the payment settles after the timeout
+                    nwc_requests::set_req_id(&conn, &event.id.to_string(), req_id)
+                        .map_err(|_| (NwcErrorCode::Internal, String::from("Database unavailable.")))?;
+                    let payment_request = PaymentRequest {
+                        req_id,
+                        uid,
+                        payment_request: Some(params.invoice),
+                        currency: Currency::BTC,
+                        recipient: None,
+                        destination: None,
+                        amount,
+                        invoice_amount: None,
+                        rate: None,
+                        fees: None,
+                    };
+                    (Api::PaymentRequest(payment_request), amount_msat)
+                }
+                "lookup_invoice" => {
+                    let params = nwc::parse_params::<nwc::LookupInvoiceParams>(request)?;
+                    let invoice = match (params.payment_hash, params.invoice) {
+                        (Some(payment_hash), _) => Invoice::get_by_payment_hash(&conn, payment_hash),
+                        (None, Some(invoice)) => Invoice::get_by_payment_request(&conn, invoice),
+                        (None, None) => {
+                            return Err((
+                                NwcErrorCode::Other,
+                                String::from("Payment hash or invoice is required."),
+                            ))
+                        }
+                    };
+                    return match invoice {
+                        Ok(invoice) if invoice.uid == connection.uid => Ok(Some(nwc::lookup_invoice_result(&invoice))),
+                        _ => Err((NwcErrorCode::NotFound, String::from("Invoice not found."))),
+                    };
+                }
+                method => {
+                    return Err((
+                        NwcErrorCode::NotImplemented,
+                        format!("Method {method} is not supported."),
+                    ))
+                }
+            };
+
+            self.nwc_pending.insert(
+                req_id,
+                NwcPending {
+                    event_id: event.id,
+                    client_pubkey: event.pubkey,
+                    method: request.method.clone(),
+                    created_at: utils::time::time_now(),
+                    reserved_msat,
+                },
+            );
+            message
+        };
+
+        self.send_to_bank(Message::Api(message)).await;
+        Ok(None)
+    }
+
+    async fn process_nwc_response(&mut self, message: &Message) {
+        let req_id = match message {
+            Message::Api(Api::PaymentResponse(payment)) => payment.req_id,
+            Message::Api(Api::InvoiceResponse(invoice)) => invoice.req_id,
+            Message::Api(Api::Balances(balances)) => balances.req_id,
+            _ => return,
+        };
+        if let Message::Api(Api::PaymentResponse(payment)) = message {
+            if nwc::pay_invoice_result(payment).is_ok() {
+                self.record_nwc_spend(req_id, nwc::payment_spent_msat(payment));
+            }
+        }
+        let pending = match self.nwc_pending.remove(&req_id) {
+            Some(pending) => pending,
+            None => return,
+        };
+        let result = match message {
+            Message::Api(Api::PaymentResponse(payment)) => nwc::pay_invoice_result(payment),
+            Message::Api(Api::InvoiceResponse(invoice)) => nwc::make_invoice_result(invoice),
+            Message::Api(Api::Balances(balances)) => nwc::balance_result(balances),
+            _ => return,
+        };
+        self.send_nwc_response(pending.event_id, pending.client_pubkey, &pending.method, result)
+            .await;
+    }
+
+    /// Counts a payment against the budget of the wallet connection that requested it, once.
+    fn record_nwc_spend(&self, req_id: Uuid, spent_msat: i64) {
+        let conn = match self.db_pool.try_get() {
+            Some(conn) => conn,
+            None => {
+                log::error!(
+                    self.logger,
+                    "Failed to get a DB connection to record {} msat spent by wallet connect request {}",
+                    spent_msat,
+                    req_id
+                );
+                return;
+            }
+        };
+        let result = conn.transaction(|| {
+            let pubkey = match nwc_requests::record_spent(&conn, req_id, spent_msat)? {
+                Some(pubkey) => pubkey,
+                None => return Ok(0),
+            };
+            let connection = NwcConnection::get_by_pubkey(&conn, &pubkey)?;
+            let now_secs = (utils::time::time_now() / 1000) as i64;
+            let (spent, period_start) = nwc::current_budget_period(&connection, now_secs);
+            let update = UpdateNwcConnection {
+                budget_spent_msat: Some(spent + spent_msat),
+                budget_period_start: Some(period_start),
+                revoked: None,
+            };
+            update.update(&conn, connection.uid, &pubkey)
+        });
+        if let Err(err) = result {
+            log::error!(
+                self.logger,
+                "Failed to record {} msat spent by wallet connect request {}, error: {:?}",
+                spent_msat,
+                req_id,
+                err
+            );
+        }
+    }
+
+    async fn send_nwc_response(
+        &self,
+        request_id: EventId,
+        client_pubkey: XOnlyPublicKey,
+        method: &str,
+        result: Result<Value, NwcError>,
+    ) {
+        let keys = self.nostr_client.keys();
+        let content = nwc::response_json(method, result).to_string();
+        let encrypted = match keys
+            .secret_key()
+            .map_err(|err| format!("{err:?}"))
+            .and_then(|secret_key| {
+                nip04::encrypt(&secret_key, &client_pubkey, content).map_err(|err| format!("{err:?}"))
+            }) {
+            Ok(encrypted) => encrypted,
+            Err(err) => {
                 log::error!(
                     self.logger,
-                    "Failed to upsert nostr profile update: {:?}, err: {:?}",
-                    profile_update,
+                    "Failed to encrypt wallet connect response to {}, error: {}",
+                    request_id,
+                    err
+                );
+                return;
+            }
+        };
+        let tags = [Tag::PubKey(client_pubkey, None), Tag::Event(request_id, None, None)];
+        match EventBuilder::new(Kind::Custom(nwc::NWC_RESPONSE_KIND), encrypted, &tags).to_event(&keys) {
+            Ok(response) => {
+                if let Err(err) = self.nostr_client.send_event(response).await {
+                    log::error!(
+                        self.logger,
+                        "Failed to send wallet connect response to {}, error: {:?}",
+                        request_id,
+                        err
+                    );
+                }
+            }
+            Err(err) => {
+                log::error!(
+                    self.logger,
+                    "Failed to create wallet connect response to {}, error: {:?}",
+                    request_id,
                     err
                 );
             }
         }
+    }
+
+    async fn store_profile(&mut self, profile_update: &NostrProfileUpdate) {
+        let newer_than_cached = self.nostr_profile_cache.get(&profile_update.pubkey).map(|existing| {
+            existing.created_at_epoch_ms < profile_update.created_at_epoch_ms
+                || (existing.created_at_epoch_ms == profile_update.created_at_epoch_ms
+                    && existing.nostr_profile.nip05_verified() != profile_update.nostr_profile.nip05_verified())
+        });
+        // the upsert only updates a row if the profile is newer than the stored one
+        let newer_than_stored = match self.db_pool.try_get() {
+            Some(conn) => match insert_profile_update(&conn, profile_update) {
+                Ok(updated) => Some(updated > 0),
+                Err(err) => {
+                    log::error!(
+                        self.logger,
+                        "Failed to upsert nostr profile update: {:?}, err: {:?}",
+                        profile_update,
+                        err
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+        // the database keeps the newest version of profiles evicted from the cache
+        if newer_than_cached.or(newer_than_stored).unwrap_or(true) {
+            self.nostr_profile_cache
+                .insert(profile_update.pubkey.clone(), profile_update.clone());
+            self.reply_if_pending(profile_update).await;
+        } else if self.nostr_profile_pending.contains_key(&profile_update.pubkey) {
+            // answered with the newer profile that was kept instead
+            if let Some(newest) = self.get_cached_profile(&profile_update.pubkey) {
+                self.reply_if_pending(&newest).await;
+            }
+        }
         let time_now_ms = utils::time::time_now();
         self.nostr_profile_pending
             .retain(|_pubkey, (request_time_ms, _request_id)| {
@@ -159,14 +603,30 @@ impl NostrEngine {
             });
     }
 
+    /// Profiles evicted from the cache are loaded back from the database.
+    fn get_cached_profile(&mut self, pubkey: &str) -> Option<NostrProfileUpdate> {
+        if let Some(profile_update) = self.nostr_profile_cache.get(pubkey) {
+            return Some(profile_update.clone());
+        }
+        let conn = self.db_pool.try_get()?;
+        let record = NostrProfileRecord::search_by_text(&conn, Some(pubkey.to_string()), None, Some(1), None)
+            .ok()?
+            .pop()?;
+        let profile_update = profile_update_from_record(record)?;
+        self.nostr_profile_cache
+            .insert(profile_update.pubkey.clone(), profile_update.clone());
+        Some(profile_update)
+    }
+
     fn search_profile_by_text(
         &self,
         pubkey: Option<String>,
         text: Option<String>,
         limit: Option<u64>,
+        offset: Option<u64>,
     ) -> QueryResult<Vec<ShareableNostrProfile>> {
         if let Some(conn) = self.db_pool.try_get() {
-            let found_profiles = NostrProfileRecord::search_by_text(&conn, pubkey, text, limit)?;
+            let found_profiles = NostrProfileRecord::search_by_text(&conn, pubkey, text, limit, offset)?;
             let nostr_profiles = found_profiles
                 .into_iter()
                 .filter_map(|record| {
@@ -189,9 +649,10 @@ impl NostrEngine {
         }
     }
 
-    pub async fn initialize_profile_cache(&mut self) {
+    /// Warms up the cache with the most recently received profiles.
+    pub async fn initialize_profile_cache(&mut self, profile_cache_size: usize) {
         let maybe_profile_records = match self.db_pool.try_get() {
-            Some(conn) => NostrProfileRecord::fetch_all(&conn).ok(),
+            Some(conn) => NostrProfileRecord::fetch_recent(&conn, profile_cache_size).ok(),
             None => {
                 log::error!(
                     self.logger,
@@ -201,30 +662,16 @@ impl NostrEngine {
             }
         };
         if let Some(profile_records) = maybe_profile_records {
-            let cache = profile_records
-                .into_iter()
-                .filter_map(|record| {
-                    if record.content.is_empty() {
-                        None
-                    } else {
-                        serde_json::from_str::<NostrProfile>(&record.content)
-                            .ok()
-                            .map(|mut profile| {
-                                profile.set_nip05_verified(record.nip05_verified);
-                                let profile_update = NostrProfileUpdate {
-                                    pubkey: record.pubkey.clone(),
-                                    content: record.content,
-                                    created_at_epoch_ms: record.created_at as u64,
-                                    received_at_epoch_ms: record.received_at as u64,
-                                    nostr_profile: profile,
-                                    validated_lnurl_pay_req: record.lnurl_pay_req,
-                                };
-                                (record.pubkey, profile_update)
-                            })
-                    }
-                })
-                .collect();
-            self.nostr_profile_cache = cache;
+            // inserting the oldest first leaves the most recent profiles as the last to be evicted
+            for profile_update in profile_records.into_iter().rev().filter_map(profile_update_from_record) {
+                self.nostr_profile_cache
+                    .insert(profile_update.pubkey.clone(), profile_update);
+            }
+            log::info!(
+                self.logger,
+                "Initialized profile cache with {} profiles",
+                self.nostr_profile_cache.len()
+            );
         }
     }
 
@@ -252,9 +699,67 @@ impl NostrEngine {
         }
     }
 
-    async fn send_zap_note(&self, zap: &NostrZapNote) {
+    /// Keeps the follows of linked pubkeys for contact suggestions, other contact lists are ignored.
+    fn store_contact_list(&self, event: &Event) {
+        let conn = match self.db_pool.try_get() {
+            Some(conn) => conn,
+            None => {
+                log::error!(
+                    self.logger,
+                    "Failed to get a DB connection to store the contact list of {}",
+                    event.pubkey
+                );
+                return;
+            }
+        };
+        let pubkey = event.pubkey.to_string();
+        if NostrPublicKey::get_by_pubkey(&conn, pubkey.clone()).is_err() {
+            return;
+        }
+        let tags = event.tags.iter().map(|tag| tag.as_vec()).collect::<Vec<_>>();
+        let follows = parse_follows(&pubkey, &tags);
+        let contact_list = NostrContactList {
+            pubkey,
+            created_at: event.created_at.as_u64() as i64,
+            received_at: utils::time::time_now() as i64,
+            follows_count: follows.len() as i32,
+        };
+        if let Err(err) = contact_list.replace(&conn, &follows) {
+            log::error!(
+                self.logger,
+                "Failed to store contact list of {}, error: {:?}",
+                contact_list.pubkey,
+                err
+            );
+        }
+    }
+
+    /// Receipts are signed with the receiver's zap key, the one advertised on their lightning address.
+    fn zap_keys(&self, uid: Option<u64>) -> Keys {
+        let zap_key = uid.and_then(|uid| {
+            let conn = self.db_pool.try_get()?;
+            NostrZapKey::get_by_uid(&conn, uid as i32).ok()
+        });
+        let secret_key = match zap_key {
+            Some(zap_key) => zap_key.secret_key(),
+            None => return self.nostr_client.keys(),
+        };
+        match secret_key.map(|secret_key| Keys::from_sk_str(&secret_key)) {
+            Some(Ok(keys)) => keys,
+            Some(Err(err)) => {
+                log::error!(self.logger, "Invalid zap key of user {:?}, error: {:?}", uid, err);
+                self.nostr_client.keys()
+            }
+            None => {
+                log::error!(self.logger, "Could not decrypt zap key of user {:?}", uid);
+                self.nostr_client.keys()
+            }
+        }
+    }
+
+    async fn send_zap_note(&mut self, zap: &NostrZapNote) {
         let (zap_note, zap_note_relays) = match utils::nostr::create_zap_note(
-            &self.nostr_client.keys(),
+            &self.zap_keys(zap.uid),
             zap.amount,
             &zap.description,
             &zap.description_hash,
@@ -273,35 +778,170 @@ impl NostrEngine {
                 return;
             }
         };
-        let client_relays = self
-            .nostr_client
-            .relays()
-            .await
-            .keys()
-            .map(|url| url.to_string())
-            .collect::<HashSet<_>>();
-        let (existing_pool_relays, unknown_relays): (Vec<_>, Vec<_>) = zap_note_relays
-            .into_iter()
-            .filter_map(|url| Url::parse(&url).ok().map(|url| url.to_string()))
-            .partition(|url| client_relays.contains(url));
-        send_to_relays(&self.nostr_client, &zap_note, &existing_pool_relays, &self.logger).await;
-        let keys = self.nostr_client.keys();
+        let now = utils::time::time_now() as i64;
+        let mut new_relays = Vec::new();
+        let mut pooled_relays = Vec::new();
+        for url in zap_note_relays.iter().filter_map(|url| normalize_url(url)) {
+            if self.relay_manager.add(&url, RelaySource::Zap, now) {
+                new_relays.push(url);
+            } else {
+                pooled_relays.push(url);
+            }
+        }
+        let publish_relays = self.relay_manager.publish_relays(now);
+        pooled_relays.retain(|url| publish_relays.contains(url));
+        self.publish_event(&zap_note, &pooled_relays).await;
+
+        if new_relays.is_empty() {
+            return;
+        }
+        // new relays are published to once connected, a failed publish shows up as an acknowledgement timeout
+        let event_id = zap_note.id.to_string();
+        for url in new_relays.iter() {
+            if let Err(err) = self.nostr_client.add_relay(url.clone(), None).await {
+                log::error!(self.logger, "Failed to add relay {} to the pool, error: {:?}", url, err);
+            }
+            self.relay_manager.record_sent(&event_id, url, now);
+        }
+        let nostr_client = self.nostr_client.clone();
         let task_logger = self.logger.clone();
         tokio::spawn(async move {
-            let options = Options::new().wait_for_connection(true).wait_for_send(true);
-            let nostr_client = Client::new_with_opts(&keys, options);
-            nostr_client.add_relays(into_relays(unknown_relays)).await.unwrap();
             nostr_client.connect().await;
-            if let Err(err) = nostr_client.send_event(zap_note.clone()).await {
-                log::error!(
-                    task_logger,
-                    "Failed to send a zap note: {:?}, error: {:?}",
-                    zap_note,
-                    err
-                );
-            }
+            send_to_relays(&nostr_client, &zap_note, &new_relays, &task_logger).await;
         });
     }
+
+    /// Sends the event to each of the relays separately, recording the outcome with the relay manager.
+    async fn publish_event(&mut self, event: &Event, relays: &[String]) -> Vec<RelayDelivery> {
+        let event_id = event.id.to_string();
+        let mut deliveries = Vec::new();
+        for url in relays.iter() {
+            let now = utils::time::time_now() as i64;
+            let error = match self.nostr_client.send_event_to(url.clone(), event.clone()).await {
+                Ok(_) => {
+                    self.relay_manager.record_sent(&event_id, url, now);
+                    None
+                }
+                Err(err) => {
+                    log::error!(
+                        self.logger,
+                        "Failed to send an event: {:?} to {}, error: {:?}",
+                        event,
+                        url,
+                        err
+                    );
+                    self.relay_manager.record_send_error(url, now);
+                    Some(format!("{err:?}"))
+                }
+            };
+            deliveries.push(RelayDelivery {
+                relay_url: url.clone(),
+                error,
+            });
+        }
+        deliveries
+    }
+
+    /// Adds a relay added by an operator to the pool. Connecting waits for the relay, so it must not
+    /// hold up the events handler. The relay receives the ongoing subscription on its next renewal.
+    async fn connect_relay(&self, url: &str) {
+        if let Err(err) = self.nostr_client.add_relay(url.to_string(), None).await {
+            log::error!(self.logger, "Failed to add relay {} to the pool, error: {:?}", url, err);
+            return;
+        }
+        let nostr_client = self.nostr_client.clone();
+        tokio::spawn(async move {
+            nostr_client.connect().await;
+        });
+    }
+
+    async fn disconnect_relay(&self, url: &str) {
+        if let Err(err) = self.nostr_client.remove_relay(url.to_string()).await {
+            log::error!(
+                self.logger,
+                "Failed to remove relay {} from the pool, error: {:?}",
+                url,
+                err
+            );
+        }
+    }
+
+    async fn update_relay(&mut self, update: &NostrRelayUpdate) {
+        let url = match normalize_url(&update.url) {
+            Some(url) => url,
+            None => {
+                log::error!(self.logger, "Received relay update with an invalid url: {:?}", update);
+                return;
+            }
+        };
+        let now = utils::time::time_now() as i64;
+        match update.action {
+            NostrRelayAction::Add => {
+                if self.relay_manager.add(&url, RelaySource::Cli, now) {
+                    self.connect_relay(&url).await;
+                }
+            }
+            NostrRelayAction::Remove => {
+                if self.relay_manager.remove(&url) {
+                    self.disconnect_relay(&url).await;
+                }
+            }
+        }
+        log::info!(self.logger, "Updated relay {}: {:?}", url, update.action);
+        self.store_relays(now);
+    }
+
+    async fn check_relays(&mut self) {
+        let now = utils::time::time_now() as i64;
+        for (url, relay) in self.nostr_client.relays().await {
+            let connected = matches!(relay.status().await, RelayStatus::Connected);
+            self.relay_manager.record_connection(&url.to_string(), connected, now);
+        }
+        for action in self.relay_manager.maintain(now) {
+            match action {
+                RelayAction::Disconnect(url) => {
+                    log::warn!(self.logger, "Dropping relay {} from the pool", url);
+                    self.disconnect_relay(&url).await;
+                }
+                RelayAction::Reconnect(url) => {
+                    log::info!(self.logger, "Adding relay {} back to the pool", url);
+                    self.connect_relay(&url).await;
+                }
+            }
+        }
+        self.store_relays(now);
+    }
+
+    fn store_relays(&self, now: i64) {
+        let conn = match self.db_pool.try_get() {
+            Some(conn) => conn,
+            None => {
+                log::error!(self.logger, "Failed to get a DB connection to store relays");
+                return;
+            }
+        };
+        for record in self.relay_manager.records(now) {
+            if let Err(err) = record.upsert(&conn) {
+                log::error!(self.logger, "Failed to store relay {}, error: {:?}", record.url, err);
+            }
+        }
+    }
+}
+
+fn profile_update_from_record(record: NostrProfileRecord) -> Option<NostrProfileUpdate> {
+    if record.content.is_empty() {
+        return None;
+    }
+    let mut profile = serde_json::from_str::<NostrProfile>(&record.content).ok()?;
+    profile.set_nip05_verified(record.nip05_verified);
+    Some(NostrProfileUpdate {
+        pubkey: record.pubkey,
+        content: record.content,
+        created_at_epoch_ms: record.created_at as u64,
+        received_at_epoch_ms: record.received_at as u64,
+        nostr_profile: profile,
+        validated_lnurl_pay_req: record.lnurl_pay_req,
+    })
 }
 
 fn insert_profile_update(conn: &diesel::PgConnection, profile_update: &NostrProfileUpdate) -> QueryResult<usize> {
@@ -0,0 +1,348 @@
+use core_types::{Currency, Money};
+use models::invoices::Invoice;
+use models::nwc_connections::NwcConnection;
+use msgs::api::{Balances, InvoiceResponse, PaymentResponse, PaymentResponseError};
+use nostr_sdk::nostr::secp256k1::XOnlyPublicKey;
+use nostr_sdk::nostr::EventId;
+use nostr_sdk::prelude::{Kind, SubscriptionFilter, Timestamp};
+use rust_decimal::prelude::{Decimal, ToPrimitive};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+pub const NWC_INFO_KIND: u64 = 13194;
+pub const NWC_REQUEST_KIND: u64 = 23194;
+pub const NWC_RESPONSE_KIND: u64 = 23195;
+
+/// Requests the bank did not answer within this time are dropped and their budget reservation released.
+pub const NWC_REQUEST_TIMEOUT: u64 = 60_000;
+/// Older requests are dropped, relays only replay requests since the subscription was last renewed an hour ago.
+pub const NWC_REQUEST_MAX_AGE_SECS: u64 = 86_400;
+/// Ids of handled requests kept in memory, older ones are looked up in the DB.
+pub const NWC_HANDLED_REQUESTS_CAPACITY: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NwcErrorCode {
+    NotImplemented,
+    InsufficientBalance,
+    QuotaExceeded,
+    Restricted,
+    Unauthorized,
+    Internal,
+    Other,
+    NotFound,
+    PaymentFailed,
+}
+
+impl NwcErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NwcErrorCode::NotImplemented => "NOT_IMPLEMENTED",
+            NwcErrorCode::InsufficientBalance => "INSUFFICIENT_BALANCE",
+            NwcErrorCode::QuotaExceeded => "QUOTA_EXCEEDED",
+            NwcErrorCode::Restricted => "RESTRICTED",
+            NwcErrorCode::Unauthorized => "UNAUTHORIZED",
+            NwcErrorCode::Internal => "INTERNAL",
+            NwcErrorCode::Other => "OTHER",
+            NwcErrorCode::NotFound => "NOT_FOUND",
+            NwcErrorCode::PaymentFailed => "PAYMENT_FAILED",
+        }
+    }
+}
+
+pub type NwcError = (NwcErrorCode, String);
+
+#[derive(Deserialize, Debug)]
+pub struct NwcRequest {
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PayInvoiceParams {
+    pub invoice: String,
+    /// Only used for invoices without an amount, in msat
+    #[serde(default)]
+    pub amount: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MakeInvoiceParams {
+    /// In msat
+    pub amount: u64,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LookupInvoiceParams {
+    #[serde(default)]
+    pub payment_hash: Option<String>,
+    #[serde(default)]
+    pub invoice: Option<String>,
+}
+
+/// A request forwarded to the bank, waiting for its response.
+#[derive(Debug, Clone)]
+pub struct NwcPending {
+    pub event_id: EventId,
+    pub client_pubkey: XOnlyPublicKey,
+    pub method: String,
+    pub created_at: u64,
+    /// Amount held against the connection budget until the payment settles
+    pub reserved_msat: i64,
+}
+
+/// Whether the request is past its NIP-40 `expiration` tag or too old to be told apart from a replay.
+pub fn is_expired(tags: &[Vec<String>], created_at: u64, now_secs: u64) -> bool {
+    let expiration = tags
+        .iter()
+        .find(|tag| tag.first().map(String::as_str) == Some("expiration"))
+        .and_then(|tag| tag.get(1)?.parse::<u64>().ok());
+    expiration.map_or(false, |expiration| expiration <= now_secs) || created_at + NWC_REQUEST_MAX_AGE_SECS < now_secs
+}
+
+pub fn parse_request(content: &str) -> Result<NwcRequest, NwcError> {
+    serde_json::from_str::<NwcRequest>(content).map_err(|_| (NwcErrorCode::Other, String::from("Invalid request.")))
+}
+
+pub fn parse_params<T: serde::de::DeserializeOwned>(request: &NwcRequest) -> Result<T, NwcError> {
+    serde_json::from_value::<T>(request.params.clone())
+        .map_err(|_| (NwcErrorCode::Other, format!("Invalid params for {}.", request.method)))
+}
+
+/// Checks that the connection is active and allowed to call the method.
+pub fn authorize(connection: &NwcConnection, method: &str) -> Result<(), NwcError> {
+    if connection.revoked {
+        return Err((NwcErrorCode::Unauthorized, String::from("Connection was revoked.")));
+    }
+    if !core_types::nostr::NWC_METHODS.contains(&method) {
+        return Err((
+            NwcErrorCode::NotImplemented,
+            format!("Method {method} is not supported."),
+        ));
+    }
+    if !connection.allowed_methods.iter().any(|allowed| allowed == method) {
+        return Err((NwcErrorCode::Restricted, format!("Method {method} is not allowed.")));
+    }
+    Ok(())
+}
+
+/// Returns the amount spent and the start of the budget period as of `now_secs`.
+pub fn current_budget_period(connection: &NwcConnection, now_secs: i64) -> (i64, i64) {
+    match connection.budget_renewal_secs {
+        Some(renewal) if renewal > 0 && now_secs >= connection.budget_period_start + renewal => {
+            let elapsed_periods = (now_secs - connection.budget_period_start) / renewal;
+            (0, connection.budget_period_start + elapsed_periods * renewal)
+        }
+        _ => (connection.budget_spent_msat, connection.budget_period_start),
+    }
+}
+
+/// Fails if paying `amount_msat` on top of the spent and reserved amounts would exceed the budget.
+pub fn check_budget(
+    connection: &NwcConnection,
+    amount_msat: i64,
+    reserved_msat: i64,
+    now_secs: i64,
+) -> Result<(), NwcError> {
+    if let Some(budget) = connection.budget_msat {
+        let (spent, _) = current_budget_period(connection, now_secs);
+        if spent + reserved_msat + amount_msat > budget {
+            return Err((NwcErrorCode::QuotaExceeded, String::from("Connection budget exceeded.")));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the invoice amount in msat, falling back to the requested amount for zero amount invoices.
+pub fn pay_invoice_amount(params: &PayInvoiceParams) -> Result<(Option<Money>, i64), NwcError> {
+    let invoice = params
+        .invoice
+        .parse::<lightning_invoice::Invoice>()
+        .map_err(|_| (NwcErrorCode::Other, String::from("Invalid invoice.")))?;
+    match (invoice.amount_milli_satoshis(), params.amount) {
+        (Some(msat), _) => Ok((None, msat as i64)),
+        (None, Some(msat)) if msat > 0 => Ok((Some(msat_to_money(msat as i64)), msat as i64)),
+        _ => Err((
+            NwcErrorCode::Other,
+            String::from("Amount is required for zero amount invoices."),
+        )),
+    }
+}
+
+pub fn msat_to_money(msat: i64) -> Money {
+    Money::from_sats(Decimal::new(msat, 3))
+}
+
+pub fn money_to_msat(money: &Money) -> i64 {
+    money
+        .try_sats()
+        .ok()
+        .and_then(|sats| (sats * Decimal::from(1000)).floor().to_i64())
+        .unwrap_or_default()
+}
+
+pub fn response_json(method: &str, result: Result<Value, NwcError>) -> Value {
+    match result {
+        Ok(result) => json!({
+            "result_type": method,
+            "result": result,
+        }),
+        Err((code, message)) => json!({
+            "result_type": method,
+            "error": {
+                "code": code.as_str(),
+                "message": message,
+            },
+        }),
+    }
+}
+
+pub fn balance_result(balances: &Balances) -> Result<Value, NwcError> {
+    let balance = balances
+        .accounts
+        .values()
+        .filter(|account| account.currency == Currency::BTC)
+        .map(|account| money_to_msat(&Money::from_btc(account.balance)))
+        .sum::<i64>();
+    Ok(json!({ "balance": balance }))
+}
+
+pub fn make_invoice_result(invoice: &InvoiceResponse) -> Result<Value, NwcError> {
+    if let Some(error) = &invoice.error {
+        return Err((NwcErrorCode::Other, format!("{error:?}")));
+    }
+    Ok(json!({
+        "type": "incoming",
+        "invoice": invoice.payment_request,
+        "description": invoice.meta,
+        "payment_hash": invoice.payment_hash,
+        "amount": money_to_msat(&invoice.amount),
+        "fees_paid": 0,
+        "created_at": utils::time::time_now() / 1000,
+    }))
+}
+
+pub fn pay_invoice_result(payment: &PaymentResponse) -> Result<Value, NwcError> {
+    match payment.error {
+        Some(PaymentResponseError::InsufficientFunds) | Some(PaymentResponseError::InsufficientFundsForFees) => {
+            Err((NwcErrorCode::InsufficientBalance, String::from("Insufficient balance.")))
+        }
+        Some(error) => Err((NwcErrorCode::PaymentFailed, format!("{error:?}"))),
+        None if payment.success => Ok(json!({
+            "preimage": payment.payment_preimage.clone().unwrap_or_default(),
+        })),
+        None => Err((NwcErrorCode::PaymentFailed, String::from("Payment failed."))),
+    }
+}
+
+/// Amount and fees of a successful payment, counted against the connection budget.
+pub fn payment_spent_msat(payment: &PaymentResponse) -> i64 {
+    payment.amount.as_ref().map(money_to_msat).unwrap_or_default()
+        + payment.fees.as_ref().map(money_to_msat).unwrap_or_default()
+}
+
+pub fn lookup_invoice_result(invoice: &Invoice) -> Value {
+    let mut result = json!({
+        "type": if invoice.incoming { "incoming" } else { "outgoing" },
+        "invoice": invoice.payment_request,
+        "description": invoice.description.clone().unwrap_or_default(),
+        "payment_hash": invoice.payment_hash,
+        "amount": invoice.value_msat,
+        "fees_paid": invoice.fees.unwrap_or_default() * 1000,
+        "created_at": invoice.created_at,
+        "expires_at": invoice.created_at + invoice.expiry,
+    });
+    if invoice.settled {
+        result["settled_at"] = json!(invoice.settled_date);
+    }
+    result
+}
+
+pub fn create_request_filter(wallet_pubkey: XOnlyPublicKey, since: u64) -> SubscriptionFilter {
+    SubscriptionFilter::new()
+        .kind(Kind::Custom(NWC_REQUEST_KIND))
+        .pubkey(wallet_pubkey)
+        .since(Timestamp::from(since))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(budget_msat: Option<i64>, budget_renewal_secs: Option<i64>) -> NwcConnection {
+        NwcConnection {
+            created_at: None,
+            pubkey: String::from("b889ff5b1513b641e2a139f661a661364979c5beee91842f8f0ef42ab558e9d4"),
+            uid: 1,
+            name: String::from("test"),
+            allowed_methods: vec![String::from("get_balance"), String::from("pay_invoice")],
+            budget_msat,
+            budget_renewal_secs,
+            budget_spent_msat: 4_000,
+            budget_period_start: 1_000,
+            revoked: false,
+        }
+    }
+
+    #[test]
+    fn test_authorize() {
+        let mut conn = connection(None, None);
+        assert!(authorize(&conn, "get_balance").is_ok());
+        assert_eq!(
+            authorize(&conn, "make_invoice").unwrap_err().0,
+            NwcErrorCode::Restricted
+        );
+        assert_eq!(
+            authorize(&conn, "list_transactions").unwrap_err().0,
+            NwcErrorCode::NotImplemented
+        );
+        conn.revoked = true;
+        assert_eq!(
+            authorize(&conn, "get_balance").unwrap_err().0,
+            NwcErrorCode::Unauthorized
+        );
+    }
+
+    #[test]
+    fn test_budget_renewal() {
+        let conn = connection(Some(10_000), Some(100));
+        assert_eq!(current_budget_period(&conn, 1_050), (4_000, 1_000));
+        assert_eq!(current_budget_period(&conn, 1_250), (0, 1_200));
+
+        assert!(check_budget(&conn, 6_000, 0, 1_050).is_ok());
+        assert_eq!(
+            check_budget(&conn, 6_000, 1, 1_050).unwrap_err().0,
+            NwcErrorCode::QuotaExceeded
+        );
+        assert!(check_budget(&conn, 10_000, 0, 1_100).is_ok());
+
+        let unlimited = connection(None, None);
+        assert!(check_budget(&unlimited, i64::MAX / 2, 0, 1_050).is_ok());
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let expiration = vec![vec![String::from("expiration"), String::from("1100")]];
+        assert!(!is_expired(&expiration, 1_000, 1_050));
+        assert!(is_expired(&expiration, 1_000, 1_100));
+        assert!(!is_expired(&[], 1_000, 1_000 + NWC_REQUEST_MAX_AGE_SECS));
+        assert!(is_expired(&[], 1_000, 1_001 + NWC_REQUEST_MAX_AGE_SECS));
+    }
+
+    #[test]
+    fn test_response_json() {
+        let ok = response_json("get_balance", Ok(json!({"balance": 1000})));
+        assert_eq!(ok, json!({"result_type": "get_balance", "result": {"balance": 1000}}));
+        let err = response_json("pay_invoice", Err((NwcErrorCode::QuotaExceeded, String::from("nope"))));
+        assert_eq!(err["error"]["code"], "QUOTA_EXCEEDED");
+        assert_eq!(err["result_type"], "pay_invoice");
+    }
+
+    #[test]
+    fn test_msat_conversion() {
+        assert_eq!(money_to_msat(&msat_to_money(1_234_567)), 1_234_567);
+        assert_eq!(money_to_msat(&Money::from_btc(Decimal::new(1, 3))), 100_000_000);
+    }
+}
@@ -0,0 +1,387 @@
+use models::nostr_relays::NostrRelay;
+use nostr_sdk::nostr::Url;
+use std::collections::HashMap;
+
+/// Consecutive failures after which a relay is not published to for a while.
+const BACKOFF_AFTER_FAILURES: i32 = 3;
+/// Consecutive failures after which a relay is dropped from the pool until its backoff expires.
+const DISCONNECT_AFTER_FAILURES: i32 = 6;
+const BASE_BACKOFF_MS: i64 = 30_000;
+const MAX_BACKOFF_MS: i64 = 6 * 60 * 60 * 1000;
+/// Zap receipt relays are only kept in the pool while they are in use.
+const ZAP_RELAY_IDLE_MS: i64 = 60 * 60 * 1000;
+/// Events not acknowledged by a relay within this time count as a failed publish.
+pub const PUBLISH_TIMEOUT_MS: i64 = 30_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelaySource {
+    Config,
+    Cli,
+    Zap,
+}
+
+impl RelaySource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RelaySource::Config => "config",
+            RelaySource::Cli => "cli",
+            RelaySource::Zap => "zap",
+        }
+    }
+
+    fn from_db(source: &str) -> Self {
+        match source {
+            "config" => RelaySource::Config,
+            "cli" => RelaySource::Cli,
+            _ => RelaySource::Zap,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelayAction {
+    Disconnect(String),
+    Reconnect(String),
+}
+
+#[derive(Debug, Clone)]
+struct RelayHealth {
+    source: RelaySource,
+    enabled: bool,
+    in_pool: bool,
+    connected: bool,
+    latency_ms: Option<i64>,
+    publish_attempts: i64,
+    publish_successes: i64,
+    consecutive_failures: i32,
+    backoff_until: i64,
+    last_used_at: i64,
+}
+
+impl RelayHealth {
+    fn new(source: RelaySource) -> Self {
+        Self {
+            source,
+            enabled: true,
+            in_pool: false,
+            connected: false,
+            latency_ms: None,
+            publish_attempts: 0,
+            publish_successes: 0,
+            consecutive_failures: 0,
+            backoff_until: 0,
+            last_used_at: 0,
+        }
+    }
+
+    fn record_failure(&mut self, now: i64) {
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= BACKOFF_AFTER_FAILURES {
+            let exponent = (self.consecutive_failures - BACKOFF_AFTER_FAILURES).min(20);
+            self.backoff_until = now + (BASE_BACKOFF_MS << exponent).min(MAX_BACKOFF_MS);
+        }
+    }
+
+    fn record_success(&mut self, latency_ms: i64) {
+        self.publish_successes += 1;
+        self.consecutive_failures = 0;
+        self.backoff_until = 0;
+        self.latency_ms = Some(match self.latency_ms {
+            Some(average) => (average * 4 + latency_ms) / 5,
+            None => latency_ms,
+        });
+    }
+}
+
+/// Tracks connection state, latency and publish success of every relay the engine uses and decides
+/// which relays are published to, dropped from the pool or connected again.
+pub struct RelayManager {
+    relays: HashMap<String, RelayHealth>,
+    /// Publish time of events waiting for an acknowledgement, by event id and relay url
+    pending_publishes: HashMap<(String, String), i64>,
+}
+
+/// Relay urls are compared in the form the relay pool reports them.
+pub fn normalize_url(url: &str) -> Option<String> {
+    Url::parse(url).ok().map(|url| url.to_string())
+}
+
+impl RelayManager {
+    /// Configured relays are used unless an operator removed them, relays added through the cli are
+    /// used again after a restart and zap relays only keep their statistics until they are needed.
+    pub fn new(configured_urls: &[String], persisted: Vec<NostrRelay>) -> Self {
+        let mut relays = HashMap::new();
+        for relay in persisted {
+            let source = RelaySource::from_db(&relay.source);
+            let health = RelayHealth {
+                source,
+                enabled: relay.enabled,
+                in_pool: relay.enabled && source == RelaySource::Cli,
+                connected: false,
+                latency_ms: relay.latency_ms,
+                publish_attempts: relay.publish_attempts,
+                publish_successes: relay.publish_successes,
+                consecutive_failures: relay.consecutive_failures,
+                backoff_until: relay.backoff_until,
+                last_used_at: relay.last_used_at,
+            };
+            relays.insert(relay.url, health);
+        }
+        for url in configured_urls.iter().filter_map(|url| normalize_url(url)) {
+            let health = relays
+                .entry(url)
+                .or_insert_with(|| RelayHealth::new(RelaySource::Config));
+            health.source = RelaySource::Config;
+            health.in_pool = health.enabled;
+        }
+        Self {
+            relays,
+            pending_publishes: HashMap::new(),
+        }
+    }
+
+    pub fn pool_relays(&self) -> Vec<String> {
+        self.relays
+            .iter()
+            .filter(|(_, health)| health.in_pool)
+            .map(|(url, _)| url.clone())
+            .collect()
+    }
+
+    /// Relays in the pool that are not backed off.
+    pub fn publish_relays(&self, now: i64) -> Vec<String> {
+        self.relays
+            .iter()
+            .filter(|(_, health)| health.in_pool && now >= health.backoff_until)
+            .map(|(url, _)| url.clone())
+            .collect()
+    }
+
+    /// Returns true if the relay has to be added to the pool. Relays removed by an operator are only
+    /// added back by an operator, and backed off zap relays wait for their backoff to expire.
+    /// Relays added by an operator are kept across restarts even if they were configured before.
+    pub fn add(&mut self, url: &str, source: RelaySource, now: i64) -> bool {
+        let health = self
+            .relays
+            .entry(url.to_string())
+            .or_insert_with(|| RelayHealth::new(source));
+        match source {
+            RelaySource::Zap => {
+                health.last_used_at = now;
+                if !health.enabled || health.in_pool || now < health.backoff_until {
+                    return false;
+                }
+            }
+            RelaySource::Config | RelaySource::Cli => {
+                health.source = source;
+                health.enabled = true;
+                health.consecutive_failures = 0;
+                health.backoff_until = 0;
+                if health.in_pool {
+                    return false;
+                }
+            }
+        }
+        health.in_pool = true;
+        true
+    }
+
+    /// Returns true if the relay has to be removed from the pool.
+    pub fn remove(&mut self, url: &str) -> bool {
+        match self.relays.get_mut(url) {
+            Some(health) => {
+                let in_pool = health.in_pool;
+                health.enabled = false;
+                health.in_pool = false;
+                health.connected = false;
+                in_pool
+            }
+            None => false,
+        }
+    }
+
+    pub fn record_sent(&mut self, event_id: &str, url: &str, now: i64) {
+        if let Some(health) = self.relays.get_mut(url) {
+            health.publish_attempts += 1;
+            health.last_used_at = now;
+            self.pending_publishes
+                .insert((event_id.to_string(), url.to_string()), now);
+        }
+    }
+
+    pub fn record_send_error(&mut self, url: &str, now: i64) {
+        if let Some(health) = self.relays.get_mut(url) {
+            health.publish_attempts += 1;
+            health.last_used_at = now;
+            health.record_failure(now);
+        }
+    }
+
+    /// Acknowledgements of events that were not published through the manager are ignored.
+    pub fn record_ok(&mut self, event_id: &str, url: &str, accepted: bool, now: i64) {
+        let sent_at = match self.pending_publishes.remove(&(event_id.to_string(), url.to_string())) {
+            Some(sent_at) => sent_at,
+            None => return,
+        };
+        if let Some(health) = self.relays.get_mut(url) {
+            if accepted {
+                health.record_success(now - sent_at);
+            } else {
+                health.record_failure(now);
+            }
+        }
+    }
+
+    /// A relay in the pool that is not connected counts as a failure.
+    pub fn record_connection(&mut self, url: &str, connected: bool, now: i64) {
+        if let Some(health) = self.relays.get_mut(url) {
+            health.connected = connected;
+            if !connected && health.in_pool {
+                health.record_failure(now);
+            }
+        }
+    }
+
+    /// Expires unacknowledged publishes and returns the relays to drop from or add back to the pool.
+    pub fn maintain(&mut self, now: i64) -> Vec<RelayAction> {
+        let expired = self
+            .pending_publishes
+            .iter()
+            .filter(|(_, sent_at)| now - **sent_at >= PUBLISH_TIMEOUT_MS)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in expired {
+            self.pending_publishes.remove(&key);
+            if let Some(health) = self.relays.get_mut(&key.1) {
+                health.record_failure(now);
+            }
+        }
+
+        let mut actions = Vec::new();
+        for (url, health) in self.relays.iter_mut() {
+            if health.in_pool {
+                let failing = health.consecutive_failures >= DISCONNECT_AFTER_FAILURES;
+                let idle = health.source == RelaySource::Zap && now - health.last_used_at >= ZAP_RELAY_IDLE_MS;
+                if failing || idle {
+                    health.in_pool = false;
+                    health.connected = false;
+                    actions.push(RelayAction::Disconnect(url.clone()));
+                }
+            } else if health.enabled && health.source != RelaySource::Zap && now >= health.backoff_until {
+                health.in_pool = true;
+                actions.push(RelayAction::Reconnect(url.clone()));
+            }
+        }
+        actions
+    }
+
+    pub fn records(&self, now: i64) -> Vec<NostrRelay> {
+        self.relays
+            .iter()
+            .map(|(url, health)| NostrRelay {
+                url: url.clone(),
+                source: health.source.as_str().to_string(),
+                enabled: health.enabled,
+                connected: health.connected,
+                latency_ms: health.latency_ms,
+                publish_attempts: health.publish_attempts,
+                publish_successes: health.publish_successes,
+                consecutive_failures: health.consecutive_failures,
+                backoff_until: health.backoff_until,
+                last_used_at: health.last_used_at,
+                updated_at: now,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG_RELAY: &str = "wss://relay.damus.io/";
+    const ZAP_RELAY: &str = "wss://nos.lol/";
+
+    fn manager() -> RelayManager {
+        RelayManager::new(&[String::from("wss://relay.damus.io")], Vec::new())
+    }
+
+    #[test]
+    fn test_publish_latency_and_backoff() {
+        let mut manager = manager();
+        assert_eq!(manager.publish_relays(0), vec![CONFIG_RELAY.to_string()]);
+
+        manager.record_sent("a", CONFIG_RELAY, 1_000);
+        manager.record_ok("a", CONFIG_RELAY, true, 1_100);
+        manager.record_sent("b", CONFIG_RELAY, 2_000);
+        manager.record_ok("b", CONFIG_RELAY, true, 2_600);
+        let record = &manager.records(3_000)[0];
+        assert_eq!(record.latency_ms, Some(200));
+        assert_eq!((record.publish_attempts, record.publish_successes), (2, 2));
+
+        for _ in 0..BACKOFF_AFTER_FAILURES {
+            manager.record_send_error(CONFIG_RELAY, 10_000);
+        }
+        assert!(manager.publish_relays(10_000).is_empty());
+        assert_eq!(
+            manager.publish_relays(10_000 + BASE_BACKOFF_MS),
+            vec![CONFIG_RELAY.to_string()]
+        );
+
+        manager.record_sent("c", CONFIG_RELAY, 50_000);
+        manager.record_ok("c", CONFIG_RELAY, true, 50_100);
+        assert_eq!(manager.publish_relays(50_100), vec![CONFIG_RELAY.to_string()]);
+    }
+
+    #[test]
+    fn test_failing_relay_is_dropped_and_reconnected() {
+        let mut manager = manager();
+        for minute in 0..DISCONNECT_AFTER_FAILURES as i64 {
+            manager.record_connection(CONFIG_RELAY, false, minute * 60_000);
+        }
+        let now = (DISCONNECT_AFTER_FAILURES as i64 - 1) * 60_000;
+        assert_eq!(
+            manager.maintain(now),
+            vec![RelayAction::Disconnect(CONFIG_RELAY.to_string())]
+        );
+        assert!(manager.pool_relays().is_empty());
+        assert!(manager.maintain(now + 1).is_empty());
+        assert_eq!(
+            manager.maintain(now + MAX_BACKOFF_MS),
+            vec![RelayAction::Reconnect(CONFIG_RELAY.to_string())]
+        );
+    }
+
+    #[test]
+    fn test_zap_relays_and_operator_changes() {
+        let mut manager = manager();
+        assert!(manager.add(ZAP_RELAY, RelaySource::Zap, 0));
+        assert!(!manager.add(ZAP_RELAY, RelaySource::Zap, 1_000));
+
+        manager.record_sent("a", ZAP_RELAY, 1_000);
+        assert!(manager.maintain(1_000 + PUBLISH_TIMEOUT_MS).is_empty());
+        assert_eq!(
+            manager
+                .records(0)
+                .iter()
+                .find(|r| r.url == ZAP_RELAY)
+                .unwrap()
+                .consecutive_failures,
+            1
+        );
+        assert_eq!(
+            manager.maintain(1_000 + ZAP_RELAY_IDLE_MS),
+            vec![RelayAction::Disconnect(ZAP_RELAY.to_string())]
+        );
+
+        assert!(manager.remove(CONFIG_RELAY));
+        assert!(!manager.add(CONFIG_RELAY, RelaySource::Zap, 0));
+        assert!(manager.maintain(MAX_BACKOFF_MS).is_empty());
+        assert!(manager.add(CONFIG_RELAY, RelaySource::Cli, 0));
+
+        let restarted = RelayManager::new(&[], manager.records(0));
+        let mut pool = restarted.pool_relays();
+        pool.sort();
+        assert_eq!(pool, vec![CONFIG_RELAY.to_string()]);
+    }
+}
@@ -0,0 +1,173 @@
+use core_types::kollider_client::Side;
+use msgs::kollider_client::Level2State;
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+/// Decimal places fill prices are rounded to, well below any tick size.
+const FILL_PRICE_DP: u32 = 8;
+
+/// Price levels of a symbol as last seen in the fed `Level2State`s.
+#[derive(Debug, Clone, Default)]
+pub struct OrderBook {
+    bids: BTreeMap<Decimal, u64>,
+    asks: BTreeMap<Decimal, u64>,
+}
+
+impl OrderBook {
+    /// Applies a snapshot or delta update, returns false for updates that could not be applied.
+    pub fn apply(&mut self, level2_state: &Level2State) -> bool {
+        match level2_state.update_type.as_str() {
+            "snapshot" => {
+                self.bids = level2_state.bids.clone();
+                self.asks = level2_state.asks.clone();
+            }
+            "delta" => {
+                apply_delta(&mut self.bids, &level2_state.bids);
+                apply_delta(&mut self.asks, &level2_state.asks);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn mid(&self) -> Option<Decimal> {
+        let best_bid = self.bids.keys().next_back()?;
+        let best_ask = self.asks.keys().next()?;
+        Some((best_bid + best_ask) / Decimal::TWO)
+    }
+
+    /// Whether an order on `side` at `price` trades against the book.
+    pub fn crosses(&self, side: Side, price: Decimal) -> bool {
+        match side {
+            Side::Bid => self.asks.keys().next().map_or(false, |best_ask| *best_ask <= price),
+            Side::Ask => self
+                .bids
+                .keys()
+                .next_back()
+                .map_or(false, |best_bid| *best_bid >= price),
+        }
+    }
+
+    /// Takes up to `quantity` contracts from the levels a market order on `side` trades against.
+    /// Returns the filled quantity and its average price, weighted by value as contracts are inverse.
+    /// Taken volume stays gone until the level is updated again.
+    pub fn take(&mut self, side: Side, quantity: u64) -> Option<(u64, Decimal)> {
+        self.take_within(side, quantity, None)
+    }
+
+    /// Same as `take`, leaving the levels beyond `limit_price` untouched.
+    pub fn take_within(&mut self, side: Side, quantity: u64, limit_price: Option<Decimal>) -> Option<(u64, Decimal)> {
+        let levels = match side {
+            Side::Bid => &mut self.asks,
+            Side::Ask => &mut self.bids,
+        };
+        let mut filled = 0;
+        let mut inverse_sum = Decimal::ZERO;
+        while filled < quantity {
+            let level = match side {
+                Side::Bid => levels.iter_mut().next(),
+                Side::Ask => levels.iter_mut().next_back(),
+            };
+            let (price, volume) = match level {
+                Some((price, volume)) => (*price, volume),
+                None => break,
+            };
+            let is_beyond_limit = match (side, limit_price) {
+                (_, None) => false,
+                (Side::Bid, Some(limit_price)) => price > limit_price,
+                (Side::Ask, Some(limit_price)) => price < limit_price,
+            };
+            if is_beyond_limit {
+                break;
+            }
+            let taken = (*volume).min(quantity - filled);
+            *volume -= taken;
+            filled += taken;
+            inverse_sum += Decimal::from(taken) / price;
+            if *volume == 0 {
+                levels.remove(&price);
+            }
+        }
+        if filled == 0 {
+            return None;
+        }
+        let average_price = (Decimal::from(filled) / inverse_sum).round_dp(FILL_PRICE_DP);
+        Some((filled, average_price))
+    }
+}
+
+fn apply_delta(levels: &mut BTreeMap<Decimal, u64>, delta: &BTreeMap<Decimal, u64>) {
+    for (price, volume) in delta {
+        if *volume == 0 {
+            levels.remove(price);
+        } else {
+            levels.insert(*price, *volume);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_types::Symbol;
+    use rust_decimal_macros::dec;
+
+    fn level2_state(update_type: &str, bids: &[(Decimal, u64)], asks: &[(Decimal, u64)]) -> Level2State {
+        Level2State {
+            update_type: update_type.to_string(),
+            seq_number: 0,
+            symbol: Symbol::from("BTCUSD.PERP"),
+            bids: bids.iter().cloned().collect(),
+            asks: asks.iter().cloned().collect(),
+        }
+    }
+
+    #[test]
+    fn test_take_walks_levels() {
+        let mut book = OrderBook::default();
+        book.apply(&level2_state(
+            "snapshot",
+            &[(dec!(19000), 100), (dec!(19500), 100)],
+            &[(dec!(20000), 100), (dec!(25000), 100)],
+        ));
+        assert_eq!(book.mid(), Some(dec!(19750)));
+
+        let (filled, price) = book.take(Side::Bid, 200).unwrap();
+        assert_eq!(filled, 200);
+        // 200 contracts bought for 100 / 20000 + 100 / 25000 bitcoin
+        assert_eq!(price.round_dp(2), dec!(22222.22));
+        assert!(book.take(Side::Bid, 1).is_none());
+
+        let (filled, price) = book.take(Side::Ask, 500).unwrap();
+        assert_eq!((filled, price.round_dp(2)), (200, dec!(19246.75)));
+    }
+
+    #[test]
+    fn test_delta_updates_levels() {
+        let mut book = OrderBook::default();
+        book.apply(&level2_state("snapshot", &[(dec!(19000), 100)], &[(dec!(20000), 100)]));
+        book.apply(&level2_state("delta", &[(dec!(19000), 0), (dec!(18000), 50)], &[]));
+        assert_eq!(book.take(Side::Ask, 100), Some((50, dec!(18000))));
+        assert!(!book.apply(&level2_state("unknown", &[], &[])));
+    }
+
+    #[test]
+    fn test_limited_take() {
+        let mut book = OrderBook::default();
+        book.apply(&level2_state(
+            "snapshot",
+            &[(dec!(19000), 100)],
+            &[(dec!(20000), 100), (dec!(25000), 100)],
+        ));
+        assert!(book.crosses(Side::Bid, dec!(20000)));
+        assert!(!book.crosses(Side::Bid, dec!(19999)));
+        assert!(book.crosses(Side::Ask, dec!(18000)));
+
+        assert_eq!(
+            book.take_within(Side::Bid, 200, Some(dec!(24000))),
+            Some((100, dec!(20000)))
+        );
+        assert!(book.take_within(Side::Bid, 200, Some(dec!(24000))).is_none());
+        assert!(!book.crosses(Side::Bid, dec!(24000)));
+    }
+}
@@ -0,0 +1,1075 @@
+pub mod book;
+pub mod position;
+
+use book::OrderBook;
+use core_types::{kollider_client::*, Currency, Symbol, SATS_IN_BITCOIN};
+use crossbeam::channel::Sender;
+use msgs::kollider_client::*;
+use msgs::Message;
+use position::{contracts_value, Position};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use utils::time::time_now;
+use uuid::Uuid;
+use ws_client::{Result, WsClient};
+use xerror::kollider_client::*;
+
+const CASH_SYMBOL: &str = "SAT";
+/// Fills kept for `take_fills`, older ones are dropped when nobody collects them.
+const MAX_RECORDED_FILLS: usize = 10_000;
+
+fn default_leverage() -> Decimal {
+    Decimal::ONE
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SimulatedExchangeSettings {
+    /// Cash balance in sats the account starts with.
+    pub initial_balance: Decimal,
+    /// Fee charged on every fill as a fraction of its value.
+    #[serde(default)]
+    pub taker_fee: Decimal,
+    /// Price penalty on top of walking the book, as a fraction of the fill price.
+    #[serde(default)]
+    pub slippage: Decimal,
+    /// Leverage positions are opened at.
+    #[serde(default = "default_leverage")]
+    pub leverage: Decimal,
+}
+
+/// Order executed by the exchange.
+#[derive(Debug, Clone)]
+pub struct SimulatedFill {
+    pub symbol: Symbol,
+    pub side: Side,
+    pub quantity: u64,
+    /// Average fill price, including the configured slippage when liquidity was taken.
+    pub price: Decimal,
+    /// Mid price of the book before the order.
+    pub mid_price: Option<Decimal>,
+    /// Taker fee in sats, resting orders are filled without fees.
+    pub fee: Decimal,
+    pub timestamp: u64,
+}
+
+impl SimulatedFill {
+    /// Cost in sats of filling at the fill price instead of the mid price, fees not included.
+    pub fn slippage(&self, contract_size: Decimal) -> Decimal {
+        let mid_price = match self.mid_price {
+            Some(mid_price) => mid_price,
+            None => return Decimal::ZERO,
+        };
+        let quantity = Decimal::from(self.quantity);
+        let mid_value = contracts_value(quantity, mid_price, contract_size);
+        let fill_value = contracts_value(quantity, self.price, contract_size);
+        Decimal::from(self.side.to_sign()) * (mid_value - fill_value)
+    }
+}
+
+/// Order accepted by the exchange, limit orders rest until the book trades through their price.
+#[derive(Debug, Clone)]
+struct OpenOrder {
+    order_id: OrderId,
+    ext_order_id: ExtOrderId,
+    symbol: Symbol,
+    side: Side,
+    quantity: u64,
+    price: Option<Decimal>,
+}
+
+#[derive(Debug)]
+struct State {
+    settings: SimulatedExchangeSettings,
+    is_connected: bool,
+    is_authenticated: bool,
+    cash: Decimal,
+    tradable_symbols: HashMap<Symbol, TradableSymbol>,
+    books: HashMap<Symbol, OrderBook>,
+    mark_prices: HashMap<Symbol, Decimal>,
+    positions: HashMap<Symbol, Position>,
+    /// Subscribed channels, `None` standing for all symbols.
+    subscriptions: Vec<(Channel, Option<Vec<Symbol>>)>,
+    fills: VecDeque<SimulatedFill>,
+    resting_orders: Vec<OpenOrder>,
+    last_order_id: OrderId,
+}
+
+impl State {
+    fn is_subscribed(&self, channel: &Channel, symbol: &Symbol) -> bool {
+        self.subscriptions.iter().any(|(subscribed, symbols)| {
+            std::mem::discriminant(subscribed) == std::mem::discriminant(channel)
+                && symbols.as_ref().map_or(true, |symbols| symbols.contains(symbol))
+        })
+    }
+
+    fn balances(&self) -> Balances {
+        Balances {
+            cash: [(Symbol::from(CASH_SYMBOL), self.cash)].into_iter().collect(),
+            isolated_margin: self
+                .positions
+                .iter()
+                .map(|(symbol, position)| (symbol.clone(), position.margin))
+                .collect(),
+            order_margin: HashMap::new(),
+            cross_margin: Decimal::ZERO,
+        }
+    }
+
+    fn price_dp(&self, symbol: &Symbol) -> u32 {
+        self.tradable_symbols
+            .get(symbol)
+            .map(|contract| contract.price_dp)
+            .unwrap_or_default()
+    }
+
+    fn contract_size(&self, symbol: &Symbol) -> Decimal {
+        self.tradable_symbols
+            .get(symbol)
+            .map(|contract| contract.contract_size)
+            .unwrap_or(Decimal::ONE)
+    }
+
+    fn position_state(&self, symbol: &Symbol) -> Option<PositionState> {
+        let position = self.positions.get(symbol)?;
+        let mark_price = self.mark_prices.get(symbol).cloned().unwrap_or(position.entry_price);
+        Some(position.to_position_state(symbol, mark_price, self.contract_size(symbol), time_now()))
+    }
+
+    fn position_state_update(&self, symbol: &Symbol) -> Option<Message> {
+        if !self.is_subscribed(&Channel::PositionStates, symbol) {
+            return None;
+        }
+        let position_state = self.position_state(symbol)?;
+        Some(Message::KolliderApiResponse(KolliderApiResponse::PositionStates(
+            Box::new(position_state),
+        )))
+    }
+
+    /// Authenticates the way the live client does before its first request, returning the responses.
+    fn initialise(&mut self) -> Result<Vec<Message>> {
+        if !self.is_connected {
+            return Err(KolliderClientError::NotConnected);
+        }
+        if self.is_authenticated {
+            return Ok(vec![]);
+        }
+        self.is_authenticated = true;
+        let mut responses = vec![KolliderApiResponse::Authenticate(Authenticate::new(String::from(
+            "success",
+        )))];
+        if !self.tradable_symbols.is_empty() {
+            responses.push(KolliderApiResponse::TradableSymbols(TradableSymbols {
+                symbols: self.tradable_symbols.clone(),
+            }));
+        }
+        let positions = self
+            .positions
+            .keys()
+            .filter_map(|symbol| Some((symbol.clone(), self.position_state(symbol)?)))
+            .collect();
+        responses.push(KolliderApiResponse::Positions(Positions { positions }));
+        responses.push(KolliderApiResponse::Balances(self.balances()));
+        Ok(responses.into_iter().map(Message::KolliderApiResponse).collect())
+    }
+
+    /// Publishes the mark price to subscribers and liquidates a position it bankrupts.
+    fn update_mark_price(&mut self, symbol: &Symbol, mark_price: Decimal) -> Vec<Message> {
+        self.mark_prices.insert(symbol.clone(), mark_price);
+        let mut messages = vec![];
+        if self.is_authenticated && self.is_subscribed(&Channel::MarkPrices, symbol) {
+            messages.push(Message::KolliderApiResponse(KolliderApiResponse::MarkPrices(
+                MarkPrice {
+                    price: mark_price,
+                    symbol: symbol.clone(),
+                },
+            )));
+        }
+        let contract_size = self.contract_size(symbol);
+        let position = match self.positions.get_mut(symbol) {
+            Some(position) => position,
+            None => return messages,
+        };
+        if position.is_bankrupt(mark_price, contract_size) {
+            eprintln!("Simulated {symbol} position liquidated at mark price {mark_price}");
+            position.liquidate();
+            messages.extend(self.position_state_update(symbol));
+        }
+        messages
+    }
+
+    fn check_contract(&self, symbol: &Symbol) -> std::result::Result<(), RejectionReason> {
+        match self.tradable_symbols.get(symbol) {
+            Some(contract) if contract.is_inverse_priced => Ok(()),
+            _ => Err(RejectionReason::ContractNotAvailable),
+        }
+    }
+
+    /// Takes liquidity up to `limit_price` if set, partially if the book is too thin. Returns the filled
+    /// quantity and its average price, nothing when no level is within the limit.
+    fn take_liquidity(
+        &mut self,
+        quantity: u64,
+        symbol: &Symbol,
+        side: Side,
+        limit_price: Option<Decimal>,
+    ) -> std::result::Result<Option<(u64, Decimal)>, RejectionReason> {
+        let mut book = self.books.get(symbol).cloned().unwrap_or_default();
+        let mid_price = book.mid();
+        let (filled, average_price) = match book.take_within(side, quantity, limit_price) {
+            Some(taken) => taken,
+            None => return Ok(None),
+        };
+        let slippage = Decimal::from(side.to_sign()) * self.settings.slippage;
+        let price = average_price * (Decimal::ONE + slippage);
+        self.book_fill(symbol, side, filled, price, mid_price, true)?;
+        self.books.insert(symbol.clone(), book);
+        Ok(Some((filled, price)))
+    }
+
+    /// Books a fill on the position, fees are only charged on taken liquidity.
+    fn book_fill(
+        &mut self,
+        symbol: &Symbol,
+        side: Side,
+        quantity: u64,
+        price: Decimal,
+        mid_price: Option<Decimal>,
+        is_taker: bool,
+    ) -> std::result::Result<(), RejectionReason> {
+        let contract_size = self.contract_size(symbol);
+        let mut position = self.positions.get(symbol).cloned().unwrap_or_default();
+        let cash_delta = position.fill(side, quantity, price, contract_size, self.settings.leverage, time_now());
+        let fee = if is_taker {
+            (contracts_value(Decimal::from(quantity), price, contract_size) * self.settings.taker_fee).round_dp(0)
+        } else {
+            Decimal::ZERO
+        };
+        let cash = self.cash + cash_delta - fee;
+        if cash < Decimal::ZERO {
+            return Err(RejectionReason::NotEnoughAvailableBalance);
+        }
+
+        self.cash = cash;
+        self.positions.insert(symbol.clone(), position);
+        if self.fills.len() == MAX_RECORDED_FILLS {
+            self.fills.pop_front();
+        }
+        self.fills.push_back(SimulatedFill {
+            symbol: symbol.clone(),
+            side,
+            quantity,
+            price,
+            mid_price,
+            fee,
+            timestamp: time_now(),
+        });
+        Ok(())
+    }
+
+    fn open_order(&mut self, ext_order_id: ExtOrderId, symbol: &Symbol, side: Side, quantity: u64) -> OpenOrder {
+        self.last_order_id += 1;
+        OpenOrder {
+            order_id: self.last_order_id,
+            ext_order_id,
+            symbol: symbol.clone(),
+            side,
+            quantity,
+            price: None,
+        }
+    }
+
+    fn to_ticks(&self, symbol: &Symbol, price: Decimal) -> u64 {
+        let scale = Decimal::from(10u64.pow(self.price_dp(symbol)));
+        (price * scale).round().to_u64().unwrap_or_default()
+    }
+
+    fn received(&self, order: &OpenOrder) -> Message {
+        let (order_type, price) = match order.price {
+            Some(price) => (OrderType::Limit, self.to_ticks(&order.symbol, price)),
+            None => (OrderType::Market, 0),
+        };
+        Message::KolliderApiResponse(KolliderApiResponse::Received(Received {
+            timestamp: time_now(),
+            order_id: order.order_id,
+            ext_order_id: order.ext_order_id,
+            uid: 0,
+            symbol: order.symbol.clone(),
+            quantity: order.quantity,
+            order_type,
+            price,
+            leverage: self.settings.leverage.to_u64().unwrap_or(1),
+        }))
+    }
+
+    fn fill(&self, order: &OpenOrder, quantity: u64, price: Decimal, is_maker: bool) -> Message {
+        Message::KolliderApiResponse(KolliderApiResponse::Fill(Fill {
+            order_id: order.order_id,
+            ext_order_id: order.ext_order_id,
+            price: self.to_ticks(&order.symbol, price),
+            quantity,
+            partial: quantity < order.quantity,
+            symbol: order.symbol.clone(),
+            leverage: self.settings.leverage.to_u64().unwrap_or(1),
+            side: order.side,
+            is_maker,
+            margin_type: MarginType::Isolated,
+            is_selftrade: false,
+            timestamp: time_now(),
+        }))
+    }
+
+    fn done(order: &OpenOrder) -> Message {
+        Message::KolliderApiResponse(KolliderApiResponse::Done(Done {
+            timestamp: time_now(),
+            order_id: order.order_id,
+            symbol: order.symbol.clone(),
+        }))
+    }
+
+    fn open_orders(&self) -> Message {
+        let mut open_orders = HashMap::<Symbol, Vec<msgs::kollider_client::OpenOrder>>::new();
+        for order in self.resting_orders.iter() {
+            open_orders
+                .entry(order.symbol.clone())
+                .or_default()
+                .push(msgs::kollider_client::OpenOrder {
+                    order_id: order.order_id,
+                    ext_order_id: order.ext_order_id,
+                    symbol: order.symbol.clone(),
+                    side: order.side,
+                    quantity: order.quantity,
+                    filled: 0,
+                    price: order
+                        .price
+                        .map(|price| self.to_ticks(&order.symbol, price))
+                        .unwrap_or_default(),
+                });
+        }
+        Message::KolliderApiResponse(KolliderApiResponse::OpenOrders(OpenOrders { open_orders }))
+    }
+
+    fn rejection(ext_order_id: ExtOrderId, symbol: &Symbol, reason: RejectionReason) -> Message {
+        Message::KolliderApiResponse(KolliderApiResponse::OrderRejection(OrderRejection {
+            symbol: symbol.clone(),
+            ext_order_id,
+            reason,
+        }))
+    }
+
+    /// Fills a market order against the book, partially if the book is too thin.
+    fn market_order(&mut self, ext_order_id: ExtOrderId, quantity: u64, symbol: &Symbol, side: Side) -> Vec<Message> {
+        let taken = self
+            .check_contract(symbol)
+            .and_then(|_| self.take_liquidity(quantity, symbol, side, None))
+            .and_then(|taken| taken.ok_or(RejectionReason::NoMarket));
+        let (filled, price) = match taken {
+            Ok(taken) => taken,
+            Err(reason) => {
+                eprintln!("Simulated {side:?} order of {quantity} {symbol} rejected: {reason:?}");
+                return vec![Self::rejection(ext_order_id, symbol, reason)];
+            }
+        };
+        let order = self.open_order(ext_order_id, symbol, side, quantity);
+        let mut messages = vec![
+            self.received(&order),
+            self.fill(&order, filled, price, false),
+            Self::done(&order),
+        ];
+        messages.extend(self.position_state_update(symbol));
+        messages
+    }
+
+    /// Takes the liquidity a limit order crosses and rests the rest of it, post-only orders crossing
+    /// the book are rejected.
+    fn limit_order(
+        &mut self,
+        ext_order_id: ExtOrderId,
+        quantity: u64,
+        symbol: &Symbol,
+        side: Side,
+        price: u64,
+        is_post_only: bool,
+    ) -> Vec<Message> {
+        let price = Decimal::new(price as i64, self.price_dp(symbol));
+        let crosses = self.books.get(symbol).map_or(false, |book| book.crosses(side, price));
+        let taken = match self.check_contract(symbol) {
+            Ok(()) if crosses && is_post_only => Err(RejectionReason::InvalidOrder),
+            Ok(()) if crosses => self.take_liquidity(quantity, symbol, side, Some(price)),
+            Ok(()) => Ok(None),
+            Err(reason) => Err(reason),
+        };
+        let taken = match taken {
+            Ok(taken) => taken,
+            Err(reason) => {
+                eprintln!("Simulated {side:?} limit order of {quantity} {symbol} at {price} rejected: {reason:?}");
+                return vec![Self::rejection(ext_order_id, symbol, reason)];
+            }
+        };
+        let mut order = self.open_order(ext_order_id, symbol, side, quantity);
+        order.price = Some(price);
+        let mut messages = vec![self.received(&order)];
+        let mut filled = 0;
+        if let Some((taken, average_price)) = taken {
+            filled = taken;
+            messages.push(self.fill(&order, taken, average_price, false));
+            messages.extend(self.position_state_update(symbol));
+        }
+        if filled < quantity {
+            order.quantity -= filled;
+            self.resting_orders.push(order);
+        } else {
+            messages.push(Self::done(&order));
+        }
+        messages
+    }
+
+    fn cancel_order(&mut self, order_id: OrderId, symbol: &Symbol) -> Vec<Message> {
+        let index = self
+            .resting_orders
+            .iter()
+            .position(|order| order.order_id == order_id && &order.symbol == symbol);
+        match index {
+            Some(index) => vec![Self::done(&self.resting_orders.remove(index))],
+            None => {
+                // Rejections are not forwarded by the live client either.
+                eprintln!("Simulated cancellation of {symbol} order {order_id} rejected, it is not open");
+                vec![]
+            }
+        }
+    }
+
+    /// Fills the resting orders the book has moved through at their limit price.
+    fn match_resting_orders(&mut self, symbol: &Symbol) -> Vec<Message> {
+        let book = match self.books.get(symbol) {
+            Some(book) => book,
+            None => return vec![],
+        };
+        let mid_price = book.mid();
+        let (crossed, resting): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.resting_orders).into_iter().partition(|order| {
+                &order.symbol == symbol && order.price.map_or(false, |price| book.crosses(order.side, price))
+            });
+        self.resting_orders = resting;
+
+        let mut messages = vec![];
+        let mut has_filled = false;
+        for order in crossed {
+            let price = order.price.unwrap_or_default();
+            match self.book_fill(symbol, order.side, order.quantity, price, mid_price, false) {
+                Ok(()) => {
+                    has_filled = true;
+                    messages.push(self.fill(&order, order.quantity, price, true));
+                }
+                Err(reason) => {
+                    eprintln!("Simulated {symbol} order {} cancelled: {reason:?}", order.order_id);
+                }
+            }
+            messages.push(Self::done(&order));
+        }
+        if has_filled {
+            messages.extend(self.position_state_update(symbol));
+        }
+        messages
+    }
+
+    fn change_margin(&mut self, symbol: &Symbol, amount: Decimal) -> KolliderApiResponse {
+        let rejection = |reason| {
+            KolliderApiResponse::ChangeMarginRejection(ChangeMarginRejection {
+                symbol: symbol.clone(),
+                reason,
+            })
+        };
+        let mark_price = self.mark_prices.get(symbol).cloned();
+        let contract_size = self.contract_size(symbol);
+        let position = match self.positions.get_mut(symbol) {
+            Some(position) if position.side().is_some() => position,
+            _ => return rejection(RejectionReason::NoOpenPosition),
+        };
+        if amount > self.cash || position.margin + amount <= Decimal::ZERO {
+            return rejection(RejectionReason::InvalidMarginChange);
+        }
+        let margin = position.margin;
+        position.margin += amount;
+        if let Some(mark_price) = mark_price {
+            if position.is_bankrupt(mark_price, contract_size) {
+                position.margin = margin;
+                return rejection(RejectionReason::InvalidMarginChange);
+            }
+        }
+        self.cash -= amount;
+        KolliderApiResponse::ChangeMarginSuccess(ChangeMarginSuccess {
+            symbol: symbol.clone(),
+            amount,
+        })
+    }
+}
+
+/// Exchange filling market orders against order books fed from recorded or live `Level2State`s,
+/// for paper trading and tests. Only inverse contracts with isolated margin are supported.
+///
+/// Margin is taken from the cash balance instead of being paid through order invoices, otherwise
+/// it emits the same responses the live client forwards. Clones share the same exchange, so one can
+/// be handed to the dealer while another feeds it market data.
+#[derive(Clone)]
+pub struct SimulatedExchange {
+    state: Arc<Mutex<State>>,
+    callback: Sender<Message>,
+}
+
+impl SimulatedExchange {
+    pub fn new(settings: SimulatedExchangeSettings, callback: Sender<Message>) -> Self {
+        let state = State {
+            cash: settings.initial_balance,
+            settings,
+            is_connected: true,
+            is_authenticated: false,
+            tradable_symbols: HashMap::new(),
+            books: HashMap::new(),
+            mark_prices: HashMap::new(),
+            positions: HashMap::new(),
+            subscriptions: Vec::new(),
+            fills: VecDeque::new(),
+            resting_orders: Vec::new(),
+            last_order_id: 0,
+        };
+        let exchange = Self {
+            state: Arc::new(Mutex::new(state)),
+            callback,
+        };
+        let responses = exchange
+            .locked_state()
+            .initialise()
+            .expect("Simulated exchange starts connected");
+        exchange.send_to_callback(responses);
+        exchange
+    }
+
+    fn locked_state(&self) -> MutexGuard<State> {
+        match self.state.lock() {
+            Ok(locked) => locked,
+            Err(err) => {
+                panic!("Could not lock a shared state, reason: {err:?}");
+            }
+        }
+    }
+
+    fn send_to_callback(&self, messages: impl IntoIterator<Item = Message>) {
+        for msg in messages {
+            if let Err(err) = self.callback.try_send(msg) {
+                panic!("Failed to send a message to a callback sender, reason: {err:?}");
+            }
+        }
+    }
+
+    /// Contracts that can be traded, announced to the client the way the exchange does after authentication.
+    pub fn set_tradable_symbols(&self, symbols: HashMap<Symbol, TradableSymbol>) {
+        let response = {
+            let mut state = self.locked_state();
+            state.tradable_symbols = symbols.clone();
+            state.is_authenticated.then(|| {
+                Message::KolliderApiResponse(KolliderApiResponse::TradableSymbols(TradableSymbols { symbols }))
+            })
+        };
+        self.send_to_callback(response);
+    }
+
+    /// Updates the book and the mark price, which follows the mid price unless set explicitly,
+    /// and forwards the update to the client if it is subscribed to the book.
+    pub fn process_level2_state(&self, level2_state: Level2State) {
+        let messages = {
+            let mut state = self.locked_state();
+            let symbol = level2_state.symbol.clone();
+            let book = state.books.entry(symbol.clone()).or_default();
+            if !book.apply(&level2_state) {
+                eprintln!("Unsupported level2 update: {}", level2_state.update_type);
+                return;
+            }
+            let mid = book.mid();
+            let mut messages = vec![];
+            if state.is_authenticated && state.is_subscribed(&Channel::OrderbookLevel2, &symbol) {
+                messages.push(Message::KolliderApiResponse(KolliderApiResponse::Level2State(
+                    level2_state,
+                )));
+            }
+            if let Some(mid) = mid {
+                messages.extend(state.update_mark_price(&symbol, mid));
+            }
+            messages.extend(state.match_resting_orders(&symbol));
+            messages
+        };
+        self.send_to_callback(messages);
+    }
+
+    pub fn process_mark_price(&self, mark_price: MarkPrice) {
+        let message = self
+            .locked_state()
+            .update_mark_price(&mark_price.symbol, mark_price.price);
+        self.send_to_callback(message);
+    }
+
+    /// Settles a funding period at the current mark price, positive rates are paid by longs.
+    pub fn apply_funding(&self, symbol: &Symbol, rate: Decimal) {
+        let messages = {
+            let mut state = self.locked_state();
+            let mark_price = match state.mark_prices.get(symbol) {
+                Some(mark_price) => *mark_price,
+                None => return,
+            };
+            let contract_size = state.contract_size(symbol);
+            let amount = match state.positions.get_mut(symbol) {
+                Some(position) if position.side().is_some() => position.pay_funding(rate, mark_price, contract_size),
+                _ => return,
+            };
+            state.cash += amount;
+            let funding_payment = KolliderApiResponse::FundingPayment(FundingPayment {
+                symbol: symbol.clone(),
+                rate,
+                amount,
+            });
+            let mut messages = vec![Message::KolliderApiResponse(funding_payment)];
+            messages.extend(state.position_state_update(symbol));
+            messages
+        };
+        self.send_to_callback(messages);
+    }
+
+    /// Fills executed since the last call, oldest first.
+    pub fn take_fills(&self) -> Vec<SimulatedFill> {
+        self.locked_state().fills.drain(..).collect()
+    }
+
+    /// Drops the connection, the client has to re-initialise once reconnected.
+    pub fn disconnect(&self) {
+        {
+            let mut state = self.locked_state();
+            state.is_connected = false;
+            state.is_authenticated = false;
+            state.subscriptions.clear();
+        }
+        let timestamp = time_now();
+        let msg = Message::KolliderApiResponse(KolliderApiResponse::Disconnected(Disconnected { timestamp }));
+        self.send_to_callback(Some(msg));
+    }
+
+    pub fn reconnect(&self) {
+        self.locked_state().is_connected = true;
+        let timestamp = time_now();
+        let msg = Message::KolliderApiResponse(KolliderApiResponse::Reconnected(Reconnected { timestamp }));
+        self.send_to_callback(Some(msg));
+    }
+
+    /// Runs a request on the state once the client is initialised, the way `checked_send_request` does.
+    fn checked_request<T>(&self, request: impl FnOnce(&mut State) -> T) -> Result<T> {
+        let (responses, result) = {
+            let mut state = self.locked_state();
+            let responses = state.initialise()?;
+            (responses, request(&mut *state))
+        };
+        self.send_to_callback(responses);
+        Ok(result)
+    }
+
+    fn order(&self, quantity: u64, currency: Currency, side: Side) -> Result<()> {
+        if matches!(currency, Currency::BTC) {
+            return Err(KolliderClientError::NonFiatCurrency);
+        }
+        self.make_order(quantity, currency.into(), side).map(|_ext_order_id| ())
+    }
+}
+
+impl WsClient for SimulatedExchange {
+    fn is_connected(&self) -> bool {
+        self.locked_state().is_connected
+    }
+
+    fn is_authenticated(&self) -> bool {
+        self.locked_state().is_authenticated
+    }
+
+    fn is_ready(&self) -> bool {
+        let state = self.locked_state();
+        state.is_connected && state.is_authenticated
+    }
+
+    fn get_balance(&self, currency: Currency) -> Result<Decimal> {
+        let state = self.locked_state();
+        if !state.is_authenticated {
+            return Err(KolliderClientError::BalanceNotAvailable);
+        }
+        if matches!(currency, Currency::BTC) {
+            return Ok(state.cash);
+        }
+        let symbol: Symbol = currency.into();
+        let position_state = state
+            .position_state(&symbol)
+            .ok_or(KolliderClientError::BalanceNotAvailable)?;
+        let side = position_state.side.ok_or(KolliderClientError::BalanceNotAvailable)?;
+        let mark_price = state
+            .mark_prices
+            .get(&symbol)
+            .ok_or(KolliderClientError::BalanceNotAvailable)?;
+        let margin = state.positions[&symbol].margin;
+        let fiat_value = (margin + position_state.upnl) * mark_price / SATS_IN_BITCOIN;
+        match side {
+            Side::Bid => Ok(-fiat_value),
+            Side::Ask => Ok(fiat_value),
+        }
+    }
+
+    fn get_all_balances(&self) -> Option<Balances> {
+        let state = self.locked_state();
+        state.is_authenticated.then(|| state.balances())
+    }
+
+    fn get_position_state(&self, symbol: &Symbol) -> Result<Option<PositionState>> {
+        let state = self.locked_state();
+        if !state.is_authenticated {
+            return Err(KolliderClientError::PositionStateNotAvailable);
+        }
+        Ok(state.position_state(symbol))
+    }
+
+    fn get_tradable_symbols(&self) -> HashMap<Symbol, TradableSymbol> {
+        let state = self.locked_state();
+        if state.is_authenticated {
+            state.tradable_symbols.clone()
+        } else {
+            HashMap::new()
+        }
+    }
+
+    fn make_withdrawal(&self, amount: u64, _payment_request: String) -> Result<()> {
+        self.checked_request(|state| {
+            let amount = Decimal::from(amount);
+            // Rejections are not forwarded by the live client either.
+            if amount > state.cash {
+                eprintln!(
+                    "Simulated withdrawal of {amount} sats rejected, balance is {}",
+                    state.cash
+                );
+                return;
+            }
+            state.cash -= amount;
+        })
+    }
+
+    fn make_order(&self, quantity: u64, symbol: Symbol, side: Side) -> Result<ExtOrderId> {
+        let ext_order_id = Uuid::new_v4();
+        let responses = self.checked_request(|state| state.market_order(ext_order_id, quantity, &symbol, side))?;
+        self.send_to_callback(responses);
+        Ok(ext_order_id)
+    }
+
+    fn make_limit_order(
+        &self,
+        quantity: u64,
+        symbol: Symbol,
+        side: Side,
+        price: u64,
+        is_post_only: bool,
+    ) -> Result<ExtOrderId> {
+        let ext_order_id = Uuid::new_v4();
+        let responses = self
+            .checked_request(|state| state.limit_order(ext_order_id, quantity, &symbol, side, price, is_post_only))?;
+        self.send_to_callback(responses);
+        Ok(ext_order_id)
+    }
+
+    fn cancel_order(&self, order_id: OrderId, symbol: Symbol) -> Result<()> {
+        let responses = self.checked_request(|state| state.cancel_order(order_id, &symbol))?;
+        self.send_to_callback(responses);
+        Ok(())
+    }
+
+    fn fetch_open_orders(&self) -> Result<()> {
+        let response = self.checked_request(|state| state.open_orders())?;
+        self.send_to_callback([response]);
+        Ok(())
+    }
+
+    fn subscribe(&self, channels: Vec<Channel>, symbols: Option<Vec<Symbol>>) -> Result<()> {
+        self.checked_request(|state| {
+            for channel in channels {
+                state.subscriptions.push((channel, symbols.clone()));
+            }
+        })
+    }
+
+    fn buy(&self, quantity: u64, currency: Currency) -> Result<()> {
+        // side is opposite because buying fiat is selling inverse contract
+        self.order(quantity, currency, Side::Ask)
+    }
+
+    fn sell(&self, quantity: u64, currency: Currency) -> Result<()> {
+        // side is opposite because selling fiat is buying inverse contract
+        self.order(quantity, currency, Side::Bid)
+    }
+
+    fn change_margin(&self, symbol: Symbol, amount: i64) -> Result<()> {
+        if amount == 0 {
+            return Err(KolliderClientError::IncorrectMarginAmount);
+        }
+        let response = self.checked_request(|state| {
+            let response = state.change_margin(&symbol, Decimal::from(amount));
+            let position_state = match response {
+                KolliderApiResponse::ChangeMarginSuccess(_) => state.position_state_update(&symbol),
+                _ => None,
+            };
+            [Some(Message::KolliderApiResponse(response)), position_state]
+        })?;
+        self.send_to_callback(response.into_iter().flatten());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam::channel::{unbounded, Receiver};
+    use rust_decimal_macros::dec;
+    use std::collections::BTreeMap;
+
+    fn btcusd() -> Symbol {
+        Symbol::from("BTCUSD.PERP")
+    }
+
+    fn tradable_symbols() -> HashMap<Symbol, TradableSymbol> {
+        let contract = TradableSymbol {
+            symbol: btcusd(),
+            contract_size: dec!(1.0),
+            max_leverage: dec!(20.0),
+            base_margin: dec!(0.01),
+            liquidation_fee: dec!(0.004),
+            is_inverse_priced: true,
+            price_dp: 0,
+            underlying_symbol: Symbol::from(".BTCUSD"),
+            last_price: dec!(20000),
+            tick_size: dec!(1.0),
+            risk_limit: dec!(150000000.0),
+        };
+        [(btcusd(), contract)].into_iter().collect()
+    }
+
+    fn snapshot(bids: BTreeMap<Decimal, u64>, asks: BTreeMap<Decimal, u64>) -> Level2State {
+        Level2State {
+            update_type: String::from("snapshot"),
+            seq_number: 0,
+            symbol: btcusd(),
+            bids,
+            asks,
+        }
+    }
+
+    fn exchange() -> (SimulatedExchange, Receiver<Message>) {
+        exchange_with_balance(dec!(10000000))
+    }
+
+    fn exchange_with_balance(initial_balance: Decimal) -> (SimulatedExchange, Receiver<Message>) {
+        let (tx, rx) = unbounded();
+        let settings = SimulatedExchangeSettings {
+            initial_balance,
+            taker_fee: dec!(0.001),
+            slippage: Decimal::ZERO,
+            leverage: Decimal::ONE,
+        };
+        let exchange = SimulatedExchange::new(settings, tx);
+        exchange.set_tradable_symbols(tradable_symbols());
+        exchange.process_level2_state(snapshot(
+            [(dec!(19900), 1000)].into_iter().collect(),
+            [(dec!(20000), 1000), (dec!(25000), 1000)].into_iter().collect(),
+        ));
+        (exchange, rx)
+    }
+
+    fn responses(rx: &Receiver<Message>) -> Vec<KolliderApiResponse> {
+        rx.try_iter()
+            .filter_map(|msg| match msg {
+                Message::KolliderApiResponse(response) => Some(response),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_initialisation_responses() {
+        let (exchange, rx) = exchange();
+        let responses = responses(&rx);
+        assert!(matches!(&responses[0], KolliderApiResponse::Authenticate(auth) if auth.success()));
+        assert!(matches!(responses[1], KolliderApiResponse::Positions(_)));
+        assert!(matches!(responses[2], KolliderApiResponse::Balances(_)));
+        assert!(matches!(responses[3], KolliderApiResponse::TradableSymbols(_)));
+        // not subscribed to the book yet
+        assert_eq!(responses.len(), 4);
+        assert!(exchange.is_ready());
+        assert_eq!(exchange.get_balance(Currency::BTC).unwrap(), dec!(10000000));
+    }
+
+    #[test]
+    fn test_market_order_fills_with_slippage() {
+        let (exchange, rx) = exchange();
+        exchange
+            .subscribe(vec![Channel::PositionStates, Channel::OrderbookLevel2], None)
+            .unwrap();
+        responses(&rx);
+
+        exchange.make_order(1500, btcusd(), Side::Bid).unwrap();
+        let position = match responses(&rx).pop() {
+            Some(KolliderApiResponse::PositionStates(position)) => position,
+            other => panic!("Expected a position state, got {other:?}"),
+        };
+        // 1500 contracts for 1000 / 20000 + 500 / 25000 bitcoin
+        assert_eq!(position.entry_price.round_dp(2), dec!(21428.57));
+        assert_eq!(position.quantity, dec!(1500));
+        assert!(matches!(position.side, Some(Side::Bid)));
+
+        let balances = exchange.get_all_balances().unwrap();
+        assert_eq!(balances.isolated_margin[&btcusd()], dec!(7000000));
+        assert_eq!(
+            balances.cash[&Symbol::from(CASH_SYMBOL)],
+            dec!(10000000) - dec!(7000000) - dec!(7000)
+        );
+
+        let fills = exchange.take_fills();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].fee, dec!(7000));
+        assert_eq!(fills[0].mid_price, Some(dec!(19950)));
+        // 1500 contracts are worth 7518797 sats at the mid price
+        assert_eq!(fills[0].slippage(Decimal::ONE).round(), dec!(518797));
+        assert!(exchange.take_fills().is_empty());
+
+        // the mark price follows the book and the taken liquidity is gone
+        exchange.process_level2_state(snapshot(
+            [(dec!(24900), 1000)].into_iter().collect(),
+            [(dec!(25100), 1000)].into_iter().collect(),
+        ));
+        assert!(matches!(responses(&rx)[..], [KolliderApiResponse::Level2State(_)]));
+        let position = exchange.get_position_state(&btcusd()).unwrap().unwrap();
+        assert_eq!(position.upnl, dec!(1000000));
+        assert!(exchange.get_balance(Currency::USD).unwrap() < Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_limit_orders_rest_until_crossed() {
+        let (exchange, rx) = exchange();
+        responses(&rx);
+
+        exchange
+            .make_limit_order(100, btcusd(), Side::Bid, 20000, true)
+            .unwrap();
+        assert!(matches!(
+            responses(&rx)[..],
+            [KolliderApiResponse::OrderRejection(OrderRejection {
+                reason: RejectionReason::InvalidOrder,
+                ..
+            })]
+        ));
+
+        // takes the 20000 level and rests the rest below the 25000 one
+        let ext_order_id = exchange
+            .make_limit_order(1500, btcusd(), Side::Bid, 21000, false)
+            .unwrap();
+        let order_id = match &responses(&rx)[..] {
+            [KolliderApiResponse::Received(received), KolliderApiResponse::Fill(fill)] => {
+                assert_eq!(received.ext_order_id, ext_order_id);
+                assert_eq!((fill.quantity, fill.price, fill.partial), (1000, 20000, true));
+                received.order_id
+            }
+            other => panic!("Expected a partially filled order, got {other:?}"),
+        };
+        exchange.fetch_open_orders().unwrap();
+        match &responses(&rx)[..] {
+            [KolliderApiResponse::OpenOrders(open_orders)] => {
+                let orders = &open_orders.open_orders[&btcusd()];
+                assert_eq!(orders.len(), 1);
+                assert_eq!((orders[0].order_id, orders[0].price), (order_id, 21000));
+            }
+            other => panic!("Expected the open orders, got {other:?}"),
+        }
+        exchange.cancel_order(order_id, btcusd()).unwrap();
+        assert!(matches!(&responses(&rx)[..], [KolliderApiResponse::Done(done)] if done.order_id == order_id));
+
+        // a resting ask fills without fees once the bids reach it
+        exchange
+            .make_limit_order(500, btcusd(), Side::Ask, 19950, true)
+            .unwrap();
+        exchange.take_fills();
+        exchange.process_level2_state(snapshot(
+            [(dec!(19960), 1000)].into_iter().collect(),
+            [(dec!(20000), 1000)].into_iter().collect(),
+        ));
+        let responses = responses(&rx);
+        assert!(matches!(
+            &responses[1..],
+            [KolliderApiResponse::Fill(fill), KolliderApiResponse::Done(_)] if fill.is_maker && fill.price == 19950
+        ));
+        let fills = exchange.take_fills();
+        assert_eq!((fills[0].quantity, fills[0].fee), (500, Decimal::ZERO));
+        let position = exchange.get_position_state(&btcusd()).unwrap().unwrap();
+        assert_eq!(position.quantity, dec!(500));
+    }
+
+    #[test]
+    fn test_rejected_order_keeps_state() {
+        let (exchange, rx) = exchange_with_balance(dec!(1000000));
+        exchange.subscribe(vec![Channel::PositionStates], None).unwrap();
+        exchange
+            .make_order(100, Symbol::from("BTCEUR.PERP"), Side::Bid)
+            .unwrap();
+        // 1000 contracts need more margin than the balance
+        exchange.make_order(1000, btcusd(), Side::Bid).unwrap();
+        assert!(!responses(&rx)
+            .iter()
+            .any(|response| matches!(response, KolliderApiResponse::PositionStates(_))));
+        assert!(exchange.get_position_state(&btcusd()).unwrap().is_none());
+        assert_eq!(exchange.get_balance(Currency::BTC).unwrap(), dec!(1000000));
+    }
+
+    #[test]
+    fn test_funding_and_margin_changes() {
+        let (exchange, rx) = exchange();
+        exchange.change_margin(btcusd(), 1000).unwrap();
+        assert!(matches!(
+            responses(&rx).pop(),
+            Some(KolliderApiResponse::ChangeMarginRejection(_))
+        ));
+
+        // selling dollars is buying the inverse contract, so the position pays funding at the 19950 mid
+        exchange.sell(1000, Currency::USD).unwrap();
+        exchange.apply_funding(&btcusd(), dec!(0.0001));
+        match responses(&rx).pop() {
+            Some(KolliderApiResponse::FundingPayment(payment)) => assert_eq!(payment.amount, dec!(-501)),
+            other => panic!("Expected a funding payment, got {other:?}"),
+        }
+
+        exchange.change_margin(btcusd(), -1000).unwrap();
+        match responses(&rx).pop() {
+            Some(KolliderApiResponse::ChangeMarginSuccess(success)) => assert_eq!(success.amount, dec!(-1000)),
+            other => panic!("Expected a margin change, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_reconnection_requires_initialisation() {
+        let (exchange, rx) = exchange();
+        exchange.disconnect();
+        assert!(matches!(
+            responses(&rx).pop(),
+            Some(KolliderApiResponse::Disconnected(_))
+        ));
+        assert!(matches!(
+            exchange.make_order(1, btcusd(), Side::Bid),
+            Err(KolliderClientError::NotConnected)
+        ));
+
+        exchange.reconnect();
+        assert!(!exchange.is_authenticated());
+        exchange.subscribe(vec![Channel::PositionStates], None).unwrap();
+        let responses = responses(&rx);
+        assert!(matches!(responses[0], KolliderApiResponse::Reconnected(_)));
+        assert!(matches!(&responses[1], KolliderApiResponse::Authenticate(auth) if auth.success()));
+        assert!(exchange.is_ready());
+    }
+}
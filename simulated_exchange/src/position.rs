@@ -0,0 +1,228 @@
+use core_types::kollider_client::{PositionState, Side};
+use core_types::{Symbol, SATS_IN_BITCOIN};
+use rust_decimal::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// Value in sats of inverse contracts at the price.
+pub fn contracts_value(quantity: Decimal, price: Decimal, contract_size: Decimal) -> Decimal {
+    if price.is_zero() {
+        return Decimal::ZERO;
+    }
+    quantity * contract_size / price * SATS_IN_BITCOIN
+}
+
+/// Isolated margin position in an inverse contract, `size` is negative for shorts.
+/// Margin and pnl are in sats.
+#[derive(Debug, Clone, Default)]
+pub struct Position {
+    pub size: Decimal,
+    pub entry_price: Decimal,
+    pub margin: Decimal,
+    pub rpnl: Decimal,
+    pub funding: Decimal,
+    pub entry_time: Option<u64>,
+}
+
+impl Position {
+    pub fn side(&self) -> Option<Side> {
+        match self.size.cmp(&Decimal::ZERO) {
+            Ordering::Greater => Some(Side::Bid),
+            Ordering::Less => Some(Side::Ask),
+            Ordering::Equal => None,
+        }
+    }
+
+    pub fn quantity(&self) -> Decimal {
+        self.size.abs()
+    }
+
+    /// Pnl of closing `quantity` contracts of the position at the price.
+    fn pnl(&self, quantity: Decimal, price: Decimal, contract_size: Decimal) -> Decimal {
+        let entry_value = contracts_value(quantity, self.entry_price, contract_size);
+        let exit_value = contracts_value(quantity, price, contract_size);
+        self.size.signum() * (entry_value - exit_value)
+    }
+
+    pub fn upnl(&self, mark_price: Decimal, contract_size: Decimal) -> Decimal {
+        self.pnl(self.quantity(), mark_price, contract_size).round_dp(0)
+    }
+
+    /// Applies a fill and returns the change of the cash balance: margin posted for the
+    /// contracts opened, margin released together with the realised pnl for the ones closed.
+    pub fn fill(
+        &mut self,
+        side: Side,
+        quantity: u64,
+        price: Decimal,
+        contract_size: Decimal,
+        leverage: Decimal,
+        timestamp: u64,
+    ) -> Decimal {
+        let sign = Decimal::from(side.to_sign());
+        let mut remaining = Decimal::from(quantity);
+        let mut cash_delta = Decimal::ZERO;
+
+        if self.size.signum() == -sign {
+            let closed = self.quantity().min(remaining);
+            let pnl = self.pnl(closed, price, contract_size).round_dp(0);
+            let released = if closed == self.quantity() {
+                self.margin
+            } else {
+                (self.margin * closed / self.quantity()).round_dp(0)
+            };
+            self.margin -= released;
+            self.rpnl += pnl;
+            self.size += sign * closed;
+            remaining -= closed;
+            cash_delta += released + pnl;
+            if self.size.is_zero() {
+                self.entry_price = Decimal::ZERO;
+                self.entry_time = None;
+            }
+        }
+
+        if !remaining.is_zero() {
+            let margin = (contracts_value(remaining, price, contract_size) / leverage).round_dp(0);
+            if self.size.is_zero() {
+                self.entry_price = price;
+                self.entry_time = Some(timestamp);
+            } else {
+                let total = self.quantity() + remaining;
+                self.entry_price = total / (self.quantity() / self.entry_price + remaining / price);
+            }
+            self.size += sign * remaining;
+            self.margin += margin;
+            cash_delta -= margin;
+        }
+
+        cash_delta
+    }
+
+    /// Funding received for the position, positive rates are paid by longs to shorts.
+    pub fn pay_funding(&mut self, rate: Decimal, mark_price: Decimal, contract_size: Decimal) -> Decimal {
+        let value = contracts_value(self.quantity(), mark_price, contract_size);
+        let amount = (-self.size.signum() * rate * value).round_dp(0);
+        self.funding += amount;
+        amount
+    }
+
+    /// Price at which the losses use up the margin, zero if it can't be reached.
+    pub fn bankruptcy_price(&self, contract_size: Decimal) -> Decimal {
+        if self.size.is_zero() || self.entry_price.is_zero() {
+            return Decimal::ZERO;
+        }
+        let margin_per_contract = self.margin / (self.quantity() * contract_size * SATS_IN_BITCOIN);
+        let inverse_price = Decimal::ONE / self.entry_price + self.size.signum() * margin_per_contract;
+        if inverse_price > Decimal::ZERO {
+            Decimal::ONE / inverse_price
+        } else {
+            Decimal::ZERO
+        }
+    }
+
+    pub fn is_bankrupt(&self, mark_price: Decimal, contract_size: Decimal) -> bool {
+        !self.size.is_zero() && self.margin + self.upnl(mark_price, contract_size) <= Decimal::ZERO
+    }
+
+    /// Closes the position at its bankruptcy price, the whole margin is lost.
+    pub fn liquidate(&mut self) {
+        self.rpnl -= self.margin;
+        self.size = Decimal::ZERO;
+        self.margin = Decimal::ZERO;
+        self.entry_price = Decimal::ZERO;
+        self.entry_time = None;
+    }
+
+    pub fn to_position_state(
+        &self,
+        symbol: &Symbol,
+        mark_price: Decimal,
+        contract_size: Decimal,
+        timestamp: u64,
+    ) -> PositionState {
+        let quantity = self.quantity();
+        let entry_value = contracts_value(quantity, self.entry_price, contract_size);
+        let mark_value = contracts_value(quantity, mark_price, contract_size);
+        let upnl = self.upnl(mark_price, contract_size);
+        let leverage = if self.margin > Decimal::ZERO {
+            entry_value / self.margin
+        } else {
+            Decimal::ZERO
+        };
+        let equity = self.margin + upnl;
+        let real_leverage = if equity > Decimal::ZERO {
+            mark_value / equity
+        } else {
+            Decimal::ZERO
+        };
+        let bankruptcy_price = self.bankruptcy_price(contract_size);
+        PositionState {
+            timestamp,
+            symbol: symbol.clone(),
+            upnl,
+            rpnl: self.rpnl,
+            funding: self.funding,
+            leverage,
+            real_leverage,
+            entry_price: self.entry_price,
+            side: self.side(),
+            quantity,
+            open_order_ids: HashSet::new(),
+            liq_price: bankruptcy_price,
+            bankruptcy_price,
+            is_liquidating: false,
+            entry_value,
+            mark_value,
+            adl_score: Decimal::ZERO,
+            entry_time: self.entry_time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_fill_open_reduce_and_flip() {
+        let mut position = Position::default();
+
+        let cash_delta = position.fill(Side::Bid, 1000, dec!(20000), dec!(1), dec!(1), 0);
+        assert_eq!(cash_delta, dec!(-5000000));
+        assert_eq!(position.side(), Some(Side::Bid));
+        assert_eq!(position.bankruptcy_price(dec!(1)), dec!(10000));
+        assert_eq!(position.upnl(dec!(25000), dec!(1)), dec!(1000000));
+
+        // closes the long with a profit of 1m sats and opens a 500 contract short
+        let cash_delta = position.fill(Side::Ask, 1500, dec!(25000), dec!(1), dec!(1), 0);
+        assert_eq!(cash_delta, dec!(5000000) + dec!(1000000) - dec!(2000000));
+        assert_eq!(position.side(), Some(Side::Ask));
+        assert_eq!(position.quantity(), dec!(500));
+        assert_eq!(position.entry_price, dec!(25000));
+        assert_eq!(position.margin, dec!(2000000));
+        assert_eq!(position.rpnl, dec!(1000000));
+        assert_eq!(position.bankruptcy_price(dec!(1)), dec!(0));
+        assert_eq!(position.upnl(dec!(20000), dec!(1)), dec!(-500000));
+
+        let cash_delta = position.fill(Side::Bid, 500, dec!(25000), dec!(1), dec!(1), 0);
+        assert_eq!(cash_delta, dec!(2000000));
+        assert_eq!(position.side(), None);
+        assert_eq!(position.margin, dec!(0));
+    }
+
+    #[test]
+    fn test_funding_and_bankruptcy() {
+        let mut position = Position::default();
+        position.fill(Side::Bid, 1000, dec!(20000), dec!(1), dec!(2), 0);
+        assert_eq!(position.margin, dec!(2500000));
+        assert_eq!(position.pay_funding(dec!(0.0001), dec!(20000), dec!(1)), dec!(-500));
+
+        assert!(!position.is_bankrupt(dec!(14000), dec!(1)));
+        assert!(position.is_bankrupt(position.bankruptcy_price(dec!(1)), dec!(1)));
+        position.liquidate();
+        assert_eq!(position.side(), None);
+        assert_eq!(position.rpnl, dec!(-2500000));
+    }
+}
@@ -1,5 +1,6 @@
 pub mod config;
 pub mod lnurl;
+pub mod mailer;
 pub mod nostr;
 pub mod slack;
 pub mod user;
@@ -77,4 +78,23 @@ mod tests {
         let url_decoded = lnurl::decode(&lnurl).unwrap();
         assert_eq!(url, url_decoded);
     }
+
+    #[test]
+    fn lnurl_auth_signature() {
+        use secp256k1::bitcoin_hashes::hex::ToHex;
+        use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let key = PublicKey::from_secret_key(&secp, &secret_key).to_string();
+        let k1 = [0x07; 32];
+        let sig = secp
+            .sign(&Message::from_slice(&k1).unwrap(), &secret_key)
+            .serialize_der()
+            .to_hex();
+
+        assert!(lnurl::verify_auth_signature(&k1.to_hex(), &sig, &key));
+        assert!(!lnurl::verify_auth_signature(&[0x08; 32].to_hex(), &sig, &key));
+        assert!(!lnurl::verify_auth_signature(&k1.to_hex(), "00", &key));
+    }
 }
@@ -1,4 +1,7 @@
 use bech32::{self, FromBase32, ToBase32};
+use secp256k1::bitcoin_hashes::hex::FromHex;
+use secp256k1::{Message, PublicKey, Secp256k1, Signature};
+use std::str::FromStr;
 
 const PREFIX: &str = "lnurl";
 
@@ -22,3 +25,28 @@ pub fn decode(encoded: &str) -> Result<String, bech32::Error> {
         .to_string();
     Ok(validated_url)
 }
+
+/// Verifies a LUD-04 login: `sig` is the DER encoded signature of the 32 byte `k1` challenge
+/// made with the wallet linking key `key`, all hex encoded.
+pub fn verify_auth_signature(k1: &str, sig: &str, key: &str) -> bool {
+    let k1 = match Vec::<u8>::from_hex(k1) {
+        Ok(k1) => k1,
+        Err(_) => return false,
+    };
+    let message = match Message::from_slice(&k1) {
+        Ok(m) => m,
+        Err(_) => return false,
+    };
+    let mut signature = match Vec::<u8>::from_hex(sig).map(|der| Signature::from_der(&der)) {
+        Ok(Ok(s)) => s,
+        _ => return false,
+    };
+    signature.normalize_s();
+    let public_key = match PublicKey::from_str(key) {
+        Ok(pk) => pk,
+        Err(_) => return false,
+    };
+    Secp256k1::verification_only()
+        .verify(&message, &signature, &public_key)
+        .is_ok()
+}
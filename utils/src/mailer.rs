@@ -0,0 +1,150 @@
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MailerSettings {
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    #[serde(default)]
+    pub smtp_username: Option<String>,
+    #[serde(default)]
+    pub smtp_password: Option<String>,
+    /// Skips STARTTLS, only meant for a local SMTP sink.
+    #[serde(default)]
+    pub smtp_insecure: bool,
+    /// Sender of every email, e.g. "Kollider Wallet <noreply@kollider.xyz>".
+    pub from: String,
+}
+
+#[derive(Debug)]
+pub enum MailerError {
+    InvalidAddress,
+    CouldNotBuildEmail,
+    CouldNotConnect,
+    CouldNotSend(String),
+}
+
+#[derive(Clone)]
+pub struct Mailer {
+    transport: SmtpTransport,
+    from: Mailbox,
+}
+
+pub fn is_valid_email(email: &str) -> bool {
+    email.contains('@') && email.parse::<Mailbox>().is_ok()
+}
+
+impl Mailer {
+    pub fn new(settings: &MailerSettings) -> Result<Self, MailerError> {
+        let from = settings
+            .from
+            .parse::<Mailbox>()
+            .map_err(|_| MailerError::InvalidAddress)?;
+        let builder = if settings.smtp_insecure {
+            SmtpTransport::builder_dangerous(&settings.smtp_host)
+        } else {
+            SmtpTransport::starttls_relay(&settings.smtp_host).map_err(|_| MailerError::CouldNotConnect)?
+        };
+        let builder = match (&settings.smtp_username, &settings.smtp_password) {
+            (Some(username), Some(password)) => {
+                builder.credentials(Credentials::new(username.clone(), password.clone()))
+            }
+            _ => builder,
+        };
+        Ok(Self {
+            transport: builder.port(settings.smtp_port).build(),
+            from,
+        })
+    }
+
+    /// Sends a plain text email, blocking until the SMTP server accepted it.
+    pub fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), MailerError> {
+        let to = to.parse::<Mailbox>().map_err(|_| MailerError::InvalidAddress)?;
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body.to_string())
+            .map_err(|_| MailerError::CouldNotBuildEmail)?;
+        self.transport
+            .send(&email)
+            .map_err(|err| MailerError::CouldNotSend(err.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Accepts a single SMTP session and returns the data of the first message.
+    fn smtp_sink(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut data = String::new();
+        let mut in_data = false;
+        writer.write_all(b"220 localhost ESMTP sink\r\n").unwrap();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            if in_data {
+                if line == ".\r\n" {
+                    writer.write_all(b"250 OK\r\n").unwrap();
+                    break;
+                }
+                data.push_str(&line);
+                continue;
+            }
+            let command = line.get(..4).unwrap_or_default().to_uppercase();
+            match command.as_str() {
+                "EHLO" => writer.write_all(b"250-localhost\r\n250 8BITMIME\r\n").unwrap(),
+                "DATA" => {
+                    in_data = true;
+                    writer.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n").unwrap();
+                }
+                _ => writer.write_all(b"250 OK\r\n").unwrap(),
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_send_to_smtp_sink() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sink = std::thread::spawn(move || smtp_sink(listener));
+
+        let settings = MailerSettings {
+            smtp_host: String::from("127.0.0.1"),
+            smtp_port: port,
+            smtp_username: None,
+            smtp_password: None,
+            smtp_insecure: true,
+            from: String::from("Kollider Wallet <noreply@kollider.xyz>"),
+        };
+        let mailer = Mailer::new(&settings).unwrap();
+        mailer
+            .send("satoshi@example.com", "Verify your email", "Your code is 21")
+            .unwrap();
+
+        let data = sink.join().unwrap();
+        assert!(data.contains("To: satoshi@example.com"));
+        assert!(data.contains("Subject: Verify your email"));
+        assert!(data.contains("Your code is 21"));
+    }
+
+    #[test]
+    fn test_is_valid_email() {
+        assert!(is_valid_email("satoshi@example.com"));
+        assert!(!is_valid_email("satoshi"));
+        assert!(!is_valid_email("satoshi@example.com\r\nBcc: eve@example.com"));
+    }
+}
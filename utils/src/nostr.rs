@@ -1,5 +1,5 @@
 use nostr_sdk::nostr::event;
-use nostr_sdk::prelude::FromPkStr;
+use nostr_sdk::prelude::{FromPkStr, FromSkStr};
 use secp256k1::bitcoin_hashes::hex::ToHex;
 
 pub const ZAP_REQUEST_MEMO: &str = "Zap Request";
@@ -19,6 +19,36 @@ pub enum ZapError {
     AmountMismatch,
     DescriptionHashMismatch,
     CouldNotCreateZapNote,
+    CouldNotCreateZapRequest,
+    MissingAmount,
+    InvalidPubkey,
+    InvalidEventId,
+}
+
+/// NIP-57 `zap` tag of a zapped event, splitting the zap between recipients by weight.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZapSplit {
+    pub pubkey: String,
+    pub relay: Option<String>,
+    pub weight: f64,
+}
+
+/// Author, id and zap splits of an event being zapped.
+#[derive(Debug, Clone)]
+pub struct ZapTarget {
+    pub pubkey: String,
+    pub event_id: String,
+    pub splits: Vec<ZapSplit>,
+}
+
+/// Recipient and amount of a zap request signed by the sender.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZapRequestTarget {
+    pub pubkey: String,
+    pub event_id: Option<String>,
+    pub amount_msat: u64,
+    /// Lnurl of the recipient the sender meant to pay.
+    pub lnurl: Option<String>,
 }
 
 pub fn validate_zap_request(json: &str, invoice_amount: u64) -> Result<nostr_sdk::nostr::Event, ZapError> {
@@ -143,6 +173,149 @@ pub fn create_zap_note(
     Ok((event, relays))
 }
 
+/// Verifies the signature of a zapped event and returns its author, id and `zap` tags.
+pub fn zap_target_from_event(json: &str) -> Result<ZapTarget, ZapError> {
+    let nostr_event = nostr_sdk::nostr::Event::from_json(json).map_err(|err| match err {
+        event::Error::InvalidSignature | event::Error::Secp256k1(_) => ZapError::InvalidSignature,
+        event::Error::Json(_) | event::Error::Hex(_) => ZapError::InvalidJson,
+    })?;
+
+    let splits = nostr_event
+        .tags
+        .iter()
+        .filter_map(|tag| match tag {
+            event::Tag::Generic(event::TagKind::Custom(tag), values) if tag == "zap" => {
+                let pubkey = get_pubkey_hex(values.first()?).ok()?;
+                let relay = values.get(1).filter(|relay| !relay.is_empty()).cloned();
+                let weight = values.get(2).and_then(|weight| weight.parse::<f64>().ok());
+                Some((pubkey, relay, weight))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // Without any weights the zap is split equally, otherwise recipients without a weight get nothing.
+    let weighted = splits.iter().any(|(_, _, weight)| weight.is_some());
+    let splits = splits
+        .into_iter()
+        .map(|(pubkey, relay, weight)| ZapSplit {
+            pubkey,
+            relay,
+            weight: if weighted { weight.unwrap_or_default() } else { 1.0 },
+        })
+        .filter(|split| split.weight.is_finite() && split.weight > 0.0)
+        .collect();
+
+    Ok(ZapTarget {
+        pubkey: nostr_event.pubkey.to_hex(),
+        event_id: nostr_event.id.to_hex(),
+        splits,
+    })
+}
+
+/// Splits the amount by weight in whole sats, the rounding remainder goes to the first recipient.
+/// Recipients whose share rounds down to nothing are left out.
+pub fn split_zap_amount(amount_msat: u64, splits: &[ZapSplit]) -> Vec<(ZapSplit, u64)> {
+    let total_weight = splits.iter().map(|split| split.weight).sum::<f64>();
+    if splits.is_empty() || total_weight <= 0.0 {
+        return Vec::new();
+    }
+    let amount_sats = amount_msat / 1000;
+    let mut shares = splits
+        .iter()
+        .map(|split| {
+            let share = (amount_sats as f64 * split.weight / total_weight).floor() as u64;
+            (split.clone(), share)
+        })
+        .collect::<Vec<_>>();
+    let distributed = shares.iter().map(|(_, share)| share).sum::<u64>();
+    shares[0].1 += amount_sats.saturating_sub(distributed);
+    shares
+        .into_iter()
+        .filter(|(_, share)| *share > 0)
+        .map(|(split, share)| (split, share * 1000))
+        .collect()
+}
+
+/// Signs a zap request (kind 9734) paying `amount_msat` to the recipient's lnurl.
+pub fn create_zap_request(
+    secret_key: &str,
+    recipient: &str,
+    event_id: Option<&str>,
+    amount_msat: u64,
+    lnurl: &str,
+    relays: &[String],
+    content: &str,
+) -> Result<String, ZapError> {
+    let keys = nostr_sdk::nostr::Keys::from_sk_str(secret_key).map_err(|_| ZapError::CouldNotCreateZapRequest)?;
+    let recipient = nostr_sdk::nostr::Keys::from_pk_str(recipient).map_err(|_| ZapError::InvalidPubkey)?;
+
+    let mut tags = vec![event::Tag::PubKey(recipient.public_key(), None)];
+    if let Some(event_id) = event_id {
+        let event_id = nostr_sdk::nostr::EventId::from_hex(event_id).map_err(|_| ZapError::InvalidEventId)?;
+        tags.push(event::Tag::Event(event_id, None, None));
+    }
+    tags.push(event::Tag::Generic(
+        event::TagKind::Custom(String::from("relays")),
+        relays.to_vec(),
+    ));
+    tags.push(event::Tag::Generic(
+        event::TagKind::Custom(String::from("amount")),
+        vec![amount_msat.to_string()],
+    ));
+    tags.push(event::Tag::Generic(
+        event::TagKind::Custom(String::from("lnurl")),
+        vec![lnurl.to_lowercase()],
+    ));
+
+    let builder = event::EventBuilder::new(event::Kind::Custom(ZAP_REQUEST_KIND), content, tags.as_slice());
+    let zap_request = builder
+        .to_event(&keys)
+        .map_err(|_| ZapError::CouldNotCreateZapRequest)?;
+    Ok(zap_request.as_json())
+}
+
+/// Validates a zap request signed by the sender, which has to name a single recipient and the amount.
+pub fn parse_zap_request(json: &str) -> Result<ZapRequestTarget, ZapError> {
+    let nostr_event = nostr_sdk::nostr::Event::from_json(json).map_err(|_| ZapError::InvalidJson)?;
+    let amount_msat = nostr_event
+        .tags
+        .iter()
+        .find_map(|tag| match tag {
+            event::Tag::Generic(event::TagKind::Custom(tag), values) if tag == "amount" => {
+                values.first().and_then(|value| value.parse::<u64>().ok())
+            }
+            _ => None,
+        })
+        .ok_or(ZapError::MissingAmount)?;
+
+    let zap_request = validate_zap_request(json, amount_msat)?;
+
+    let mut pubkeys = zap_request.tags.iter().filter_map(|tag| match tag {
+        event::Tag::PubKey(pubkey, _) => Some(pubkey.to_hex()),
+        _ => None,
+    });
+    let pubkey = pubkeys.next().ok_or(ZapError::NoPubkeyTag)?;
+    if pubkeys.next().is_some() {
+        return Err(ZapError::InvalidPubkey);
+    }
+    let event_id = zap_request.tags.iter().find_map(|tag| match tag {
+        event::Tag::Event(event_id, _, _) => Some(event_id.to_hex()),
+        _ => None,
+    });
+    let lnurl = zap_request.tags.iter().find_map(|tag| match tag {
+        event::Tag::Generic(event::TagKind::Custom(tag), values) if tag == "lnurl" => values.first().cloned(),
+        _ => None,
+    });
+
+    Ok(ZapRequestTarget {
+        pubkey,
+        event_id,
+        amount_msat,
+        lnurl,
+    })
+}
+
 #[derive(Debug)]
 pub enum PubKeyError {
     InvalidPubKey,
@@ -153,3 +326,105 @@ pub fn get_pubkey_hex(pubkey: &str) -> Result<String, PubKeyError> {
     let pubkey_hex = keys.public_key().to_hex();
     Ok(pubkey_hex)
 }
+
+/// Generates a fresh key pair, returned as hex encoded (secret key, public key).
+pub fn generate_keypair_hex() -> Result<(String, String), PubKeyError> {
+    let keys = nostr_sdk::nostr::Keys::generate();
+    let secret_key = keys.secret_key().map_err(|_| PubKeyError::InvalidPubKey)?;
+    Ok((secret_key.display_secret().to_string(), keys.public_key().to_hex()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(pubkey: &str, weight: f64) -> ZapSplit {
+        ZapSplit {
+            pubkey: pubkey.to_string(),
+            relay: None,
+            weight,
+        }
+    }
+
+    #[test]
+    fn test_split_zap_amount() {
+        let splits = vec![split("a", 1.0), split("b", 1.0), split("c", 1.0)];
+        let shares = split_zap_amount(100_000, &splits)
+            .into_iter()
+            .map(|(split, amount)| (split.pubkey, amount))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            shares,
+            vec![
+                (String::from("a"), 34_000),
+                (String::from("b"), 33_000),
+                (String::from("c"), 33_000)
+            ]
+        );
+
+        let splits = vec![split("a", 99.0), split("b", 1.0)];
+        let shares = split_zap_amount(10_000, &splits);
+        assert_eq!(shares.len(), 1);
+        assert_eq!(shares[0].1, 10_000);
+
+        assert!(split_zap_amount(10_000, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_create_and_parse_zap_request() {
+        let sender = nostr_sdk::nostr::Keys::generate();
+        let secret_key = sender.secret_key().unwrap().display_secret().to_string();
+        let (_, recipient) = generate_keypair_hex().unwrap();
+        let relays = vec![String::from("wss://relay.damus.io")];
+
+        let zap_request = create_zap_request(
+            &secret_key,
+            &recipient,
+            None,
+            21_000,
+            "LNURL1DP68GURN8GHJ7",
+            &relays,
+            "",
+        )
+        .unwrap();
+        let target = parse_zap_request(&zap_request).unwrap();
+        assert_eq!(target.pubkey, recipient);
+        assert_eq!(target.event_id, None);
+        assert_eq!(target.amount_msat, 21_000);
+        assert_eq!(target.lnurl.as_deref(), Some("lnurl1dp68gurn8ghj7"));
+        assert!(validate_zap_request(&zap_request, 21_000).is_ok());
+        assert!(matches!(
+            validate_zap_request(&zap_request, 1_000),
+            Err(ZapError::AmountMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_zap_target_from_event() {
+        let author = nostr_sdk::nostr::Keys::generate();
+        let (_, first) = generate_keypair_hex().unwrap();
+        let (_, second) = generate_keypair_hex().unwrap();
+        let tags = vec![
+            event::Tag::Generic(
+                event::TagKind::Custom(String::from("zap")),
+                vec![first.clone(), String::from("wss://nos.lol"), String::from("3")],
+            ),
+            event::Tag::Generic(event::TagKind::Custom(String::from("zap")), vec![second, String::new()]),
+        ];
+        let note = event::EventBuilder::new_text_note("gm", tags.as_slice())
+            .to_event(&author)
+            .unwrap();
+
+        let target = zap_target_from_event(&note.as_json()).unwrap();
+        assert_eq!(target.pubkey, author.public_key().to_hex());
+        assert_eq!(target.event_id, note.id.to_hex());
+        assert_eq!(
+            target.splits,
+            vec![ZapSplit {
+                pubkey: first,
+                relay: Some(String::from("wss://nos.lol")),
+                weight: 3.0,
+            }]
+        );
+    }
+}
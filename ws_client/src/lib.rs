@@ -1,4 +1,4 @@
-use core_types::kollider_client::{Balances, PositionState, Side};
+use core_types::kollider_client::{Balances, ExtOrderId, OrderId, PositionState, Side};
 use core_types::{Currency, Symbol};
 use msgs::kollider_client::{Channel, TradableSymbol};
 use rust_decimal::Decimal;
@@ -16,7 +16,19 @@ pub trait WsClient {
     fn get_position_state(&self, symbol: &Symbol) -> Result<Option<PositionState>>;
     fn get_tradable_symbols(&self) -> HashMap<Symbol, TradableSymbol>;
     fn make_withdrawal(&self, amount: u64, payment_request: String) -> Result<()>;
-    fn make_order(&self, quantity: u64, symbol: Symbol, side: Side) -> Result<()>;
+    fn make_order(&self, quantity: u64, symbol: Symbol, side: Side) -> Result<ExtOrderId>;
+    /// `price` is an integer of the symbol's price decimal places.
+    fn make_limit_order(
+        &self,
+        quantity: u64,
+        symbol: Symbol,
+        side: Side,
+        price: u64,
+        is_post_only: bool,
+    ) -> Result<ExtOrderId>;
+    fn cancel_order(&self, order_id: OrderId, symbol: Symbol) -> Result<()>;
+    /// Answered with `OpenOrders`.
+    fn fetch_open_orders(&self) -> Result<()>;
     fn subscribe(&self, chanels: Vec<Channel>, symbols: Option<Vec<Symbol>>) -> Result<()>;
     fn buy(&self, quantity: u64, currency: Currency) -> Result<()>;
     fn sell(&self, quantity: u64, currency: Currency) -> Result<()>;
@@ -17,6 +17,12 @@ pub enum AuthError {
     RegistrationsDisabled,
     #[error(display = "Invalid username")]
     InvalidUsername,
+    #[error(display = "Unknown or expired lnurl auth challenge.")]
+    InvalidLnurlAuthChallenge,
+    #[error(display = "New registrations require an invite code.")]
+    InviteCodeRequired,
+    #[error(display = "Invite code is invalid, used up or expired.")]
+    InvalidInviteCode,
 }
 
 #[derive(Debug, Error, Serialize)]
@@ -60,6 +66,24 @@ pub enum NostrEngineError {
     UnableToLoadProfile,
     #[error(display = "Unable to send private message.")]
     UnableToSendPrivateMessage,
+    #[error(display = "Nostr Wallet Connect is not configured on this server.")]
+    WalletConnectNotConfigured,
+    #[error(display = "Unknown wallet connection.")]
+    UnknownWalletConnection,
+    #[error(display = "Zaps are not configured on this server.")]
+    ZapsNotConfigured,
+    #[error(display = "Invalid zap request.")]
+    InvalidZapRequest,
+    #[error(display = "Zap recipient has no lightning address.")]
+    RecipientNotZappable,
+    #[error(display = "NIP-05 name is already taken.")]
+    Nip05NameTaken,
+    #[error(display = "NIP-05 name is reserved.")]
+    Nip05NameReserved,
+    #[error(display = "Maximum number of NIP-05 names reached.")]
+    Nip05NameLimitReached,
+    #[error(display = "Unknown NIP-05 name.")]
+    UnknownNip05Name,
 }
 
 #[derive(Debug, Error, Serialize)]
@@ -92,6 +116,63 @@ pub enum ExternalError {
     FailedToFetchExternalData,
 }
 
+#[derive(Debug, Error, Serialize)]
+#[error(display = "An Error has occurred during two factor authentication.")]
+pub enum TotpError {
+    #[error(display = "Two factor authentication is not configured on this server.")]
+    TotpNotConfigured,
+    #[error(display = "Two factor authentication is not set up for this user.")]
+    TotpNotEnrolled,
+    #[error(display = "Two factor authentication is already enabled.")]
+    TotpAlreadyEnabled,
+    #[error(display = "A two factor authentication code is required.")]
+    TotpCodeRequired,
+    #[error(display = "Invalid two factor authentication code.")]
+    InvalidTotpCode,
+}
+
+#[derive(Debug, Error, Serialize)]
+#[error(display = "An Error has occurred whilst resolving a lightning address.")]
+pub enum LnurlPayError {
+    #[error(display = "Recipient is not a valid lightning address or lnurl.")]
+    InvalidRecipient,
+    #[error(display = "Paying a lightning address requires a bitcoin amount.")]
+    AmountRequired,
+    #[error(display = "External lightning addresses can only be paid from a bitcoin account.")]
+    BtcAmountRequired,
+    #[error(display = "Amount is outside of the range accepted by the recipient.")]
+    AmountOutOfRange,
+    #[error(display = "Comment is not accepted by the recipient.")]
+    CommentNotAllowed,
+    #[error(display = "Recipient service could not be reached.")]
+    ServiceUnavailable,
+    #[error(display = "Recipient service returned an error: {}", _0)]
+    ServiceError(String),
+    #[error(display = "Recipient service returned an invalid invoice.")]
+    InvalidInvoice,
+    #[error(display = "Recipient does not accept zaps.")]
+    ZapsNotSupported,
+}
+
+#[derive(Debug, Error, Serialize)]
+#[error(display = "An Error has occurred whilst verifying an email address.")]
+pub enum EmailError {
+    #[error(display = "Sending emails is not configured on this server.")]
+    EmailNotConfigured,
+    #[error(display = "No email address has been set.")]
+    NoEmailAddress,
+    #[error(display = "Invalid email address.")]
+    InvalidEmailAddress,
+    #[error(display = "Email address is already verified.")]
+    EmailAlreadyVerified,
+    #[error(display = "A verification email has been sent recently, try again later.")]
+    VerificationRequestedTooOften,
+    #[error(display = "Invalid or expired verification token.")]
+    InvalidVerificationToken,
+    #[error(display = "Failed to send email.")]
+    FailedToSendEmail,
+}
+
 #[derive(Debug, Error, Serialize)]
 #[serde(untagged)]
 pub enum ApiError {
@@ -111,6 +192,12 @@ pub enum ApiError {
     Nostr(NostrEngineError),
     #[error(display = "Admin error.")]
     Admin(AdminError),
+    #[error(display = "Totp error.")]
+    Totp(TotpError),
+    #[error(display = "Lnurl pay error.")]
+    LnurlPay(LnurlPayError),
+    #[error(display = "Email error.")]
+    Email(EmailError),
 }
 
 impl error::ResponseError for ApiError {
@@ -122,6 +209,9 @@ impl error::ResponseError for ApiError {
                 AuthError::RegistrationLimitExceeded => HttpResponse::Unauthorized(),
                 AuthError::RegistrationsDisabled => HttpResponse::Unauthorized(),
                 AuthError::InvalidUsername => HttpResponse::Unauthorized(),
+                AuthError::InvalidLnurlAuthChallenge => HttpResponse::Unauthorized(),
+                AuthError::InviteCodeRequired => HttpResponse::Unauthorized(),
+                AuthError::InvalidInviteCode => HttpResponse::Unauthorized(),
             },
             ApiError::Db(db) => match db {
                 DbError::DbConnectionError => HttpResponse::InternalServerError(),
@@ -145,8 +235,44 @@ impl error::ResponseError for ApiError {
             ApiError::Nostr(nostr_engine_error) => match nostr_engine_error {
                 NostrEngineError::UnableToLoadProfile => HttpResponse::InternalServerError(),
                 NostrEngineError::UnableToSendPrivateMessage => HttpResponse::InternalServerError(),
+                NostrEngineError::WalletConnectNotConfigured => HttpResponse::InternalServerError(),
+                NostrEngineError::UnknownWalletConnection => HttpResponse::NotFound(),
+                NostrEngineError::ZapsNotConfigured => HttpResponse::InternalServerError(),
+                NostrEngineError::InvalidZapRequest => HttpResponse::BadRequest(),
+                NostrEngineError::RecipientNotZappable => HttpResponse::BadRequest(),
+                NostrEngineError::Nip05NameTaken => HttpResponse::Conflict(),
+                NostrEngineError::Nip05NameReserved => HttpResponse::BadRequest(),
+                NostrEngineError::Nip05NameLimitReached => HttpResponse::BadRequest(),
+                NostrEngineError::UnknownNip05Name => HttpResponse::NotFound(),
             },
             ApiError::Admin(AdminError::NoPermission) => HttpResponse::Unauthorized(),
+            ApiError::Totp(totp) => match totp {
+                TotpError::TotpNotConfigured => HttpResponse::InternalServerError(),
+                TotpError::TotpNotEnrolled => HttpResponse::BadRequest(),
+                TotpError::TotpAlreadyEnabled => HttpResponse::Conflict(),
+                TotpError::TotpCodeRequired => HttpResponse::Unauthorized(),
+                TotpError::InvalidTotpCode => HttpResponse::Unauthorized(),
+            },
+            ApiError::LnurlPay(lnurl_pay) => match lnurl_pay {
+                LnurlPayError::InvalidRecipient => HttpResponse::BadRequest(),
+                LnurlPayError::AmountRequired => HttpResponse::BadRequest(),
+                LnurlPayError::BtcAmountRequired => HttpResponse::BadRequest(),
+                LnurlPayError::AmountOutOfRange => HttpResponse::BadRequest(),
+                LnurlPayError::CommentNotAllowed => HttpResponse::BadRequest(),
+                LnurlPayError::ServiceUnavailable => HttpResponse::BadGateway(),
+                LnurlPayError::ServiceError(_) => HttpResponse::BadGateway(),
+                LnurlPayError::InvalidInvoice => HttpResponse::BadGateway(),
+                LnurlPayError::ZapsNotSupported => HttpResponse::BadRequest(),
+            },
+            ApiError::Email(email) => match email {
+                EmailError::EmailNotConfigured => HttpResponse::InternalServerError(),
+                EmailError::NoEmailAddress => HttpResponse::BadRequest(),
+                EmailError::InvalidEmailAddress => HttpResponse::BadRequest(),
+                EmailError::EmailAlreadyVerified => HttpResponse::Conflict(),
+                EmailError::VerificationRequestedTooOften => HttpResponse::TooManyRequests(),
+                EmailError::InvalidVerificationToken => HttpResponse::BadRequest(),
+                EmailError::FailedToSendEmail => HttpResponse::BadGateway(),
+            },
         };
         response_builder.json(json!({ "error": self }))
     }
@@ -159,6 +285,9 @@ impl error::ResponseError for ApiError {
                 AuthError::RegistrationLimitExceeded => StatusCode::UNAUTHORIZED,
                 AuthError::RegistrationsDisabled => StatusCode::UNAUTHORIZED,
                 AuthError::InvalidUsername => StatusCode::UNAUTHORIZED,
+                AuthError::InvalidLnurlAuthChallenge => StatusCode::UNAUTHORIZED,
+                AuthError::InviteCodeRequired => StatusCode::UNAUTHORIZED,
+                AuthError::InvalidInviteCode => StatusCode::UNAUTHORIZED,
             },
             ApiError::Db(db) => match db {
                 DbError::DbConnectionError => StatusCode::INTERNAL_SERVER_ERROR,
@@ -183,8 +312,44 @@ impl error::ResponseError for ApiError {
             ApiError::Nostr(nostr_engine_error) => match nostr_engine_error {
                 NostrEngineError::UnableToLoadProfile => StatusCode::INTERNAL_SERVER_ERROR,
                 NostrEngineError::UnableToSendPrivateMessage => StatusCode::INTERNAL_SERVER_ERROR,
+                NostrEngineError::WalletConnectNotConfigured => StatusCode::INTERNAL_SERVER_ERROR,
+                NostrEngineError::UnknownWalletConnection => StatusCode::NOT_FOUND,
+                NostrEngineError::ZapsNotConfigured => StatusCode::INTERNAL_SERVER_ERROR,
+                NostrEngineError::InvalidZapRequest => StatusCode::BAD_REQUEST,
+                NostrEngineError::RecipientNotZappable => StatusCode::BAD_REQUEST,
+                NostrEngineError::Nip05NameTaken => StatusCode::CONFLICT,
+                NostrEngineError::Nip05NameReserved => StatusCode::BAD_REQUEST,
+                NostrEngineError::Nip05NameLimitReached => StatusCode::BAD_REQUEST,
+                NostrEngineError::UnknownNip05Name => StatusCode::NOT_FOUND,
             },
             ApiError::Admin(AdminError::NoPermission) => StatusCode::UNAUTHORIZED,
+            ApiError::Totp(totp) => match totp {
+                TotpError::TotpNotConfigured => StatusCode::INTERNAL_SERVER_ERROR,
+                TotpError::TotpNotEnrolled => StatusCode::BAD_REQUEST,
+                TotpError::TotpAlreadyEnabled => StatusCode::CONFLICT,
+                TotpError::TotpCodeRequired => StatusCode::UNAUTHORIZED,
+                TotpError::InvalidTotpCode => StatusCode::UNAUTHORIZED,
+            },
+            ApiError::LnurlPay(lnurl_pay) => match lnurl_pay {
+                LnurlPayError::InvalidRecipient => StatusCode::BAD_REQUEST,
+                LnurlPayError::AmountRequired => StatusCode::BAD_REQUEST,
+                LnurlPayError::BtcAmountRequired => StatusCode::BAD_REQUEST,
+                LnurlPayError::AmountOutOfRange => StatusCode::BAD_REQUEST,
+                LnurlPayError::CommentNotAllowed => StatusCode::BAD_REQUEST,
+                LnurlPayError::ServiceUnavailable => StatusCode::BAD_GATEWAY,
+                LnurlPayError::ServiceError(_) => StatusCode::BAD_GATEWAY,
+                LnurlPayError::InvalidInvoice => StatusCode::BAD_GATEWAY,
+                LnurlPayError::ZapsNotSupported => StatusCode::BAD_REQUEST,
+            },
+            ApiError::Email(email) => match email {
+                EmailError::EmailNotConfigured => StatusCode::INTERNAL_SERVER_ERROR,
+                EmailError::NoEmailAddress => StatusCode::BAD_REQUEST,
+                EmailError::InvalidEmailAddress => StatusCode::BAD_REQUEST,
+                EmailError::EmailAlreadyVerified => StatusCode::CONFLICT,
+                EmailError::VerificationRequestedTooOften => StatusCode::TOO_MANY_REQUESTS,
+                EmailError::InvalidVerificationToken => StatusCode::BAD_REQUEST,
+                EmailError::FailedToSendEmail => StatusCode::BAD_GATEWAY,
+            },
         }
     }
 }